@@ -0,0 +1,58 @@
+# Fehler
+error-invalid-arguments = Ungültige Anzahl von Argumenten: { $count }
+error-invalid-uuid = Zeichenkette ist keine gültige UUID
+error-missing-xml-node = Fehlender XML-Knoten: { $node }
+error-invalid-playing-status = Ungültiger Wiedergabestatus
+error-unsupported-operation = Player unterstützt { $operation } nicht
+error-unknown-schema = Unbekanntes { $player }-Datenbankschema: { $found }
+error-unknown-category = Keine OPML-Kategorie mit dem Namen '{ $name }'
+error-missing-credentials = Kein Benutzername oder Passwort für { $player }
+error-verification-failed = Überprüfung der { $player }-Ausgabe fehlgeschlagen: { $reason }
+error-denied-warnings = { $count } verbotene Warnungen sind aufgetreten
+error-unknown-save-file = Es ist nicht erkennbar, welcher Player '{ $path }' geschrieben hat, bitte mit der Option des Players angeben
+error-auto-conflict = --auto hat eine { $player }-Sicherung gefunden, das lässt sich nicht mit --{ $player } kombinieren
+error-missing-in-out = Ein Quell- (--in-*) und mindestens ein Ziel-Player (--out-*) sind erforderlich
+error-skipped = Auf Wunsch übersprungen
+error-feed-not-found = Keine Datei im Feed-Verzeichnis für '{ $url }'
+error-unknown-player = Es gibt keinen beschreibbaren Player namens '{ $player }'
+error-unknown-profile = Kein Profil namens '{ $name }' in der Konfigurationsdatei
+error-invalid-feed-auth = --feed-auth erwartet HOST=BENUTZER:PASSWORT
+error-same-file = Die Ausgabe '{ $output }' ist dieselbe Datei wie die Eingabe '{ $input }' und würde sie ersetzen, dafür --in-place angeben
+error-file-locked = '{ $path }' ist von einem anderen Programm gesperrt, etwa einem weiteren Lauf dieses Programms, nach dessen Ende erneut versuchen
+error-fetch-panicked = Ein Thread zum Abrufen der Feeds ist abgestürzt, es wurde nichts konvertiert: { $reason }
+error-resume-output = Das Fortsetzungstoken gilt für --out-{ $player } { $path }, das nicht unter den Ausgaben ist
+error-round-trip-file = Die Rundreise braucht eine { $player }-Sicherung, bitte mit --{ $player } angeben
+
+# Warnungen
+warning-prefix = Warnung
+warning-denied-prefix = Fehler
+warning-unit-mismatch = { $url } steht bei { $progress }s, ist aber nur { $duration }s lang, der Player speichert eventuell eine andere Einheit
+
+# Konvertierung
+populating = Lese '{ $title }' ({ $url })
+skipping-podcast = Überspringe Podcast '{ $title }' ({ $url }): { $error }
+skipping-settings = Die Einstellungen von '{ $title }' ({ $url }) werden nicht übernommen: { $error }
+saving-to = Speichere nach '{ $player }'
+heartbeat = { $operation } läuft seit { $secs }s
+operation-fetch = Abruf von '{ $title }' ({ $url })
+operation-save = Speichern nach '{ $player }'
+changes-computed = { $player }: { $podcasts } Podcasts zu schreiben, { $updates } Episoden geändert
+changes-exported = Änderungen für { $outputs } Ausgaben in '{ $path }' gespeichert, zum Schreiben den Unterbefehl apply verwenden
+source-changed = '{ $path }' wurde seit dem Export der Änderungen verändert, das seitdem Gehörte wird übernommen
+converted-opml-written = Die { $podcasts } Podcasts mit konvertierten Episoden in '{ $path }' geschrieben
+skip-hint = s und Enter überspringt den Feed, der gerade abgerufen wird
+skip-hint-jobs = s und Enter überspringt die Feeds, die gerade abgerufen werden, bis zu { $jobs } auf einmal (--jobs 1 überspringt einzeln)
+skipping-no-url = Überspringe '{ $title }', der Quell-Player speichert die Feed-URL nicht, zum Konvertieren eine OPML-Datei mit --opml angeben
+skipping-feed = Überspringe '{ $title }' ({ $url }) auf Wunsch
+partial-saved = Die { $count } vor dem Fehler gespeicherten Podcasts stehen in '{ $path }', zum Speichern der übrigen denselben Befehl mit --resume { $token } ausführen
+partial-failed = Konnte keine Teilausgabe speichern: { $error }
+skipped-summary = { $podcasts } Podcasts und { $tracks } Episoden wurden wegen Fehlern übersprungen, siehe oben
+summary-read = { $podcasts } Podcasts mit { $tracks } Episoden aus { $player } gelesen
+listening-time = { $listened } Stunden gehört, { $completion } % vollständig, { $backlog } Stunden übrig in { $episodes } Episoden
+summary-saved = { $player } in '{ $path }' gespeichert
+summary-warnings = { $count } Warnungen { $name }
+summary-error = Der Lauf ist fehlgeschlagen: { $error }
+summary-subject-succeeded = podcast_history_converter: Konvertierung erfolgreich
+summary-subject-failed = podcast_history_converter: Konvertierung fehlgeschlagen
+summary-mailed = Zusammenfassung an { $to } geschickt
+summary-mail-failed = Zusammenfassung konnte nicht an { $to } geschickt werden: { $error }
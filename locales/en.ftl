@@ -0,0 +1,58 @@
+# Errors
+error-invalid-arguments = Invalid number of call arguments: { $count }
+error-invalid-uuid = String is not a valid UUID
+error-missing-xml-node = Missing XML node: { $node }
+error-invalid-playing-status = Invalid playing status
+error-unsupported-operation = Player does not support { $operation }
+error-unknown-schema = Unknown { $player } database schema: { $found }
+error-unknown-category = No OPML category named '{ $name }'
+error-missing-credentials = No username or password for { $player }
+error-verification-failed = { $player } output failed verification: { $reason }
+error-denied-warnings = { $count } denied warnings were raised
+error-unknown-save-file = Could not tell which player wrote '{ $path }', give it with the player's own option instead
+error-auto-conflict = --auto found a { $player } save file, which can not be combined with --{ $player }
+error-missing-in-out = A source (--in-*) and at least one destination (--out-*) player are required
+error-skipped = Skipped on request
+error-feed-not-found = No file in the feeds directory for '{ $url }'
+error-unknown-player = No player that can be written to is called '{ $player }'
+error-unknown-profile = No profile named '{ $name }' in the config file
+error-invalid-feed-auth = --feed-auth takes HOST=USER:PASSWORD
+error-same-file = The output '{ $output }' is the same file as the input '{ $input }' and would replace it, give --in-place to do so
+error-file-locked = '{ $path }' is locked by another program, such as another run of this one, try again once it has finished
+error-fetch-panicked = A thread fetching the feeds crashed, no conversion was written: { $reason }
+error-resume-output = The resume token is for --out-{ $player } { $path }, which is not among the outputs
+error-round-trip-file = The round trip needs a { $player } save file, give it with --{ $player }
+
+# Warnings
+warning-prefix = warning
+warning-denied-prefix = error
+warning-unit-mismatch = { $url } is at { $progress }s but only { $duration }s long, the player may store another unit
+
+# Conversion
+populating = Populating '{ $title }' ({ $url })
+skipping-podcast = Skipping podcast '{ $title }' ({ $url }): { $error }
+skipping-settings = Not converting the settings of '{ $title }' ({ $url }): { $error }
+saving-to = Saving to '{ $player }'
+heartbeat = Still { $operation } after { $secs }s
+operation-fetch = fetching '{ $title }' ({ $url })
+operation-save = saving to '{ $player }'
+changes-computed = { $player }: { $podcasts } podcasts to write, { $updates } episodes changed
+changes-exported = Saved the changes for { $outputs } outputs to '{ $path }', write them with the apply subcommand
+source-changed = '{ $path }' has changed since the changes were exported, keeping the listening done since
+converted-opml-written = Wrote the { $podcasts } podcasts with converted episodes to '{ $path }'
+skip-hint = Type s and press Enter to skip the feed being fetched
+skip-hint-jobs = Type s and press Enter to skip the feeds being fetched, all of up to { $jobs } at once (--jobs 1 skips one at a time)
+skipping-no-url = Skipping '{ $title }', the source player does not keep its feed URL, give an OPML file with --opml to convert it
+skipping-feed = Skipping '{ $title }' ({ $url }) on request
+partial-saved = The { $count } podcasts saved before the failure are in '{ $path }', run the same command with --resume { $token } to save the rest
+partial-failed = Could not save a partial output: { $error }
+skipped-summary = Skipped { $podcasts } podcasts and { $tracks } tracks because of errors, see above
+summary-read = Read { $podcasts } podcasts with { $tracks } episodes from { $player }
+listening-time = { $listened } hours listened, { $completion }% complete, { $backlog } hours left in { $episodes } episodes
+summary-saved = Saved { $player } to '{ $path }'
+summary-warnings = { $count } { $name } warnings
+summary-error = The run failed: { $error }
+summary-subject-succeeded = podcast_history_converter: conversion succeeded
+summary-subject-failed = podcast_history_converter: conversion failed
+summary-mailed = Mailed the summary to { $to }
+summary-mail-failed = Could not mail the summary to { $to }: { $error }
@@ -0,0 +1,65 @@
+use crate::fetch;
+use crate::{BoxResult, Error};
+
+use reqwest::Url;
+use serde::Deserialize;
+
+// Some exports list a podcast by its Apple Podcasts page
+// (`https://podcasts.apple.com/us/podcast/NAME/id123456789`) rather than its
+// feed. Such a URL is resolved to the feed through the iTunes lookup API before
+// anything is fetched, and the page URL is kept as the podcast's previous URL
+// so --updated-opml replaces it with the feed's.
+
+const LOOKUP: &str = "https://itunes.apple.com/lookup";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LookupResult {
+	feed_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Lookup {
+	#[serde(default)]
+	results: Vec<LookupResult>,
+}
+
+/// The Apple Podcasts ID of a podcast page URL, None for any other URL
+fn podcast_id(url: &Url) -> Option<String> {
+	let host = url.host_str()?.to_lowercase();
+	if host != "podcasts.apple.com" && host != "itunes.apple.com" {
+		return None;
+	}
+
+	// The ID is the last path segment, `id` and its digits, or in older links an `id` parameter
+	let from_path = url
+		.path_segments()?
+		.filter(|segment| segment.starts_with("id"))
+		.last()
+		.map(|segment| &segment[2..]);
+	let from_query = url
+		.query_pairs()
+		.find(|(name, _)| name == "id")
+		.map(|(_, value)| value.into_owned());
+	from_path
+		.map(String::from)
+		.or(from_query)
+		.filter(|id| !id.is_empty() && id.bytes().all(|byte| byte.is_ascii_digit()))
+}
+
+/// The feed of an Apple Podcasts page URL, None if `url` is not one
+pub fn resolve(url: &Url) -> BoxResult<Option<Url>> {
+	let id = match podcast_id(url) {
+		Some(id) => id,
+		None => return Ok(None),
+	};
+
+	let lookup_url = Url::parse_with_params(LOOKUP, &[("id", id.as_str()), ("entity", "podcast")])?;
+	let lookup: Lookup = serde_json::from_str(&fetch::get(lookup_url)?)?;
+	let feed_url = lookup
+		.results
+		.into_iter()
+		.find_map(|result| result.feed_url)
+		.ok_or_else(|| Error::FeedNotFound(url.to_string()))?;
+	Ok(Some(Url::parse(&feed_url)?))
+}
@@ -0,0 +1,336 @@
+use crate::clock::Clock;
+use crate::i18n::tr;
+use crate::player::{find_episodes, Candidate, ChangeSet, JsonPodcast, Player, SaveOptions};
+use crate::podcast::{PlayingStatus, Podcast, Track};
+use crate::provenance::Provenance;
+use crate::url_key::url_key;
+use crate::warning;
+use crate::{temp_file_beside, BoxResult, Error, PlayerArgs};
+
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::time::UNIX_EPOCH;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use serde::{Deserialize, Serialize};
+
+// With --export-changes a conversion stops once it has worked out what it would
+// write, and saves that to a file instead: for each output, every podcast with
+// the state each of its episodes will be given, in the JSON file player's
+// layout. The file can be looked over and edited, and `apply` writes it out
+// later without fetching a feed or reading the source player again, so the
+// slow matching and the writing to the players' databases can happen apart.
+//
+// Each output is written on top of the save file its changes were worked out
+// from, and a copy of that file taken from the device since (e.g. by a
+// scheduled backup) may have listening in it the changes do not. The file's
+// size, modification time and a hash of it are kept with the changes, and when
+// they no longer match `apply` reads the newer copy and keeps every episode's
+// state from it that is further along than the changes', instead of undoing
+// it. Episodes are found in it by the --match-strategy chain, as the players
+// find them. There is no watch mode, the check is made each time `apply` runs,
+// and save files that are directories are not checked.
+
+const SCHEMA_VERSION: u32 = 1;
+
+/// What a save file was when the changes were worked out from it
+#[derive(Serialize, Deserialize, PartialEq)]
+struct Stamp {
+	len: u64,
+	/// Unix time in seconds
+	modified: u64,
+	/// FNV-1a of the contents, in hex
+	hash: String,
+}
+
+/// FNV-1a of a file, read a buffer at a time so large save files are never held whole
+fn fnv1a(path: &str) -> io::Result<u64> {
+	let mut reader = BufReader::new(File::open(path)?);
+	let mut hash = 0xcbf2_9ce4_8422_2325u64;
+	loop {
+		let buf = reader.fill_buf()?;
+		if buf.is_empty() {
+			return Ok(hash);
+		}
+		for byte in buf {
+			hash = (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3);
+		}
+		let len = buf.len();
+		reader.consume(len);
+	}
+}
+
+impl Stamp {
+	/// None for a directory or a file that can not be read
+	fn of(path: &str) -> Option<Self> {
+		let metadata = fs::metadata(path).ok()?;
+		if !metadata.is_file() {
+			return None;
+		}
+		let hash = fnv1a(path).ok()?;
+
+		Some(Self {
+			len: metadata.len(),
+			modified: metadata
+				.modified()
+				.ok()?
+				.duration_since(UNIX_EPOCH)
+				.map_or(0, |since| since.as_secs()),
+			hash: format!("{:016x}", hash),
+		})
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct Target {
+	/// Command line name of the player to write
+	player: String,
+	/// Save file the changes are applied on top of
+	source: String,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	source_stamp: Option<Stamp>,
+	output: String,
+	podcasts: Vec<JsonPodcast>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ChangesFile {
+	version: u32,
+	/// Command line name of the player the history was converted from
+	source_player: String,
+	/// Unix time the changes were worked out at, the save time when applied with --deterministic
+	/// or --timestamp
+	timestamp: i64,
+	fixed_clock: bool,
+	deterministic: bool,
+	targets: Vec<Target>,
+}
+
+impl ChangesFile {
+	pub fn new(source_player: &str, options: &SaveOptions, fixed_clock: bool) -> Self {
+		Self {
+			version: SCHEMA_VERSION,
+			source_player: source_player.into(),
+			timestamp: options.clock.unix_secs(),
+			fixed_clock,
+			deterministic: options.deterministic,
+			targets: Vec::new(),
+		}
+	}
+
+	/// Only the episodes that change the save file are kept, unless the player is written
+	/// `whole` podcasts
+	pub fn add(
+		&mut self,
+		player: &str,
+		source: &str,
+		output: &str,
+		changes: &ChangeSet,
+		whole: bool,
+	) {
+		let podcasts = if whole {
+			changes.podcasts.iter().map(JsonPodcast::from).collect()
+		} else {
+			changes
+				.changed_podcasts()
+				.iter()
+				.map(JsonPodcast::from)
+				.collect()
+		};
+		self.targets.push(Target {
+			player: player.into(),
+			source: source.into(),
+			source_stamp: Stamp::of(source),
+			output: output.into(),
+			podcasts,
+		});
+	}
+
+	pub fn write(&self, path: &str) -> BoxResult<()> {
+		let file = File::create(path)?;
+		serde_json::to_writer_pretty(&file, self)?;
+		file.sync_all()?;
+		println!(
+			"{}",
+			tr(
+				"changes-exported",
+				&[("path", &path), ("outputs", &self.targets.len())]
+			)
+		);
+		Ok(())
+	}
+
+	fn load(path: &str) -> BoxResult<Self> {
+		let changes: Self = serde_json::from_reader(File::open(path)?)?;
+		if changes.version > SCHEMA_VERSION {
+			return Err(Error::UnknownSchema(
+				"changes file",
+				String::from("version ") + &changes.version.to_string(),
+			)
+			.into());
+		}
+		Ok(changes)
+	}
+}
+
+/// How far along an episode is, a played one furthest
+fn progress_rank(track: &Track) -> (u8, i32) {
+	match track.playing_status {
+		PlayingStatus::Played => (2, 0),
+		PlayingStatus::Playing => (1, track.progress),
+		PlayingStatus::Unplayed => (0, track.progress),
+	}
+}
+
+/// The episodes the player's save file has now of each podcast. Read as the file keeps them
+/// where the player can list them, otherwise by populating the podcasts again, quietly as
+/// their warnings were given when the changes were worked out
+fn current_tracks(player: &mut dyn Player, podcasts: &[Podcast]) -> BoxResult<Vec<Vec<Track>>> {
+	if let Ok(local) = player.local_podcasts() {
+		return Ok(podcasts
+			.iter()
+			.map(|podcast| {
+				let key = url_key(podcast.url.as_str());
+				local
+					.iter()
+					.find(|stored| {
+						stored
+							.url
+							.as_ref()
+							.map_or(false, |url| url_key(url.as_str()) == key)
+					})
+					.or_else(|| local.iter().find(|stored| stored.title == podcast.title))
+					.map_or_else(Vec::new, |stored| stored.tracks.clone())
+			})
+			.collect());
+	}
+	warning::quietly(|| {
+		podcasts
+			.iter()
+			.map(|podcast| {
+				player
+					.populate(podcast.clone())
+					.map(|populated| populated.tracks)
+			})
+			.collect()
+	})
+}
+
+/// The podcasts with every episode the player's save file has further along taken from it
+fn rebaseline(player: &mut dyn Player, podcasts: Vec<Podcast>) -> BoxResult<Vec<Podcast>> {
+	let current = current_tracks(player, &podcasts)?;
+	let mut rebased = Vec::with_capacity(podcasts.len());
+	for (mut podcast, current) in podcasts.into_iter().zip(current) {
+		// Found the way the players find episodes, by the --match-strategy chain
+		let candidates: Vec<Candidate<&Track>> = current
+			.iter()
+			.map(|stored| Candidate {
+				guid: Some(stored.guid.clone()),
+				url: Some(stored.url.to_string()),
+				title: Some(stored.title.clone()),
+				published: stored.published,
+				duration: stored.duration,
+				value: stored,
+			})
+			.collect();
+		let matches = find_episodes(&podcast.tracks, |guid| guid.to_string(), &candidates);
+		for (track, found) in podcast.tracks.iter_mut().zip(matches) {
+			let newer = found
+				.map(|candidate| candidate.value)
+				.filter(|stored| progress_rank(stored) > progress_rank(track));
+			if let Some(newer) = newer {
+				track.playing_status = newer.playing_status;
+				track.progress = newer.progress;
+				track.last_played = newer.last_played.or(track.last_played);
+			}
+		}
+		rebased.push(podcast);
+	}
+	Ok(rebased)
+}
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+	SubCommand::with_name("apply")
+		.about("Write out the changes a conversion run with --export-changes saved")
+		.arg(
+			Arg::with_name("changes")
+				.takes_value(true)
+				.value_name("FILE")
+				.help("Changes file written by --export-changes")
+				.required(true),
+		)
+		.arg(
+			Arg::with_name("only")
+				.long("only")
+				.takes_value(true)
+				.value_name("PLAYER")
+				.multiple(true)
+				.number_of_values(1)
+				.help("Only write the outputs for this player, can be given more than once"),
+		)
+}
+
+pub fn run(matches: &ArgMatches, players_args: &[PlayerArgs]) -> BoxResult<()> {
+	let changes = ChangesFile::load(matches.value_of("changes").expect("no changes file"))?;
+	let only: Option<Vec<&str>> = matches.values_of("only").map(|only| only.collect());
+
+	// The provenance record names the player as a static string, like a conversion's does
+	let source_player = players_args
+		.iter()
+		.find(|player_args| player_args.cli_name == changes.source_player)
+		.map_or("changes file", |player_args| player_args.cli_name);
+
+	for target in changes.targets.iter() {
+		if only
+			.as_ref()
+			.map_or(false, |only| !only.contains(&target.player.as_str()))
+		{
+			continue;
+		}
+		println!("{}", tr("saving-to", &[("player", &target.player)]));
+
+		let player_args = players_args
+			.iter()
+			.find(|player_args| player_args.cli_name == target.player && !player_args.read_only)
+			.ok_or_else(|| Error::UnknownPlayer(target.player.clone()))?;
+		let mut player = (player_args.factory)(&target.source)?;
+		let mut podcasts = target
+			.podcasts
+			.iter()
+			.map(JsonPodcast::to_podcast)
+			.collect::<BoxResult<Vec<_>>>()?;
+		if target.source_stamp.is_some() && Stamp::of(&target.source) != target.source_stamp {
+			println!("{}", tr("source-changed", &[("path", &target.source)]));
+			podcasts = rebaseline(player.as_mut(), podcasts)?;
+		}
+		let change_set = ChangeSet::new(podcasts.iter());
+
+		let clock = if changes.fixed_clock {
+			Clock::fixed(changes.timestamp as u64)
+		} else {
+			Clock::system()?
+		};
+		let options = SaveOptions {
+			clock,
+			deterministic: changes.deterministic,
+			provenance: Provenance::new(
+				source_player,
+				clock.unix_secs(),
+				podcasts.len(),
+				podcasts.iter().map(|pod| pod.tracks.len()).sum(),
+			),
+		};
+
+		let stores_provenance = player.stores_provenance();
+		let mut out_file = temp_file_beside(&target.output)?;
+		player.apply_changes(&change_set, out_file.as_file_mut(), &options)?;
+		out_file.as_file().sync_all()?;
+		out_file.persist(&target.output)?;
+
+		if !stores_provenance {
+			options.provenance.write_sidecar(&target.output)?;
+		}
+	}
+
+	Ok(())
+}
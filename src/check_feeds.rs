@@ -0,0 +1,240 @@
+use crate::warning::{self, warn, Warning};
+use crate::BoxResult;
+use crate::{fetch, opml, output};
+
+use std::fmt;
+use std::time::Duration;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use reqwest::{header, Client, RedirectPolicy, StatusCode, Url};
+
+const MAX_REDIRECTS: usize = 10;
+
+const PARKING_HOSTS: [&str; 6] = [
+	"sedoparking.com",
+	"parkingcrew.net",
+	"bodis.com",
+	"dan.com",
+	"afternic.com",
+	"hugedomains.com",
+];
+
+const PARKING_PHRASES: [&str; 5] = [
+	"domain is for sale",
+	"buy this domain",
+	"domain may be for sale",
+	"this domain is parked",
+	"parked free",
+];
+
+#[derive(Debug)]
+pub enum FeedHealth {
+	Alive,
+	Moved(Url),
+	Gone,
+	Parked,
+	Timeout,
+	Failed(String),
+}
+
+impl fmt::Display for FeedHealth {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			FeedHealth::Alive => write!(f, "alive"),
+			FeedHealth::Moved(url) => write!(f, "moved to {}", url),
+			FeedHealth::Gone => write!(f, "gone"),
+			FeedHealth::Parked => write!(f, "parked"),
+			FeedHealth::Timeout => write!(f, "timeout"),
+			FeedHealth::Failed(reason) => write!(f, "failed: {}", reason),
+		}
+	}
+}
+
+impl FeedHealth {
+	fn is_dead(&self) -> bool {
+		match self {
+			FeedHealth::Gone | FeedHealth::Parked => true,
+			_ => false,
+		}
+	}
+}
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+	SubCommand::with_name("check-feeds")
+		.about("Check the health of every feed in an OPML file")
+		.arg(
+			Arg::with_name("opml")
+				.long("opml")
+				.takes_value(true)
+				.value_name("FILE")
+				.multiple(true)
+				.number_of_values(1)
+				.help("OPML file (or directory of OPML files) containing the feeds to check")
+				.required(true),
+		)
+		.arg(
+			Arg::with_name("timeout")
+				.long("timeout")
+				.takes_value(true)
+				.value_name("SECS")
+				.default_value("30")
+				.help("Seconds to wait for each feed before reporting a timeout"),
+		)
+		.arg(
+			Arg::with_name("cleaned-opml")
+				.long("cleaned-opml")
+				.takes_value(true)
+				.value_name("FILE")
+				.help(
+					"Write a copy of the OPML with moved feeds fixed and dead feeds commented out",
+				),
+		)
+}
+
+fn is_parked(url: &Url, body: &str) -> bool {
+	let on_parking_host = url.host_str().map_or(false, |host| {
+		PARKING_HOSTS
+			.iter()
+			.any(|parking| host == *parking || host.ends_with(&format!(".{}", parking)))
+	});
+
+	let body = body.to_lowercase();
+	on_parking_host || PARKING_PHRASES.iter().any(|phrase| body.contains(phrase))
+}
+
+fn is_feed(body: &str) -> bool {
+	roxmltree::Document::parse(body)
+		.map(|doc| {
+			let root = doc.root_element();
+			root.tag_name().name() == "rss" && opml::find_child(root, "channel").is_ok()
+		})
+		.unwrap_or(false)
+}
+
+pub fn check(client: &Client, url: &str) -> FeedHealth {
+	let mut url = match Url::parse(url) {
+		Ok(url) => url,
+		Err(err) => return FeedHealth::Failed(err.to_string()),
+	};
+
+	// Most recent URL reached only through permanent redirects
+	let mut moved_to = None;
+	let mut permanent = true;
+
+	for _ in 0..MAX_REDIRECTS {
+		let mut resp = match client.get(url.clone()).send() {
+			Ok(resp) => resp,
+			Err(ref err) if err.is_timeout() => return FeedHealth::Timeout,
+			Err(err) => return FeedHealth::Failed(err.to_string()),
+		};
+
+		let status = resp.status();
+
+		if status.is_redirection() {
+			let location = match resp
+				.headers()
+				.get(header::LOCATION)
+				.and_then(|loc| loc.to_str().ok())
+				.and_then(|loc| url.join(loc).ok())
+			{
+				Some(location) => location,
+				None => return FeedHealth::Failed(format!("{} without a valid location", status)),
+			};
+
+			permanent &=
+				status == StatusCode::MOVED_PERMANENTLY || status == StatusCode::PERMANENT_REDIRECT;
+			if permanent {
+				moved_to = Some(location.clone());
+			}
+
+			url = location;
+			continue;
+		}
+
+		if status == StatusCode::GONE {
+			return FeedHealth::Gone;
+		}
+
+		let body = match resp.text() {
+			Ok(body) => body,
+			Err(ref err) if err.is_timeout() => return FeedHealth::Timeout,
+			Err(err) => return FeedHealth::Failed(err.to_string()),
+		};
+
+		if is_parked(&url, &body) {
+			return FeedHealth::Parked;
+		}
+
+		if !status.is_success() {
+			return FeedHealth::Failed(status.to_string());
+		}
+
+		if !is_feed(&body) {
+			return FeedHealth::Failed(String::from("response is not an RSS feed"));
+		}
+
+		return match moved_to {
+			Some(new_url) => FeedHealth::Moved(new_url),
+			None => FeedHealth::Alive,
+		};
+	}
+
+	FeedHealth::Failed(String::from("too many redirects"))
+}
+
+pub fn run(matches: &ArgMatches) -> BoxResult<()> {
+	let timeout = matches
+		.value_of("timeout")
+		.expect("no timeout")
+		.parse::<u64>()?;
+
+	let client = fetch::configure(Client::builder())
+		.redirect(RedirectPolicy::none())
+		.timeout(Duration::from_secs(timeout))
+		.build()?;
+
+	let mut categories = opml::read_all(matches.values_of("opml").expect("no opml file"))?;
+
+	let (mut alive, mut moved, mut dead, mut failed) = (0, 0, 0, 0);
+
+	for category in categories.iter_mut() {
+		for feed in category.feeds.iter_mut() {
+			let health = check(&client, feed.url.as_str());
+			if output::plain() {
+				println!("{}: '{}' ({})", health, feed.title, feed.url);
+			} else {
+				println!("[{}] '{}' ({})", health, feed.title, feed.url);
+			}
+
+			match health {
+				FeedHealth::Alive => alive += 1,
+				FeedHealth::Moved(new_url) => {
+					moved += 1;
+					feed.url = new_url.to_string();
+				}
+				ref health if health.is_dead() => {
+					warn(
+						Warning::DeadFeed,
+						format!("'{}' ({}) is {}", feed.title, feed.url, health),
+					);
+					dead += 1;
+					feed.disabled = true;
+				}
+				_ => failed += 1,
+			}
+		}
+	}
+
+	println!(
+		"{} alive, {} moved, {} dead, {} timed out or failed",
+		alive, moved, dead, failed
+	);
+
+	if let Some(path) = matches.value_of("cleaned-opml") {
+		let mut out_file = std::fs::File::create(path)?;
+		opml::write(&mut out_file, &categories)?;
+		out_file.sync_all()?;
+	}
+
+	Ok(warning::check_denied()?)
+}
@@ -0,0 +1,214 @@
+use crate::BoxResult;
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Time of every timestamp written with --deterministic and no --timestamp, 2000-01-01T00:00:00
+/// UTC. Players may read a 0 timestamp as never set, so the epoch itself is not used
+pub const DETERMINISTIC_SECS: u64 = 946_684_800;
+
+/// Source of every timestamp written into a player's save file
+#[derive(Debug, Clone, Copy)]
+pub struct Clock {
+	since_epoch: Duration,
+}
+
+impl Clock {
+	pub fn system() -> BoxResult<Self> {
+		Ok(Self {
+			since_epoch: SystemTime::now().duration_since(UNIX_EPOCH)?,
+		})
+	}
+
+	pub fn fixed(unix_secs: u64) -> Self {
+		Self {
+			since_epoch: Duration::from_secs(unix_secs),
+		}
+	}
+
+	pub fn unix_secs(&self) -> i64 {
+		self.since_epoch.as_secs() as i64
+	}
+
+	pub fn unix_millis(&self) -> i64 {
+		self.since_epoch.as_millis() as i64
+	}
+
+	/// UTC time as `YYYY-MM-DDTHH:MM:SS`
+	pub fn iso8601(&self) -> String {
+		let secs = self.unix_secs();
+		let (days, secs_of_day) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+		let (year, month, day) = civil_from_days(days);
+
+		format!(
+			"{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+			year,
+			month,
+			day,
+			secs_of_day / 3600,
+			secs_of_day / 60 % 60,
+			secs_of_day % 60
+		)
+	}
+
+	/// UTC time as a mail `Date:` header has it, `Sat, 01 Jan 2000 00:00:00 +0000`
+	pub fn rfc2822(&self) -> String {
+		const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+		const MONTHS: [&str; 12] = [
+			"Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+		];
+		let secs = self.unix_secs();
+		let (days, secs_of_day) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+		let (year, month, day) = civil_from_days(days);
+
+		// The epoch was a Thursday
+		format!(
+			"{}, {:02} {} {:04} {:02}:{:02}:{:02} +0000",
+			WEEKDAYS[(days + 4).rem_euclid(7) as usize],
+			day,
+			MONTHS[month as usize - 1],
+			year,
+			secs_of_day / 3600,
+			secs_of_day / 60 % 60,
+			secs_of_day % 60
+		)
+	}
+}
+
+/// Civil date from days since the epoch, see http://howardhinnant.github.io/date_algorithms.html
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+	let z = days + 719468;
+	let era = z.div_euclid(146097);
+	let doe = z - era * 146097;
+	let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp = (5 * doy + 2) / 153;
+	let day = doy - (153 * mp + 2) / 5 + 1;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 };
+	let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+	(year, month, day)
+}
+
+/// Unix seconds of a `YYYY-MM-DDTHH:MM:SS` time. Fractions of a second are ignored, a `Z` or
+/// no zone is taken as UTC and a `+HH:MM` or `-HHMM` offset is applied
+pub fn parse_iso8601(text: &str) -> Option<i64> {
+	let (text, zone) = (text.get(..19)?, &text[19..]);
+	let field = |range: std::ops::Range<usize>| text.get(range)?.parse::<i64>().ok();
+	let (year, month, day) = (field(0..4)?, field(5..7)?, field(8..10)?);
+	let (hour, minute, second) = (field(11..13)?, field(14..16)?, field(17..19)?);
+	if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+		return None;
+	}
+
+	let local = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second;
+	Some(local - zone_offset(zone))
+}
+
+/// Seconds east of UTC of the zone after the seconds of an ISO 8601 time, after any fraction
+fn zone_offset(zone: &str) -> i64 {
+	let zone = zone.trim_start_matches(|c: char| c == '.' || c.is_ascii_digit());
+	let sign = match zone.chars().next() {
+		Some('+') => 1,
+		Some('-') => -1,
+		_ => return 0,
+	};
+	let digits: String = zone[1..].chars().filter(|c| c.is_ascii_digit()).collect();
+	let hours = digits.get(..2).and_then(|hours| hours.parse::<i64>().ok());
+	let minutes = digits
+		.get(2..4)
+		.and_then(|minutes| minutes.parse::<i64>().ok());
+	sign * (hours.unwrap_or(0) * 3600 + minutes.unwrap_or(0) * 60)
+}
+
+/// Days since the epoch from the civil date, the inverse of Clock::iso8601
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+	let year = if month <= 2 { year - 1 } else { year };
+	let era = year.div_euclid(400);
+	let yoe = year - era * 400;
+	let mp = if month > 2 { month - 3 } else { month + 9 };
+	let doy = (153 * mp + 2) / 5 + day - 1;
+	let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+	era * 146097 + doe - 719468
+}
+
+const MONTHS: [&str; 12] = [
+	"jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// Unix seconds of an RSS `pubDate` such as `Tue, 10 Jun 2003 04:00:00 GMT`. The day name is
+/// optional, as are the seconds, and unknown zone names are taken as UTC
+pub fn parse_rfc2822(text: &str) -> Option<i64> {
+	let text = text.rsplit(',').next()?;
+	let mut fields = text.split_whitespace();
+	let day: i64 = fields.next()?.parse().ok()?;
+	let month = fields.next()?.get(..3)?.to_lowercase();
+	let month = MONTHS.iter().position(|name| *name == month)? as i64 + 1;
+	let year: i64 = match fields.next()?.parse().ok()? {
+		year if year < 50 => year + 2000,
+		year if year < 100 => year + 1900,
+		year => year,
+	};
+	if !(1..=31).contains(&day) {
+		return None;
+	}
+
+	let mut time = fields
+		.next()?
+		.split(':')
+		.map(|part| part.parse::<i64>().ok());
+	let (hour, minute) = (time.next()??, time.next()??);
+	let second = time.next().unwrap_or(Some(0))?;
+
+	let zone = fields.next().unwrap_or("GMT");
+	let offset = match zone.as_bytes().first() {
+		Some(b'+') | Some(b'-') if zone.len() == 5 => {
+			let value: i64 = zone[1..].parse().ok()?;
+			let secs = value / 100 * 3600 + value % 100 * 60;
+			if zone.starts_with('-') {
+				-secs
+			} else {
+				secs
+			}
+		}
+		_ => {
+			let hours = match zone.to_uppercase().as_str() {
+				"EDT" => -4,
+				"EST" | "CDT" => -5,
+				"CST" | "MDT" => -6,
+				"MST" | "PDT" => -7,
+				"PST" => -8,
+				_ => 0,
+			};
+			hours * 3600
+		}
+	};
+
+	Some(days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second - offset)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_zones_and_fractions() {
+		let utc = parse_iso8601("2020-05-01T12:00:00").unwrap();
+		assert_eq!(parse_iso8601("2020-05-01T12:00:00Z"), Some(utc));
+		assert_eq!(parse_iso8601("2020-05-01T12:00:00.250Z"), Some(utc));
+		assert_eq!(parse_iso8601("2020-05-01T14:00:00+02:00"), Some(utc));
+		assert_eq!(parse_iso8601("2020-05-01T07:30:00-0430"), Some(utc));
+		assert_eq!(Clock::fixed(utc as u64).iso8601(), "2020-05-01T12:00:00");
+	}
+
+	#[test]
+	fn formats_mail_dates() {
+		assert_eq!(
+			Clock::fixed(DETERMINISTIC_SECS).rfc2822(),
+			"Sat, 01 Jan 2000 00:00:00 +0000"
+		);
+		let utc = parse_iso8601("2020-05-01T12:34:56Z").unwrap();
+		assert_eq!(
+			Clock::fixed(utc as u64).rfc2822(),
+			"Fri, 01 May 2020 12:34:56 +0000"
+		);
+	}
+}
@@ -0,0 +1,261 @@
+use crate::{BoxResult, Error};
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::iter::Peekable;
+use std::path::PathBuf;
+use std::str::Chars;
+
+// Profiles are kept in a config file in a small subset of TOML, one
+// `[profile.NAME]` table per profile with a `key = value` line for each
+// argument it gives, and `#` comments. A key is the argument's long name
+// without the dashes. `true` gives a flag and `false` leaves it out, a string
+// or number gives the value, and an array, which may span lines, the argument
+// once per value. `--profile NAME` puts the profile's arguments in front of the
+// command line's, which win where both give the same argument.
+//
+//     [profile.phone-to-tablet]
+//     in-beyondpod = true
+//     beyondpod = "/backups/BeyondPod_Backup.bpbak"
+//     out-pocketcasts = true
+//     pocketcasts = "/backups/pocketcasts"
+//     opml = ["/backups/podcasts.opml", "/backups/extra.opml"]
+
+/// `podcast_history_converter/config.toml` in the user's config directory, used without --config
+fn default_path() -> Option<PathBuf> {
+	let dir = env::var_os("XDG_CONFIG_HOME")
+		.filter(|dir| !dir.is_empty())
+		.map(PathBuf::from)
+		.or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+		.or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+	Some(dir.join("podcast_history_converter").join("config.toml"))
+}
+
+/// Value of `--name VALUE` or `--name=VALUE` on the command line
+fn arg_value(args: &[OsString], name: &str) -> Option<String> {
+	let flag = format!("--{}", name);
+	let prefix = format!("--{}=", name);
+	args.iter().enumerate().find_map(|(i, arg)| {
+		let arg = arg.to_str()?;
+		if arg == flag {
+			args.get(i + 1)?.to_str().map(String::from)
+		} else {
+			arg.strip_prefix(prefix.as_str()).map(String::from)
+		}
+	})
+}
+
+/// The rest of a `"` string, with its escapes replaced
+fn basic_string(chars: &mut Peekable<Chars>) -> String {
+	let mut string = String::new();
+	while let Some(c) = chars.next() {
+		match c {
+			'"' => break,
+			'\\' => match chars.next() {
+				Some('n') => string.push('\n'),
+				Some('t') => string.push('\t'),
+				Some('r') => string.push('\r'),
+				Some('u') => {
+					let hex: String = chars.by_ref().take(4).collect();
+					if let Some(c) = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+						string.push(c);
+					}
+				}
+				Some(c) if c == '"' || c == '\\' => string.push(c),
+				// Kept as written, such as the backslashes of a Windows path
+				Some(c) => {
+					string.push('\\');
+					string.push(c);
+				}
+				None => (),
+			},
+			c => string.push(c),
+		}
+	}
+	string
+}
+
+/// A value without its quotes, or the values of an array, which may be nested. Commas, brackets
+/// and `#` in quoted strings are part of them. None while an array is not closed yet, as it goes
+/// on on the next line
+fn parse_value(value: &str) -> Option<Vec<String>> {
+	let mut values = Vec::new();
+	let mut bare = String::new();
+	let mut depth = 0;
+	let mut chars = value.trim().chars().peekable();
+
+	let end_bare = |bare: &mut String, values: &mut Vec<String>| {
+		if !bare.trim().is_empty() {
+			values.push(bare.trim().to_string());
+		}
+		bare.clear();
+	};
+	while let Some(c) = chars.next() {
+		match c {
+			'"' => values.push(basic_string(&mut chars)),
+			'\'' => values.push(chars.by_ref().take_while(|c| *c != '\'').collect()),
+			'[' => depth += 1,
+			']' => {
+				end_bare(&mut bare, &mut values);
+				depth -= 1;
+			}
+			',' if depth > 0 => end_bare(&mut bare, &mut values),
+			// A comment runs to the end of its line
+			'#' => {
+				while chars.peek().map_or(false, |c| *c != '\n') {
+					chars.next();
+				}
+			}
+			c => bare.push(c),
+		}
+	}
+	end_bare(&mut bare, &mut values);
+
+	if depth > 0 {
+		None
+	} else {
+		Some(values)
+	}
+}
+
+/// The arguments a `key = value` line gives, `true` a flag and `false` none
+fn push_values(args: &mut Vec<OsString>, key: &str, values: Vec<String>) {
+	match values.as_slice() {
+		[value] if value == "true" => args.push(format!("--{}", key).into()),
+		[value] if value == "false" => (),
+		_ => {
+			for value in values {
+				args.push(format!("--{}", key).into());
+				args.push(value.into());
+			}
+		}
+	}
+}
+
+/// Arguments the named profile gives, in the order of the file
+fn profile_args(text: &str, name: &str) -> Option<Vec<OsString>> {
+	let heading = format!("[profile.{}]", name);
+	let mut args = Vec::new();
+	let mut found = false;
+	let mut in_profile = false;
+	// An array that goes on over the next lines
+	let mut open: Option<(String, String)> = None;
+
+	for line in text.lines().map(str::trim) {
+		if let Some((key, value)) = open.take() {
+			let value = value + "\n" + line;
+			match parse_value(&value) {
+				Some(values) => push_values(&mut args, &key, values),
+				None => open = Some((key, value)),
+			}
+			continue;
+		}
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+		if line.starts_with('[') {
+			in_profile = line == heading || line == format!("[profile.\"{}\"]", name);
+			found |= in_profile;
+			continue;
+		}
+		if !in_profile {
+			continue;
+		}
+
+		let mut parts = line.splitn(2, '=');
+		let (key, value) = match (parts.next(), parts.next()) {
+			(Some(key), Some(value)) => (key.trim().trim_matches('"'), value.trim()),
+			_ => continue,
+		};
+		match parse_value(value) {
+			Some(values) => push_values(&mut args, key, values),
+			None => open = Some((key.to_string(), value.to_string())),
+		}
+	}
+
+	if found {
+		Some(args)
+	} else {
+		None
+	}
+}
+
+/// The command line with the arguments of the --profile it names put in, those it gives
+/// itself are left out of the profile's
+pub fn expand_profile(args: Vec<OsString>) -> BoxResult<Vec<OsString>> {
+	let name = match arg_value(&args, "profile") {
+		Some(name) => name,
+		None => return Ok(args),
+	};
+	let path = match arg_value(&args, "config") {
+		Some(path) => PathBuf::from(path),
+		None => default_path().ok_or_else(|| Error::UnknownProfile(name.clone()))?,
+	};
+	let text = fs::read_to_string(&path)?;
+	let profile = profile_args(&text, &name).ok_or_else(|| Error::UnknownProfile(name))?;
+
+	// Each profile argument is a flag, maybe followed by its value
+	let given = |flag: &OsString| {
+		let flag = flag.to_string_lossy();
+		args.iter().any(|arg| {
+			let arg = arg.to_string_lossy();
+			arg == flag || arg.starts_with(&format!("{}=", flag))
+		})
+	};
+	let mut expanded = vec![args[0].clone()];
+	let mut skipping = false;
+	for arg in profile.into_iter() {
+		if arg.to_string_lossy().starts_with("--") {
+			skipping = given(&arg);
+		}
+		if !skipping {
+			expanded.push(arg);
+		}
+	}
+	expanded.extend(args.into_iter().skip(1));
+	Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn reads_quoted_values_and_arrays() {
+		let text = r#"
+[profile.other]
+json = "other.json"
+
+[profile.backup]
+in-beyondpod = true
+out-json = false
+beyondpod = "C:\Backups\BeyondPod, old.bpbak" # the last one
+json = 'history.json'
+opml = [
+	"podcasts, part 1.opml", # main list
+	'extra [2].opml',
+]
+"#;
+		let args: Vec<String> = profile_args(text, "backup")
+			.unwrap()
+			.into_iter()
+			.map(|arg| arg.into_string().unwrap())
+			.collect();
+		assert_eq!(
+			args,
+			vec![
+				"--in-beyondpod",
+				"--beyondpod",
+				"C:\\Backups\\BeyondPod, old.bpbak",
+				"--json",
+				"history.json",
+				"--opml",
+				"podcasts, part 1.opml",
+				"--opml",
+				"extra [2].opml",
+			]
+		);
+		assert!(profile_args(text, "missing").is_none());
+	}
+}
@@ -0,0 +1,83 @@
+use std::io;
+
+/// Split CSV text into rows of fields, following RFC 4180 quoting. Blank lines are skipped
+pub fn read(text: &str) -> Vec<Vec<String>> {
+	read_numbered(text)
+		.into_iter()
+		.map(|(_, row)| row)
+		.collect()
+}
+
+/// Rows of fields as `read` splits them, each with the line it starts on, counting from 1. A
+/// quoted field can span lines, so this is not the row's index
+pub fn read_numbered(text: &str) -> Vec<(usize, Vec<String>)> {
+	let mut rows = Vec::new();
+	let mut row = Vec::new();
+	let mut field = String::new();
+	let mut quoted = false;
+	let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+	let mut line = 1;
+	let mut row_line = 1;
+
+	while let Some(c) = chars.next() {
+		if c == '\n' {
+			line += 1;
+		}
+		match (c, quoted) {
+			('"', true) if chars.peek() == Some(&'"') => {
+				field.push('"');
+				chars.next();
+			}
+			('"', true) => quoted = false,
+			('"', false) if field.is_empty() => quoted = true,
+			(',', false) => row.push(std::mem::replace(&mut field, String::new())),
+			('\r', false) => (),
+			('\n', false) => {
+				row.push(std::mem::replace(&mut field, String::new()));
+				if row.len() > 1 || !row[0].is_empty() {
+					rows.push((row_line, std::mem::replace(&mut row, Vec::new())));
+				} else {
+					row.clear();
+				}
+				row_line = line;
+			}
+			(c, _) => field.push(c),
+		}
+	}
+
+	if !field.is_empty() || !row.is_empty() {
+		row.push(field);
+		rows.push((row_line, row));
+	}
+	rows
+}
+
+/// Write one row of fields, quoting those that need it, ended with CRLF as RFC 4180 has it
+pub fn write_row<W: io::Write + ?Sized, S: AsRef<str>>(w: &mut W, fields: &[S]) -> io::Result<()> {
+	let fields: Vec<String> = fields
+		.iter()
+		.map(|field| {
+			let field = field.as_ref();
+			if field.contains(|c| c == ',' || c == '"' || c == '\r' || c == '\n') {
+				format!("\"{}\"", field.replace('"', "\"\""))
+			} else {
+				field.to_string()
+			}
+		})
+		.collect();
+	write!(w, "{}\r\n", fields.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn numbers_rows_by_the_line_they_start_on() {
+		let text = "a,b\r\n\r\n\"one\ntwo\",c\r\nd,e\r\n";
+		let rows = read_numbered(text);
+		let lines: Vec<usize> = rows.iter().map(|(line, _)| *line).collect();
+		assert_eq!(lines, vec![1, 3, 5]);
+		assert_eq!(rows[1].1, vec!["one\ntwo", "c"]);
+	}
+}
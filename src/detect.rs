@@ -0,0 +1,228 @@
+use crate::player::{
+	quote_identifier, AntennaPodActions, CPod, Escapepod, GooglePodcasts, GpodderActions, JsonFile,
+	NewPlayer, PlayerFM, Podcini, Podverse, Spotify,
+};
+use crate::{BoxResult, Error, PlayerArgs};
+
+use std::fs::File;
+use std::io::Read;
+
+use rusqlite::{Connection, OpenFlags};
+use serde_json::Value;
+
+const SQLITE_MAGIC: &[u8] = b"SQLite format 3\0";
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// Bytes read from the start of a file to tell what it is, enough for any signature, the
+/// root element of an XML file and the header of a CSV file
+const PEEK_LEN: u64 = 4096;
+/// Larger JSON files are not parsed to find out what they are, no export comes near it
+const MAX_JSON_LEN: u64 = 64 * 1024 * 1024;
+
+/// Files inside a zip backup that give away the app that wrote it
+const ZIP_ENTRIES: [(&str, &str); 8] = [
+	("beyondpod.db", "beyondpod"),
+	("beyondpod.db.autobak", "beyondpod"),
+	("castbox.db", "castbox"),
+	("doggcatcher.db", "doggcatcher"),
+	("podcastrepublic.db", "podcastrepublic"),
+	("pocketcasts", "pocketcasts"),
+	("pocketcasts.db", "pocketcasts"),
+	("Podcasts/MyActivity.json", "googlepodcasts"),
+];
+
+/// Tables (or `table.column`s) that together identify a player's SQLite database, the first
+/// match wins
+const SQLITE_TABLES: [(&[&str], &str); 11] = [
+	(
+		&["schema_version", "podcasts", "tracks", "runs"],
+		"history-db",
+	),
+	(&["episodes", "podcasts"], "pocketcasts"),
+	(&["feeditems", "feedmedia", "feeds"], "antennapod"),
+	(&["episodes", "shows", "source"], "gnomepodcasts"),
+	(&["feeds", "entries", "enclosures"], "kasts"),
+	(&["rss_feed", "rss_item"], "newsboat"),
+	(
+		&["zepisode.zstate", "zepisode.zplayhead", "zpodcast"],
+		"castro",
+	),
+	(&["zepisode", "zpodcast"], "downcast"),
+	(&["files", "path", "bookmark"], "kodi"),
+	(&["song", "path"], "kodi"),
+	(&["sticker"], "mpd"),
+];
+
+fn sniff_zip(path: &str) -> BoxResult<Option<&'static str>> {
+	let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+	for i in 0..archive.len() {
+		let name = archive.by_index(i)?.name().to_string();
+		if let Some((_, player)) = ZIP_ENTRIES.iter().find(|(entry, _)| name.ends_with(entry)) {
+			return Ok(Some(*player));
+		}
+	}
+	Ok(None)
+}
+
+fn sniff_sqlite(path: &str) -> BoxResult<Option<&'static str>> {
+	let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+	let mut stmt = conn.prepare("SELECT name FROM sqlite_master WHERE type = 'table'")?;
+	let mut rows = stmt.query(rusqlite::NO_PARAMS)?;
+	let mut tables = Vec::new();
+	while let Some(row) = rows.next()? {
+		let name: String = row.get(0)?;
+		tables.push(name.to_lowercase());
+	}
+
+	let mut columns = Vec::new();
+	for table in tables.iter() {
+		let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", quote_identifier(table)))?;
+		let mut rows = stmt.query(rusqlite::NO_PARAMS)?;
+		while let Some(row) = rows.next()? {
+			let column: String = row.get(1)?;
+			columns.push(format!("{}.{}", table, column.to_lowercase()));
+		}
+	}
+	tables.extend(columns);
+
+	Ok(SQLITE_TABLES
+		.iter()
+		.find(|(wanted, _)| wanted.iter().all(|table| tables.iter().any(|t| t == table)))
+		.map(|(_, player)| *player))
+}
+
+/// Recognise the JSON exports by the keys of their top level object or first list item
+fn sniff_json(json: &Value) -> Option<&'static str> {
+	let has = |value: &Value, key: &str| value.get(key).is_some();
+	match json {
+		Value::Object(_) if has(json, "plays") => Some(PlayerFM::cli_name()),
+		// The response of the gpodder episodes endpoint, AntennaPod only writes bare lists
+		Value::Object(_) if has(json, "actions") => Some(GpodderActions::cli_name()),
+		Value::Object(_) if has(json, "subscriptions") || has(json, "completed") => {
+			Some(CPod::cli_name())
+		}
+		Value::Object(_) if has(json, "version") && has(json, "podcasts") => {
+			Some(JsonFile::cli_name())
+		}
+		Value::Object(_) if has(json, "podcasts") => Some(Escapepod::cli_name()),
+		Value::Object(_) if has(json, "historyItems") || has(json, "queueItems") => {
+			Some(Podverse::cli_name())
+		}
+		Value::Array(items) => {
+			let first = items.first()?;
+			if has(first, "action") && has(first, "episode") {
+				Some(AntennaPodActions::cli_name())
+			} else if has(first, "playState") && has(first, "downloadUrl") {
+				Some(Podcini::cli_name())
+			} else if has(first, "msPlayed") || has(first, "ms_played") {
+				Some(Spotify::cli_name())
+			} else if has(first, "title") && has(first, "subtitles") {
+				Some(GooglePodcasts::cli_name())
+			} else {
+				None
+			}
+		}
+		_ => None,
+	}
+}
+
+/// The tool's own CSV layout, by the feed_url column of its header
+fn sniff_csv(text: &str) -> Option<&'static str> {
+	let header = text.trim_start_matches('\u{feff}').lines().next()?;
+	if header.split(',').any(|name| name.trim() == "feed_url") {
+		Some("csv")
+	} else {
+		None
+	}
+}
+
+/// By the root element, the first one after the XML declaration, doctype and comments
+fn sniff_xml(head: &str) -> Option<&'static str> {
+	let mut rest = head;
+	let root = loop {
+		rest = &rest[rest.find('<')? + 1..];
+		if rest.starts_with(char::is_alphabetic) {
+			let end = rest
+				.find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+				.unwrap_or(rest.len());
+			break &rest[..end];
+		}
+	};
+	match root {
+		"plist" => Some("itunes"),
+		_ => None,
+	}
+}
+
+/// Command line name of the player that wrote the file, from its signature and contents. Only
+/// the start of the file is read unless it looks like JSON, so scanning a directory of media
+/// and other binaries stays quick, and a file that is not text is simply not recognised
+pub fn sniff(path: &str) -> BoxResult<Option<&'static str>> {
+	let file = File::open(path)?;
+	let len = file.metadata()?.len();
+	let mut head = Vec::new();
+	file.take(PEEK_LEN).read_to_end(&mut head)?;
+
+	if head.starts_with(SQLITE_MAGIC) {
+		return sniff_sqlite(path);
+	} else if head.starts_with(ZIP_MAGIC) {
+		return sniff_zip(path);
+	}
+
+	let text = String::from_utf8_lossy(&head);
+	let text = text.trim_start_matches('\u{feff}').trim_start();
+	Ok(match text.chars().next() {
+		Some('{') | Some('[') if len <= MAX_JSON_LEN => {
+			let bytes = std::fs::read(path)?;
+			serde_json::from_slice::<Value>(&bytes)
+				.ok()
+				.and_then(|json| sniff_json(&json))
+		}
+		Some('<') => sniff_xml(text),
+		Some(_) => sniff_csv(text),
+		None => None,
+	})
+}
+
+/// Work out which player wrote the save file, failing when none did
+pub fn detect<'a>(path: &str, players_args: &'a [PlayerArgs]) -> BoxResult<&'a PlayerArgs> {
+	let player_args = sniff(path)?
+		.and_then(|cli_name| {
+			players_args
+				.iter()
+				.find(|player_args| player_args.cli_name == cli_name)
+		})
+		.ok_or_else(|| Error::UnknownSaveFile(path.into()))?;
+	println!("'{}' looks like a {} save file", path, player_args.name);
+	Ok(player_args)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn detected_names_are_players() {
+		let players_args = crate::all_players_args();
+		let names: Vec<&str> = players_args
+			.iter()
+			.map(|player_args| player_args.cli_name)
+			.collect();
+
+		let detected = ZIP_ENTRIES
+			.iter()
+			.map(|(_, player)| player)
+			.chain(SQLITE_TABLES.iter().map(|(_, player)| player));
+		for player in detected {
+			assert!(names.contains(player), "{}", player);
+		}
+	}
+
+	#[test]
+	fn sniffs_episode_actions() {
+		let json = serde_json::json!([{ "podcast": "", "episode": "", "action": "play" }]);
+		assert_eq!(sniff_json(&json), Some("antennapod-actions"));
+		let json = serde_json::json!({ "actions": [], "timestamp": 0 });
+		assert_eq!(sniff_json(&json), Some("gpodder-actions"));
+	}
+}
@@ -0,0 +1,627 @@
+use crate::json_feed;
+use crate::opml::find_child;
+use crate::storage::{self, Storage};
+use crate::url_key::url_key;
+use crate::{BoxResult, Error};
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use reqwest::header::{
+	HeaderMap, HeaderName, HeaderValue, ACCEPT, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+	LAST_MODIFIED, RETRY_AFTER, USER_AGENT,
+};
+use reqwest::{
+	Client, ClientBuilder, Proxy, RedirectPolicy, RequestBuilder, Response, StatusCode, Url,
+};
+use serde::{Deserialize, Serialize};
+
+// Feeds are fetched with a client that gives up on a request after --timeout
+// seconds. Requests that fail in a way that may pass (the connection failed or
+// timed out, the server was busy or had an error) are sent again up to
+// --retries times, waiting twice as long before each. They go through the
+// --proxy if one is given, `socks5://` included for Tor, and otherwise through
+// the proxy in the HTTP_PROXY / HTTPS_PROXY environment variables.
+//
+// Requests name the tool in their User-Agent, or send the --user-agent given,
+// and ask for RSS or Atom. Hosts that refuse the tool's name with a 403 are
+// asked again as a browser. Requests to one host are spaced --host-interval
+// milliseconds apart however many --jobs there are, and a Retry-After from a
+// busy host holds back every request to it for that long.
+//
+// A feed reached only through permanent redirects (301 or 308) has moved, and
+// the URL it moved to is returned with it.
+//
+// Private feeds are fetched with the user and password in their URL, or those
+// --feed-auth gives for the host, sent as basic auth rather than in the URL.
+// Feed URLs are printed with the password and any query parameter that looks
+// like a token hidden, so a log can be shared.
+//
+// With --cache-dir every feed body is kept in the cache storage with the ETag
+// and Last-Modified the server sent, and the next run asks for the feed only if
+// it changed since. A feed that has not changed is read from the cache, so
+// re-running a conversion to tweak its outputs does not download everything
+// again. The cache is only a help: when it can not be read or written the feed
+// is fetched as usual.
+//
+// With --feeds-dir nothing is fetched at all, every feed is read from a file in
+// the directory instead, for machines without a network and for going over a
+// matching problem with the same feeds again. A file is found by its
+// `atom:link rel="self"` URL, by the feed title, or by the name the cache gives
+// it, so a cache directory works as a feeds directory too. `file://` URLs in
+// an OPML file are always read from disk.
+
+/// Set by --timeout, seconds a request may take, 0 for no limit
+static TIMEOUT: AtomicU64 = AtomicU64::new(30);
+/// Set by --retries, times a failed request is sent again
+static RETRIES: AtomicUsize = AtomicUsize::new(2);
+/// Pause before the first retry, doubled before each of the others
+const FIRST_BACKOFF: Duration = Duration::from_secs(1);
+/// Redirects followed before a feed is given up on
+const MAX_REDIRECTS: usize = 10;
+/// Longest Retry-After waited for, a host asking for longer is given up on
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+/// Set by --host-interval, milliseconds between the starts of two requests to one host
+static HOST_INTERVAL: AtomicU64 = AtomicU64::new(500);
+
+const DEFAULT_USER_AGENT: &str = concat!(
+	env!("CARGO_PKG_NAME"),
+	"/",
+	env!("CARGO_PKG_VERSION"),
+	" (+https://github.com/JamesStewy/podcast_history_converter)"
+);
+/// Sent after a 403 to the tool's own User-Agent, for hosts that only serve browsers
+const BROWSER_USER_AGENT: &str = "Mozilla/5.0 (compatible; podcast_history_converter)";
+const FEED_ACCEPT: &str = "application/rss+xml, application/atom+xml, application/feed+json, application/xml;q=0.9, text/xml;q=0.9, */*;q=0.8";
+
+/// Query parameters hidden in printed URLs, matched anywhere in the lowercase name
+const SECRET_PARAMS: [&str; 6] = ["token", "key", "auth", "secret", "pass", "sig"];
+
+/// User name and password for basic auth
+type Credentials = (String, Option<String>);
+
+pub fn set_timeout(secs: u64) {
+	TIMEOUT.store(secs, Ordering::Relaxed);
+}
+
+pub fn set_retries(retries: usize) {
+	RETRIES.store(retries, Ordering::Relaxed);
+}
+
+pub fn set_host_interval(millis: u64) {
+	HOST_INTERVAL.store(millis, Ordering::Relaxed);
+}
+
+lazy_static! {
+	/// Set by --feed-auth, by lowercase host
+	static ref FEED_AUTH: RwLock<HashMap<String, Credentials>> = RwLock::new(HashMap::new());
+	/// Set by --user-agent
+	static ref CUSTOM_USER_AGENT: RwLock<Option<HeaderValue>> = RwLock::new(None);
+	/// When the next request to each lowercase host may start
+	static ref HOST_NEXT_REQUEST: Mutex<HashMap<String, Instant>> = Mutex::new(HashMap::new());
+	/// Set by --proxy
+	static ref PROXY: RwLock<Option<Proxy>> = RwLock::new(None);
+	/// Set by --cache-dir
+	static ref CACHE: RwLock<Option<Arc<dyn Storage>>> = RwLock::new(None);
+	/// Set by --feeds-dir, with the feed files in it by the url_key of their self link and
+	/// by their lowercase title
+	static ref FEEDS_DIR: RwLock<Option<(PathBuf, HashMap<String, PathBuf>)>> = RwLock::new(None);
+}
+
+/// Add a --feed-auth HOST=USER:PASSWORD
+pub fn add_feed_auth(value: &str) -> BoxResult<()> {
+	let mut parts = value.splitn(2, '=');
+	let (host, credentials) = match (parts.next(), parts.next()) {
+		(Some(host), Some(credentials)) if !host.is_empty() && !credentials.is_empty() => {
+			(host, credentials)
+		}
+		_ => return Err(Error::InvalidFeedAuth.into()),
+	};
+
+	let mut credentials = credentials.splitn(2, ':');
+	let user = credentials.next().unwrap_or("").to_string();
+	let password = credentials.next().map(String::from);
+	FEED_AUTH
+		.write()
+		.expect("feed auth lock poisoned")
+		.insert(host.to_lowercase(), (user, password));
+	Ok(())
+}
+
+/// Decode the %XX escapes of a URL's user name or password
+fn percent_decode(text: &str) -> String {
+	let bytes = text.as_bytes();
+	let mut decoded = Vec::with_capacity(bytes.len());
+	let mut i = 0;
+	while i < bytes.len() {
+		let escaped = if bytes[i] == b'%' && i + 2 < bytes.len() {
+			std::str::from_utf8(&bytes[i + 1..i + 3])
+				.ok()
+				.and_then(|hex| u8::from_str_radix(hex, 16).ok())
+		} else {
+			None
+		};
+		match escaped {
+			Some(byte) => {
+				decoded.push(byte);
+				i += 3;
+			}
+			None => {
+				decoded.push(bytes[i]);
+				i += 1;
+			}
+		}
+	}
+	String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The URL without the user and password in it, and those or the --feed-auth ones for its host
+pub fn split_credentials(url: &Url) -> (Url, Option<Credentials>) {
+	let mut bare = url.clone();
+	bare.set_username("").ok();
+	bare.set_password(None).ok();
+
+	let credentials = if !url.username().is_empty() {
+		Some((
+			percent_decode(url.username()),
+			url.password().map(percent_decode),
+		))
+	} else {
+		url.host_str().and_then(|host| {
+			FEED_AUTH
+				.read()
+				.expect("feed auth lock poisoned")
+				.get(&host.to_lowercase())
+				.cloned()
+		})
+	};
+	(bare, credentials)
+}
+
+/// The URL to print, with its password and any query parameter that may be a token hidden
+pub fn redact(url: &Url) -> String {
+	let mut redacted = url.clone();
+	if url.password().is_some() {
+		redacted.set_password(Some("***")).ok();
+	}
+	if url.query().is_some() {
+		let pairs: Vec<(String, String)> = url
+			.query_pairs()
+			.map(|(name, value)| {
+				let lower = name.to_lowercase();
+				if SECRET_PARAMS.iter().any(|secret| lower.contains(secret)) {
+					(name.into_owned(), String::from("***"))
+				} else {
+					(name.into_owned(), value.into_owned())
+				}
+			})
+			.collect();
+		redacted.query_pairs_mut().clear().extend_pairs(pairs);
+	}
+	redacted.to_string()
+}
+
+/// `text` with `url` hidden wherever it appears, with or without its credentials, for error
+/// messages that quote the URL they were about
+pub fn redact_in(text: &str, url: &Url) -> String {
+	let (bare, _) = split_credentials(url);
+	text.replace(url.as_str(), &redact(url))
+		.replace(bare.as_str(), &redact(&bare))
+}
+
+pub fn set_user_agent(user_agent: &str) -> BoxResult<()> {
+	*CUSTOM_USER_AGENT.write().expect("user agent lock poisoned") =
+		Some(HeaderValue::from_str(user_agent)?);
+	Ok(())
+}
+
+pub fn set_proxy(url: &str) -> BoxResult<()> {
+	*PROXY.write().expect("proxy lock poisoned") = Some(Proxy::all(url)?);
+	Ok(())
+}
+
+/// Cache feeds in the storage at `location`, a directory or `:memory:`
+pub fn set_cache_dir(location: &str) -> BoxResult<()> {
+	*CACHE.write().expect("cache lock poisoned") = Some(storage::open(location)?);
+	Ok(())
+}
+
+pub fn set_feeds_dir(dir: &str) -> BoxResult<()> {
+	let mut index = HashMap::new();
+	for entry in fs::read_dir(dir)? {
+		let path = entry?.path();
+		if !path.is_file()
+			|| !path
+				.extension()
+				.map_or(false, |ext| ext == "xml" || ext == "rss" || ext == "json")
+		{
+			continue;
+		}
+
+		// Files that are not feeds can still be found by their cache name
+		let text = fs::read_to_string(&path)?;
+		if json_feed::is_json_feed(&text) {
+			if let Some((self_url, title)) = json_feed::self_url_and_title(&text) {
+				if let Some(self_url) = self_url {
+					index.insert(url_key(&self_url), path.clone());
+				}
+				if let Some(title) = title {
+					index
+						.entry(title.trim().to_lowercase())
+						.or_insert_with(|| path.clone());
+				}
+			}
+			continue;
+		}
+		let doc = match roxmltree::Document::parse(&text) {
+			Ok(doc) => doc,
+			Err(_) => continue,
+		};
+		let channel = match find_child(doc.root_element(), "channel") {
+			Ok(channel) => channel,
+			Err(_) => continue,
+		};
+		for child in channel.children().filter(|n| n.is_element()) {
+			match child.tag_name().name() {
+				"link" if child.attribute("rel") == Some("self") => {
+					if let Some(href) = child.attribute("href") {
+						index.insert(url_key(href), path.clone());
+					}
+				}
+				"title" => {
+					if let Some(title) = child.text() {
+						index
+							.entry(title.trim().to_lowercase())
+							.or_insert_with(|| path.clone());
+					}
+				}
+				_ => (),
+			}
+		}
+	}
+
+	*FEEDS_DIR.write().expect("feeds dir lock poisoned") = Some((PathBuf::from(dir), index));
+	Ok(())
+}
+
+/// Body of a feed read from disk, for `file://` URLs and for every feed with --feeds-dir.
+/// None when the feed is to be fetched
+pub fn local_feed(url: &Url, title: &str) -> BoxResult<Option<String>> {
+	if url.scheme() == "file" {
+		let path = url
+			.to_file_path()
+			.map_err(|_| Error::FeedNotFound(url.to_string()))?;
+		return Ok(Some(fs::read_to_string(path)?));
+	}
+
+	let feeds_dir = FEEDS_DIR.read().expect("feeds dir lock poisoned");
+	let (dir, index) = match &*feeds_dir {
+		Some(feeds_dir) => feeds_dir,
+		None => return Ok(None),
+	};
+
+	let cache_name = dir.join(Entry::new(url).body);
+	let path = index
+		.get(&url_key(url.as_str()))
+		.or_else(|| index.get(&title.trim().to_lowercase()))
+		.cloned()
+		.or_else(|| Some(cache_name).filter(|path| path.is_file()))
+		.ok_or_else(|| Error::FeedNotFound(url.to_string()))?;
+	Ok(Some(fs::read_to_string(path)?))
+}
+
+/// What the server sent to tell whether the feed changed since
+#[derive(Serialize, Deserialize)]
+struct Validators {
+	url: String,
+	etag: Option<String>,
+	last_modified: Option<String>,
+}
+
+/// The keys a feed is cached under, named by a hash of its URL
+struct Entry {
+	body: String,
+	validators: String,
+}
+
+impl Entry {
+	fn new(url: &Url) -> Self {
+		// FNV-1a, stable between runs and builds unlike the std hasher
+		let hash = url
+			.as_str()
+			.bytes()
+			.fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+				(hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+			});
+
+		Self {
+			body: format!("{:016x}.xml", hash),
+			validators: format!("{:016x}.json", hash),
+		}
+	}
+
+	fn load(&self, cache: &dyn Storage, url: &Url) -> Option<(Validators, String)> {
+		let validators: Validators =
+			serde_json::from_slice(&cache.get(&self.validators).ok()??).ok()?;
+		if validators.url != url.as_str() {
+			return None;
+		}
+		Some((
+			validators,
+			String::from_utf8(cache.get(&self.body).ok()??).ok()?,
+		))
+	}
+
+	fn store(&self, cache: &dyn Storage, validators: &Validators, body: &str) -> io::Result<()> {
+		cache.put(&self.body, body.as_bytes())?;
+		cache.put(&self.validators, &serde_json::to_vec(validators)?)
+	}
+}
+
+/// Send the client's requests with the User-Agent and Accept headers for feeds, through
+/// --proxy or the proxy the environment names
+pub fn configure(builder: ClientBuilder) -> ClientBuilder {
+	let mut headers = HeaderMap::new();
+	headers.insert(
+		USER_AGENT,
+		CUSTOM_USER_AGENT
+			.read()
+			.expect("user agent lock poisoned")
+			.clone()
+			.unwrap_or_else(|| HeaderValue::from_static(DEFAULT_USER_AGENT)),
+	);
+	headers.insert(ACCEPT, HeaderValue::from_static(FEED_ACCEPT));
+	let builder = builder.default_headers(headers);
+
+	match &*PROXY.read().expect("proxy lock poisoned") {
+		Some(proxy) => builder.proxy(proxy.clone()),
+		None => builder.use_sys_proxy(),
+	}
+}
+
+fn build_client(redirects: RedirectPolicy) -> reqwest::Result<Client> {
+	let timeout = match TIMEOUT.load(Ordering::Relaxed) {
+		0 => None,
+		secs => Some(Duration::from_secs(secs)),
+	};
+	configure(Client::builder())
+		.timeout(timeout)
+		.redirect(redirects)
+		.build()
+}
+
+/// Client for fetching feeds, with --timeout, --user-agent and --proxy
+pub fn client() -> reqwest::Result<Client> {
+	build_client(RedirectPolicy::default())
+}
+
+/// Where the redirects of a request led, while they were all permanent
+#[derive(Default)]
+struct Moves {
+	temporary: bool,
+	moved_to: Option<Url>,
+}
+
+/// Follow redirects like the default policy, recording the moves in `moves`
+fn recording_redirects(moves: Arc<Mutex<Moves>>) -> RedirectPolicy {
+	RedirectPolicy::custom(move |attempt| {
+		if attempt.previous().len() >= MAX_REDIRECTS {
+			return attempt.too_many_redirects();
+		}
+		let mut moves = moves.lock().expect("redirects lock poisoned");
+		moves.temporary |= attempt.status() != StatusCode::MOVED_PERMANENTLY
+			&& attempt.status() != StatusCode::PERMANENT_REDIRECT;
+		if !moves.temporary {
+			moves.moved_to = Some(attempt.url().clone());
+		}
+		attempt.follow()
+	})
+}
+
+/// Wait until a request to the URL's host may start, and hold the next one back
+/// --host-interval after it
+fn wait_for_host(url: &Url) {
+	let host = match url.host_str() {
+		Some(host) => host.to_lowercase(),
+		None => return,
+	};
+	let start = {
+		let mut next_request = HOST_NEXT_REQUEST
+			.lock()
+			.expect("host rate limit lock poisoned");
+		let now = Instant::now();
+		let start = next_request
+			.get(&host)
+			.cloned()
+			.filter(|next| *next > now)
+			.unwrap_or(now);
+		next_request.insert(
+			host,
+			start + Duration::from_millis(HOST_INTERVAL.load(Ordering::Relaxed)),
+		);
+		start
+	};
+
+	let now = Instant::now();
+	if start > now {
+		thread::sleep(start - now);
+	}
+}
+
+/// Hold back every request to the URL's host for `delay`
+fn hold_host(url: &Url, delay: Duration) {
+	if let Some(host) = url.host_str() {
+		let until = Instant::now() + delay;
+		let mut next_request = HOST_NEXT_REQUEST
+			.lock()
+			.expect("host rate limit lock poisoned");
+		let next = next_request.entry(host.to_lowercase()).or_insert(until);
+		if *next < until {
+			*next = until;
+		}
+	}
+}
+
+/// Seconds a busy host asked to be left alone for, HTTP dates are not understood
+fn retry_after(response: &Response) -> Option<Duration> {
+	header(response, RETRY_AFTER)
+		.and_then(|value| value.trim().parse().ok())
+		.map(Duration::from_secs)
+}
+
+/// Send the request `build` makes with the client and `credentials`, retrying with backoff
+/// while it fails in a way that may pass. Returns the response and where the URL moved to
+fn send<F: Fn(&Client) -> RequestBuilder>(
+	url: &Url,
+	credentials: &Option<Credentials>,
+	build: F,
+) -> reqwest::Result<(Response, Option<Url>)> {
+	let moves = Arc::new(Mutex::new(Moves::default()));
+	let client = build_client(recording_redirects(moves.clone()))?;
+	let mut backoff = FIRST_BACKOFF;
+	let mut retries = RETRIES.load(Ordering::Relaxed);
+	let mut as_browser = false;
+
+	loop {
+		wait_for_host(url);
+		*moves.lock().expect("redirects lock poisoned") = Moves::default();
+		let mut request = build(&client);
+		if let Some((user, password)) = credentials {
+			request = request.basic_auth(user, password.clone());
+		}
+		if as_browser {
+			request = request.header(USER_AGENT, BROWSER_USER_AGENT);
+		}
+		let result = request.send();
+
+		let wait = match &result {
+			Ok(response)
+				if response.status() == StatusCode::FORBIDDEN
+					&& !as_browser && CUSTOM_USER_AGENT
+					.read()
+					.expect("user agent lock poisoned")
+					.is_none() =>
+			{
+				println!("'{}' refused the request, asking as a browser", redact(url));
+				as_browser = true;
+				continue;
+			}
+			Ok(response)
+				if response.status() == StatusCode::TOO_MANY_REQUESTS
+					|| response.status().is_server_error() =>
+			{
+				let asked = retry_after(response);
+				if let Some(delay) = asked {
+					hold_host(url, delay);
+				}
+				Some(asked.unwrap_or(backoff))
+			}
+			Ok(_) => None,
+			Err(err) if err.is_timeout() || err.is_http() => Some(backoff),
+			Err(_) => None,
+		};
+		let wait = match wait {
+			Some(wait) if retries > 0 && wait <= MAX_RETRY_AFTER => wait,
+			_ => {
+				let moved_to = moves
+					.lock()
+					.expect("redirects lock poisoned")
+					.moved_to
+					.take();
+				return result.map(|response| (response, moved_to));
+			}
+		};
+
+		println!(
+			"Fetching '{}' failed, trying again in {}s",
+			redact(url),
+			wait.as_secs()
+		);
+		thread::sleep(wait);
+		backoff *= 2;
+		retries -= 1;
+	}
+}
+
+fn header(response: &Response, name: HeaderName) -> Option<String> {
+	response
+		.headers()
+		.get(name)
+		.and_then(|value| value.to_str().ok())
+		.map(String::from)
+}
+
+/// Body of `url`, sent like a feed request with its retries and spacing per host but never
+/// cached, for the lookup APIs feeds are found through
+pub fn get(url: Url) -> reqwest::Result<String> {
+	let (url, credentials) = split_credentials(&url);
+	let (response, _) = send(&url, &credentials, |client| client.get(url.clone()))?;
+	response.error_for_status()?.text()
+}
+
+/// Whether a fetch failed because the feed is not there any more, the server answered 404 or 410
+pub fn is_gone(err: &(dyn std::error::Error + 'static)) -> bool {
+	err.downcast_ref::<reqwest::Error>()
+		.and_then(|err| err.status())
+		.map_or(false, |status| {
+			status == StatusCode::NOT_FOUND || status == StatusCode::GONE
+		})
+}
+
+/// Body of the feed at `url`, from the cache when the server says it has not changed, and the
+/// URL the feed permanently moved to
+pub fn feed(url: Url) -> reqwest::Result<(String, Option<Url>)> {
+	let (url, credentials) = split_credentials(&url);
+	let cache = CACHE.read().expect("cache lock poisoned").clone();
+	let cache = match cache {
+		Some(cache) => cache,
+		None => {
+			let (response, moved_to) = send(&url, &credentials, |client| client.get(url.clone()))?;
+			return Ok((response.error_for_status()?.text()?, moved_to));
+		}
+	};
+
+	let entry = Entry::new(&url);
+	let cached = entry.load(cache.as_ref(), &url);
+
+	let (response, moved_to) = send(&url, &credentials, |client| {
+		let mut request = client.get(url.clone());
+		if let Some((validators, _)) = &cached {
+			if let Some(etag) = &validators.etag {
+				request = request.header(IF_NONE_MATCH, etag.as_str());
+			}
+			if let Some(last_modified) = &validators.last_modified {
+				request = request.header(IF_MODIFIED_SINCE, last_modified.as_str());
+			}
+		}
+		request
+	})?;
+	if response.status() == StatusCode::NOT_MODIFIED {
+		if let Some((_, body)) = cached {
+			println!("'{}' has not changed, using the cached copy", redact(&url));
+			return Ok((body, moved_to));
+		}
+	}
+	// An error page is not the feed, and must not replace the cached copy
+	let mut response = response.error_for_status()?;
+
+	let validators = Validators {
+		url: url.to_string(),
+		etag: header(&response, ETAG),
+		last_modified: header(&response, LAST_MODIFIED),
+	};
+	let body = response.text()?;
+	if response.status().is_success() {
+		if let Err(err) = entry.store(cache.as_ref(), &validators, &body) {
+			println!("Could not cache '{}': {}", redact(&url), err);
+		}
+	}
+	Ok((body, moved_to))
+}
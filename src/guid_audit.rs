@@ -0,0 +1,36 @@
+use crate::player::BeyondPod;
+use crate::podcast::Podcast;
+use crate::BoxResult;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+	SubCommand::with_name("audit-guids")
+		.about("Compare the track ids BeyondPod stored for a feed with the ones computed from its guids")
+		.arg(
+			Arg::with_name("beyondpod")
+				.long("beyondpod")
+				.takes_value(true)
+				.value_name("FILE")
+				.help("BeyondPod backup file")
+				.required(true),
+		)
+		.arg(
+			Arg::with_name("feed")
+				.long("feed")
+				.takes_value(true)
+				.value_name("URL")
+				.help("URL of the feed to audit")
+				.required(true),
+		)
+}
+
+pub fn run(matches: &ArgMatches) -> BoxResult<()> {
+	let url = matches.value_of("feed").expect("no feed url");
+	let podcast = Podcast::new(url, url)?;
+
+	BeyondPod::audit_guids(
+		matches.value_of("beyondpod").expect("no beyondpod file"),
+		&podcast,
+	)
+}
@@ -0,0 +1,74 @@
+use crate::i18n::tr;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Set by --heartbeat, seconds between the lines printed for a slow operation, 0 turns them off
+static INTERVAL: AtomicU64 = AtomicU64::new(10);
+
+pub fn set_interval(secs: u64) {
+	INTERVAL.store(secs, Ordering::Relaxed);
+}
+
+/// Prints which operation is still running and for how long every interval, until dropped.
+/// Tells a slow feed or database apart from a hang, and names what to report
+pub struct Heartbeat {
+	done: Arc<(Mutex<bool>, Condvar)>,
+	thread: Option<JoinHandle<()>>,
+}
+
+impl Heartbeat {
+	pub fn start(operation: String) -> Self {
+		let done = Arc::new((Mutex::new(false), Condvar::new()));
+		let interval = INTERVAL.load(Ordering::Relaxed);
+		if interval == 0 {
+			return Self { done, thread: None };
+		}
+
+		let thread_done = done.clone();
+		let thread = thread::spawn(move || {
+			let started = Instant::now();
+			let (lock, cvar) = &*thread_done;
+			let mut done = lock.lock().expect("heartbeat lock poisoned");
+
+			while !*done {
+				let (guard, timeout) = cvar
+					.wait_timeout(done, Duration::from_secs(interval))
+					.expect("heartbeat lock poisoned");
+				done = guard;
+				if timeout.timed_out() && !*done {
+					println!(
+						"{}",
+						tr(
+							"heartbeat",
+							&[
+								("operation", &operation),
+								("secs", &started.elapsed().as_secs())
+							]
+						)
+					);
+				}
+			}
+		});
+
+		Self {
+			done,
+			thread: Some(thread),
+		}
+	}
+}
+
+impl Drop for Heartbeat {
+	fn drop(&mut self) {
+		let (lock, cvar) = &*self.done;
+		if let Ok(mut done) = lock.lock() {
+			*done = true;
+			cvar.notify_one();
+		}
+		if let Some(thread) = self.thread.take() {
+			let _ = thread.join();
+		}
+	}
+}
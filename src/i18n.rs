@@ -0,0 +1,66 @@
+use std::env;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Messages are kept in a small subset of the Fluent format, one `id = text`
+// per line with `{ $name }` placeholders and `#` comments. English is the
+// fallback for any message a translation is missing. A new translation is a
+// `locales/<lang>.ftl` file added to LANGUAGES and CATALOGS.
+
+pub const LANGUAGES: [&str; 2] = ["en", "de"];
+
+const CATALOGS: [&str; 2] = [
+	include_str!("../locales/en.ftl"),
+	include_str!("../locales/de.ftl"),
+];
+
+static LOCALE: AtomicUsize = AtomicUsize::new(0);
+
+/// Language part of a POSIX locale, e.g. `de` for `de_AT.UTF-8`
+fn language(locale: &str) -> &str {
+	locale
+		.split(|c| c == '_' || c == '.' || c == '@')
+		.next()
+		.unwrap_or("")
+}
+
+/// Use the given language, otherwise the one from the environment like gettext does
+pub fn configure(lang: Option<&str>) {
+	let from_env = || {
+		["LC_ALL", "LC_MESSAGES", "LANG"]
+			.iter()
+			.filter_map(|var| env::var(var).ok())
+			.find(|value| !value.is_empty())
+	};
+	let locale = lang.map(String::from).or_else(from_env).unwrap_or_default();
+
+	let index = LANGUAGES
+		.iter()
+		.position(|name| *name == language(locale.as_str()))
+		.unwrap_or(0);
+	LOCALE.store(index, Ordering::Relaxed);
+}
+
+fn lookup<'a>(catalog: &'a str, id: &str) -> Option<&'a str> {
+	catalog
+		.lines()
+		.map(str::trim)
+		.filter(|line| !line.is_empty() && !line.starts_with('#'))
+		.filter_map(|line| {
+			let mut parts = line.splitn(2, '=');
+			Some((parts.next()?.trim(), parts.next()?.trim()))
+		})
+		.find(|(key, _)| *key == id)
+		.map(|(_, text)| text)
+}
+
+/// The message with the given id in the configured language, with its placeholders filled in
+pub fn tr(id: &str, args: &[(&str, &dyn fmt::Display)]) -> String {
+	let text = lookup(CATALOGS[LOCALE.load(Ordering::Relaxed)], id)
+		.or_else(|| lookup(CATALOGS[0], id))
+		.unwrap_or(id);
+
+	args.iter().fold(String::from(text), |text, (name, value)| {
+		text.replace(&format!("{{ ${} }}", name), &value.to_string())
+	})
+}
@@ -0,0 +1,107 @@
+use crate::clock;
+use crate::podcast::{Page, Track};
+use crate::BoxResult;
+
+use reqwest::Url;
+use serde::Deserialize;
+
+// JSON Feed (https://www.jsonfeed.org/version/1.1/) is read alongside RSS. A
+// feed is told apart by its body starting with `{`, not by its content type,
+// as servers often send it as `application/json` or `text/plain` and feeds
+// from the cache or --feeds-dir have none. Each item with an attachment is an
+// episode: the item's `id` is its guid and the first audio attachment its
+// enclosure, any other attachments are alternate URLs. Items without one are
+// posts, not episodes, and left out like RSS items without an enclosure.
+
+#[derive(Deserialize)]
+struct Attachment {
+	url: String,
+	#[serde(default)]
+	mime_type: String,
+	duration_in_seconds: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct Item {
+	/// A number in some feeds, though the spec asks for a string
+	id: serde_json::Value,
+	title: Option<String>,
+	date_published: Option<String>,
+	#[serde(default)]
+	attachments: Vec<Attachment>,
+}
+
+#[derive(Deserialize)]
+struct Feed {
+	title: Option<String>,
+	feed_url: Option<String>,
+	next_url: Option<String>,
+	#[serde(default)]
+	items: Vec<Item>,
+}
+
+/// Whether a feed body is a JSON Feed rather than XML
+pub fn is_json_feed(body: &str) -> bool {
+	body.trim_start_matches('\u{feff}')
+		.trim_start()
+		.starts_with('{')
+}
+
+/// Its `feed_url` and title, for finding the feed in --feeds-dir
+pub fn self_url_and_title(body: &str) -> Option<(Option<String>, Option<String>)> {
+	let feed: Feed = serde_json::from_str(body.trim_start_matches('\u{feff}')).ok()?;
+	Some((feed.feed_url, feed.title))
+}
+
+impl Item {
+	fn into_track(self) -> Option<Track> {
+		let guid = match self.id {
+			serde_json::Value::String(id) => id,
+			serde_json::Value::Number(id) => id.to_string(),
+			_ => return None,
+		};
+
+		let mut attachments = self.attachments;
+		if attachments.is_empty() {
+			return None;
+		}
+		let audio = attachments
+			.iter()
+			.position(|attachment| attachment.mime_type.starts_with("audio/"))
+			.unwrap_or(0);
+		let enclosure = attachments.remove(audio);
+
+		let mut track = Track::new(
+			guid,
+			Url::parse(&enclosure.url).ok()?,
+			self.title.unwrap_or_default(),
+			enclosure
+				.duration_in_seconds
+				.map(|secs| secs.round() as i32),
+		);
+		track.published = self
+			.date_published
+			.as_ref()
+			.and_then(|date| clock::parse_iso8601(date));
+		track.alternate_urls = attachments
+			.iter()
+			.filter_map(|attachment| Url::parse(&attachment.url).ok())
+			.collect();
+		Some(track)
+	}
+}
+
+/// Episodes of a JSON Feed and the URL of its next page, it has no feed GUID
+pub fn read_page(body: &str) -> BoxResult<Page> {
+	let feed: Feed = serde_json::from_str(body.trim_start_matches('\u{feff}'))?;
+	let tracks = feed
+		.items
+		.into_iter()
+		.filter_map(Item::into_track)
+		.collect();
+	Ok(Page {
+		tracks,
+		next: feed.next_url,
+		guid: None,
+	})
+}
@@ -0,0 +1,95 @@
+use crate::{BoxResult, Error};
+
+use std::fs::{self, File, OpenOptions};
+use std::path::Path;
+
+use fs2::FileExt;
+
+// For the whole run every input file is locked shared, and every output
+// through an `OUTPUT.lock` file beside it locked exclusively, as the output
+// itself is replaced rather than written to. A second run started while one is
+// still going (e.g. overlapping cron jobs), or any other program that takes
+// advisory locks, fails straight away instead of interleaving its writes with
+// this one's. An input that is also an output (--in-place) is covered by the
+// output's lock. File systems that can not lock files are used without.
+//
+// The locks are advisory and local. They do not keep out the player app, an
+// editor or anything else that does not ask for them, nor runs on other
+// machines: a cloud sync client (Dropbox, OneDrive, Syncthing, ...) can still
+// upload an output while it is being replaced, or put another device's copy
+// in its place between this run reading and writing it, and two machines
+// syncing the same folder each lock only their own copy. Network file systems
+// may not lock at all, or only per client.
+//
+// Lock files are left in place when the run ends. Removing one would let a run
+// that had just opened it lock the removed file while a third created and
+// locked a new one, and both would write the output at once.
+
+pub struct Locks {
+	/// Held open only to keep their locks
+	files: Vec<File>,
+}
+
+impl Drop for Locks {
+	fn drop(&mut self) {
+		for file in self.files.drain(..) {
+			let _ = file.unlock();
+		}
+	}
+}
+
+/// Lock an open file, false where its file system can not lock files
+fn lock(file: &File, path: &str, exclusive: bool) -> BoxResult<bool> {
+	let locked = if exclusive {
+		file.try_lock_exclusive()
+	} else {
+		file.try_lock_shared()
+	};
+
+	match locked {
+		Ok(()) => Ok(true),
+		Err(ref err) if err.kind() == fs2::lock_contended_error().kind() => {
+			Err(Error::FileLocked(path.into()).into())
+		}
+		Err(err) => {
+			println!("Not locking '{}': {}", path, err);
+			Ok(false)
+		}
+	}
+}
+
+/// Lock the outputs and the inputs that are not also outputs, until the `Locks` are dropped
+pub fn lock_all(inputs: &[&str], outputs: &[&str]) -> BoxResult<Locks> {
+	let mut locks = Locks { files: Vec::new() };
+
+	for output in outputs.iter() {
+		let lock_path = format!("{}.lock", output);
+		let created = !Path::new(&lock_path).exists();
+		let file = OpenOptions::new()
+			.write(true)
+			.create(true)
+			.open(&lock_path)?;
+		if lock(&file, output, true)? {
+			locks.files.push(file);
+		} else if created {
+			// Nothing can hold a lock on this file system, so no other run relies on the file
+			let _ = fs::remove_file(&lock_path);
+		}
+	}
+
+	for input in inputs.iter() {
+		if outputs.iter().any(|output| crate::same_file(output, input)) {
+			continue;
+		}
+		// Inputs that are not files, such as server URLs, have nothing to lock
+		let file = match File::open(input) {
+			Ok(file) => file,
+			Err(_) => continue,
+		};
+		if lock(&file, input, false)? {
+			locks.files.push(file);
+		}
+	}
+
+	Ok(locks)
+}
@@ -1,16 +1,55 @@
+extern crate atty;
 extern crate byteorder;
 extern crate clap;
+extern crate fs2;
+extern crate lazy_static;
 extern crate reqwest;
 extern crate roxmltree;
 extern crate rusqlite;
+extern crate serde;
+extern crate serde_json;
+extern crate sha1;
 extern crate tempfile;
 extern crate zip;
 
+mod apple_lookup;
+mod apply;
+mod check_feeds;
+mod clock;
+mod config;
+mod csv;
+mod detect;
+mod fetch;
+mod guid_audit;
+mod heartbeat;
+mod i18n;
+mod json_feed;
+mod lock;
+mod opml;
+mod output;
 mod player;
 mod podcast;
+mod podcast_index;
+mod provenance;
+mod resume;
+mod round_trip;
+mod scan;
+mod skip;
+mod storage;
+mod subscriptions;
+mod summary;
+mod url_key;
+mod version;
+mod warning;
+mod wizard;
 
-use player::Player;
-use podcast::Podcast;
+use clock::Clock;
+use heartbeat::Heartbeat;
+use i18n::tr;
+use player::{Player, SaveOptions, Subscription};
+use podcast::{Podcast, PodcastSettings, Track};
+use provenance::Provenance;
+use warning::{warn, Warning};
 
 use std::borrow::{Borrow, BorrowMut};
 use std::collections::HashMap;
@@ -18,8 +57,10 @@ use std::error;
 use std::fmt;
 use std::io;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
-use clap::{Arg, ArgGroup, ArgMatches};
+use clap::{AppSettings, Arg, ArgGroup, ArgMatches};
 use rusqlite::Connection;
 use tempfile::NamedTempFile;
 
@@ -31,16 +72,59 @@ pub enum Error {
 	InvalidUUID,
 	MissingXMLNode(&'static str),
 	InvalidPlayingStatus,
+	UnsupportedOperation(&'static str),
+	UnknownSchema(&'static str, String),
+	UnknownCategory(String),
+	MissingCredentials(&'static str),
+	VerificationFailed(&'static str, String),
+	DeniedWarnings(usize),
+	UnknownSaveFile(String),
+	Skipped,
+	FeedNotFound(String),
+	UnknownPlayer(String),
+	UnknownProfile(String),
+	InvalidFeedAuth,
+	SameFile(String, String),
+	FileLocked(String),
+	FetchPanicked(String),
 }
 
 impl fmt::Display for Error {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		match self {
-			Error::InvalidArguments(n) => write!(f, "Invalid number of call arguments: {}", n),
-			Error::InvalidUUID => write!(f, "String is not a valid UUID"),
-			Error::MissingXMLNode(node) => write!(f, "Missing XML node: {}", node),
-			Error::InvalidPlayingStatus => write!(f, "Invalid playing status"),
-		}
+		let message = match self {
+			Error::InvalidArguments(n) => tr("error-invalid-arguments", &[("count", n)]),
+			Error::InvalidUUID => tr("error-invalid-uuid", &[]),
+			Error::MissingXMLNode(node) => tr("error-missing-xml-node", &[("node", node)]),
+			Error::InvalidPlayingStatus => tr("error-invalid-playing-status", &[]),
+			Error::UnsupportedOperation(op) => {
+				tr("error-unsupported-operation", &[("operation", op)])
+			}
+			Error::UnknownSchema(player, found) => tr(
+				"error-unknown-schema",
+				&[("player", player), ("found", found)],
+			),
+			Error::UnknownCategory(name) => tr("error-unknown-category", &[("name", name)]),
+			Error::MissingCredentials(player) => {
+				tr("error-missing-credentials", &[("player", player)])
+			}
+			Error::VerificationFailed(player, reason) => tr(
+				"error-verification-failed",
+				&[("player", player), ("reason", reason)],
+			),
+			Error::DeniedWarnings(n) => tr("error-denied-warnings", &[("count", n)]),
+			Error::UnknownSaveFile(path) => tr("error-unknown-save-file", &[("path", path)]),
+			Error::Skipped => tr("error-skipped", &[]),
+			Error::FeedNotFound(url) => tr("error-feed-not-found", &[("url", url)]),
+			Error::UnknownPlayer(player) => tr("error-unknown-player", &[("player", player)]),
+			Error::UnknownProfile(name) => tr("error-unknown-profile", &[("name", name)]),
+			Error::InvalidFeedAuth => tr("error-invalid-feed-auth", &[]),
+			Error::SameFile(output, input) => {
+				tr("error-same-file", &[("output", output), ("input", input)])
+			}
+			Error::FileLocked(path) => tr("error-file-locked", &[("path", path)]),
+			Error::FetchPanicked(reason) => tr("error-fetch-panicked", &[("reason", reason)]),
+		};
+		f.write_str(message.as_str())
 	}
 }
 
@@ -50,18 +134,19 @@ impl error::Error for Error {
 	}
 }
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone)]
 struct UUID(u128);
 
 impl fmt::Debug for UUID {
 	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-		write!(f, "FeedId({})", self.to_string())
+		write!(f, "FeedId({})", self)
 	}
 }
 
-impl ToString for UUID {
-	fn to_string(&self) -> String {
-		format!(
+impl fmt::Display for UUID {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
 			"{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
 			(self.0 >> 96) as u32,
 			(self.0 >> 80) as u16,
@@ -84,10 +169,22 @@ impl UUID {
 		}
 
 		let s_num = s[..8].to_string() + &s[9..13] + &s[14..18] + &s[19..23] + &s[24..];
-		u128::from_str_radix(&s_num, 16)
+		Self::from_simple_str(s_num)
+	}
+
+	fn from_simple_str(s: String) -> BoxResult<Self> {
+		if s.len() != 32 {
+			return Err(Error::InvalidUUID.into());
+		}
+
+		u128::from_str_radix(&s, 16)
 			.map(|n| Self(n))
 			.map_err(|err| err.into())
 	}
+
+	fn to_simple_string(&self) -> String {
+		format!("{:032x}", self.0)
+	}
 }
 
 struct SQLLiteDatabase {
@@ -107,6 +204,21 @@ impl BorrowMut<Connection> for SQLLiteDatabase {
 	}
 }
 
+/// Set by --trace-sql, checked when each database is opened
+static TRACE_SQL: AtomicBool = AtomicBool::new(false);
+
+fn trace_sql(statement: &str, elapsed: Duration) {
+	if output::plain() {
+		eprintln!("sql {:.3}ms: {}", elapsed.as_secs_f64() * 1000.0, statement);
+		return;
+	}
+	eprintln!(
+		"[sql {:>8.3}ms] {}",
+		elapsed.as_secs_f64() * 1000.0,
+		statement
+	);
+}
+
 impl SQLLiteDatabase {
 	pub fn open<P: AsRef<Path>>(path: P) -> BoxResult<Self> {
 		SQLLiteDatabase::open_from_reader(&mut std::fs::File::open(path)?)
@@ -116,8 +228,14 @@ impl SQLLiteDatabase {
 		let mut temp_file = NamedTempFile::new()?;
 		io::copy(r, &mut temp_file)?;
 
+		let mut conn = Connection::open(temp_file.path())?;
+		url_key::register(&conn)?;
+		if TRACE_SQL.load(Ordering::Relaxed) {
+			conn.profile(Some(trace_sql));
+		}
+
 		Ok(Self {
-			conn: Connection::open(temp_file.path())?,
+			conn,
 			file: temp_file,
 		})
 	}
@@ -130,35 +248,95 @@ impl SQLLiteDatabase {
 	}
 }
 
+/// A temporary file in the same directory as `path`, so it can be persisted over it in one step
+pub fn temp_file_beside(path: &str) -> io::Result<NamedTempFile> {
+	let dir = Path::new(path)
+		.parent()
+		.filter(|dir| !dir.as_os_str().is_empty())
+		.unwrap_or_else(|| Path::new("."));
+	NamedTempFile::new_in(dir)
+}
+
+#[cfg(unix)]
+fn same_inode(a: &std::fs::Metadata, b: &std::fs::Metadata) -> bool {
+	use std::os::unix::fs::MetadataExt;
+	a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+#[cfg(not(unix))]
+fn same_inode(_a: &std::fs::Metadata, _b: &std::fs::Metadata) -> bool {
+	false
+}
+
+/// Whether two paths name the same existing file, through links or another spelling
+fn same_file(a: &str, b: &str) -> bool {
+	match (std::fs::metadata(a), std::fs::metadata(b)) {
+		(Ok(a_meta), Ok(b_meta)) if same_inode(&a_meta, &b_meta) => return true,
+		(Ok(_), Ok(_)) => (),
+		_ => return false,
+	}
+
+	match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+		(Ok(a), Ok(b)) => a == b,
+		_ => false,
+	}
+}
+
 struct PlayerArgs {
+	name: &'static str,
 	cli_name: &'static str,
 	in_name: String,
 	out_name: String,
 	player_help: String,
 	in_help: String,
 	out_help: String,
+	value_name: &'static str,
+	read_only: bool,
+	write_only: bool,
+	writes_through: bool,
+	status_encodings: &'static [player::StatusEncoding],
+	schema_versions: Vec<&'static str>,
+	file_names: &'static [&'static str],
 	factory: fn(&str) -> BoxResult<Box<dyn Player>>,
 }
 
 impl PlayerArgs {
 	fn new<T: player::NewPlayer>() -> Self {
 		Self {
+			name: T::name(),
 			cli_name: T::cli_name(),
 			in_name: String::from("in-") + T::cli_name(),
 			out_name: String::from("out-") + T::cli_name(),
-			player_help: String::from("the ") + T::name() + " save file",
+			player_help: match T::value_name() {
+				"URL" => {
+					String::from("the ")
+						+ T::name() + " server, credentials can be given in the URL"
+				}
+				_ => String::from("the ") + T::name() + " save file",
+			},
 			in_help: String::from("Convert from ") + T::name(),
 			out_help: String::from("Convert to ") + T::name() + " and output to FILE",
+			value_name: T::value_name(),
+			read_only: T::read_only(),
+			write_only: T::write_only(),
+			writes_through: T::writes_through(),
+			status_encodings: T::status_encodings(),
+			schema_versions: T::schema_versions(),
+			file_names: T::file_names(),
 			factory: T::new,
 		}
 	}
 
-	fn get(&self) -> [Arg<'_, '_>; 3] {
-		let player_arg = Arg::with_name(self.cli_name)
+	fn get_player(&self) -> Arg<'_, '_> {
+		Arg::with_name(self.cli_name)
 			.long(self.cli_name)
 			.takes_value(true)
-			.value_name("FILE")
-			.help(self.player_help.as_str());
+			.value_name(self.value_name)
+			.help(self.player_help.as_str())
+	}
+
+	fn get(&self) -> Vec<Arg<'_, '_>> {
+		let player_arg = self.get_player();
 
 		let in_arg = Arg::with_name(self.in_name.as_str())
 			.long(self.in_name.as_str())
@@ -166,6 +344,10 @@ impl PlayerArgs {
 			.group("in")
 			.help(self.in_help.as_str());
 
+		if self.read_only {
+			return vec![player_arg, in_arg];
+		}
+
 		let out_arg = Arg::with_name(self.out_name.as_str())
 			.long(self.out_name.as_str())
 			.requires(self.cli_name)
@@ -174,7 +356,28 @@ impl PlayerArgs {
 			.value_name("FILE")
 			.help(self.out_help.as_str());
 
-		[player_arg, in_arg, out_arg]
+		vec![player_arg, in_arg, out_arg]
+	}
+
+	fn describe(&self) {
+		if self.read_only {
+			println!("{} (--{}, input only)", self.name, self.cli_name);
+		} else {
+			println!("{} (--{})", self.name, self.cli_name);
+		}
+
+		for encoding in self.status_encodings.iter() {
+			let values: Vec<String> = encoding
+				.values
+				.iter()
+				.map(|(status, value)| format!("{:?} = {}", status, value))
+				.collect();
+			if output::plain() {
+				println!("{} {}: {}", self.name, encoding.field, values.join(", "));
+			} else {
+				println!("\t{}: {}", encoding.field, values.join(", "));
+			}
+		}
 	}
 
 	fn create_player(&self, matches: &ArgMatches) -> Option<BoxResult<Box<dyn Player>>> {
@@ -184,14 +387,197 @@ impl PlayerArgs {
 	}
 }
 
-fn populate(player: &mut Box<dyn Player>, podcasts: Vec<Podcast>) -> BoxResult<Vec<Podcast>> {
-	podcasts
+/// Values of a global arg, which clap only keeps in the matches of the subcommand it followed
+fn global_values<'a>(matches: &'a ArgMatches, name: &str) -> Vec<&'a str> {
+	let mut values: Vec<&str> = matches.values_of(name).map_or(vec![], |v| v.collect());
+	if let Some(sub_matches) = matches.subcommand().1 {
+		values.extend(sub_matches.values_of(name).into_iter().flatten());
+	}
+	values
+}
+
+/// Whether a global flag was given before or after the subcommand
+fn global_flag(matches: &ArgMatches, name: &str) -> bool {
+	matches.is_present(name)
+		|| matches
+			.subcommand()
+			.1
+			.map_or(false, |sub_matches| sub_matches.is_present(name))
+}
+
+/// url_keys of the feeds the player is subscribed to, only looked up when a feed has moved
+fn subscribed_keys(player: &dyn Player, podcasts: &[Podcast]) -> Option<Vec<String>> {
+	if podcasts.iter().all(|pod| pod.previous_url.is_none()) {
+		return None;
+	}
+	Some(
+		player
+			.subscriptions()
+			.ok()?
+			.iter()
+			.filter_map(|sub| sub.url.as_ref())
+			.map(|url| url_key::url_key(url.as_str()))
+			.collect(),
+	)
+}
+
+/// Whether the player only knows the moved podcast by the URL it moved from
+fn knows_previous_url(subscribed: &Option<Vec<String>>, pod: &Podcast) -> bool {
+	match (subscribed, &pod.previous_url) {
+		(Some(keys), Some(previous)) => {
+			let knows = |url: &reqwest::Url| keys.contains(&url_key::url_key(url.as_str()));
+			!knows(&pod.url) && knows(previous)
+		}
+		_ => false,
+	}
+}
+
+/// Swap a moved podcast's URL with the one it moved from
+fn swap_urls(pod: &mut Podcast) {
+	if let Some(previous) = pod.previous_url.as_mut() {
+		std::mem::swap(&mut pod.url, previous);
+	}
+}
+
+/// Podcasts of the feeds that could not be fetched, with the episodes the source player has of
+/// them, already in their state there. None for players that can not list their episodes
+fn dead_feeds(player: &dyn Player, failed: podcast::FailedFeeds) -> Vec<Podcast> {
+	if failed.is_empty() {
+		return Vec::new();
+	}
+	match player.local_podcasts() {
+		Ok(local) => player::podcasts_for_failed(local, failed),
+		Err(err) => {
+			warn(
+				Warning::FeedFailed,
+				format!(
+					"The episodes of the {} feeds that could not be fetched are left out, they could not be read from the source player: {}",
+					failed.len(),
+					err
+				),
+			);
+			Vec::new()
+		}
+	}
+}
+
+/// Podcasts and tracks left out of the conversion because reading or writing them failed
+#[derive(Default)]
+struct SkipCounts {
+	podcasts: usize,
+	tracks: usize,
+}
+
+/// Podcasts the player fails on are reported and left out, as are the tracks of the others it
+/// fails on, the count of both is returned too
+fn populate(
+	player: &mut Box<dyn Player>,
+	podcasts: Vec<Podcast>,
+	settings: bool,
+) -> (Vec<Podcast>, SkipCounts) {
+	let mut populated = Vec::with_capacity(podcasts.len());
+	let mut skipped = SkipCounts::default();
+	let subscribed = subscribed_keys(player.as_ref(), &podcasts);
+
+	for mut pod in podcasts.into_iter() {
+		// Asked for by the URL the player knows, converted on with the one the feed moved to
+		let swapped = knows_previous_url(&subscribed, &pod);
+		if swapped {
+			swap_urls(&mut pod);
+		}
+		println!(
+			"{}",
+			tr(
+				"populating",
+				&[("title", &pod.title), ("url", &fetch::redact(&pod.url))]
+			)
+		);
+		let (title, url) = (pod.title.clone(), fetch::redact(&pod.url));
+		let tracks = pod.tracks.len();
+		match player.populate(pod) {
+			Ok(mut pod) => {
+				// Players only drop the tracks they fail to read
+				skipped.tracks += tracks.saturating_sub(pod.tracks.len());
+				if swapped {
+					swap_urls(&mut pod);
+				}
+				// Missing settings only lose the settings, not the history
+				if settings {
+					if let Err(err) = player.populate_settings(&mut pod) {
+						println!(
+							"{}",
+							tr(
+								"skipping-settings",
+								&[("title", &title), ("url", &url), ("error", &err)]
+							)
+						);
+					}
+				}
+				populated.push(pod)
+			}
+			Err(err) => {
+				println!(
+					"{}",
+					tr(
+						"skipping-podcast",
+						&[("title", &title), ("url", &url), ("error", &err)]
+					)
+				);
+				skipped.podcasts += 1;
+			}
+		}
+	}
+
+	(populated, skipped)
+}
+
+/// OPML feeds for the source player's subscriptions, or for the converted podcasts when it
+/// can not list them. Subscriptions kept without a URL take the one of the podcast with their
+/// title, those matching none are left out as OPML needs the URL. Errors reading the
+/// subscriptions are returned
+fn subscription_feeds(
+	subs: BoxResult<Vec<Subscription>>,
+	podcasts: &[Podcast],
+) -> BoxResult<Vec<opml::Feed>> {
+	let subs = match subs {
+		Ok(subs) => subs,
+		Err(err) => match err.downcast_ref::<Error>() {
+			Some(Error::UnsupportedOperation(_)) => {
+				podcasts.iter().map(Subscription::from).collect()
+			}
+			_ => return Err(err),
+		},
+	};
+
+	Ok(subs
 		.into_iter()
-		.map(|pod| {
-			println!("Populating '{}' ({})", pod.title, pod.url);
-			player.populate(pod)
+		.filter_map(|sub| {
+			let url = match sub.url {
+				Some(url) => url,
+				None => match podcasts
+					.iter()
+					.find(|pod| pod.title.to_lowercase() == sub.title.to_lowercase())
+				{
+					Some(pod) => pod.url.clone(),
+					None => {
+						warn(
+							Warning::PodcastLeftOut,
+							format!(
+								"Leaving '{}' out of the OPML file, its feed URL is not known",
+								sub.title
+							),
+						);
+						return None;
+					}
+				},
+			};
+			Some(opml::Feed {
+				title: sub.title,
+				url: url.to_string(),
+				disabled: false,
+			})
 		})
-		.collect()
+		.collect())
 }
 
 fn get_players(
@@ -210,11 +596,56 @@ fn get_players(
 }
 
 fn main() -> BoxResult<()> {
-	// Array of posible players
-	let players_args = [
+	let result = run();
+	summary::send(&result);
+	result
+}
+
+/// Every player the converter knows
+fn all_players_args() -> Vec<PlayerArgs> {
+	vec![
+		PlayerArgs::new::<player::AnnotatedFeed>(),
+		PlayerArgs::new::<player::AntennaPod>(),
+		PlayerArgs::new::<player::AntennaPodActions>(),
+		PlayerArgs::new::<player::Audiobookshelf>(),
 		PlayerArgs::new::<player::BeyondPod>(),
+		PlayerArgs::new::<player::Castbox>(),
+		PlayerArgs::new::<player::Castro>(),
+		PlayerArgs::new::<player::CPod>(),
+		PlayerArgs::new::<player::CsvFile>(),
+		PlayerArgs::new::<player::DoggCatcher>(),
+		PlayerArgs::new::<player::Downcast>(),
+		PlayerArgs::new::<player::Escapepod>(),
+		PlayerArgs::new::<player::GnomePodcasts>(),
+		PlayerArgs::new::<player::GooglePodcasts>(),
+		PlayerArgs::new::<player::Gpodder>(),
+		PlayerArgs::new::<player::GpodderActions>(),
+		PlayerArgs::new::<player::HistoryDb>(),
+		PlayerArgs::new::<player::HtmlReport>(),
+		PlayerArgs::new::<player::ITunes>(),
+		PlayerArgs::new::<player::Jellyfin>(),
+		PlayerArgs::new::<player::JsonFile>(),
+		PlayerArgs::new::<player::Kasts>(),
+		PlayerArgs::new::<player::Kodi>(),
+		PlayerArgs::new::<player::ListenLater>(),
+		PlayerArgs::new::<player::MarkdownSummary>(),
+		PlayerArgs::new::<player::MPD>(),
+		PlayerArgs::new::<player::Newsboat>(),
+		PlayerArgs::new::<player::NextcloudGpodder>(),
+		PlayerArgs::new::<player::PlayerFM>(),
+		PlayerArgs::new::<player::Plex>(),
+		PlayerArgs::new::<player::PodcastRepublic>(),
+		PlayerArgs::new::<player::Podcini>(),
+		PlayerArgs::new::<player::Podverse>(),
 		PlayerArgs::new::<player::PocketCasts>(),
-	];
+		PlayerArgs::new::<player::PocketCastsCloud>(),
+		PlayerArgs::new::<player::Spotify>(),
+		PlayerArgs::new::<player::Stitcher>(),
+	]
+}
+
+fn run() -> BoxResult<()> {
+	let players_args = all_players_args();
 
 	// Construct global cli
 	let mut app = clap::App::new("podcast_history_converter")
@@ -223,11 +654,356 @@ fn main() -> BoxResult<()> {
 				.long("opml")
 				.takes_value(true)
 				.value_name("FILE")
-				.help("OPML file containing all the feeds to convert")
-				.required(true),
+				.multiple(true)
+				.number_of_values(1)
+				.help("OPML file (or directory of OPML files) containing the feeds to convert, can be given more than once, defaults to the source player's subscriptions"),
+		)
+		.arg(
+			Arg::with_name("category")
+				.long("category")
+				.takes_value(true)
+				.value_name("NAME")
+				.multiple(true)
+				.number_of_values(1)
+				.requires("opml")
+				.help("Only convert the feeds in this OPML category, can be given more than once"),
+		)
+		.arg(
+			Arg::with_name("timestamp")
+				.long("timestamp")
+				.takes_value(true)
+				.value_name("UNIX_SECS")
+				.help("Use this time for every timestamp written instead of the current time"),
+		)
+		.arg(
+			Arg::with_name("deterministic")
+				.long("deterministic")
+				.help("Produce byte-identical output for identical input (fixes the timestamp to 2000-01-01T00:00:00 UTC unless --timestamp is given)"),
+		)
+		.arg(
+			Arg::with_name("trace-sql")
+				.long("trace-sql")
+				.global(true)
+				.help("Log every SQL statement run against the players' databases with its timing"),
+		)
+		.arg(
+			Arg::with_name("suppress")
+				.long("suppress")
+				.takes_value(true)
+				.value_name("WARN")
+				.multiple(true)
+				.number_of_values(1)
+				.possible_values(&warning::NAMES)
+				.global(true)
+				.help("Do not print this kind of warning"),
+		)
+		.arg(
+			Arg::with_name("deny")
+				.long("deny")
+				.takes_value(true)
+				.value_name("WARN")
+				.multiple(true)
+				.number_of_values(1)
+				.possible_values(&warning::NAMES)
+				.global(true)
+				.help("Fail the run if this kind of warning is raised"),
+		)
+		.arg(
+			Arg::with_name("lang")
+				.long("lang")
+				.takes_value(true)
+				.value_name("LANG")
+				.possible_values(&i18n::LANGUAGES)
+				.global(true)
+				.help("Language of the messages, defaults to the one set by LC_ALL, LC_MESSAGES or LANG"),
+		)
+		.arg(
+			Arg::with_name("profile")
+				.long("profile")
+				.takes_value(true)
+				.value_name("NAME")
+				.help("Take the arguments of this profile from the config file, those given on the command line win"),
+		)
+		.arg(
+			Arg::with_name("config")
+				.long("config")
+				.takes_value(true)
+				.value_name("FILE")
+				.requires("profile")
+				.help("Config file to read --profile from, defaults to podcast_history_converter/config.toml in the user's config directory"),
+		)
+		.arg(
+			Arg::with_name("feed-auth")
+				.long("feed-auth")
+				.takes_value(true)
+				.value_name("HOST=USER:PASSWORD")
+				.multiple(true)
+				.number_of_values(1)
+				.help("Fetch private feeds on HOST with this user and password, can be given more than once"),
+		)
+		.arg(
+			Arg::with_name("user-agent")
+				.long("user-agent")
+				.takes_value(true)
+				.value_name("UA")
+				.global(true)
+				.help("User-Agent header to fetch feeds with, instead of the tool's name and version"),
+		)
+		.arg(
+			Arg::with_name("proxy")
+				.long("proxy")
+				.takes_value(true)
+				.value_name("URL")
+				.global(true)
+				.help("Fetch feeds through this proxy (http://, https:// or socks5://), defaults to the one set by HTTP_PROXY or HTTPS_PROXY"),
+		)
+		.arg(
+			Arg::with_name("plain")
+				.long("plain")
+				.global(true)
+				.help("Print simple lines without indentation, alignment or symbols, for screen readers and log files"),
+		)
+		.arg(
+			Arg::with_name("version")
+				.long("version")
+				.short("V")
+				.help("Print the version, with --json also the supported players and features"),
+		)
+		.arg(
+			Arg::with_name("json")
+				.long("json")
+				.requires("version")
+				.help("Print --version as JSON for packagers and wrapper programs"),
+		)
+		.arg(
+			Arg::with_name("list-players")
+				.long("list-players")
+				.help("List the supported players and how they store the playing status"),
+		)
+		.arg(
+			Arg::with_name("rounding")
+				.long("rounding")
+				.takes_value(true)
+				.value_name("POLICY")
+				.possible_values(&player::ROUNDING_NAMES)
+				.default_value("nearest")
+				.help("How positions kept in milliseconds or fractions of a second are turned into seconds"),
+		)
+		.arg(
+			Arg::with_name("listen-later-status")
+				.long("listen-later-status")
+				.takes_value(true)
+				.value_name("STATUS")
+				.possible_values(&player::LISTEN_LATER_STATUS_NAMES)
+				.default_value("unplayed")
+				.help("Status of the episodes bookmarked in --listen-later, the other episodes are left as they are"),
+		)
+		.arg(
+			Arg::with_name("match-strategy")
+				.long("match-strategy")
+				.takes_value(true)
+				.value_name("STRATEGIES")
+				.use_delimiter(true)
+				.possible_values(&player::MATCH_STRATEGY_NAMES)
+				.help("How episodes are found in the players, each tried in turn until one finds the episode [default: guid,url,normalized-url,title-date]"),
+		)
+		.arg(
+			Arg::with_name("snap-to-chapters")
+				.long("snap-to-chapters")
+				.takes_value(true)
+				.value_name("SECS")
+				.help("Move positions within SECS seconds of a chapter start in the feed onto it"),
+		)
+		.arg(
+			Arg::with_name("heartbeat")
+				.long("heartbeat")
+				.takes_value(true)
+				.value_name("SECS")
+				.default_value("10")
+				.help("Print a line every SECS seconds while a feed is fetched or a player is saved, 0 for never"),
+		)
+		.arg(
+			Arg::with_name("cache-dir")
+				.long("cache-dir")
+				.takes_value(true)
+				.value_name("DIR")
+				.help("Keep fetched feeds in DIR, or with :memory: for this run only, and only download them again when they changed"),
+		)
+		.arg(
+			Arg::with_name("feeds-dir")
+				.long("feeds-dir")
+				.takes_value(true)
+				.value_name("DIR")
+				.help("Read every feed from the files in DIR instead of fetching it"),
+		)
+		.arg(
+			Arg::with_name("timeout")
+				.long("timeout")
+				.takes_value(true)
+				.value_name("SECS")
+				.default_value("30")
+				.help("Give up on a request to a feed after SECS seconds, 0 for never"),
+		)
+		.arg(
+			Arg::with_name("retries")
+				.long("retries")
+				.takes_value(true)
+				.value_name("N")
+				.default_value("2")
+				.help("Send a request that failed with a timeout, connection or server error again up to N times, waiting longer each time"),
+		)
+		.arg(
+			Arg::with_name("host-interval")
+				.long("host-interval")
+				.takes_value(true)
+				.value_name("MS")
+				.default_value("500")
+				.help("Start requests to the same host at least MS milliseconds apart"),
+		)
+		.arg(
+			Arg::with_name("jobs")
+				.long("jobs")
+				.short("j")
+				.takes_value(true)
+				.value_name("N")
+				.default_value("4")
+				.help("Fetch up to N feeds at once"),
+		)
+		.arg(
+			Arg::with_name("feed-pages")
+				.long("feed-pages")
+				.takes_value(true)
+				.value_name("N")
+				.default_value("50")
+				.help("Read up to N pages of feeds that split their episodes over pages linked with rel=\"next\""),
+		)
+		.arg(
+			Arg::with_name("resume")
+				.long("resume")
+				.takes_value(true)
+				.value_name("TOKEN")
+				.help("Finish the save a failed run left a partial output and this resume token for"),
+		)
+		.arg(
+			Arg::with_name("in-place")
+				.long("in-place")
+				.help("Allow an output to be the same file as an input, replacing it"),
+		)
+		.arg(
+			Arg::with_name("export-changes")
+				.long("export-changes")
+				.takes_value(true)
+				.value_name("FILE")
+				.conflicts_with_all(&["resume", "apply-subscription-diff"])
+				.help("Save what would be written to each output to FILE instead of writing it, for the apply subcommand"),
+		)
+		.arg(
+			Arg::with_name("email-summary")
+				.long("email-summary")
+				.takes_value(true)
+				.value_name("ADDRESS")
+				.help("Mail a summary of the run to ADDRESS once it ends, through --smtp-relay or else sendmail"),
+		)
+		.arg(
+			Arg::with_name("smtp-relay")
+				.long("smtp-relay")
+				.takes_value(true)
+				.value_name("HOST[:PORT]")
+				.requires("email-summary")
+				.help("SMTP server to send --email-summary through, without login or TLS"),
+		)
+		.arg(
+			Arg::with_name("email-from")
+				.long("email-from")
+				.takes_value(true)
+				.value_name("ADDRESS")
+				.requires("email-summary")
+				.help("Sender address of --email-summary"),
+		)
+		.arg(
+			Arg::with_name("convert-settings")
+				.long("convert-settings")
+				.help("Also carry per-podcast settings (auto archive, notifications, update interval, download count, playlist) over where both players have them"),
+		)
+		.arg(
+			Arg::with_name("out-opml")
+				.long("out-opml")
+				.takes_value(true)
+				.value_name("FILE")
+				.group("out")
+				.help("Write the source player's subscriptions to an OPML file, in the categories of the --opml file"),
+		)
+		.arg(
+			Arg::with_name("without-feeds")
+				.long("without-feeds")
+				.conflicts_with("opml")
+				.help("Convert the podcasts and episodes the source player has without fetching their feeds, for shows whose feed is gone"),
+		)
+		.arg(
+			Arg::with_name("podcast-index-key")
+				.long("podcast-index-key")
+				.takes_value(true)
+				.value_name("KEY")
+				.help("Look feeds that can not be fetched up on podcastindex.org with this API key, and fetch them from the URL it has"),
+		)
+		.arg(
+			Arg::with_name("podcast-index-secret")
+				.long("podcast-index-secret")
+				.takes_value(true)
+				.value_name("SECRET")
+				.requires("podcast-index-key")
+				.help("Secret of the --podcast-index-key, defaults to the PODCAST_INDEX_SECRET environment variable"),
+		)
+		.arg(
+			Arg::with_name("converted-opml")
+				.long("converted-opml")
+				.takes_value(true)
+				.value_name("FILE")
+				.group("out")
+				.help("After the run, write an OPML file with only the podcasts that had an episode played or in progress, to subscribe to in the destination player before restoring its backup"),
+		)
+		.arg(
+			Arg::with_name("updated-opml")
+				.long("updated-opml")
+				.takes_value(true)
+				.value_name("FILE")
+				.requires("opml")
+				.help("Write a copy of the --opml file with the URLs of feeds that permanently moved updated"),
+		)
+		.arg(
+			Arg::with_name("apply-subscription-diff")
+				.long("apply-subscription-diff")
+				.takes_value(true)
+				.value_name("SOURCE")
+				.possible_values(&["opml", "in"])
+				.help("Subscribe and unsubscribe each destination player to match the OPML file or the source player"),
+		)
+		.group(ArgGroup::with_name("in"))
+		.group(ArgGroup::with_name("out").multiple(true))
+		.arg(
+			Arg::with_name("auto")
+				.long("auto")
+				.takes_value(true)
+				.value_name("FILE")
+				.group("in")
+				.help("Convert from this save file, working out which player wrote it"),
 		)
-		.group(ArgGroup::with_name("in").required(true))
-		.group(ArgGroup::with_name("out").required(true).multiple(true));
+		.setting(AppSettings::SubcommandsNegateReqs);
+
+	let subcommands = vec![
+		apply::subcommand(),
+		check_feeds::subcommand(),
+		guid_audit::subcommand(),
+		subscriptions::subcommand(&players_args),
+		round_trip::subcommand(&players_args),
+		wizard::subcommand(),
+		scan::subcommand(),
+	];
+	let subcommand_names: Vec<String> = subcommands
+		.iter()
+		.map(|subcommand| subcommand.get_name().to_string())
+		.collect();
+	app = app.subcommands(subcommands);
 
 	// Add cli for each player
 	for player_args in players_args.iter() {
@@ -235,17 +1011,173 @@ fn main() -> BoxResult<()> {
 	}
 
 	// Parse cli args
-	let matches = app.get_matches();
+	let matches = app.get_matches_from(config::expand_profile(std::env::args_os().collect())?);
+
+	TRACE_SQL.store(global_flag(&matches, "trace-sql"), Ordering::Relaxed);
+	output::set_plain(global_flag(&matches, "plain"));
+
+	i18n::configure(global_values(&matches, "lang").pop());
+
+	warning::configure(
+		global_values(&matches, "suppress"),
+		global_values(&matches, "deny"),
+	);
+
+	if let Some(user_agent) = global_values(&matches, "user-agent").pop() {
+		fetch::set_user_agent(user_agent)?;
+	}
+	if let Some(proxy) = global_values(&matches, "proxy").pop() {
+		fetch::set_proxy(proxy)?;
+	}
+	if let Some(to) = matches.value_of("email-summary") {
+		summary::configure(
+			to,
+			matches.value_of("email-from"),
+			matches.value_of("smtp-relay"),
+		);
+	}
+
+	if matches.is_present("version") {
+		return version::print(&players_args, &subcommand_names, matches.is_present("json"));
+	}
+
+	if matches.is_present("list-players") {
+		for player_args in players_args.iter() {
+			player_args.describe();
+		}
+		return Ok(());
+	}
+
+	if let Some(apply_matches) = matches.subcommand_matches("apply") {
+		return apply::run(apply_matches, &players_args);
+	}
+
+	if let Some(check_matches) = matches.subcommand_matches("check-feeds") {
+		return check_feeds::run(check_matches);
+	}
+
+	if let Some(audit_matches) = matches.subcommand_matches("audit-guids") {
+		return guid_audit::run(audit_matches);
+	}
+
+	if let Some(sub_matches) = matches.subcommand_matches("compare-subscriptions") {
+		return subscriptions::run(sub_matches, &players_args);
+	}
+
+	if let Some(round_trip_matches) = matches.subcommand_matches("check-round-trip") {
+		return round_trip::run(round_trip_matches, &players_args);
+	}
+
+	if let Some(wizard_matches) = matches.subcommand_matches("wizard") {
+		return wizard::run(wizard_matches, &players_args);
+	}
+
+	if let Some(scan_matches) = matches.subcommand_matches("scan") {
+		return scan::run(scan_matches, &players_args);
+	}
+
+	// The in and out groups are only required when converting
+	if !matches.is_present("in") || !matches.is_present("out") {
+		clap::Error::with_description(
+			tr("error-missing-in-out", &[]).as_str(),
+			clap::ErrorKind::MissingRequiredArgument,
+		)
+		.exit();
+	}
+
+	let deterministic = matches.is_present("deterministic");
+
+	fetch::set_timeout(
+		matches
+			.value_of("timeout")
+			.expect("timeout has a default")
+			.parse()?,
+	);
+	fetch::set_host_interval(
+		matches
+			.value_of("host-interval")
+			.expect("host-interval has a default")
+			.parse()?,
+	);
+	fetch::set_retries(
+		matches
+			.value_of("retries")
+			.expect("retries has a default")
+			.parse()?,
+	);
+	for value in matches.values_of("feed-auth").into_iter().flatten() {
+		fetch::add_feed_auth(value)?;
+	}
+	if let Some(dir) = matches.value_of("cache-dir") {
+		fetch::set_cache_dir(dir)?;
+	}
+	if let Some(dir) = matches.value_of("feeds-dir") {
+		fetch::set_feeds_dir(dir)?;
+	}
+	podcast::set_jobs(
+		matches
+			.value_of("jobs")
+			.expect("jobs has a default")
+			.parse()?,
+	);
+	if let Some(key) = matches.value_of("podcast-index-key") {
+		podcast_index::set_credentials(key, matches.value_of("podcast-index-secret"))?;
+	}
+	podcast::set_max_pages(
+		matches
+			.value_of("feed-pages")
+			.expect("feed-pages has a default")
+			.parse()?,
+	);
+	heartbeat::set_interval(
+		matches
+			.value_of("heartbeat")
+			.expect("heartbeat has a default")
+			.parse()?,
+	);
+	player::set_rounding(
+		matches
+			.value_of("rounding")
+			.expect("rounding has a default"),
+	);
+	player::set_listen_later_status(
+		matches
+			.value_of("listen-later-status")
+			.expect("listen-later-status has a default"),
+	);
+	if let Some(strategies) = matches.values_of("match-strategy") {
+		player::set_match_strategies(strategies);
+	}
+
+	let clock = match matches.value_of("timestamp") {
+		Some(secs) => Clock::fixed(secs.parse()?),
+		None if deterministic => Clock::fixed(clock::DETERMINISTIC_SECS),
+		None => Clock::system()?,
+	};
 
 	// Initialise all the players needed for the given args
 	let mut players = get_players(&matches, &players_args)?;
 
 	// Get cli name of the source player
-	let in_player = players_args
-		.iter()
-		.find(|player_args| matches.is_present(player_args.in_name.as_str()))
-		.map(|player_args| player_args.cli_name)
-		.expect("input player not found in args list");
+	let in_player = match matches.value_of("auto") {
+		Some(path) => {
+			let player_args = detect::detect(path, &players_args)?;
+			if players.contains_key(player_args.cli_name) {
+				clap::Error::with_description(
+					tr("error-auto-conflict", &[("player", &player_args.cli_name)]).as_str(),
+					clap::ErrorKind::ArgumentConflict,
+				)
+				.exit();
+			}
+			players.insert(player_args.cli_name, (player_args.factory)(path)?);
+			player_args.cli_name
+		}
+		None => players_args
+			.iter()
+			.find(|player_args| matches.is_present(player_args.in_name.as_str()))
+			.map(|player_args| player_args.cli_name)
+			.expect("input player not found in args list"),
+	};
 
 	// Get (cli name of destination player, output file path) pairs for the given args
 	let outputs: Vec<(&'static str, &'_ str)> = players_args
@@ -257,33 +1189,446 @@ fn main() -> BoxResult<()> {
 		})
 		.collect();
 
-	// Parse the given OPML file and pull podcast data
-	let podcasts = podcast::from_opml(matches.value_of("opml").expect("no opml file"))?;
+	// (cli name of the player reading it, path) for every file that is read
+	let inputs: Vec<(&'static str, &'_ str)> = players_args
+		.iter()
+		.filter(|player_args| !player_args.write_only)
+		.filter_map(|player_args| {
+			matches
+				.value_of(player_args.cli_name)
+				.map(|path| (player_args.cli_name, path))
+		})
+		.chain(matches.value_of("auto").map(|path| (in_player, path)))
+		.chain(
+			matches
+				.values_of("opml")
+				.into_iter()
+				.flatten()
+				.map(|path| ("opml", path)),
+		)
+		.collect();
+
+	// Replacing a file that is also read loses it if the run goes wrong, so ask for it, unless
+	// it is a file that is written back to itself
+	if !matches.is_present("in-place") {
+		for (output_player, output) in outputs.iter() {
+			let writes_through = players_args.iter().any(|player_args| {
+				player_args.cli_name == *output_player && player_args.writes_through
+			});
+			if let Some((_, input)) = inputs.iter().find(|(input_player, input)| {
+				!(writes_through && input_player == output_player) && same_file(output, input)
+			}) {
+				return Err(Error::SameFile(output.to_string(), input.to_string()).into());
+			}
+		}
+	}
 
-	// Populate empty track data from the source player
-	let podcasts = populate(
-		players.get_mut(in_player).expect("input player not found"),
-		podcasts,
+	// Held until the run ends, so an overlapping run can not write to the same files
+	let _locks = lock::lock_all(
+		&inputs.iter().map(|(_, path)| *path).collect::<Vec<_>>(),
+		&outputs.iter().map(|(_, path)| *path).collect::<Vec<_>>(),
 	)?;
 
+	let convert_settings = matches.is_present("convert-settings");
+
+	let (mut podcasts, mut skipped) = match matches.values_of("opml") {
+		Some(opml) => {
+			skip::listen();
+
+			// Parse the given OPML file and pull podcast data
+			let (podcasts, failed) =
+				podcast::from_opml(opml, matches.values_of("category").map(|c| c.collect()))?;
+
+			// Populate empty track data from the source player
+			let player = players.get_mut(in_player).expect("input player not found");
+			let (mut podcasts, skipped) = populate(player, podcasts, convert_settings);
+			podcasts.extend(dead_feeds(player.as_ref(), failed));
+			(podcasts, skipped)
+		}
+		// Feed-less, from what the source player keeps of each podcast
+		None if matches.is_present("without-feeds") => {
+			let subs: Vec<Subscription> = players
+				.iter()
+				.filter(|(name, _)| **name != in_player)
+				.filter_map(|(_, other)| other.subscriptions().ok())
+				.flatten()
+				.collect();
+			let local = players
+				.get(in_player)
+				.expect("input player not found")
+				.local_podcasts()?;
+			(
+				player::podcasts_with_urls(local, &subs),
+				SkipCounts::default(),
+			)
+		}
+		None => {
+			let player = players.get_mut(in_player).expect("input player not found");
+			match player.stored_podcasts()? {
+				// Save files that hold the whole feed need not fetch it again
+				Some(mut podcasts) => {
+					if !convert_settings {
+						for podcast in podcasts.iter_mut() {
+							podcast.settings = PodcastSettings::default();
+						}
+					}
+					(podcasts, SkipCounts::default())
+				}
+				// Otherwise the feeds the player is subscribed to are fetched
+				None => {
+					skip::listen();
+					let (podcasts, failed) = podcast::from_subscriptions(player.subscriptions()?)?;
+					let (mut podcasts, skipped) = populate(player, podcasts, convert_settings);
+					podcasts.extend(dead_feeds(player.as_ref(), failed));
+					(podcasts, skipped)
+				}
+			}
+		}
+	};
+
+	if let Some(tolerance) = matches.value_of("snap-to-chapters") {
+		let tolerance: i32 = tolerance.parse()?;
+		for podcast in podcasts.iter_mut() {
+			for track in podcast.tracks.iter_mut() {
+				track.snap_to_chapter(tolerance);
+			}
+		}
+	}
+
+	for podcast in podcasts.iter() {
+		for track in podcast.tracks.iter() {
+			// Positions read as milliseconds instead of seconds end up far past the end
+			if let Some(duration) = track.duration {
+				if duration > 0 && track.progress > duration * 2 {
+					warn(
+						Warning::UnitMismatch,
+						tr(
+							"warning-unit-mismatch",
+							&[
+								("url", &track.url),
+								("progress", &track.progress),
+								("duration", &duration),
+							],
+						),
+					);
+				}
+			}
+		}
+	}
+
+	// Stop before writing anything if a denied warning came up while reading
+	warning::check_denied()?;
+
+	if deterministic {
+		podcasts.sort_by(|a, b| a.url.as_str().cmp(b.url.as_str()));
+		for podcast in podcasts.iter_mut() {
+			podcast.tracks.sort_by(|a, b| a.guid.cmp(&b.guid));
+		}
+	}
+
+	let save_options = SaveOptions {
+		clock,
+		deterministic,
+		provenance: Provenance::new(
+			in_player,
+			clock.unix_secs(),
+			podcasts.len(),
+			podcasts.iter().map(|pod| pod.tracks.len()).sum(),
+		),
+	};
+	summary::note(tr(
+		"summary-read",
+		&[
+			("player", &in_player),
+			("podcasts", &save_options.provenance.podcasts),
+			("tracks", &save_options.provenance.tracks),
+		],
+	));
+	let totals = player::Totals::of(podcasts.iter().flat_map(|pod| pod.tracks.iter()));
+	let time = tr(
+		"listening-time",
+		&[
+			("listened", &totals.listened_hours()),
+			("completion", &totals.completion()),
+			("backlog", &totals.backlog_hours()),
+			("episodes", &(totals.playing + totals.unplayed())),
+		],
+	);
+	println!("{}", time);
+	summary::note(time);
+
+	// Subscriptions each destination player should end up with
+	let wanted_subs = match matches.value_of("apply-subscription-diff") {
+		Some("in") => Some(
+			players
+				.get(in_player)
+				.expect("input player not found")
+				.subscriptions()?,
+		),
+		Some(_) => Some(podcasts.iter().map(Subscription::from).collect()),
+		None => None,
+	};
+
+	if let Some(path) = matches.value_of("out-opml") {
+		println!("{}", tr("saving-to", &[("player", &"OPML")]));
+		let known = match matches.values_of("opml") {
+			Some(opml) => opml::read_all(opml)?,
+			None => Vec::new(),
+		};
+		let subs = players
+			.get(in_player)
+			.expect("input player not found")
+			.subscriptions();
+		let mut out_file = std::fs::File::create(path)?;
+		opml::write(
+			&mut out_file,
+			&opml::categorise(subscription_feeds(subs, &podcasts)?, &known, "Podcasts"),
+		)?;
+		out_file.sync_all()?;
+	}
+
+	if let Some(path) = matches.value_of("updated-opml") {
+		let mut categories = opml::read_all(matches.values_of("opml").expect("no opml file"))?;
+		for feed in categories
+			.iter_mut()
+			.flat_map(|category| category.feeds.iter_mut())
+		{
+			let key = url_key::url_key(&feed.url);
+			if let Some(pod) = podcasts.iter().find(|pod| {
+				pod.previous_url
+					.as_ref()
+					.map_or(false, |previous| url_key::url_key(previous.as_str()) == key)
+			}) {
+				feed.url = pod.url.to_string();
+			}
+		}
+		let mut out_file = std::fs::File::create(path)?;
+		opml::write(&mut out_file, &categories)?;
+		out_file.sync_all()?;
+	}
+
+	// A resumed run only finishes the one save, starting from its partial output
+	let resume = match matches.value_of("resume") {
+		Some(token) => Some(resume::ResumeToken::load(token)?),
+		None => None,
+	};
+	let outputs: Vec<(&'static str, &str)> = match &resume {
+		Some(token) => {
+			let outputs: Vec<_> = outputs
+				.into_iter()
+				.filter(|&(player, path)| player == token.player && path == token.output)
+				.collect();
+			if outputs.is_empty() {
+				clap::Error::with_description(
+					tr(
+						"error-resume-output",
+						&[("player", &token.player), ("path", &token.output)],
+					)
+					.as_str(),
+					clap::ErrorKind::MissingRequiredArgument,
+				)
+				.exit();
+			}
+			outputs
+		}
+		None => outputs,
+	};
+
+	// With --export-changes the change sets are collected and nothing is written to the outputs
+	let mut exported = match matches.value_of("export-changes") {
+		Some(_) => Some(apply::ChangesFile::new(
+			in_player,
+			&save_options,
+			matches.is_present("timestamp") || deterministic,
+		)),
+		None => None,
+	};
+
 	// Loop through the output pairs
 	for (player, path) in outputs.into_iter() {
-		println!("Saving to '{}'", player);
+		println!("{}", tr("saving-to", &[("player", &player)]));
+
+		let player_args = players_args
+			.iter()
+			.find(|player_args| player_args.cli_name == player)
+			.expect("output player not found in args list");
+		let source = match &resume {
+			Some(token) => token.partial.clone(),
+			None => matches
+				.value_of(player)
+				.expect("output player has no save file")
+				.to_string(),
+		};
 
 		// Remove player from map
-		let p = players.remove(player).expect("input player not found");
+		let mut p = match &resume {
+			Some(_) => (player_args.factory)(&source)?,
+			None => players.remove(player).expect("input player not found"),
+		};
 
-		// Open output file
-		let mut out_file = std::fs::OpenOptions::new()
-			.write(true)
-			.create(true)
-			.truncate(true)
-			.open(path)?;
+		if let Some(wanted) = &wanted_subs {
+			subscriptions::apply_diff(p.as_mut(), wanted)?;
+		}
+
+		// Work out what will be written before touching the output
+		// Skip podcasts that were just unsubscribed from, or saved before a resumed run
+		let subscribed = subscribed_keys(p.as_ref(), &podcasts);
+		let to_save: Vec<Podcast> = podcasts
+			.iter()
+			.filter(|pod| {
+				wanted_subs.as_ref().map_or(true, |wanted| {
+					subscriptions::contains(wanted, &Subscription::from(*pod))
+				})
+			})
+			.filter(|pod| {
+				resume
+					.as_ref()
+					.map_or(true, |token| !token.is_completed(pod))
+			})
+			.map(|pod| {
+				// Written under the URL the player knows a moved feed by
+				let mut pod = pod.clone();
+				if knows_previous_url(&subscribed, &pod) {
+					swap_urls(&mut pod);
+				}
+				pod
+			})
+			.collect();
+		let changes = p.compute_changes(&mut to_save.iter())?;
+		let computed = tr(
+			"changes-computed",
+			&[
+				("player", &player),
+				("podcasts", &changes.podcasts.len()),
+				("updates", &changes.updates().count()),
+			],
+		);
+		println!("{}", computed);
+		summary::note(computed);
+		if let Some(exported) = exported.as_mut() {
+			exported.add(player, &source, path, &changes, p.writes_whole_podcasts());
+			continue;
+		}
 
-		// Write podcast data to the output file
-		p.save(&mut podcasts.iter(), &mut out_file)?;
+		// Write to a file beside the output that only replaces it once complete
+		let mut out_file = temp_file_beside(path)?;
+		let stores_provenance = p.stores_provenance();
+		let heartbeat = Heartbeat::start(tr("operation-save", &[("player", &player)]));
+		let saved = p.apply_changes(&changes, out_file.as_file_mut(), &save_options);
+		drop(heartbeat);
+
+		let stats = match saved {
+			Ok(stats) => stats,
+			Err(err) => {
+				if let Err(partial_err) = resume::save_partial(
+					player_args,
+					&source,
+					path,
+					changes.applied(),
+					resume.as_ref(),
+					&save_options,
+				) {
+					println!("{}", tr("partial-failed", &[("error", &partial_err)]));
+				}
+				return Err(err);
+			}
+		};
+		skipped.tracks += stats.skipped;
+		out_file.as_file().sync_all()?;
+		out_file.persist(path)?;
+
+		if !stores_provenance {
+			save_options.provenance.write_sidecar(path)?;
+		}
+		summary::note(tr("summary-saved", &[("player", &player), ("path", &path)]));
+	}
+
+	if let Some(exported) = &exported {
+		exported.write(matches.value_of("export-changes").expect("no changes file"))?;
+	}
+
+	if let Some(path) = matches.value_of("converted-opml") {
+		let known = match matches.values_of("opml") {
+			Some(opml) => opml::read_all(opml)?,
+			None => Vec::new(),
+		};
+		let feeds: Vec<opml::Feed> = podcasts
+			.iter()
+			.filter(|pod| pod.tracks.iter().any(Track::has_history))
+			.map(|pod| opml::Feed {
+				title: pod.title.clone(),
+				url: pod.url.to_string(),
+				disabled: false,
+			})
+			.collect();
+		let written = tr(
+			"converted-opml-written",
+			&[("podcasts", &feeds.len()), ("path", &path)],
+		);
+		let mut out_file = std::fs::File::create(path)?;
+		opml::write(&mut out_file, &opml::categorise(feeds, &known, "Podcasts"))?;
 		out_file.sync_all()?;
+		println!("{}", written);
+		summary::note(written);
+	}
+
+	warning::check_denied()?;
+
+	if skipped.podcasts > 0 || skipped.tracks > 0 {
+		let skipped = tr(
+			"skipped-summary",
+			&[("podcasts", &skipped.podcasts), ("tracks", &skipped.tracks)],
+		);
+		println!("{}", skipped);
+		summary::note(skipped);
 	}
 
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use podcast::PlayingStatus;
+
+	const STATUSES: [PlayingStatus; 3] = [
+		PlayingStatus::Unplayed,
+		PlayingStatus::Playing,
+		PlayingStatus::Played,
+	];
+
+	#[test]
+	fn status_encodings_encode_and_decode() {
+		for player_args in all_players_args().iter() {
+			for encoding in player_args.status_encodings.iter() {
+				let context = format!("{} {}", player_args.name, encoding.field);
+
+				for &status in STATUSES.iter() {
+					let value = encoding.encode(status);
+					let decoded = encoding.decode(value).expect(&context);
+					// Players that keep no in progress status store it as unplayed
+					let shared = STATUSES
+						.iter()
+						.any(|&other| other != status && encoding.encode(other) == value);
+					if shared {
+						assert_ne!(status, PlayingStatus::Played, "{}", context);
+						assert_eq!(encoding.encode(decoded), value, "{}", context);
+					} else {
+						assert_eq!(decoded, status, "{}", context);
+					}
+				}
+
+				// Values only ever read, such as older ones, decode to a status
+				for &(status, value) in encoding.values.iter() {
+					let decoded = encoding.decode(value).expect(&context);
+					assert!(
+						decoded == status || encoding.encode(decoded) == value,
+						"{}",
+						context
+					);
+				}
+				assert!(encoding.decode(i64::min_value()).is_err(), "{}", context);
+			}
+		}
+	}
+}
@@ -0,0 +1,195 @@
+use crate::url_key::url_key;
+use crate::{BoxResult, Error};
+
+use std::collections::HashSet;
+use std::io;
+use std::path::Path;
+
+use roxmltree::Node;
+
+pub fn find_child<'a>(node: Node<'a, 'a>, child: &'static str) -> Result<Node<'a, 'a>, Error> {
+	node.children()
+		.find(|n| n.is_element() && n.tag_name().name() == child)
+		.ok_or(Error::MissingXMLNode(child))
+}
+
+fn outlines<'a>(node: Node<'a, 'a>) -> impl Iterator<Item = Node<'a, 'a>> {
+	node.children()
+		.filter(|n| n.is_element() && n.tag_name().name() == "outline")
+}
+
+#[derive(Debug, Clone)]
+pub struct Feed {
+	pub title: String,
+	pub url: String,
+	/// Written out inside an XML comment so the feed is kept for reference but not imported
+	pub disabled: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Category {
+	pub title: String,
+	pub feeds: Vec<Feed>,
+}
+
+pub fn read<P: AsRef<Path>>(path: P) -> BoxResult<Vec<Category>> {
+	let opml_str = std::fs::read_to_string(path)?;
+	let doc = roxmltree::Document::parse(opml_str.as_str())?;
+
+	let categories = outlines(find_child(doc.root_element(), "body")?) // all category nodes
+		.map(|category| Category {
+			title: category
+				.attribute("text")
+				.or_else(|| category.attribute("title"))
+				.unwrap_or("")
+				.into(),
+			feeds: outlines(category) // all feed nodes in this category
+				.filter_map(|feed| {
+					Some(Feed {
+						title: feed.attribute("text")?.into(),
+						url: feed.attribute("xmlUrl")?.into(),
+						disabled: false,
+					})
+				})
+				.collect(),
+		})
+		.collect();
+
+	Ok(categories)
+}
+
+fn read_path(path: &Path) -> BoxResult<Vec<Category>> {
+	if !path.is_dir() {
+		return read(path);
+	}
+
+	// Every OPML file directly inside the directory, in name order
+	let mut files = std::fs::read_dir(path)?
+		.map(|entry| entry.map(|entry| entry.path()))
+		.collect::<io::Result<Vec<_>>>()?;
+	files.retain(|file| {
+		file.is_file()
+			&& file
+				.extension()
+				.map_or(false, |ext| ext == "opml" || ext == "xml")
+	});
+	files.sort();
+
+	let mut categories = Vec::new();
+	for file in files.iter() {
+		categories.append(&mut read(file)?);
+	}
+	Ok(categories)
+}
+
+/// Read several OPML files (or directories of them), merging categories with the same
+/// title and dropping feeds that were already seen
+pub fn read_all<'a, I: IntoIterator<Item = &'a str>>(paths: I) -> BoxResult<Vec<Category>> {
+	let mut merged: Vec<Category> = Vec::new();
+	let mut seen = HashSet::new();
+
+	for path in paths {
+		for category in read_path(Path::new(path))?.into_iter() {
+			let feeds: Vec<Feed> = category
+				.feeds
+				.into_iter()
+				.filter(|feed| seen.insert(url_key(feed.url.as_str())))
+				.collect();
+
+			match merged.iter_mut().find(|c| c.title == category.title) {
+				Some(existing) => existing.feeds.extend(feeds),
+				None => merged.push(Category {
+					title: category.title,
+					feeds,
+				}),
+			}
+		}
+	}
+
+	Ok(merged)
+}
+
+/// Group feeds under the category they are in within `known`, the rest under `default`,
+/// keeping the order of `known`
+pub fn categorise(feeds: Vec<Feed>, known: &[Category], default: &str) -> Vec<Category> {
+	let mut categories: Vec<Category> = known
+		.iter()
+		.map(|category| Category {
+			title: category.title.clone(),
+			feeds: Vec::new(),
+		})
+		.collect();
+	let mut others = Vec::new();
+
+	for feed in feeds.into_iter() {
+		let key = url_key(feed.url.as_str());
+		match known.iter().position(|category| {
+			category
+				.feeds
+				.iter()
+				.any(|known| url_key(known.url.as_str()) == key)
+		}) {
+			Some(i) => categories[i].feeds.push(feed),
+			None => others.push(feed),
+		}
+	}
+
+	if !others.is_empty() {
+		match categories
+			.iter_mut()
+			.find(|category| category.title == default)
+		{
+			Some(category) => category.feeds.extend(others),
+			None => categories.push(Category {
+				title: default.into(),
+				feeds: others,
+			}),
+		}
+	}
+
+	categories.retain(|category| !category.feeds.is_empty());
+	categories
+}
+
+pub fn escape(s: &str) -> String {
+	s.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+		.replace('"', "&quot;")
+}
+
+pub fn write<W: io::Write>(w: &mut W, categories: &[Category]) -> io::Result<()> {
+	writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+	writeln!(w, r#"<opml version="1.0">"#)?;
+	writeln!(w, "\t<head>")?;
+	writeln!(w, "\t\t<title>podcast_history_converter</title>")?;
+	writeln!(w, "\t</head>")?;
+	writeln!(w, "\t<body>")?;
+
+	for category in categories {
+		let title = escape(&category.title);
+		writeln!(w, "\t\t<outline text=\"{}\" title=\"{}\">", title, title)?;
+
+		for feed in category.feeds.iter() {
+			let title = escape(&feed.title);
+			let outline = format!(
+				"<outline text=\"{}\" title=\"{}\" type=\"rss\" xmlUrl=\"{}\"/>",
+				title,
+				title,
+				escape(&feed.url)
+			);
+
+			if feed.disabled {
+				// "--" is not allowed inside XML comments
+				writeln!(w, "\t\t\t<!-- {} -->", outline.replace("--", "- -"))?;
+			} else {
+				writeln!(w, "\t\t\t{}", outline)?;
+			}
+		}
+
+		writeln!(w, "\t\t</outline>")?;
+	}
+
+	writeln!(w, "\t</body>")?;
+	writeln!(w, "</opml>")
+}
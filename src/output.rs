@@ -0,0 +1,13 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Set by --plain, checked wherever output is indented, aligned or grouped under a heading
+static PLAIN: AtomicBool = AtomicBool::new(false);
+
+pub fn set_plain(plain: bool) {
+	PLAIN.store(plain, Ordering::Relaxed);
+}
+
+/// Whether every line should stand on its own, for screen readers and log files
+pub fn plain() -> bool {
+	PLAIN.load(Ordering::Relaxed)
+}
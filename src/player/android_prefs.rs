@@ -0,0 +1,118 @@
+use crate::opml::escape;
+use crate::BoxResult;
+
+use std::io;
+
+use roxmltree::Node;
+
+/// One `<boolean name=".." value=".."/>`, `<string name="..">..</string>` or similar entry
+struct Entry {
+	tag: String,
+	name: String,
+	value: Option<String>,
+	text: Option<String>,
+	/// Items of a `<set>`
+	items: Vec<String>,
+}
+
+impl Entry {
+	fn from_node(node: Node) -> Option<Self> {
+		Some(Self {
+			tag: node.tag_name().name().into(),
+			name: node.attribute("name")?.into(),
+			value: node.attribute("value").map(String::from),
+			text: node
+				.text()
+				.map(String::from)
+				.filter(|text| !text.trim().is_empty()),
+			items: node
+				.children()
+				.filter(|n| n.is_element())
+				.map(|n| n.text().unwrap_or("").into())
+				.collect(),
+		})
+	}
+}
+
+/// An Android shared preferences file, as apps keep their settings in
+pub struct Preferences {
+	entries: Vec<Entry>,
+}
+
+impl Preferences {
+	pub fn parse(text: &str) -> BoxResult<Self> {
+		let doc = roxmltree::Document::parse(text)?;
+		Ok(Self {
+			entries: doc
+				.root_element()
+				.children()
+				.filter(|n| n.is_element())
+				.filter_map(Entry::from_node)
+				.collect(),
+		})
+	}
+
+	pub fn get_bool(&self, name: &str) -> Option<bool> {
+		self.entries
+			.iter()
+			.find(|entry| entry.tag == "boolean" && entry.name == name)
+			.and_then(|entry| entry.value.as_ref())
+			.map(|value| value == "true")
+	}
+
+	/// Change the entry or add it at the end
+	pub fn set_bool(&mut self, name: &str, value: bool) {
+		let value = Some(value.to_string());
+		match self.entries.iter_mut().find(|entry| entry.name == name) {
+			Some(entry) => {
+				entry.tag = String::from("boolean");
+				entry.value = value;
+			}
+			None => self.entries.push(Entry {
+				tag: String::from("boolean"),
+				name: name.into(),
+				value,
+				text: None,
+				items: Vec::new(),
+			}),
+		}
+	}
+
+	/// Write the file the way Android does, keeping the order of the entries
+	pub fn write<W: io::Write>(&self, w: &mut W) -> io::Result<()> {
+		writeln!(
+			w,
+			"<?xml version='1.0' encoding='utf-8' standalone='yes' ?>"
+		)?;
+		writeln!(w, "<map>")?;
+		for entry in self.entries.iter() {
+			let name = escape(&entry.name);
+			match (&entry.value, &entry.text) {
+				(Some(value), _) => writeln!(
+					w,
+					"    <{} name=\"{}\" value=\"{}\" />",
+					entry.tag,
+					name,
+					escape(value)
+				)?,
+				(None, Some(text)) => writeln!(
+					w,
+					"    <{} name=\"{}\">{}</{}>",
+					entry.tag,
+					name,
+					escape(text),
+					entry.tag
+				)?,
+				(None, None) if !entry.items.is_empty() => {
+					writeln!(w, "    <{} name=\"{}\">", entry.tag, name)?;
+					for item in entry.items.iter() {
+						writeln!(w, "        <string>{}</string>", escape(item))?;
+					}
+					writeln!(w, "    </{}>", entry.tag)?;
+				}
+				(None, None) => writeln!(w, "    <{} name=\"{}\" />", entry.tag, name)?,
+			}
+		}
+		writeln!(w, "</map>")
+	}
+}
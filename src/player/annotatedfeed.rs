@@ -0,0 +1,248 @@
+use crate::fetch;
+use crate::opml::find_child;
+use crate::player::{NewPlayer, Player, Subscription};
+use crate::podcast::{PlayingStatus, Podcast, Track};
+use crate::url_key::url_key;
+use crate::warning::{warn, Warning};
+use crate::BoxResult;
+
+use std::path::Path;
+
+use reqwest::Url;
+use roxmltree::Node;
+
+// Some self-hosted tools track what has been listened to in the feeds they
+// serve, marking each item with its state. There is no standard for this, so
+// any element of an item whose local name is one of STATUS_NAMES gives the
+// state (as its text or `state` attribute, e.g. `<media:status state="...">`)
+// and one of POSITION_NAMES the position in seconds or [[hh:]mm:]ss, whatever
+// the namespace. Items without either are unplayed.
+
+const STATUS_NAMES: [&str; 4] = ["status", "playstate", "played", "listened"];
+const POSITION_NAMES: [&str; 3] = ["position", "progress", "playposition"];
+
+fn parse_status(value: &str) -> Option<PlayingStatus> {
+	match value.trim().to_lowercase().as_str() {
+		"played" | "listened" | "complete" | "completed" | "finished" | "true" | "yes" | "1" => {
+			Some(PlayingStatus::Played)
+		}
+		"playing" | "in-progress" | "inprogress" | "started" | "partial" => {
+			Some(PlayingStatus::Playing)
+		}
+		"unplayed" | "new" | "false" | "no" | "0" => Some(PlayingStatus::Unplayed),
+		_ => None,
+	}
+}
+
+fn parse_position(value: &str) -> Option<i32> {
+	let value = value.trim();
+	Track::duration_from_str(value.split('.').next().unwrap_or(value))
+}
+
+fn child_named<'a>(item: Node<'a, 'a>, names: &[&str]) -> Option<Node<'a, 'a>> {
+	item.children()
+		.find(|n| n.is_element() && names.contains(&n.tag_name().name().to_lowercase().as_str()))
+}
+
+fn child_text<'a>(node: Node<'a, 'a>, name: &str) -> Option<&'a str> {
+	node.children()
+		.find(|n| n.is_element() && n.tag_name().name() == name)
+		.and_then(|n| n.text())
+}
+
+struct Item {
+	guid: Option<String>,
+	url: Option<String>,
+	status: PlayingStatus,
+	progress: i32,
+}
+
+impl Item {
+	fn from_node(item: Node) -> Self {
+		let status = child_named(item, &STATUS_NAMES)
+			.and_then(|n| n.attribute("state").or_else(|| n.text()))
+			.and_then(parse_status);
+		let progress = child_named(item, &POSITION_NAMES)
+			.and_then(|n| n.text())
+			.and_then(parse_position)
+			.unwrap_or(0);
+
+		Self {
+			guid: child_text(item, "guid").map(String::from),
+			url: item
+				.children()
+				.find(|n| n.is_element() && n.tag_name().name() == "enclosure")
+				.and_then(|n| n.attribute("url"))
+				.map(String::from),
+			// A position without a state means the item was started
+			status: status.unwrap_or(if progress > 0 {
+				PlayingStatus::Playing
+			} else {
+				PlayingStatus::Unplayed
+			}),
+			progress,
+		}
+	}
+
+	fn is_track(&self, track: &Track) -> bool {
+		self.guid.as_ref() == Some(&track.guid)
+			|| self.url.as_ref().map_or(false, |url| track.has_url(url))
+	}
+}
+
+struct Channel {
+	/// Where the feed says it lives, or the URL it was fetched from
+	url: Option<Url>,
+	title: String,
+	items: Vec<Item>,
+}
+
+impl Channel {
+	fn parse(text: &str, fetched_from: Option<&Url>) -> BoxResult<Self> {
+		let doc = roxmltree::Document::parse(text)?;
+		let channel = find_child(doc.root_element(), "channel")?;
+
+		let self_link = channel
+			.children()
+			.find(|n| {
+				n.is_element()
+					&& n.tag_name().name() == "link"
+					&& n.attribute("rel") == Some("self")
+			})
+			.and_then(|n| n.attribute("href"))
+			.and_then(|href| Url::parse(href).ok());
+
+		Ok(Self {
+			url: self_link.or_else(|| fetched_from.cloned()),
+			title: child_text(channel, "title").unwrap_or("").trim().into(),
+			items: channel
+				.children()
+				.filter(|n| n.is_element() && n.tag_name().name() == "item")
+				.map(Item::from_node)
+				.collect(),
+		})
+	}
+
+	/// By feed URL, or by title for a feed file that does not name its URL
+	fn is_podcast(&self, podcast: &Podcast) -> bool {
+		match &self.url {
+			Some(url) => url_key(url.as_str()) == url_key(podcast.url.as_str()),
+			None => self.title.to_lowercase() == podcast.title.trim().to_lowercase(),
+		}
+	}
+}
+
+/// Fetch a private feed, credentials in the URL are sent as basic auth
+fn fetch(value: &str) -> BoxResult<Channel> {
+	let (url, credentials) = fetch::split_credentials(&Url::parse(value)?);
+
+	let mut request = fetch::client()?.get(url.clone());
+	if let Some((user, password)) = credentials {
+		request = request.basic_auth(user, password);
+	}
+	let text = request.send()?.error_for_status()?.text()?;
+	Channel::parse(&text, Some(&url))
+}
+
+fn read_path(path: &Path) -> BoxResult<Vec<Channel>> {
+	if !path.is_dir() {
+		return Ok(vec![Channel::parse(&std::fs::read_to_string(path)?, None)?]);
+	}
+
+	// Every feed file directly inside the directory, in name order
+	let mut files = std::fs::read_dir(path)?
+		.map(|entry| entry.map(|entry| entry.path()))
+		.collect::<std::io::Result<Vec<_>>>()?;
+	files.retain(|file| {
+		file.is_file()
+			&& file
+				.extension()
+				.map_or(false, |ext| ext == "xml" || ext == "rss")
+	});
+	files.sort();
+
+	files
+		.iter()
+		.map(|file| Channel::parse(&std::fs::read_to_string(file)?, None))
+		.collect()
+}
+
+pub struct AnnotatedFeed {
+	channels: Vec<Channel>,
+}
+
+impl Player for AnnotatedFeed {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		let channel = match self
+			.channels
+			.iter()
+			.find(|channel| channel.is_podcast(&podcast))
+		{
+			Some(channel) => channel,
+			None => {
+				warn(
+					Warning::UnmatchedTrack,
+					format!("Podcast not found: {}", fetch::redact(&podcast.url)),
+				);
+				return Ok(podcast);
+			}
+		};
+
+		for track in podcast.tracks.iter_mut() {
+			match channel.items.iter().find(|item| item.is_track(track)) {
+				Some(item) => {
+					track.playing_status = item.status;
+					track.progress = item.progress;
+				}
+				None => warn(
+					Warning::UnmatchedTrack,
+					format!("Track not found: {:?}", track),
+				),
+			}
+		}
+
+		Ok(podcast)
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		Ok(self
+			.channels
+			.iter()
+			.map(|channel| Subscription {
+				url: channel.url.clone(),
+				title: channel.title.clone(),
+			})
+			.collect())
+	}
+
+	fn episode_count(&self) -> BoxResult<Option<usize>> {
+		Ok(Some(
+			self.channels
+				.iter()
+				.map(|channel| channel.items.len())
+				.sum(),
+		))
+	}
+}
+
+impl NewPlayer for AnnotatedFeed {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		let channels = if path.starts_with("http://") || path.starts_with("https://") {
+			vec![fetch(path)?]
+		} else {
+			read_path(Path::new(path))?
+		};
+
+		Ok(Box::new(Self { channels }))
+	}
+
+	fn name() -> &'static str {
+		"Annotated feed"
+	}
+	fn cli_name() -> &'static str {
+		"feed-state"
+	}
+	fn read_only() -> bool {
+		true
+	}
+}
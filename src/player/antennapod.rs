@@ -0,0 +1,244 @@
+use crate::fetch;
+use crate::player::{
+	count_rows, find_by_urls, populate_tracks, save_track, secs_from_millis, IoWriteSeek,
+	NewPlayer, Player, SaveOptions, StatusEncoding, Subscription, WriteStats,
+};
+use crate::podcast::{PlayingStatus, Podcast, Track};
+use crate::warning::{warn, Warning};
+use crate::{BoxResult, SQLLiteDatabase};
+
+use std::borrow::Borrow;
+
+use reqwest::Url;
+use rusqlite::Connection;
+
+// FeedItems.read is -1 for new episodes that were never looked at, 0 once they are seen and 1
+// when played. New episodes read as unplayed and are left new when written unplayed
+const READ: StatusEncoding = StatusEncoding {
+	field: "FeedItems.read",
+	values: &[
+		(PlayingStatus::Unplayed, 0),
+		(PlayingStatus::Playing, 0),
+		(PlayingStatus::Played, 1),
+		(PlayingStatus::Unplayed, -1),
+	],
+};
+
+// AntennaPod keeps feeds, their episodes and the episodes' media files in separate tables of its
+// database (Settings > Backup > Database export). Positions and times are in milliseconds. Its
+// History view lists the media with a last_played_time, so writing that time together with the
+// playback_completion_date of played episodes adds them to the playback history
+pub struct AntennaPod {
+	db: SQLLiteDatabase,
+}
+
+struct Media {
+	id: i64,
+	item_id: i64,
+	read: i64,
+	position: i64,
+	last_played_time: i64,
+}
+
+impl AntennaPod {
+	fn get_feed(&self, url: &Url) -> BoxResult<i64> {
+		let conn: &Connection = self.db.borrow();
+		let mut stmt =
+			conn.prepare("SELECT id FROM Feeds WHERE url_key(download_url) = url_key(:url)")?;
+		let mut rows = stmt.query_named(&[(":url", &url.to_string())])?;
+		let first_row = rows.next()?.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+		Ok(first_row.get(0)?)
+	}
+
+	fn query_media(&self, condition: &str, feed_id: i64, value: &str) -> rusqlite::Result<Media> {
+		let conn: &Connection = self.db.borrow();
+		let mut stmt = conn.prepare(&format!(
+			"SELECT FeedMedia.id, FeedItems.id, FeedItems.read, COALESCE(FeedMedia.position, 0), COALESCE(FeedMedia.last_played_time, 0) FROM FeedItems JOIN FeedMedia ON FeedMedia.feeditem = FeedItems.id WHERE FeedItems.feed = :feed AND {}",
+			condition
+		))?;
+		let mut rows = stmt.query_named(&[(":feed", &feed_id), (":value", &value)])?;
+		let first_row = rows.next()?;
+		first_row
+			.ok_or(rusqlite::Error::QueryReturnedNoRows)
+			.and_then(|row| {
+				Ok(Media {
+					id: row.get(0)?,
+					item_id: row.get(1)?,
+					read: row.get(2)?,
+					position: row.get(3)?,
+					last_played_time: row.get(4)?,
+				})
+			})
+	}
+
+	/// Find the episode by its guid, or by its media URLs when AntennaPod stored none
+	fn get_media(&self, feed_id: i64, track: &Track) -> rusqlite::Result<Media> {
+		match self.query_media("FeedItems.item_identifier = :value", feed_id, &track.guid) {
+			Err(rusqlite::Error::QueryReturnedNoRows) => find_by_urls(track, |url| {
+				self.query_media(
+					"url_key(FeedMedia.download_url) = url_key(:value)",
+					feed_id,
+					url.as_str(),
+				)
+			})
+			.map(|(_, found)| found),
+			result => result,
+		}
+	}
+
+	fn update_media(&self, media: &Media, track: &Track, now: i64) -> rusqlite::Result<()> {
+		let conn: &Connection = self.db.borrow();
+		let read = READ.encode(track.playing_status);
+		conn.execute_named(
+			"UPDATE FeedItems SET read = CASE WHEN :read = 0 AND read = -1 THEN read ELSE :read END WHERE id = :item",
+			&[(":item", &media.item_id), (":read", &read)],
+		)?;
+
+		// Listens are dated when the source player last played them, or at the conversion
+		let played_at = track
+			.last_played
+			.filter(|secs| *secs >= 0)
+			.map_or(now, |secs| secs * 1000);
+		let played = track.playing_status == PlayingStatus::Played;
+		let started = track.playing_status != PlayingStatus::Unplayed;
+		// Keep the completion date of episodes that were already played
+		conn.execute_named(
+			"UPDATE FeedMedia SET position = :position, playback_completion_date = CASE WHEN NOT :played THEN 0 WHEN playback_completion_date > 0 THEN playback_completion_date ELSE :played_at END, last_played_time = CASE WHEN :started THEN MAX(COALESCE(last_played_time, 0), :played_at) ELSE last_played_time END WHERE id = :media",
+			&[
+				(":media", &media.id),
+				(":position", &(track.progress as i64 * 1000)),
+				(":played", &played),
+				(":started", &started),
+				(":played_at", &played_at),
+			],
+		)
+		.map(|_| ())
+	}
+}
+
+impl Player for AntennaPod {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		let id = self.get_feed(&podcast.url)?;
+
+		populate_tracks(&mut podcast, |track| {
+			match self.get_media(id, track) {
+				Ok(media) => {
+					track.progress = secs_from_millis(media.position);
+
+					track.playing_status = if READ.decode(media.read)? == PlayingStatus::Played {
+						PlayingStatus::Played
+					} else if track.progress > 0 {
+						PlayingStatus::Playing
+					} else {
+						PlayingStatus::Unplayed
+					};
+
+					if media.last_played_time > 0 {
+						track.last_played = Some(media.last_played_time / 1000);
+					}
+
+					Ok(())
+				}
+				Err(rusqlite::Error::QueryReturnedNoRows) => {
+					warn(
+						Warning::UnmatchedTrack,
+						format!("Track not found: {:?}", track),
+					);
+					Ok(())
+				}
+				Err(err) => Err(err),
+			}?;
+			Ok(())
+		});
+
+		Ok(podcast)
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		let conn: &Connection = self.db.borrow();
+		let mut stmt = conn.prepare("SELECT download_url, title FROM Feeds")?;
+		let mut rows = stmt.query(rusqlite::NO_PARAMS)?;
+
+		let mut subs = Vec::new();
+		while let Some(row) = rows.next()? {
+			let url: String = row.get(0)?;
+			subs.push(Subscription {
+				url: Some(Url::parse(url.as_str())?),
+				title: row.get(1)?,
+			});
+		}
+		Ok(subs)
+	}
+
+	fn unsubscribe(&mut self, _sub: &Subscription) -> BoxResult<bool> {
+		// AntennaPod has no unsubscribed flag, and its episodes can not be reached once the feed is
+		// deleted, so the history would be lost with it
+		Ok(false)
+	}
+
+	fn save(
+		self: Box<Self>,
+		podcasts: &mut dyn Iterator<Item = &'_ Podcast>,
+		w: &mut dyn IoWriteSeek,
+		options: &SaveOptions,
+	) -> BoxResult<WriteStats> {
+		let mut stats = WriteStats::default();
+		let now = options.clock.unix_millis();
+
+		for podcast in podcasts {
+			println!(
+				"Saving '{}' ({})",
+				podcast.title,
+				fetch::redact(&podcast.url)
+			);
+			let id = self.get_feed(&podcast.url)?;
+
+			for track in podcast.tracks.iter() {
+				match self.get_media(id, track) {
+					Ok(media) => {
+						if save_track(track, self.update_media(&media, track, now), &mut stats)
+							.is_some()
+						{
+							stats.record(true);
+						}
+					}
+					Err(rusqlite::Error::QueryReturnedNoRows) => stats.record(false),
+					Err(err) => {
+						save_track::<(), _>(track, Err(err), &mut stats);
+					}
+				}
+			}
+		}
+
+		// Copy temp file to output
+		let mut temp_file = self.db.into_file()?;
+		std::io::copy(&mut temp_file, w)?;
+		Ok(stats)
+	}
+
+	fn episode_count(&self) -> BoxResult<Option<usize>> {
+		let conn: &Connection = self.db.borrow();
+		count_rows(conn, "FeedItems").map(Some)
+	}
+}
+
+impl NewPlayer for AntennaPod {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		Ok(Box::new(Self {
+			db: SQLLiteDatabase::open(path)?,
+		}))
+	}
+
+	fn name() -> &'static str {
+		"AntennaPod"
+	}
+	fn cli_name() -> &'static str {
+		"antennapod"
+	}
+	fn file_names() -> &'static [&'static str] {
+		&["AntennaPodBackup-*.db"]
+	}
+	fn status_encodings() -> &'static [StatusEncoding] {
+		&[READ]
+	}
+}
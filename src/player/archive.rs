@@ -0,0 +1,116 @@
+use crate::player::{IoWriteSeek, SaveOptions};
+use crate::provenance;
+use crate::{BoxResult, SQLLiteDatabase};
+
+use std::borrow::Borrow;
+use std::fs::File;
+use std::io::{self, Seek};
+
+use rusqlite::Connection;
+
+/// A zip backup with a SQLite database stored alongside other files
+pub struct SQLiteArchive {
+	archive: zip::ZipArchive<File>,
+	db: SQLLiteDatabase,
+	/// The first entry is the one the database was read from, all of them are written on save
+	db_files: Vec<&'static str>,
+}
+
+impl Borrow<Connection> for SQLiteArchive {
+	fn borrow(&self) -> &Connection {
+		self.db.borrow()
+	}
+}
+
+impl SQLiteArchive {
+	pub fn open(path: &str, db_file: &'static str) -> BoxResult<Self> {
+		let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+		let db = SQLLiteDatabase::open_from_reader(&mut archive.by_name(db_file)?)?;
+
+		Ok(Self {
+			archive,
+			db,
+			db_files: vec![db_file],
+		})
+	}
+
+	pub fn contains(path: &str, name: &str) -> BoxResult<bool> {
+		let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+		let found = archive.by_name(name).is_ok();
+		Ok(found)
+	}
+
+	/// Also write the updated database over the `name` entry when saving
+	pub fn add_db_alias(&mut self, name: &'static str) {
+		self.db_files.push(name);
+	}
+
+	/// Close the database and open what was written to it again with a fresh connection
+	pub fn reopen(self) -> BoxResult<Self> {
+		let mut db_file = self.db.into_file()?;
+		Ok(Self {
+			archive: self.archive,
+			db: SQLLiteDatabase::open_from_reader(&mut db_file)?,
+			db_files: self.db_files,
+		})
+	}
+
+	pub fn file_names(&mut self) -> zip::result::ZipResult<Vec<String>> {
+		(0..self.archive.len())
+			.map(|i| Ok(self.archive.by_index(i)?.name().to_owned()))
+			.collect()
+	}
+
+	pub fn by_name(&mut self, name: &str) -> zip::result::ZipResult<zip::read::ZipFile<'_>> {
+		self.archive.by_name(name)
+	}
+
+	/// Copy every file to `w`, writing the updated database and the `replaced` files in their place
+	pub fn save(
+		mut self,
+		w: &mut dyn IoWriteSeek,
+		replaced: &mut [(&str, &mut dyn io::Read)],
+		save_options: &SaveOptions,
+	) -> BoxResult<()> {
+		let mut zip = zip::ZipWriter::new(w);
+		let mut options = zip::write::FileOptions::default();
+
+		let mut file_names = (0..self.archive.len())
+			.map(|i| Ok(self.archive.by_index(i)?.name().to_owned()))
+			.collect::<zip::result::ZipResult<Vec<String>>>()?;
+
+		if save_options.deterministic {
+			options = options.last_modified_time(zip::DateTime::default());
+			file_names.sort();
+		}
+
+		let mut db_temp_file = self.db.into_file()?;
+
+		for file_name in file_names.into_iter() {
+			// Replaced by the record of this conversion
+			if file_name == provenance::ARCHIVE_ENTRY {
+				continue;
+			}
+
+			let mut in_file = self.archive.by_name(file_name.as_str())?;
+
+			let out_file: &mut dyn io::Read = if self.db_files.iter().any(|f| *f == file_name) {
+				db_temp_file.seek(io::SeekFrom::Start(0))?;
+				&mut db_temp_file
+			} else if let Some((_, r)) = replaced.iter_mut().find(|(name, _)| *name == file_name) {
+				&mut **r
+			} else {
+				&mut in_file
+			};
+
+			zip.start_file(file_name, options)?;
+			io::copy(out_file, &mut zip)?;
+		}
+
+		zip.start_file(provenance::ARCHIVE_ENTRY, options)?;
+		serde_json::to_writer_pretty(&mut zip, &save_options.provenance)?;
+
+		zip.finish()?;
+		Ok(())
+	}
+}
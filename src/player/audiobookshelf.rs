@@ -0,0 +1,323 @@
+use crate::fetch;
+use crate::player::server::Server;
+use crate::player::{
+	secs_from_f64, IoWriteSeek, NewPlayer, Player, SaveOptions, Subscription, WriteStats,
+};
+use crate::podcast::{PlayingStatus, Podcast, Track};
+use crate::url_key::url_key;
+use crate::warning::{warn, Warning};
+use crate::BoxResult;
+
+use std::collections::HashMap;
+
+use reqwest::{header, Client, Url};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+// Audiobookshelf keeps podcasts as library items and playback as media progress
+// objects of the logged in user, keyed by library item and episode id. Times
+// are in seconds. Saving patches the progress of every episode whose state
+// differs from the server and writes the patches to the output file as a record.
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Library {
+	id: String,
+	media_type: String,
+}
+
+#[derive(Deserialize)]
+struct Libraries {
+	libraries: Vec<Library>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Metadata {
+	#[serde(default)]
+	title: String,
+	#[serde(default)]
+	feed_url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Enclosure {
+	url: String,
+}
+
+#[derive(Deserialize)]
+struct Episode {
+	id: String,
+	#[serde(default)]
+	guid: Option<String>,
+	enclosure: Option<Enclosure>,
+}
+
+#[derive(Deserialize)]
+struct Media {
+	metadata: Metadata,
+	#[serde(default)]
+	episodes: Vec<Episode>,
+}
+
+#[derive(Deserialize)]
+struct LibraryItem {
+	id: String,
+	media: Media,
+}
+
+#[derive(Deserialize)]
+struct LibraryItems {
+	results: Vec<LibraryItem>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MediaProgress {
+	library_item_id: String,
+	#[serde(default)]
+	episode_id: Option<String>,
+	#[serde(default)]
+	current_time: f64,
+	#[serde(default)]
+	is_finished: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Me {
+	#[serde(default)]
+	media_progress: Vec<MediaProgress>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ProgressUpdate {
+	#[serde(skip)]
+	path: String,
+	library_item_id: String,
+	episode_id: String,
+	current_time: f64,
+	is_finished: bool,
+}
+
+pub struct Audiobookshelf {
+	client: Client,
+	server: Server,
+	/// Podcast library items, without their episodes
+	items: Vec<LibraryItem>,
+	/// Progress keyed by (library item id, episode id)
+	progress: HashMap<(String, String), MediaProgress>,
+}
+
+impl Audiobookshelf {
+	fn get<T: DeserializeOwned>(&self, path: &str) -> BoxResult<T> {
+		Ok(self
+			.client
+			.get(self.server.endpoint(path)?)
+			.header(
+				header::AUTHORIZATION,
+				format!("Bearer {}", self.server.password),
+			)
+			.send()?
+			.error_for_status()?
+			.json()?)
+	}
+
+	fn find_item(&self, url: &Url) -> Option<&LibraryItem> {
+		self.items.iter().find(|item| {
+			item.media
+				.metadata
+				.feed_url
+				.as_ref()
+				.map_or(false, |feed_url| url_key(feed_url) == url_key(url.as_str()))
+		})
+	}
+
+	fn get_episodes(&self, item_id: &str) -> BoxResult<Vec<Episode>> {
+		let item: LibraryItem = self.get(&format!("api/items/{}?expanded=1", item_id))?;
+		Ok(item.media.episodes)
+	}
+
+	fn find_episode<'a>(episodes: &'a [Episode], track: &Track) -> Option<&'a Episode> {
+		episodes.iter().find(|episode| {
+			episode.guid.as_ref() == Some(&track.guid)
+				|| episode
+					.enclosure
+					.as_ref()
+					.map_or(false, |enclosure| track.has_url(enclosure.url.as_str()))
+		})
+	}
+
+	fn state(&self, item_id: &str, episode_id: &str) -> (PlayingStatus, i32) {
+		match self
+			.progress
+			.get(&(item_id.to_string(), episode_id.to_string()))
+		{
+			Some(progress) => {
+				let position = secs_from_f64(progress.current_time);
+				if progress.is_finished {
+					(PlayingStatus::Played, position)
+				} else if position > 0 {
+					(PlayingStatus::Playing, position)
+				} else {
+					(PlayingStatus::Unplayed, 0)
+				}
+			}
+			None => (PlayingStatus::Unplayed, 0),
+		}
+	}
+}
+
+impl Player for Audiobookshelf {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		let item_id = match self.find_item(&podcast.url) {
+			Some(item) => item.id.clone(),
+			None => {
+				warn(
+					Warning::UnmatchedTrack,
+					format!("Podcast not found: {}", fetch::redact(&podcast.url)),
+				);
+				return Ok(podcast);
+			}
+		};
+		let episodes = self.get_episodes(item_id.as_str())?;
+
+		for track in podcast.tracks.iter_mut() {
+			match Self::find_episode(&episodes, track) {
+				Some(episode) => {
+					let (status, progress) = self.state(item_id.as_str(), episode.id.as_str());
+					track.playing_status = status;
+					track.progress = progress;
+				}
+				None => warn(
+					Warning::UnmatchedTrack,
+					format!("Track not found: {:?}", track),
+				),
+			}
+		}
+
+		Ok(podcast)
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		Ok(self
+			.items
+			.iter()
+			.map(|item| Subscription {
+				url: item
+					.media
+					.metadata
+					.feed_url
+					.as_ref()
+					.and_then(|url| Url::parse(url.as_str()).ok()),
+				title: item.media.metadata.title.clone(),
+			})
+			.collect())
+	}
+
+	fn save(
+		self: Box<Self>,
+		podcasts: &mut dyn Iterator<Item = &'_ Podcast>,
+		w: &mut dyn IoWriteSeek,
+		_options: &SaveOptions,
+	) -> BoxResult<WriteStats> {
+		let mut updates = Vec::new();
+
+		for podcast in podcasts {
+			println!(
+				"Saving '{}' ({})",
+				podcast.title,
+				fetch::redact(&podcast.url)
+			);
+			let item_id = match self.find_item(&podcast.url) {
+				Some(item) => item.id.clone(),
+				None => {
+					warn(
+						Warning::UnmatchedTrack,
+						format!("Podcast not found: {}", fetch::redact(&podcast.url)),
+					);
+					continue;
+				}
+			};
+			let episodes = self.get_episodes(item_id.as_str())?;
+
+			for track in podcast.tracks.iter() {
+				let episode = match Self::find_episode(&episodes, track) {
+					Some(episode) => episode,
+					None => continue,
+				};
+				if self.state(item_id.as_str(), episode.id.as_str())
+					== (track.playing_status, track.progress)
+				{
+					continue;
+				}
+
+				let update = ProgressUpdate {
+					path: format!("api/me/progress/{}/{}", item_id, episode.id),
+					library_item_id: item_id.clone(),
+					episode_id: episode.id.clone(),
+					current_time: track.progress as f64,
+					is_finished: track.playing_status == PlayingStatus::Played,
+				};
+				self.client
+					.patch(self.server.endpoint(update.path.as_str())?)
+					.header(
+						header::AUTHORIZATION,
+						format!("Bearer {}", self.server.password),
+					)
+					.json(&update)
+					.send()?
+					.error_for_status()?;
+				updates.push(update);
+			}
+		}
+
+		println!("Updated {} episodes", updates.len());
+		serde_json::to_writer_pretty(w, &updates)?;
+		Ok(WriteStats::default())
+	}
+}
+
+impl NewPlayer for Audiobookshelf {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		let mut player = Self {
+			client: Client::new(),
+			server: Server::parse_token(path, "AUDIOBOOKSHELF_TOKEN", Self::name())?,
+			items: Vec::new(),
+			progress: HashMap::new(),
+		};
+
+		let libraries: Libraries = player.get("api/libraries")?;
+		for library in libraries
+			.libraries
+			.iter()
+			.filter(|library| library.media_type == "podcast")
+		{
+			let items: LibraryItems = player.get(&format!("api/libraries/{}/items", library.id))?;
+			player.items.extend(items.results);
+		}
+
+		let me: Me = player.get("api/me")?;
+		player.progress = me
+			.media_progress
+			.into_iter()
+			.filter_map(|progress| {
+				let episode_id = progress.episode_id.clone()?;
+				Some(((progress.library_item_id.clone(), episode_id), progress))
+			})
+			.collect();
+
+		Ok(Box::new(player))
+	}
+
+	fn name() -> &'static str {
+		"Audiobookshelf"
+	}
+	fn cli_name() -> &'static str {
+		"audiobookshelf"
+	}
+	fn value_name() -> &'static str {
+		"URL"
+	}
+}
@@ -1,18 +1,194 @@
-use crate::player::{IoWriteSeek, NewPlayer, Player};
-use crate::podcast::{PlayingStatus, Podcast};
-use crate::{BoxResult, SQLLiteDatabase, UUID};
+use crate::fetch;
+use crate::player::archive::SQLiteArchive;
+use crate::player::matching::{self, Candidate};
+use crate::player::{
+	count_rows, quote_identifier, save_track, IoWriteSeek, LocalPodcast, NewPlayer, Player,
+	SaveOptions, StatusEncoding, Subscription, WriteStats,
+};
+use crate::podcast::{PlayingStatus, Podcast, Track};
+use crate::warning::{warn, Warning};
+use crate::{output, BoxResult, Error, UUID};
 
 use std::borrow::Borrow;
-use std::collections::HashMap;
-use std::fs::File;
+use std::collections::{HashMap, HashSet};
 use std::io::{self, Seek};
 
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 use reqwest::Url;
+use rusqlite::types::{FromSql, ToSql, Value};
 use rusqlite::Connection;
 
 const HISTORY_FILE: &str = "BeyondPodItemHistory.bin.autobak";
-const DB_FILE: &str = "beyondpod.db.autobak";
+// Some backups contain both databases, the newer one is used
+const DB_FILES: [&str; 2] = ["beyondpod.db.autobak", "beyondpod.db"];
+
+const PLAYED: StatusEncoding = StatusEncoding {
+	field: "tracks.played",
+	values: &[
+		(PlayingStatus::Unplayed, 0),
+		(PlayingStatus::Playing, 0),
+		(PlayingStatus::Played, 1),
+	],
+};
+
+// Every track in the database also has an entry in the history file
+const HISTORY_FLAGS: StatusEncoding = StatusEncoding {
+	field: "BeyondPodItemHistory flags",
+	values: &[
+		(PlayingStatus::Unplayed, 64),
+		(PlayingStatus::Playing, 64),
+		(PlayingStatus::Played, 65),
+	],
+};
+
+/// Table and column names used by a version of the BeyondPod database
+struct Schema {
+	version: &'static str,
+	feeds: &'static str,
+	feed_id: &'static str,
+	feed_url: &'static str,
+	feed_name: &'static str,
+	feed_unread: &'static str,
+	tracks: &'static str,
+	track_item_id: &'static str,
+	track_feed_id: &'static str,
+	track_played: &'static str,
+	track_played_time: &'static str,
+	feed_last_update: &'static str,
+	// The feed settings columns are not needed to convert the history, backups
+	// without them only lose the settings
+	feed_update_interval: &'static str,
+	feed_download_count: &'static str,
+	feed_category: &'static str,
+	// Only needed to read the episodes without their feed, or to match them by more than
+	// their guid
+	track_url: &'static str,
+	track_name: &'static str,
+	track_total_time: &'static str,
+	/// Older versions store feed ids as 32 hex digits without hyphens
+	hyphenated_ids: bool,
+}
+
+static SCHEMAS: [Schema; 2] = [
+	Schema {
+		version: "4.x",
+		feeds: "feeds",
+		feed_id: "feedid",
+		feed_url: "url",
+		feed_name: "name",
+		feed_unread: "hasunread",
+		tracks: "tracks",
+		track_item_id: "orgrssitemid",
+		track_feed_id: "parentfeedid",
+		track_played: "played",
+		track_played_time: "playedtime",
+		feed_last_update: "lastupdate",
+		feed_update_interval: "updateinterval",
+		feed_download_count: "maxdownloads",
+		feed_category: "category",
+		track_url: "url",
+		track_name: "name",
+		track_total_time: "totaltime",
+		hyphenated_ids: true,
+	},
+	Schema {
+		version: "3.x",
+		feeds: "Feeds",
+		feed_id: "FeedID",
+		feed_url: "Url",
+		feed_name: "Name",
+		feed_unread: "HasUnread",
+		tracks: "Tracks",
+		track_item_id: "RssItemID",
+		track_feed_id: "FeedID",
+		track_played: "Played",
+		track_played_time: "PlayedTime",
+		feed_last_update: "LastUpdate",
+		feed_update_interval: "UpdateInterval",
+		feed_download_count: "MaxDownloads",
+		feed_category: "Category",
+		track_url: "Url",
+		track_name: "Name",
+		track_total_time: "TotalTime",
+		hyphenated_ids: false,
+	},
+];
+
+impl Schema {
+	fn columns(conn: &Connection, table: &str) -> rusqlite::Result<Vec<String>> {
+		let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", quote_identifier(table)))?;
+		let mut rows = stmt.query(rusqlite::NO_PARAMS)?;
+
+		let mut columns = Vec::new();
+		while let Some(row) = rows.next()? {
+			let name: String = row.get(1)?;
+			columns.push(name.to_lowercase());
+		}
+		Ok(columns)
+	}
+
+	fn matches(&self, conn: &Connection) -> rusqlite::Result<bool> {
+		let has_all = |table: &str, wanted: &[&str]| -> rusqlite::Result<bool> {
+			let columns = Self::columns(conn, table)?;
+			Ok(wanted
+				.iter()
+				.all(|column| columns.contains(&column.to_lowercase())))
+		};
+
+		Ok(has_all(
+			self.feeds,
+			&[
+				self.feed_id,
+				self.feed_url,
+				self.feed_name,
+				self.feed_unread,
+			],
+		)? && has_all(
+			self.tracks,
+			&[
+				self.track_item_id,
+				self.track_feed_id,
+				self.track_played,
+				self.track_played_time,
+			],
+		)?)
+	}
+
+	fn detect(conn: &Connection) -> BoxResult<&'static Self> {
+		for schema in SCHEMAS.iter() {
+			if schema.matches(conn)? {
+				return Ok(schema);
+			}
+		}
+
+		// Name the tables that were found to help with supporting the new schema
+		let mut stmt = conn.prepare("SELECT name FROM sqlite_master WHERE type = 'table'")?;
+		let mut rows = stmt.query(rusqlite::NO_PARAMS)?;
+		let mut tables = Vec::new();
+		while let Some(row) = rows.next()? {
+			let name: String = row.get(0)?;
+			tables.push(name);
+		}
+
+		Err(Error::UnknownSchema("BeyondPod", String::from("tables ") + &tables.join(", ")).into())
+	}
+
+	fn format_id(&self, id: &UUID) -> String {
+		if self.hyphenated_ids {
+			id.to_string()
+		} else {
+			id.to_simple_string()
+		}
+	}
+
+	fn parse_id(&self, id: String) -> BoxResult<UUID> {
+		if self.hyphenated_ids {
+			UUID::from_str(id)
+		} else {
+			UUID::from_simple_str(id)
+		}
+	}
+}
 
 struct HistoryTokenIter<R: ReadBytesExt> {
 	r: R,
@@ -20,7 +196,7 @@ struct HistoryTokenIter<R: ReadBytesExt> {
 
 impl<R: ReadBytesExt> HistoryTokenIter<R> {
 	fn new(r: R) -> Self {
-		Self { r: r }
+		Self { r }
 	}
 }
 
@@ -44,11 +220,56 @@ impl<R: ReadBytesExt> Iterator for HistoryTokenIter<R> {
 }
 
 pub struct BeyondPod {
-	archive: zip::ZipArchive<File>,
-	db: SQLLiteDatabase,
+	archive: SQLiteArchive,
+	schema: &'static Schema,
 }
 
 impl BeyondPod {
+	fn open(path: &str, db_file: &'static str) -> BoxResult<Self> {
+		let archive = SQLiteArchive::open(path, db_file)?;
+		let schema = Schema::detect(archive.borrow())?;
+
+		Ok(Self { archive, schema })
+	}
+
+	fn last_update(&self) -> i64 {
+		let s = self.schema;
+		let conn: &Connection = self.archive.borrow();
+		conn.query_row(
+			&format!("SELECT MAX({}) FROM {}", s.feed_last_update, s.feeds),
+			rusqlite::NO_PARAMS,
+			|row| row.get::<_, Option<i64>>(0),
+		)
+		.unwrap_or(None)
+		.unwrap_or(0)
+	}
+
+	fn feed_urls(&self) -> BoxResult<HashSet<String>> {
+		Ok(self
+			.subscriptions()?
+			.into_iter()
+			.filter_map(|sub| sub.url.map(|url| url.to_string()))
+			.collect())
+	}
+
+	fn warn_on_feed_mismatch(a: &Self, a_name: &str, b: &Self, b_name: &str) -> BoxResult<()> {
+		let (a_urls, b_urls) = (a.feed_urls()?, b.feed_urls()?);
+
+		for url in a_urls.difference(&b_urls) {
+			println!(
+				"Warning: feed {} is only in '{}', not '{}'",
+				url, a_name, b_name
+			);
+		}
+		for url in b_urls.difference(&a_urls) {
+			println!(
+				"Warning: feed {} is only in '{}', not '{}'",
+				url, b_name, a_name
+			);
+		}
+		Ok(())
+	}
+
 	fn guid_to_track_id(guid: &String) -> u32 {
 		let mut acc = 0u32;
 		for &b in guid.as_bytes() {
@@ -58,20 +279,240 @@ impl BeyondPod {
 	}
 
 	fn get_feed(&self, url: &Url) -> BoxResult<(UUID, i32)> {
-		let conn: &Connection = self.db.borrow();
-		let mut stmt = conn.prepare("SELECT feedid, hasunread FROM feeds WHERE url = :url")?;
+		let s = self.schema;
+		let conn: &Connection = self.archive.borrow();
+		let mut stmt = conn.prepare(&format!(
+			"SELECT {}, {} FROM {} WHERE url_key({}) = url_key(:url)",
+			s.feed_id, s.feed_unread, s.feeds, s.feed_url
+		))?;
 		let mut rows = stmt.query_named(&[(":url", &url.to_string())])?;
 		let first_row = rows.next()?.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
-		Ok((UUID::from_str(first_row.get(0)?)?, first_row.get(1)?))
+		Ok((s.parse_id(first_row.get(0)?)?, first_row.get(1)?))
+	}
+
+	fn get_track_ids(&self, feed_id: &UUID) -> BoxResult<HashSet<u32>> {
+		let s = self.schema;
+		let conn: &Connection = self.archive.borrow();
+		let mut stmt = conn.prepare(&format!(
+			"SELECT {} FROM {} WHERE {} = :parentfeedid",
+			s.track_item_id, s.tracks, s.track_feed_id
+		))?;
+		let mut rows = stmt.query_named(&[(":parentfeedid", &s.format_id(feed_id))])?;
+
+		let mut ids = HashSet::new();
+		while let Some(row) = rows.next()? {
+			let id: String = row.get(0)?;
+			ids.insert(i32::from_str_radix(id.as_str(), 10)? as u32);
+		}
+		Ok(ids)
+	}
+
+	/// Every track of the feed, for the match strategies to find the feed's among. Older backups
+	/// without the URL, name or length columns only match by guid
+	fn get_candidates(&self, feed_id: &UUID) -> BoxResult<Vec<Candidate<u32>>> {
+		let s = self.schema;
+		let conn: &Connection = self.archive.borrow();
+		let columns = Schema::columns(conn, s.tracks)?;
+		let optional = |column: &'static str| {
+			if columns.contains(&column.to_lowercase()) {
+				column
+			} else {
+				"NULL"
+			}
+		};
+		let mut stmt = conn.prepare(&format!(
+			"SELECT {}, {}, {}, {} FROM {} WHERE {} = :parentfeedid",
+			s.track_item_id,
+			optional(s.track_url),
+			optional(s.track_name),
+			optional(s.track_total_time),
+			s.tracks,
+			s.track_feed_id
+		))?;
+		let mut rows = stmt.query_named(&[(":parentfeedid", &s.format_id(feed_id))])?;
+
+		let mut candidates = Vec::new();
+		while let Some(row) = rows.next()? {
+			let id: String = row.get(0)?;
+			candidates.push(Candidate {
+				value: i32::from_str_radix(id.as_str(), 10)? as u32,
+				guid: Some(id),
+				url: row.get(1)?,
+				title: row.get(2)?,
+				published: None,
+				duration: row
+					.get::<_, Option<i32>>(3)?
+					.filter(|&duration| duration > 0),
+			});
+		}
+		Ok(candidates)
+	}
+
+	/// Id of each track in the database, that of its guid when no match strategy finds it
+	fn find_track_ids(tracks: &[Track], candidates: &[Candidate<u32>]) -> Vec<u32> {
+		let guid_key = |guid: &str| (Self::guid_to_track_id(&guid.to_string()) as i32).to_string();
+		matching::find_all(tracks, guid_key, candidates)
+			.into_iter()
+			.zip(tracks)
+			.map(|(found, track)| {
+				found.map_or_else(|| Self::guid_to_track_id(&track.guid), |found| found.value)
+			})
+			.collect()
+	}
+
+	/// `None` when the column is missing from this backup or empty for the feed
+	fn get_feed_setting<T: FromSql>(&self, feed_id: &UUID, column: &str) -> BoxResult<Option<T>> {
+		let s = self.schema;
+		let conn: &Connection = self.archive.borrow();
+		if !Schema::columns(conn, s.feeds)?.contains(&column.to_lowercase()) {
+			return Ok(None);
+		}
+
+		Ok(conn.query_row_named(
+			&format!(
+				"SELECT {} FROM {} WHERE {} = :feedid",
+				column, s.feeds, s.feed_id
+			),
+			&[(":feedid", &s.format_id(feed_id))],
+			|row| row.get(0),
+		)?)
+	}
+
+	/// Returns false when the column is missing from this backup
+	fn set_feed_setting(&self, feed_id: &UUID, column: &str, value: &dyn ToSql) -> BoxResult<bool> {
+		let s = self.schema;
+		let conn: &Connection = self.archive.borrow();
+		if !Schema::columns(conn, s.feeds)?.contains(&column.to_lowercase()) {
+			return Ok(false);
+		}
+
+		conn.execute_named(
+			&format!(
+				"UPDATE {} SET {} = :value WHERE {} = :feedid",
+				s.feeds, column, s.feed_id
+			),
+			&[(":value", value), (":feedid", &s.format_id(feed_id))],
+		)?;
+		Ok(true)
+	}
+
+	fn save_settings(&self, feed_id: &UUID, podcast: &Podcast) -> BoxResult<()> {
+		let s = self.schema;
+		let settings = &podcast.settings;
+		let values: [(&str, &str, Option<&dyn ToSql>); 3] = [
+			(
+				"update interval",
+				s.feed_update_interval,
+				settings.update_interval.as_ref().map(|v| v as &dyn ToSql),
+			),
+			(
+				"download count",
+				s.feed_download_count,
+				settings.download_count.as_ref().map(|v| v as &dyn ToSql),
+			),
+			(
+				"playlist",
+				s.feed_category,
+				settings.playlist.as_ref().map(|v| v as &dyn ToSql),
+			),
+		];
+
+		for (name, column, value) in values.iter() {
+			if let Some(value) = value {
+				if !self.set_feed_setting(feed_id, column, *value)? {
+					warn(
+						Warning::LossyField,
+						format!(
+							"This BeyondPod backup has no {} column, the {} of '{}' is not written",
+							column, name, podcast.title
+						),
+					);
+				}
+			}
+		}
+		Ok(())
+	}
+
+	/// Print which track ids computed from the podcast's guids exist in the database and the
+	/// history file, and which ids in the database match no guid in the feed
+	pub fn audit_guids(path: &str, podcast: &Podcast) -> BoxResult<()> {
+		let mut beyondpod = Self::load(path)?;
+		let (feed_id, _unread) = beyondpod.get_feed(&podcast.url)?;
+		let db_ids = beyondpod.get_track_ids(&feed_id)?;
+		let history = beyondpod.get_feed_history(&feed_id)?;
+
+		println!(
+			"Feed {} ({} schema), {} tracks in the database, {} in the history file",
+			beyondpod.schema.format_id(&feed_id),
+			beyondpod.schema.version,
+			db_ids.len(),
+			history.len()
+		);
+
+		let mut computed = HashSet::new();
+		for track in podcast.tracks.iter() {
+			let track_id = Self::guid_to_track_id(&track.guid);
+			computed.insert(track_id);
+
+			let status = match (db_ids.contains(&track_id), history.contains_key(&track_id)) {
+				(true, true) => "ok",
+				(true, false) => "database only",
+				(false, true) => "history only",
+				(false, false) => "MISSING",
+			};
+			if output::plain() {
+				println!(
+					"Track {}: {}, {} ({})",
+					track_id as i32, status, track.title, track.guid
+				);
+			} else {
+				println!(
+					"\t{:>11} {:<13} {} ({})",
+					track_id as i32, status, track.title, track.guid
+				);
+			}
+		}
+
+		let orphans: Vec<u32> = db_ids
+			.iter()
+			.chain(history.keys())
+			.filter(|id| !computed.contains(*id))
+			.cloned()
+			.collect::<HashSet<u32>>()
+			.into_iter()
+			.collect();
+
+		if !orphans.is_empty() {
+			if output::plain() {
+				for id in orphans.iter() {
+					println!("Track id {} matches no guid in the feed", *id as i32);
+				}
+			} else {
+				println!("Track ids in the backup that match no guid in the feed:");
+				for id in orphans.iter() {
+					println!("\t{:>11}", *id as i32);
+				}
+			}
+		}
+
+		let matched = computed.iter().filter(|id| db_ids.contains(*id)).count();
+		if matched == 0 && !db_ids.is_empty() {
+			println!("No guids matched, the feed's guids have likely changed since BeyondPod downloaded them");
+		}
+
+		Ok(())
 	}
 
 	fn get_track(&self, feed_id: &UUID, track_id: u32) -> rusqlite::Result<(bool, i32)> {
-		let conn: &Connection = self.db.borrow();
-		let mut stmt = conn
-			.prepare("SELECT played,playedtime FROM tracks WHERE orgrssitemid = :orgrssitemid and parentfeedid = :parentfeedid")?;
+		let s = self.schema;
+		let conn: &Connection = self.archive.borrow();
+		let mut stmt = conn.prepare(&format!(
+			"SELECT {}, {} FROM {} WHERE {} = :orgrssitemid and {} = :parentfeedid",
+			s.track_played, s.track_played_time, s.tracks, s.track_item_id, s.track_feed_id
+		))?;
 		let mut rows = stmt.query_named(&[
 			(":orgrssitemid", &(track_id as i32).to_string()),
-			(":parentfeedid", &feed_id.to_string()),
+			(":parentfeedid", &s.format_id(feed_id)),
 		])?;
 		let first_row = rows.next()?;
 		first_row
@@ -85,17 +526,21 @@ impl BeyondPod {
 		track_id: u32,
 		played: bool,
 		played_time: i32,
-	) -> rusqlite::Result<()> {
-		let conn: &Connection = self.db.borrow();
+	) -> rusqlite::Result<usize> {
+		let s = self.schema;
+		let conn: &Connection = self.archive.borrow();
 		conn.execute_named(
-			"UPDATE tracks SET played = :played, playedtime = :playedtime WHERE orgrssitemid = :orgrssitemid and parentfeedid = :parentfeedid",
+			&format!(
+				"UPDATE {} SET {} = :played, {} = :playedtime WHERE {} = :orgrssitemid and {} = :parentfeedid",
+				s.tracks, s.track_played, s.track_played_time, s.track_item_id, s.track_feed_id
+			),
 			&[
 				(":orgrssitemid", &(track_id as i32).to_string()),
-				(":parentfeedid", &feed_id.to_string()),
+				(":parentfeedid", &s.format_id(feed_id)),
 				(":played", &played),
 				(":playedtime", &played_time),
 			],
-		).map(|_| ())
+		)
 	}
 
 	fn get_feed_history(&mut self, feed: &UUID) -> BoxResult<HashMap<u32, u32>> {
@@ -103,7 +548,7 @@ impl BeyondPod {
 		let mut iter = HistoryTokenIter::new(item_history);
 
 		while let Some((id_str, count)) = iter.next() {
-			let id = UUID::from_str(id_str)?;
+			let id = self.schema.parse_id(id_str)?;
 			if &id == feed {
 				return iter
 					.take(count as usize)
@@ -135,10 +580,10 @@ impl BeyondPod {
 
 	fn write_feed_history<W: WriteBytesExt>(
 		w: &mut W,
-		id: &UUID,
+		id: String,
 		feed: Vec<(u32, u32)>,
 	) -> io::Result<()> {
-		Self::write_history_token(w, id.to_string(), feed.len() as u32)?;
+		Self::write_history_token(w, id, feed.len() as u32)?;
 		for (id, flags) in feed.into_iter() {
 			Self::write_history_token(w, (id as i32).to_string(), flags)?;
 		}
@@ -150,10 +595,10 @@ impl Player for BeyondPod {
 	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
 		let (id, _unread) = self.get_feed(&podcast.url)?;
 		let history = self.get_feed_history(&id)?;
+		let candidates = self.get_candidates(&id)?;
+		let track_ids = BeyondPod::find_track_ids(&podcast.tracks, &candidates);
 
-		for track in podcast.tracks.iter_mut() {
-			let track_id = BeyondPod::guid_to_track_id(&track.guid);
-
+		for (track, track_id) in podcast.tracks.iter_mut().zip(track_ids) {
 			let (sql_played, sql_progress) = self.get_track(&id, track_id).ok().map_or_else(
 				|| (None, None),
 				|(played, played_time)| {
@@ -168,7 +613,9 @@ impl Player for BeyondPod {
 				},
 			);
 
-			let history_played = history.get(&track_id).map(|&flags| flags == 65);
+			let history_played = history
+				.get(&track_id)
+				.map(|&flags| flags as i64 == HISTORY_FLAGS.encode(PlayingStatus::Played));
 
 			let played =
 				if let Some((sql, history)) = sql_played.and_then(|s| Some((s, history_played?))) {
@@ -201,75 +648,270 @@ impl Player for BeyondPod {
 		Ok(podcast)
 	}
 
+	fn populate_settings(&self, podcast: &mut Podcast) -> BoxResult<()> {
+		let s = self.schema;
+		let (id, _unread) = self.get_feed(&podcast.url)?;
+
+		podcast.settings.update_interval = self.get_feed_setting(&id, s.feed_update_interval)?;
+		podcast.settings.download_count = self.get_feed_setting(&id, s.feed_download_count)?;
+		podcast.settings.playlist = self
+			.get_feed_setting::<String>(&id, s.feed_category)?
+			.filter(|category| !category.is_empty());
+		Ok(())
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		let s = self.schema;
+		let conn: &Connection = self.archive.borrow();
+		let mut stmt = conn.prepare(&format!(
+			"SELECT {}, {} FROM {}",
+			s.feed_url, s.feed_name, s.feeds
+		))?;
+		let mut rows = stmt.query(rusqlite::NO_PARAMS)?;
+
+		let mut subs = Vec::new();
+		while let Some(row) = rows.next()? {
+			let url: String = row.get(0)?;
+			let title: String = row.get(1)?;
+			// One feed with a broken URL should not stop the others from being converted
+			match Url::parse(url.as_str()) {
+				Ok(url) => subs.push(Subscription {
+					url: Some(url),
+					title,
+				}),
+				Err(err) => warn(
+					Warning::FeedFailed,
+					format!(
+						"Skipping feed '{}', its URL {} is invalid: {}",
+						title, url, err
+					),
+				),
+			}
+		}
+		Ok(subs)
+	}
+
+	fn local_podcasts(&self) -> BoxResult<Vec<LocalPodcast>> {
+		let s = self.schema;
+		let conn: &Connection = self.archive.borrow();
+		let columns = Schema::columns(conn, s.tracks)?;
+		if let Some(missing) = [s.track_url, s.track_name, s.track_total_time]
+			.iter()
+			.find(|column| !columns.contains(&column.to_lowercase()))
+		{
+			return Err(Error::UnknownSchema(
+				"BeyondPod",
+				format!("no {}.{} column", s.tracks, missing),
+			)
+			.into());
+		}
+
+		// Tracks are only known by a hash of their guid, so other players find these by their URL
+		let mut stmt = conn.prepare(&format!(
+			"SELECT {f}.{}, {f}.{}, {t}.{}, {t}.{}, {t}.{}, {t}.{}, {t}.{}, {f}.{} FROM {t} JOIN {f} ON {f}.{} = {t}.{} ORDER BY {f}.{}",
+			s.feed_url,
+			s.feed_name,
+			s.track_url,
+			s.track_name,
+			s.track_total_time,
+			s.track_played,
+			s.track_played_time,
+			s.feed_id,
+			s.feed_id,
+			s.track_feed_id,
+			s.feed_id,
+			f = s.feeds,
+			t = s.tracks
+		))?;
+		let mut rows = stmt.query(rusqlite::NO_PARAMS)?;
+
+		let mut podcasts: Vec<LocalPodcast> = Vec::new();
+		let mut last_feed: Option<Value> = None;
+		while let Some(row) = rows.next()? {
+			let url = match row
+				.get::<_, Option<String>>(2)?
+				.and_then(|url| Url::parse(&url).ok())
+			{
+				Some(url) => url,
+				None => continue,
+			};
+			let mut track = Track::new(
+				url.to_string(),
+				url,
+				row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+				row.get::<_, Option<i32>>(4)?
+					.filter(|&duration| duration > 0),
+			);
+			track.progress = row.get::<_, Option<i32>>(6)?.unwrap_or(0).max(0);
+			track.playing_status = if row.get::<_, Option<bool>>(5)?.unwrap_or(false) {
+				PlayingStatus::Played
+			} else if track.progress > 0 {
+				PlayingStatus::Playing
+			} else {
+				PlayingStatus::Unplayed
+			};
+
+			let feed: Value = row.get(7)?;
+			if last_feed.as_ref() != Some(&feed) {
+				let title = row.get::<_, Option<String>>(1)?.unwrap_or_default();
+				// A feed without a readable URL is left to be matched by its title
+				let url = match row.get::<_, Option<String>>(0)? {
+					Some(url) => match Url::parse(&url) {
+						Ok(url) => Some(url),
+						Err(err) => {
+							warn(
+								Warning::FeedFailed,
+								format!(
+									"Feed URL of '{}' could not be read, matching it by title: {}",
+									title, err
+								),
+							);
+							None
+						}
+					},
+					None => None,
+				};
+				podcasts.push(LocalPodcast {
+					title,
+					url,
+					tracks: Vec::new(),
+				});
+				last_feed = Some(feed);
+			}
+			podcasts
+				.last_mut()
+				.expect("podcast pushed for the feed")
+				.tracks
+				.push(track);
+		}
+		Ok(podcasts)
+	}
+
+	fn unsubscribe(&mut self, _sub: &Subscription) -> BoxResult<bool> {
+		// BeyondPod has no unsubscribed flag, and its episodes can not be reached once the feed
+		// is deleted, so the history would be lost with it
+		Ok(false)
+	}
+
+	fn stores_provenance(&self) -> bool {
+		true
+	}
+
+	// The history file is written anew from the tracks given
+	fn writes_whole_podcasts(&self) -> bool {
+		true
+	}
+
 	fn save(
 		mut self: Box<Self>,
 		podcasts: &mut dyn Iterator<Item = &'_ Podcast>,
 		w: &mut dyn IoWriteSeek,
-	) -> BoxResult<()> {
-		let mut zip = zip::ZipWriter::new(w);
-		let options = zip::write::FileOptions::default();
-
+		options: &SaveOptions,
+	) -> BoxResult<WriteStats> {
 		let mut new_hist_file = io::Cursor::new(vec![0; 0]);
+		let mut stats = WriteStats::default();
 
 		for podcast in podcasts {
-			println!("Saving '{}' ({})", podcast.title, podcast.url);
+			println!(
+				"Saving '{}' ({})",
+				podcast.title,
+				fetch::redact(&podcast.url)
+			);
 			let (id, _unread) = self.get_feed(&podcast.url)?;
+			self.save_settings(&id, podcast)?;
 			let mut history_tracks: Vec<(u32, u32)> = Vec::with_capacity(podcast.tracks.len());
+			let candidates = self.get_candidates(&id)?;
+			let track_ids = BeyondPod::find_track_ids(&podcast.tracks, &candidates);
 
-			for track in podcast.tracks.iter() {
-				let track_id = BeyondPod::guid_to_track_id(&track.guid);
-				let played = track.playing_status == PlayingStatus::Played;
+			for (track, track_id) in podcast.tracks.iter().zip(track_ids) {
+				let played = PLAYED.encode(track.playing_status) != 0;
 				let is_in_db = self.get_track(&id, track_id).is_ok();
 
 				if is_in_db {
-					self.update_track(&id, track_id, played, track.progress)?;
+					match save_track(
+						track,
+						self.update_track(&id, track_id, played, track.progress),
+						&mut stats,
+					) {
+						Some(rows) => stats.record(rows > 0),
+						None => continue,
+					}
+				} else {
+					stats.record(false);
 				}
 
 				if played || is_in_db {
-					history_tracks.push((track_id, if played { 65 } else { 64 }));
+					let flags = HISTORY_FLAGS.encode(track.playing_status) as u32;
+					history_tracks.push((track_id, flags));
 				}
 			}
 
 			if history_tracks.len() > 0 {
-				Self::write_feed_history(&mut new_hist_file, &id, history_tracks)?;
+				Self::write_feed_history(
+					&mut new_hist_file,
+					self.schema.format_id(&id),
+					history_tracks,
+				)?;
 			}
 		}
 
-		let mut db_temp_file = self.db.into_file()?;
+		stats.report(Self::name());
 
 		// Copy all the files from the input archive to the output archive
-		for i in 0..self.archive.len() {
-			let mut in_file = self.archive.by_index(i)?;
-			let file_name = in_file.name().to_owned();
-
-			let out_file: &mut dyn io::Read = match file_name.as_str() {
-				HISTORY_FILE => {
-					new_hist_file.seek(io::SeekFrom::Start(0))?;
-					&mut new_hist_file
-				}
-				DB_FILE => &mut db_temp_file,
-				_ => &mut in_file,
-			};
+		new_hist_file.seek(io::SeekFrom::Start(0))?;
+		self.archive
+			.save(w, &mut [(HISTORY_FILE, &mut new_hist_file)], options)?;
+		Ok(stats)
+	}
+
+	fn schema_version(&self) -> Option<&'static str> {
+		Some(self.schema.version)
+	}
 
-			zip.start_file(file_name, options)?;
-			io::copy(out_file, &mut zip)?;
+	fn episode_count(&self) -> BoxResult<Option<usize>> {
+		let conn: &Connection = self.archive.borrow();
+		count_rows(conn, self.schema.tracks).map(Some)
+	}
+}
+
+impl BeyondPod {
+	fn load(path: &str) -> BoxResult<Self> {
+		let mut found = Vec::with_capacity(DB_FILES.len());
+		for &db_file in DB_FILES.iter() {
+			if SQLiteArchive::contains(path, db_file)? {
+				found.push((db_file, Self::open(path, db_file)?));
+			}
 		}
 
-		zip.finish()?;
-		Ok(())
+		let db_files: Vec<&'static str> = found.iter().map(|(db_file, _)| *db_file).collect();
+		let mut found = found.into_iter();
+		let (mut newest_file, mut newest) =
+			found.next().ok_or(zip::result::ZipError::FileNotFound)?;
+
+		for (db_file, mut other) in found {
+			Self::warn_on_feed_mismatch(&newest, newest_file, &other, db_file)?;
+
+			if other.last_update() > newest.last_update() {
+				std::mem::swap(&mut newest, &mut other);
+				newest_file = db_file;
+			}
+		}
+
+		// Keep every copy of the database in the backup up to date
+		for db_file in db_files
+			.into_iter()
+			.filter(|&db_file| db_file != newest_file)
+		{
+			newest.archive.add_db_alias(db_file);
+		}
+
+		Ok(newest)
 	}
 }
 
 impl NewPlayer for BeyondPod {
 	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
-		let f = File::open(path)?;
-		let mut archive = zip::ZipArchive::new(f)?;
-		let db = SQLLiteDatabase::open_from_reader(&mut archive.by_name(DB_FILE)?)?;
-
-		Ok(Box::new(Self {
-			archive: archive,
-			db: db,
-		}))
+		Ok(Box::new(Self::load(path)?))
 	}
 
 	fn name() -> &'static str {
@@ -278,4 +920,13 @@ impl NewPlayer for BeyondPod {
 	fn cli_name() -> &'static str {
 		"beyondpod"
 	}
+	fn file_names() -> &'static [&'static str] {
+		&["*.bpbak"]
+	}
+	fn status_encodings() -> &'static [StatusEncoding] {
+		&[PLAYED, HISTORY_FLAGS]
+	}
+	fn schema_versions() -> Vec<&'static str> {
+		SCHEMAS.iter().map(|schema| schema.version).collect()
+	}
 }
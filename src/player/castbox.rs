@@ -0,0 +1,122 @@
+use crate::player::archive::SQLiteArchive;
+use crate::player::{
+	count_rows, find_by_urls, populate_tracks, secs_from_millis, NewPlayer, Player, StatusEncoding,
+	Subscription,
+};
+use crate::podcast::{PlayingStatus, Podcast};
+use crate::warning::{warn, Warning};
+use crate::BoxResult;
+
+use std::borrow::Borrow;
+
+use reqwest::Url;
+use rusqlite::Connection;
+
+const DB_FILE: &str = "castbox.db";
+
+const STATUS: StatusEncoding = StatusEncoding {
+	field: "episode.status",
+	values: &[
+		(PlayingStatus::Unplayed, 0),
+		(PlayingStatus::Playing, 1),
+		(PlayingStatus::Played, 2),
+	],
+};
+
+pub struct Castbox {
+	archive: SQLiteArchive,
+}
+
+impl Castbox {
+	fn get_channel(&self, url: &Url) -> BoxResult<String> {
+		let conn: &Connection = self.archive.borrow();
+		let mut stmt =
+			conn.prepare("SELECT cid FROM channel WHERE url_key(url) = url_key(:url)")?;
+		let mut rows = stmt.query_named(&[(":url", &url.to_string())])?;
+		let first_row = rows.next()?.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+		Ok(first_row.get(0)?)
+	}
+
+	fn get_episode(&self, channel_id: &String, episode_url: &Url) -> rusqlite::Result<(i64, i64)> {
+		let conn: &Connection = self.archive.borrow();
+		let mut stmt =
+			conn.prepare("SELECT status, play_time FROM episode WHERE cid = :cid AND url_key(url) = url_key(:url)")?;
+		let mut rows =
+			stmt.query_named(&[(":cid", channel_id), (":url", &episode_url.to_string())])?;
+		let first_row = rows.next()?;
+		first_row
+			.ok_or(rusqlite::Error::QueryReturnedNoRows)
+			.and_then(|row| Ok((row.get(0)?, row.get(1)?)))
+	}
+}
+
+impl Player for Castbox {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		let id = self.get_channel(&podcast.url)?;
+
+		populate_tracks(&mut podcast, |track| {
+			match find_by_urls(track, |url| self.get_episode(&id, url)).map(|(_, found)| found) {
+				Ok((status, play_time_ms)) => {
+					track.progress = secs_from_millis(play_time_ms);
+
+					track.playing_status = STATUS.decode(status)?;
+
+					Ok(())
+				}
+				Err(rusqlite::Error::QueryReturnedNoRows) => {
+					warn(
+						Warning::UnmatchedTrack,
+						format!("Track not found: {:?}", track),
+					);
+					Ok(())
+				}
+				Err(err) => Err(err),
+			}?;
+			Ok(())
+		});
+
+		Ok(podcast)
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		let conn: &Connection = self.archive.borrow();
+		let mut stmt = conn.prepare("SELECT url, title FROM channel")?;
+		let mut rows = stmt.query(rusqlite::NO_PARAMS)?;
+
+		let mut subs = Vec::new();
+		while let Some(row) = rows.next()? {
+			let url: String = row.get(0)?;
+			subs.push(Subscription {
+				url: Some(Url::parse(url.as_str())?),
+				title: row.get(1)?,
+			});
+		}
+		Ok(subs)
+	}
+
+	fn episode_count(&self) -> BoxResult<Option<usize>> {
+		let conn: &Connection = self.archive.borrow();
+		count_rows(conn, "episode").map(Some)
+	}
+}
+
+impl NewPlayer for Castbox {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		Ok(Box::new(Self {
+			archive: SQLiteArchive::open(path, DB_FILE)?,
+		}))
+	}
+
+	fn name() -> &'static str {
+		"Castbox"
+	}
+	fn cli_name() -> &'static str {
+		"castbox"
+	}
+	fn read_only() -> bool {
+		true
+	}
+	fn status_encodings() -> &'static [StatusEncoding] {
+		&[STATUS]
+	}
+}
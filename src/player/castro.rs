@@ -0,0 +1,155 @@
+use crate::player::{
+	count_rows, populate_tracks, secs_from_f64, NewPlayer, Player, StatusEncoding, Subscription,
+};
+use crate::podcast::{PlayingStatus, Podcast};
+use crate::warning::{warn, Warning};
+use crate::{BoxResult, SQLLiteDatabase};
+
+use std::borrow::Borrow;
+
+use reqwest::Url;
+use rusqlite::Connection;
+
+const STATE: StatusEncoding = StatusEncoding {
+	field: "ZEPISODE.ZSTATE",
+	values: &[
+		(PlayingStatus::Unplayed, 0),
+		(PlayingStatus::Playing, 1),
+		(PlayingStatus::Played, 2),
+	],
+};
+
+// Episodes cleared from the inbox or queue without being played
+const STATE_ARCHIVED: i64 = 3;
+
+// Castro's export is a Core Data SQLite store like Downcast's. An episode's
+// state says whether it is in the inbox, queued, played or archived, and the
+// playhead is in seconds. Queued episodes only count as in progress once they
+// have a playhead, and archived ones as unplayed. Stars have no equivalent in
+// the other players and are reported as lost.
+pub struct Castro {
+	db: SQLLiteDatabase,
+}
+
+impl Castro {
+	fn get_podcast(&self, url: &Url) -> BoxResult<i64> {
+		let conn: &Connection = self.db.borrow();
+		let mut stmt =
+			conn.prepare("SELECT Z_PK FROM ZPODCAST WHERE url_key(ZFEEDURL) = url_key(:url)")?;
+		let mut rows = stmt.query_named(&[(":url", &url.to_string())])?;
+		let first_row = rows.next()?.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+		Ok(first_row.get(0)?)
+	}
+
+	fn get_episode(&self, podcast_id: i64, guid: &String) -> rusqlite::Result<(i64, f64, bool)> {
+		let conn: &Connection = self.db.borrow();
+		let mut stmt = conn
+			.prepare("SELECT ZSTATE, ZPLAYHEAD, ZSTARRED FROM ZEPISODE WHERE ZPODCAST = :podcast AND ZGUID = :guid")?;
+		let mut rows = stmt.query_named(&[(":podcast", &podcast_id), (":guid", guid)])?;
+		let first_row = rows.next()?;
+		first_row
+			.ok_or(rusqlite::Error::QueryReturnedNoRows)
+			.and_then(|row| {
+				Ok((
+					row.get(0)?,
+					row.get::<_, Option<f64>>(1)?.unwrap_or(0.0),
+					row.get::<_, Option<bool>>(2)?.unwrap_or(false),
+				))
+			})
+	}
+}
+
+impl Player for Castro {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		let id = self.get_podcast(&podcast.url)?;
+		let mut starred = 0;
+
+		populate_tracks(&mut podcast, |track| {
+			match self.get_episode(id, &track.guid) {
+				Ok((state, playhead, is_starred)) => {
+					if is_starred {
+						starred += 1;
+					}
+					track.progress = secs_from_f64(playhead);
+
+					track.playing_status = match state {
+						STATE_ARCHIVED => PlayingStatus::Unplayed,
+						state => match STATE.decode(state)? {
+							PlayingStatus::Playing if track.progress == 0 => {
+								PlayingStatus::Unplayed
+							}
+							status => status,
+						},
+					};
+
+					Ok(())
+				}
+				Err(rusqlite::Error::QueryReturnedNoRows) => {
+					warn(
+						Warning::UnmatchedTrack,
+						format!("Track not found: {:?}", track),
+					);
+					Ok(())
+				}
+				Err(err) => Err(err.into()),
+			}
+		});
+
+		if starred > 0 {
+			warn(
+				Warning::LossyField,
+				format!(
+					"{} starred episodes of '{}' lose their star",
+					starred, podcast.title
+				),
+			);
+		}
+
+		Ok(podcast)
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		let conn: &Connection = self.db.borrow();
+		let mut stmt = conn.prepare("SELECT ZFEEDURL, ZTITLE FROM ZPODCAST")?;
+		let mut rows = stmt.query(rusqlite::NO_PARAMS)?;
+
+		let mut subs = Vec::new();
+		while let Some(row) = rows.next()? {
+			let url: String = row.get(0)?;
+			subs.push(Subscription {
+				url: Some(Url::parse(url.as_str())?),
+				title: row.get(1)?,
+			});
+		}
+		Ok(subs)
+	}
+
+	fn episode_count(&self) -> BoxResult<Option<usize>> {
+		let conn: &Connection = self.db.borrow();
+		count_rows(conn, "ZEPISODE").map(Some)
+	}
+}
+
+impl NewPlayer for Castro {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		Ok(Box::new(Self {
+			db: SQLLiteDatabase::open(path)?,
+		}))
+	}
+
+	fn name() -> &'static str {
+		"Castro"
+	}
+	fn cli_name() -> &'static str {
+		"castro"
+	}
+	fn file_names() -> &'static [&'static str] {
+		&["Castro*.sqlite"]
+	}
+	fn read_only() -> bool {
+		true
+	}
+	fn status_encodings() -> &'static [StatusEncoding] {
+		&[STATE]
+	}
+}
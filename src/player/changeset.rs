@@ -0,0 +1,192 @@
+use crate::podcast::{Podcast, Track};
+
+use std::cell::Cell;
+
+// Saving to a player happens in two phases. `compute_changes` works out
+// everything the save will write and touches nothing, `apply_changes` then
+// writes it out. Stopping a run between the two always leaves the output as it
+// was, and the change set in between can be looked over, kept, or handed to a
+// player later.
+//
+// The change set keeps the whole of every podcast, as most players are written
+// whole podcasts, and marks which of the episodes differ from what the save
+// file has now. The save file is read back through the player's own `populate`,
+// so an episode it can not find counts as changed.
+
+/// What saving to one player will write, worked out before anything is written
+pub struct ChangeSet {
+	/// Every podcast as it will be handed to the player, in order
+	pub podcasts: Vec<Podcast>,
+	/// For each podcast, whether each of its tracks differs from the save file
+	changed: Vec<Vec<bool>>,
+	/// How many podcasts the player has taken while applying
+	handed_out: Cell<usize>,
+}
+
+/// Whether the save file's state of an episode is not the one it will be given
+fn differs(track: &Track, current: Option<&Podcast>) -> bool {
+	current
+		.and_then(|current| {
+			current
+				.tracks
+				.iter()
+				.find(|stored| stored.guid == track.guid)
+		})
+		.map_or(true, |stored| {
+			stored.playing_status != track.playing_status || stored.progress != track.progress
+		})
+}
+
+impl ChangeSet {
+	/// Every episode of `podcasts` counts as changed
+	pub fn new<'a, I: IntoIterator<Item = &'a Podcast>>(podcasts: I) -> Self {
+		let podcasts: Vec<Podcast> = podcasts.into_iter().cloned().collect();
+		let changed = podcasts
+			.iter()
+			.map(|podcast| vec![true; podcast.tracks.len()])
+			.collect();
+		Self {
+			podcasts,
+			changed,
+			handed_out: Cell::new(0),
+		}
+	}
+
+	/// Compare `podcasts` with the state the save file has of each, `None` where the player
+	/// could not read the podcast from it
+	pub fn diff(podcasts: Vec<Podcast>, current: Vec<Option<Podcast>>) -> Self {
+		let changed = podcasts
+			.iter()
+			.zip(current.iter())
+			.map(|(podcast, current)| {
+				podcast
+					.tracks
+					.iter()
+					.map(|track| differs(track, current.as_ref()))
+					.collect()
+			})
+			.collect();
+		Self {
+			podcasts,
+			changed,
+			handed_out: Cell::new(0),
+		}
+	}
+
+	/// The podcasts for the player to write, counting each one it takes
+	pub fn apply_iter<'a>(&'a self) -> impl Iterator<Item = &'a Podcast> + 'a {
+		self.handed_out.set(0);
+		self.podcasts
+			.iter()
+			.inspect(move |_| self.handed_out.set(self.handed_out.get() + 1))
+	}
+
+	/// Podcasts the player finished with before an apply stopped. The one it was working on
+	/// when it failed is not counted
+	pub fn applied(&self) -> &[Podcast] {
+		&self.podcasts[..self.handed_out.get().saturating_sub(1)]
+	}
+
+	/// Every podcast with only its episodes whose state differs from the save file's. Podcasts
+	/// with none are left out, unless they carry settings to write
+	pub fn changed_podcasts(&self) -> Vec<Podcast> {
+		self.podcasts
+			.iter()
+			.zip(self.changed.iter())
+			.filter_map(|(podcast, changed)| {
+				let tracks: Vec<Track> = podcast
+					.tracks
+					.iter()
+					.zip(changed.iter())
+					.filter(|(_, changed)| **changed)
+					.map(|(track, _)| track.clone())
+					.collect();
+				if tracks.is_empty() && podcast.settings.is_empty() {
+					return None;
+				}
+				let mut podcast = podcast.clone();
+				podcast.tracks = tracks;
+				Some(podcast)
+			})
+			.collect()
+	}
+
+	/// Every episode whose state differs from the save file's
+	pub fn updates(&self) -> impl Iterator<Item = (&Podcast, &Track)> {
+		self.podcasts
+			.iter()
+			.zip(self.changed.iter())
+			.flat_map(|(podcast, changed)| {
+				podcast
+					.tracks
+					.iter()
+					.zip(changed.iter())
+					.filter(|(_, changed)| **changed)
+					.map(move |(track, _)| (podcast, track))
+			})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::podcast::PodcastSettings;
+
+	use reqwest::Url;
+
+	fn podcast(progress: &[i32]) -> Podcast {
+		let tracks = progress
+			.iter()
+			.enumerate()
+			.map(|(i, progress)| {
+				let mut track = Track::new(
+					format!("ep-{}", i),
+					Url::parse(&format!("https://example.com/ep{}.mp3", i)).unwrap(),
+					format!("Episode {}", i),
+					None,
+				);
+				track.progress = *progress;
+				track
+			})
+			.collect();
+		Podcast {
+			url: Url::parse("https://example.com/feed.xml").unwrap(),
+			guid: None,
+			title: "Podcast".into(),
+			tracks,
+			settings: PodcastSettings::default(),
+			previous_url: None,
+		}
+	}
+
+	#[test]
+	fn only_episodes_unlike_the_save_file_are_updates() {
+		let changes = ChangeSet::diff(
+			vec![podcast(&[0, 30, 60])],
+			vec![Some(podcast(&[0, 30, 0]))],
+		);
+		let updates: Vec<&str> = changes
+			.updates()
+			.map(|(_, track)| track.guid.as_str())
+			.collect();
+		assert_eq!(updates, vec!["ep-2"]);
+	}
+
+	#[test]
+	fn changed_podcasts_keep_only_changed_episodes() {
+		let changes = ChangeSet::diff(
+			vec![podcast(&[0, 30, 60]), podcast(&[10])],
+			vec![Some(podcast(&[0, 30, 0])), Some(podcast(&[10]))],
+		);
+		let changed = changes.changed_podcasts();
+		assert_eq!(changed.len(), 1);
+		assert_eq!(changed[0].tracks.len(), 1);
+		assert_eq!(changed[0].tracks[0].guid, "ep-2");
+	}
+
+	#[test]
+	fn podcasts_missing_from_the_save_file_are_all_updates() {
+		let changes = ChangeSet::diff(vec![podcast(&[0, 30])], vec![None]);
+		assert_eq!(changes.updates().count(), 2);
+	}
+}
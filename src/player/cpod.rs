@@ -0,0 +1,220 @@
+use crate::fetch;
+use crate::player::{
+	secs_from_f64, IoWriteSeek, NewPlayer, Player, SaveOptions, Subscription, WriteStats,
+};
+use crate::podcast::{PlayingStatus, Podcast, Track};
+use crate::url_key::url_key;
+use crate::warning::{warn, Warning};
+use crate::BoxResult;
+
+use std::fs::File;
+
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+// CPod (the Electron app) keeps its state in a single data.json. Episodes that
+// have been started sit in the `queue` with their progress in seconds, and
+// finished episodes are listed in `completed`. Both reference the episode by
+// feed URL and guid. As with Escapepod, unknown fields are kept in `other`.
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueuedEpisode {
+	feed_url: String,
+	guid: String,
+	#[serde(default)]
+	url: String,
+	#[serde(default)]
+	title: String,
+	#[serde(default)]
+	progress: f64,
+	#[serde(flatten)]
+	other: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CompletedEpisode {
+	feed_url: String,
+	guid: String,
+	#[serde(flatten)]
+	other: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Show {
+	title: String,
+	feed_url: String,
+	#[serde(flatten)]
+	other: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Data {
+	#[serde(default)]
+	subscriptions: Vec<Show>,
+	#[serde(default)]
+	queue: Vec<QueuedEpisode>,
+	#[serde(default)]
+	completed: Vec<CompletedEpisode>,
+	#[serde(flatten)]
+	other: Map<String, Value>,
+}
+
+pub struct CPod {
+	data: Data,
+}
+
+fn same_url(a: &str, b: &Url) -> bool {
+	url_key(a) == url_key(b.as_str())
+}
+
+impl CPod {
+	fn has_show(&self, url: &Url) -> bool {
+		self.data
+			.subscriptions
+			.iter()
+			.any(|show| same_url(show.feed_url.as_str(), url))
+	}
+
+	fn queue_position(&self, url: &Url, track: &Track) -> Option<usize> {
+		self.data
+			.queue
+			.iter()
+			.position(|ep| ep.guid == track.guid && same_url(ep.feed_url.as_str(), url))
+	}
+
+	fn is_completed(&self, url: &Url, track: &Track) -> bool {
+		self.data
+			.completed
+			.iter()
+			.any(|ep| ep.guid == track.guid && same_url(ep.feed_url.as_str(), url))
+	}
+}
+
+impl Player for CPod {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		if !self.has_show(&podcast.url) {
+			warn(
+				Warning::UnmatchedTrack,
+				format!("Podcast not found: {}", fetch::redact(&podcast.url)),
+			);
+			return Ok(podcast);
+		}
+
+		for track in podcast.tracks.iter_mut() {
+			let queued = self.queue_position(&podcast.url, track);
+			let completed = self.is_completed(&podcast.url, track);
+
+			if let Some(i) = queued {
+				track.progress = secs_from_f64(self.data.queue[i].progress);
+			}
+
+			track.playing_status = if completed {
+				PlayingStatus::Played
+			} else if track.progress > 0 {
+				PlayingStatus::Playing
+			} else {
+				PlayingStatus::Unplayed
+			};
+		}
+
+		Ok(podcast)
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		Ok(self
+			.data
+			.subscriptions
+			.iter()
+			.map(|show| Subscription {
+				url: Url::parse(show.feed_url.as_str()).ok(),
+				title: show.title.clone(),
+			})
+			.collect())
+	}
+
+	fn save(
+		mut self: Box<Self>,
+		podcasts: &mut dyn Iterator<Item = &'_ Podcast>,
+		w: &mut dyn IoWriteSeek,
+		_options: &SaveOptions,
+	) -> BoxResult<WriteStats> {
+		for podcast in podcasts {
+			println!(
+				"Saving '{}' ({})",
+				podcast.title,
+				fetch::redact(&podcast.url)
+			);
+			if !self.has_show(&podcast.url) {
+				warn(
+					Warning::UnmatchedTrack,
+					format!("Podcast not found: {}", fetch::redact(&podcast.url)),
+				);
+				continue;
+			}
+
+			for track in podcast.tracks.iter() {
+				let queued = self.queue_position(&podcast.url, track);
+				let completed = self.is_completed(&podcast.url, track);
+
+				match (track.playing_status, queued) {
+					// Finished episodes leave the queue
+					(PlayingStatus::Played, Some(i)) => {
+						self.data.queue.remove(i);
+					}
+					(PlayingStatus::Playing, Some(i)) => {
+						self.data.queue[i].progress = track.progress as f64;
+					}
+					(PlayingStatus::Playing, None) => self.data.queue.push(QueuedEpisode {
+						feed_url: podcast.url.to_string(),
+						guid: track.guid.clone(),
+						url: track.url.to_string(),
+						title: track.title.clone(),
+						progress: track.progress as f64,
+						other: Map::new(),
+					}),
+					// Keep episodes the user queued but has not started
+					(PlayingStatus::Unplayed, Some(i)) => self.data.queue[i].progress = 0.0,
+					(_, None) => {}
+				}
+
+				if track.playing_status == PlayingStatus::Played && !completed {
+					self.data.completed.push(CompletedEpisode {
+						feed_url: podcast.url.to_string(),
+						guid: track.guid.clone(),
+						other: Map::new(),
+					});
+				} else if track.playing_status != PlayingStatus::Played && completed {
+					let url = &podcast.url;
+					self.data.completed.retain(|ep| {
+						!(ep.guid == track.guid && same_url(ep.feed_url.as_str(), url))
+					});
+				}
+			}
+		}
+
+		serde_json::to_writer_pretty(w, &self.data)?;
+		Ok(WriteStats::default())
+	}
+}
+
+impl NewPlayer for CPod {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		Ok(Box::new(Self {
+			data: serde_json::from_reader(File::open(path)?)?,
+		}))
+	}
+
+	fn name() -> &'static str {
+		"CPod"
+	}
+	fn cli_name() -> &'static str {
+		"cpod"
+	}
+	fn file_names() -> &'static [&'static str] {
+		&["data.json"]
+	}
+}
@@ -0,0 +1,309 @@
+use crate::csv;
+use crate::fetch;
+use crate::player::{IoWriteSeek, NewPlayer, Player, SaveOptions, Subscription, WriteStats};
+use crate::podcast::{PlayingStatus, Podcast, PodcastSettings, Track};
+use crate::url_key::url_key;
+use crate::warning::{warn, Warning};
+use crate::{BoxResult, Error};
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use reqwest::Url;
+
+// One row per episode, for looking at or editing a history in a spreadsheet.
+// Columns are found by their header so they can be reordered, and extra
+// columns are ignored. Like the JSON file it holds the whole feed, so it can
+// be converted from without an OPML file. Settings are not kept.
+
+const COLUMNS: [&str; 8] = [
+	"podcast", "feed_url", "episode", "guid", "url", "status", "progress", "duration",
+];
+
+fn status_name(status: PlayingStatus) -> &'static str {
+	match status {
+		PlayingStatus::Unplayed => "unplayed",
+		PlayingStatus::Playing => "playing",
+		PlayingStatus::Played => "played",
+	}
+}
+
+fn parse_status(name: &str) -> Result<PlayingStatus, Error> {
+	match name.trim().to_lowercase().as_str() {
+		"unplayed" | "" => Ok(PlayingStatus::Unplayed),
+		"playing" => Ok(PlayingStatus::Playing),
+		"played" => Ok(PlayingStatus::Played),
+		_ => Err(Error::InvalidPlayingStatus),
+	}
+}
+
+struct Row {
+	podcast: String,
+	feed_url: String,
+	episode: String,
+	guid: String,
+	url: String,
+	status: PlayingStatus,
+	progress: i32,
+	duration: Option<i32>,
+}
+
+impl Row {
+	fn from_fields(fields: &HashMap<&str, String>) -> BoxResult<Self> {
+		let get = |column: &str| fields.get(column).cloned().unwrap_or_default();
+		let number = |column: &str| -> BoxResult<Option<i32>> {
+			match fields.get(column).map(|value| value.trim()) {
+				Some(value) if !value.is_empty() => Ok(Some(value.parse()?)),
+				_ => Ok(None),
+			}
+		};
+
+		// Rows are grouped into podcasts by their feed, so one without a usable feed URL is of no use
+		Url::parse(&get("feed_url"))?;
+
+		Ok(Self {
+			podcast: get("podcast"),
+			feed_url: get("feed_url"),
+			episode: get("episode"),
+			guid: get("guid"),
+			url: get("url"),
+			status: parse_status(&get("status"))?,
+			progress: std::cmp::max(number("progress")?.unwrap_or(0), 0),
+			duration: number("duration")?,
+		})
+	}
+
+	fn from_track(podcast: &Podcast, track: &Track) -> Self {
+		Self {
+			podcast: podcast.title.clone(),
+			feed_url: podcast.url.to_string(),
+			episode: track.title.clone(),
+			guid: track.guid.clone(),
+			url: track.url.to_string(),
+			status: track.playing_status,
+			progress: track.progress,
+			duration: track.duration,
+		}
+	}
+
+	fn fields(&self) -> [String; 8] {
+		[
+			self.podcast.clone(),
+			self.feed_url.clone(),
+			self.episode.clone(),
+			self.guid.clone(),
+			self.url.clone(),
+			status_name(self.status).to_string(),
+			self.progress.to_string(),
+			self.duration
+				.map_or(String::new(), |duration| duration.to_string()),
+		]
+	}
+
+	fn is_podcast(&self, url: &Url) -> bool {
+		url_key(self.feed_url.as_str()) == url_key(url.as_str())
+	}
+
+	fn is_track(&self, track: &Track) -> bool {
+		(!self.guid.is_empty() && self.guid == track.guid) || track.has_url(self.url.as_str())
+	}
+}
+
+pub struct CsvFile {
+	rows: Vec<Row>,
+}
+
+impl Player for CsvFile {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		let rows: Vec<&Row> = self
+			.rows
+			.iter()
+			.filter(|row| row.is_podcast(&podcast.url))
+			.collect();
+		if rows.is_empty() {
+			warn(
+				Warning::UnmatchedTrack,
+				format!("Podcast not found: {}", fetch::redact(&podcast.url)),
+			);
+			return Ok(podcast);
+		}
+
+		for track in podcast.tracks.iter_mut() {
+			match rows.iter().find(|row| row.is_track(track)) {
+				Some(row) => {
+					track.progress = row.progress;
+					track.playing_status = row.status;
+				}
+				None => warn(
+					Warning::UnmatchedTrack,
+					format!("Track not found: {:?}", track),
+				),
+			}
+		}
+
+		Ok(podcast)
+	}
+
+	fn stored_podcasts(&self) -> BoxResult<Option<Vec<Podcast>>> {
+		// Podcasts are in the order their first row appears
+		let mut podcasts: Vec<Podcast> = Vec::new();
+		for row in self.rows.iter() {
+			// Rows with only a guid can be matched to a feed, but are no track without it
+			let url = match Url::parse(&row.url) {
+				Ok(url) => url,
+				Err(err) => {
+					warn(
+						Warning::SkippedRow,
+						format!(
+							"Skipping episode '{}' ({}) of '{}', its url can not be read: {}",
+							row.episode, row.guid, row.podcast, err
+						),
+					);
+					continue;
+				}
+			};
+			let track = Track {
+				guid: row.guid.clone(),
+				url,
+				title: row.episode.clone(),
+				duration: row.duration,
+				published: None,
+				chapters: Vec::new(),
+				season: None,
+				episode: None,
+				transcripts: Vec::new(),
+				alternate_urls: Vec::new(),
+				progress: row.progress,
+				playing_status: row.status,
+				last_played: None,
+			};
+
+			match podcasts
+				.iter_mut()
+				.find(|podcast| row.is_podcast(&podcast.url))
+			{
+				Some(podcast) => podcast.tracks.push(track),
+				None => podcasts.push(Podcast {
+					url: Url::parse(&row.feed_url)?,
+					title: row.podcast.clone(),
+					tracks: vec![track],
+					settings: PodcastSettings::default(),
+					previous_url: None,
+					guid: None,
+				}),
+			}
+		}
+		Ok(Some(podcasts))
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		let mut subs: Vec<Subscription> = Vec::new();
+		for row in self.rows.iter() {
+			if !subs
+				.iter()
+				.any(|sub| sub.url.as_ref().map_or(false, |url| row.is_podcast(url)))
+			{
+				subs.push(Subscription {
+					url: Some(Url::parse(&row.feed_url)?),
+					title: row.podcast.clone(),
+				});
+			}
+		}
+		Ok(subs)
+	}
+
+	fn episode_count(&self) -> BoxResult<Option<usize>> {
+		Ok(Some(self.rows.len()))
+	}
+
+	// The rows of podcasts given replace those in the file
+	fn writes_whole_podcasts(&self) -> bool {
+		true
+	}
+
+	fn save(
+		mut self: Box<Self>,
+		podcasts: &mut dyn Iterator<Item = &'_ Podcast>,
+		w: &mut dyn IoWriteSeek,
+		_options: &SaveOptions,
+	) -> BoxResult<WriteStats> {
+		for podcast in podcasts {
+			println!(
+				"Saving '{}' ({})",
+				podcast.title,
+				fetch::redact(&podcast.url)
+			);
+
+			// The rows of podcasts already in the file are replaced, the others are kept
+			self.rows.retain(|row| !row.is_podcast(&podcast.url));
+			self.rows.extend(
+				podcast
+					.tracks
+					.iter()
+					.map(|track| Row::from_track(podcast, track)),
+			);
+		}
+
+		csv::write_row(w, &COLUMNS)?;
+		for row in self.rows.iter() {
+			csv::write_row(w, &row.fields())?;
+		}
+		Ok(WriteStats::default())
+	}
+}
+
+impl NewPlayer for CsvFile {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		// A file that does not exist yet is an empty one to write to
+		if !Path::new(path).exists() {
+			return Ok(Box::new(Self { rows: Vec::new() }));
+		}
+
+		let mut lines = csv::read_numbered(&std::fs::read_to_string(path)?).into_iter();
+		let header: Vec<String> = lines
+			.next()
+			.map(|(_, header)| header)
+			.unwrap_or_default()
+			.iter()
+			.map(|name| name.trim().to_lowercase())
+			.collect();
+
+		if !header.iter().any(|name| name == "feed_url")
+			|| !header.iter().any(|name| name == "guid" || name == "url")
+		{
+			return Err(Error::UnknownSchema(
+				"CSV file",
+				String::from("columns ") + &header.join(", "),
+			)
+			.into());
+		}
+
+		let mut rows = Vec::new();
+		for (number, line) in lines {
+			let fields: HashMap<&str, String> = header
+				.iter()
+				.map(String::as_str)
+				.zip(line.into_iter())
+				.collect();
+			match Row::from_fields(&fields) {
+				Ok(row) => rows.push(row),
+				Err(err) => warn(
+					Warning::SkippedRow,
+					format!("Skipping line {} of {}: {}", number, path, err),
+				),
+			}
+		}
+
+		Ok(Box::new(Self { rows }))
+	}
+
+	fn name() -> &'static str {
+		"CSV file"
+	}
+	fn cli_name() -> &'static str {
+		"csv"
+	}
+	fn writes_through() -> bool {
+		true
+	}
+}
@@ -0,0 +1,129 @@
+use crate::player::archive::SQLiteArchive;
+use crate::player::{
+	count_rows, find_by_urls, populate_tracks, secs_from_millis, NewPlayer, Player, StatusEncoding,
+	Subscription,
+};
+use crate::podcast::{PlayingStatus, Podcast};
+use crate::warning::{warn, Warning};
+use crate::BoxResult;
+
+use std::borrow::Borrow;
+
+use reqwest::Url;
+use rusqlite::Connection;
+
+const DB_FILE: &str = "doggcatcher.db";
+
+const IS_READ: StatusEncoding = StatusEncoding {
+	field: "items.is_read",
+	values: &[
+		(PlayingStatus::Unplayed, 0),
+		(PlayingStatus::Playing, 0),
+		(PlayingStatus::Played, 1),
+	],
+};
+
+pub struct DoggCatcher {
+	archive: SQLiteArchive,
+}
+
+impl DoggCatcher {
+	fn get_feed(&self, url: &Url) -> BoxResult<i64> {
+		let conn: &Connection = self.archive.borrow();
+		let mut stmt = conn.prepare("SELECT id FROM feeds WHERE url_key(url) = url_key(:url)")?;
+		let mut rows = stmt.query_named(&[(":url", &url.to_string())])?;
+		let first_row = rows.next()?.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+		Ok(first_row.get(0)?)
+	}
+
+	fn get_item(&self, feed_id: i64, enclosure_url: &Url) -> rusqlite::Result<(i64, i64)> {
+		let conn: &Connection = self.archive.borrow();
+		let mut stmt = conn
+			.prepare("SELECT is_read, position FROM items WHERE feed_id = :feed_id AND url_key(enclosure_url) = url_key(:enclosure_url)")?;
+		let mut rows = stmt.query_named(&[
+			(":feed_id", &feed_id),
+			(":enclosure_url", &enclosure_url.to_string()),
+		])?;
+		let first_row = rows.next()?;
+		first_row
+			.ok_or(rusqlite::Error::QueryReturnedNoRows)
+			.and_then(|row| Ok((row.get(0)?, row.get(1)?)))
+	}
+}
+
+impl Player for DoggCatcher {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		let id = self.get_feed(&podcast.url)?;
+
+		populate_tracks(&mut podcast, |track| {
+			match find_by_urls(track, |url| self.get_item(id, url)).map(|(_, found)| found) {
+				Ok((is_read, position_ms)) => {
+					track.progress = secs_from_millis(position_ms);
+
+					track.playing_status = if IS_READ.decode(is_read)? == PlayingStatus::Played {
+						PlayingStatus::Played
+					} else if track.progress > 0 {
+						PlayingStatus::Playing
+					} else {
+						PlayingStatus::Unplayed
+					};
+
+					Ok(())
+				}
+				Err(rusqlite::Error::QueryReturnedNoRows) => {
+					warn(
+						Warning::UnmatchedTrack,
+						format!("Track not found: {:?}", track),
+					);
+					Ok(())
+				}
+				Err(err) => Err(err),
+			}?;
+			Ok(())
+		});
+
+		Ok(podcast)
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		let conn: &Connection = self.archive.borrow();
+		let mut stmt = conn.prepare("SELECT url, title FROM feeds")?;
+		let mut rows = stmt.query(rusqlite::NO_PARAMS)?;
+
+		let mut subs = Vec::new();
+		while let Some(row) = rows.next()? {
+			let url: String = row.get(0)?;
+			subs.push(Subscription {
+				url: Some(Url::parse(url.as_str())?),
+				title: row.get(1)?,
+			});
+		}
+		Ok(subs)
+	}
+
+	fn episode_count(&self) -> BoxResult<Option<usize>> {
+		let conn: &Connection = self.archive.borrow();
+		count_rows(conn, "items").map(Some)
+	}
+}
+
+impl NewPlayer for DoggCatcher {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		Ok(Box::new(Self {
+			archive: SQLiteArchive::open(path, DB_FILE)?,
+		}))
+	}
+
+	fn name() -> &'static str {
+		"DoggCatcher"
+	}
+	fn cli_name() -> &'static str {
+		"doggcatcher"
+	}
+	fn read_only() -> bool {
+		true
+	}
+	fn status_encodings() -> &'static [StatusEncoding] {
+		&[IS_READ]
+	}
+}
@@ -0,0 +1,181 @@
+use crate::fetch;
+use crate::player::{
+	count_rows, populate_tracks, save_track, secs_from_f64, IoWriteSeek, NewPlayer, Player,
+	SaveOptions, StatusEncoding, Subscription, WriteStats,
+};
+use crate::podcast::{PlayingStatus, Podcast};
+use crate::warning::{warn, Warning};
+use crate::{BoxResult, SQLLiteDatabase};
+
+use std::borrow::Borrow;
+
+use reqwest::Url;
+use rusqlite::Connection;
+
+const PLAYED: StatusEncoding = StatusEncoding {
+	field: "ZEPISODE.ZPLAYED",
+	values: &[
+		(PlayingStatus::Unplayed, 0),
+		(PlayingStatus::Playing, 0),
+		(PlayingStatus::Played, 1),
+	],
+};
+
+// Downcast backups are a Core Data SQLite store, so every table and column
+// carries the Z prefix and rows reference each other by their Z_PK.
+pub struct Downcast {
+	db: SQLLiteDatabase,
+}
+
+impl Downcast {
+	fn get_podcast(&self, url: &Url) -> BoxResult<i64> {
+		let conn: &Connection = self.db.borrow();
+		let mut stmt =
+			conn.prepare("SELECT Z_PK FROM ZPODCAST WHERE url_key(ZFEEDURL) = url_key(:url)")?;
+		let mut rows = stmt.query_named(&[(":url", &url.to_string())])?;
+		let first_row = rows.next()?.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+		Ok(first_row.get(0)?)
+	}
+
+	fn get_episode(&self, podcast_id: i64, guid: &String) -> rusqlite::Result<(bool, f64)> {
+		let conn: &Connection = self.db.borrow();
+		let mut stmt = conn
+			.prepare("SELECT ZPLAYED, ZPLAYBACKPOSITION FROM ZEPISODE WHERE ZPODCAST = :podcast AND ZGUID = :guid")?;
+		let mut rows = stmt.query_named(&[(":podcast", &podcast_id), (":guid", guid)])?;
+		let first_row = rows.next()?;
+		first_row
+			.ok_or(rusqlite::Error::QueryReturnedNoRows)
+			.and_then(|row| Ok((row.get(0)?, row.get(1)?)))
+	}
+
+	fn update_episode(
+		&self,
+		podcast_id: i64,
+		guid: &String,
+		played: bool,
+		position: i32,
+	) -> rusqlite::Result<()> {
+		let conn: &Connection = self.db.borrow();
+		conn.execute_named(
+			"UPDATE ZEPISODE SET ZPLAYED = :played, ZPLAYBACKPOSITION = :position WHERE ZPODCAST = :podcast AND ZGUID = :guid",
+			&[
+				(":podcast", &podcast_id),
+				(":guid", guid),
+				(":played", &played),
+				(":position", &(position as f64)),
+			],
+		).map(|_| ())
+	}
+}
+
+impl Player for Downcast {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		let id = self.get_podcast(&podcast.url)?;
+
+		populate_tracks(&mut podcast, |track| {
+			match self.get_episode(id, &track.guid) {
+				Ok((played, position)) => {
+					track.progress = secs_from_f64(position);
+
+					track.playing_status = if played {
+						PlayingStatus::Played
+					} else if track.progress > 0 {
+						PlayingStatus::Playing
+					} else {
+						PlayingStatus::Unplayed
+					};
+
+					Ok(())
+				}
+				Err(rusqlite::Error::QueryReturnedNoRows) => {
+					warn(
+						Warning::UnmatchedTrack,
+						format!("Track not found: {:?}", track),
+					);
+					Ok(())
+				}
+				Err(err) => Err(err),
+			}?;
+			Ok(())
+		});
+
+		Ok(podcast)
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		let conn: &Connection = self.db.borrow();
+		let mut stmt = conn.prepare("SELECT ZFEEDURL, ZTITLE FROM ZPODCAST")?;
+		let mut rows = stmt.query(rusqlite::NO_PARAMS)?;
+
+		let mut subs = Vec::new();
+		while let Some(row) = rows.next()? {
+			let url: String = row.get(0)?;
+			subs.push(Subscription {
+				url: Some(Url::parse(url.as_str())?),
+				title: row.get(1)?,
+			});
+		}
+		Ok(subs)
+	}
+
+	fn unsubscribe(&mut self, _sub: &Subscription) -> BoxResult<bool> {
+		// Downcast has no unsubscribed flag, and its episodes can not be reached once the feed
+		// is deleted, so the history would be lost with it
+		Ok(false)
+	}
+
+	fn save(
+		self: Box<Self>,
+		podcasts: &mut dyn Iterator<Item = &'_ Podcast>,
+		w: &mut dyn IoWriteSeek,
+		_options: &SaveOptions,
+	) -> BoxResult<WriteStats> {
+		let mut stats = WriteStats::default();
+
+		for podcast in podcasts {
+			println!(
+				"Saving '{}' ({})",
+				podcast.title,
+				fetch::redact(&podcast.url)
+			);
+			let id = self.get_podcast(&podcast.url)?;
+
+			for track in podcast.tracks.iter() {
+				let played = PLAYED.encode(track.playing_status) != 0;
+				save_track(
+					track,
+					self.update_episode(id, &track.guid, played, track.progress),
+					&mut stats,
+				);
+			}
+		}
+
+		// Copy temp file to output
+		let mut temp_file = self.db.into_file()?;
+		std::io::copy(&mut temp_file, w)?;
+		Ok(stats)
+	}
+
+	fn episode_count(&self) -> BoxResult<Option<usize>> {
+		let conn: &Connection = self.db.borrow();
+		count_rows(conn, "ZEPISODE").map(Some)
+	}
+}
+
+impl NewPlayer for Downcast {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		Ok(Box::new(Self {
+			db: SQLLiteDatabase::open(path)?,
+		}))
+	}
+
+	fn name() -> &'static str {
+		"Downcast"
+	}
+	fn cli_name() -> &'static str {
+		"downcast"
+	}
+	fn status_encodings() -> &'static [StatusEncoding] {
+		&[PLAYED]
+	}
+}
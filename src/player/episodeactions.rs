@@ -0,0 +1,173 @@
+use crate::fetch;
+use crate::player::gpodder::{latest_actions, populate_from, EpisodeAction};
+use crate::player::{IoWriteSeek, NewPlayer, Player, SaveOptions, Subscription, WriteStats};
+use crate::podcast::Podcast;
+use crate::url_key::url_key;
+use crate::BoxResult;
+
+use std::fs::File;
+use std::marker::PhantomData;
+use std::path::Path;
+
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+// gpodder episode actions kept in a plain file instead of on a server. Several
+// podcatchers and sync tools read and write these, AntennaPod among them, so
+// they are a simple way to move history between players without touching
+// their databases. Saving keeps every existing action and appends one for each
+// episode whose state changed.
+
+/// What a flavour of episode actions file is called
+pub trait Flavour {
+	const NAME: &'static str;
+	const CLI_NAME: &'static str;
+}
+
+/// Any file of episode actions, as uploaded to or downloaded from a gpodder server
+pub struct Gpodder;
+
+impl Flavour for Gpodder {
+	const NAME: &'static str = "gpodder episode actions file";
+	const CLI_NAME: &'static str = "gpodder-actions";
+}
+
+/// AntennaPod's play history import and export
+pub struct AntennaPod;
+
+impl Flavour for AntennaPod {
+	const NAME: &'static str = "AntennaPod episode actions";
+	const CLI_NAME: &'static str = "antennapod-actions";
+}
+
+/// Files are usually a bare list, as uploaded, but the response of the episodes
+/// endpoint wraps it with the time to ask for changes since. Files are written
+/// back in the layout they were read in
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Layout {
+	List(Vec<EpisodeAction>),
+	Wrapped {
+		actions: Vec<EpisodeAction>,
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		timestamp: Option<i64>,
+	},
+}
+
+pub struct EpisodeActionsFile<F: Flavour> {
+	layout: Layout,
+	flavour: PhantomData<F>,
+}
+
+pub type AntennaPodActions = EpisodeActionsFile<AntennaPod>;
+pub type GpodderActions = EpisodeActionsFile<Gpodder>;
+
+impl<F: Flavour> EpisodeActionsFile<F> {
+	fn actions(&self) -> &Vec<EpisodeAction> {
+		match &self.layout {
+			Layout::List(actions) => actions,
+			Layout::Wrapped { actions, .. } => actions,
+		}
+	}
+
+	fn actions_mut(&mut self) -> &mut Vec<EpisodeAction> {
+		match &mut self.layout {
+			Layout::List(actions) => actions,
+			Layout::Wrapped { actions, .. } => actions,
+		}
+	}
+}
+
+impl<F: Flavour> Player for EpisodeActionsFile<F> {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		let latest = latest_actions(self.actions().iter().cloned(), &podcast.url);
+		populate_from(&latest, &mut podcast);
+		Ok(podcast)
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		// The file only names feeds by URL, in the order they first appear
+		let mut subs: Vec<Subscription> = Vec::new();
+		for action in self.actions().iter() {
+			let key = url_key(action.podcast());
+			if !subs.iter().any(|sub| {
+				sub.url
+					.as_ref()
+					.map_or(false, |url| url_key(url.as_str()) == key)
+			}) {
+				subs.push(Subscription {
+					url: Some(Url::parse(action.podcast())?),
+					title: action.podcast().into(),
+				});
+			}
+		}
+		Ok(subs)
+	}
+
+	fn episode_count(&self) -> BoxResult<Option<usize>> {
+		let mut episodes: Vec<String> = self
+			.actions()
+			.iter()
+			.map(|action| url_key(action.episode()))
+			.collect();
+		episodes.sort();
+		episodes.dedup();
+		Ok(Some(episodes.len()))
+	}
+
+	fn save(
+		mut self: Box<Self>,
+		podcasts: &mut dyn Iterator<Item = &'_ Podcast>,
+		w: &mut dyn IoWriteSeek,
+		options: &SaveOptions,
+	) -> BoxResult<WriteStats> {
+		let timestamp = options.clock.iso8601();
+		let mut added = Vec::new();
+
+		for podcast in podcasts {
+			println!(
+				"Saving '{}' ({})",
+				podcast.title,
+				fetch::redact(&podcast.url)
+			);
+			let current = latest_actions(self.actions().iter().cloned(), &podcast.url);
+
+			added.extend(
+				podcast.tracks.iter().filter_map(|track| {
+					EpisodeAction::update(&current, podcast, track, &timestamp)
+				}),
+			);
+		}
+
+		println!("Adding {} episode actions", added.len());
+		self.actions_mut().append(&mut added);
+		serde_json::to_writer_pretty(w, &self.layout)?;
+		Ok(WriteStats::default())
+	}
+}
+
+impl<F: Flavour + 'static> NewPlayer for EpisodeActionsFile<F> {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		// A file that does not exist yet is an empty one to write to
+		let layout = if Path::new(path).exists() {
+			serde_json::from_reader(File::open(path)?)?
+		} else {
+			Layout::List(Vec::new())
+		};
+
+		Ok(Box::new(Self {
+			layout,
+			flavour: PhantomData,
+		}))
+	}
+
+	fn name() -> &'static str {
+		F::NAME
+	}
+	fn cli_name() -> &'static str {
+		F::CLI_NAME
+	}
+	fn writes_through() -> bool {
+		true
+	}
+}
@@ -0,0 +1,176 @@
+use crate::fetch;
+use crate::player::{
+	secs_from_millis, IoWriteSeek, NewPlayer, Player, SaveOptions, Subscription, WriteStats,
+};
+use crate::podcast::{PlayingStatus, Podcast, Track};
+use crate::url_key::url_key;
+use crate::warning::{warn, Warning};
+use crate::BoxResult;
+
+use std::fs::File;
+
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+// Escapepod keeps its whole library in one JSON document, which makes this the
+// simplest backend: read it all in `new`, look things up in `populate`, edit it
+// in place and write it back out in `save`. Fields this tool does not know
+// about are kept in `other` so they survive the round trip untouched.
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Episode {
+	guid: String,
+	url: String,
+	#[serde(default)]
+	position_ms: i64,
+	#[serde(default)]
+	played: bool,
+	#[serde(flatten)]
+	other: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Show {
+	title: String,
+	feed_url: String,
+	#[serde(default)]
+	episodes: Vec<Episode>,
+	#[serde(flatten)]
+	other: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Collection {
+	podcasts: Vec<Show>,
+	#[serde(flatten)]
+	other: Map<String, Value>,
+}
+
+pub struct Escapepod {
+	collection: Collection,
+}
+
+impl Escapepod {
+	fn find_show(&self, url: &Url) -> Option<&Show> {
+		self.collection
+			.podcasts
+			.iter()
+			.find(|show| url_key(show.feed_url.as_str()) == url_key(url.as_str()))
+	}
+
+	fn find_show_mut(&mut self, url: &Url) -> Option<&mut Show> {
+		self.collection
+			.podcasts
+			.iter_mut()
+			.find(|show| url_key(show.feed_url.as_str()) == url_key(url.as_str()))
+	}
+
+	fn is_episode(episode: &Episode, track: &Track) -> bool {
+		episode.guid == track.guid || track.has_url(episode.url.as_str())
+	}
+}
+
+impl Player for Escapepod {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		let show = match self.find_show(&podcast.url) {
+			Some(show) => show,
+			None => {
+				warn(
+					Warning::UnmatchedTrack,
+					format!("Podcast not found: {}", fetch::redact(&podcast.url)),
+				);
+				return Ok(podcast);
+			}
+		};
+
+		for track in podcast.tracks.iter_mut() {
+			match show.episodes.iter().find(|ep| Self::is_episode(ep, track)) {
+				Some(episode) => {
+					track.progress = secs_from_millis(episode.position_ms);
+					track.playing_status = if episode.played {
+						PlayingStatus::Played
+					} else if track.progress > 0 {
+						PlayingStatus::Playing
+					} else {
+						PlayingStatus::Unplayed
+					};
+				}
+				None => warn(
+					Warning::UnmatchedTrack,
+					format!("Track not found: {:?}", track),
+				),
+			}
+		}
+
+		Ok(podcast)
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		Ok(self
+			.collection
+			.podcasts
+			.iter()
+			.map(|show| Subscription {
+				url: Url::parse(show.feed_url.as_str()).ok(),
+				title: show.title.clone(),
+			})
+			.collect())
+	}
+
+	fn save(
+		mut self: Box<Self>,
+		podcasts: &mut dyn Iterator<Item = &'_ Podcast>,
+		w: &mut dyn IoWriteSeek,
+		_options: &SaveOptions,
+	) -> BoxResult<WriteStats> {
+		for podcast in podcasts {
+			println!(
+				"Saving '{}' ({})",
+				podcast.title,
+				fetch::redact(&podcast.url)
+			);
+			let show = match self.find_show_mut(&podcast.url) {
+				Some(show) => show,
+				None => {
+					warn(
+						Warning::UnmatchedTrack,
+						format!("Podcast not found: {}", fetch::redact(&podcast.url)),
+					);
+					continue;
+				}
+			};
+
+			for track in podcast.tracks.iter() {
+				if let Some(episode) = show
+					.episodes
+					.iter_mut()
+					.find(|ep| Self::is_episode(ep, track))
+				{
+					episode.position_ms = track.progress as i64 * 1000;
+					episode.played = track.playing_status == PlayingStatus::Played;
+				}
+			}
+		}
+
+		serde_json::to_writer_pretty(w, &self.collection)?;
+		Ok(WriteStats::default())
+	}
+}
+
+impl NewPlayer for Escapepod {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		Ok(Box::new(Self {
+			collection: serde_json::from_reader(File::open(path)?)?,
+		}))
+	}
+
+	fn name() -> &'static str {
+		"Escapepod"
+	}
+	fn cli_name() -> &'static str {
+		"escapepod"
+	}
+}
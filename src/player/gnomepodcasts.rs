@@ -0,0 +1,195 @@
+use crate::fetch;
+use crate::player::{
+	count_rows, populate_tracks, save_track, IoWriteSeek, NewPlayer, Player, SaveOptions,
+	StatusEncoding, Subscription, WriteStats,
+};
+use crate::podcast::{PlayingStatus, Podcast};
+use crate::warning::{warn, Warning};
+use crate::{BoxResult, SQLLiteDatabase};
+
+use std::borrow::Borrow;
+
+use reqwest::Url;
+use rusqlite::Connection;
+
+// episodes.played holds the unix time the episode was marked as played, or NULL
+const PLAYED: StatusEncoding = StatusEncoding {
+	field: "episodes.played IS NOT NULL",
+	values: &[
+		(PlayingStatus::Unplayed, 0),
+		(PlayingStatus::Playing, 0),
+		(PlayingStatus::Played, 1),
+	],
+};
+
+// GNOME Podcasts stores feed URLs in the source table, which shows point at
+// through source_id. Play positions are in seconds. The database normally
+// lives at ~/.local/share/gnome-podcasts/podcasts.db
+pub struct GnomePodcasts {
+	db: SQLLiteDatabase,
+}
+
+impl GnomePodcasts {
+	fn get_show(&self, url: &Url) -> BoxResult<i64> {
+		let conn: &Connection = self.db.borrow();
+		let mut stmt = conn.prepare(
+			"SELECT shows.id FROM shows JOIN source ON source.id = shows.source_id WHERE url_key(source.uri) = url_key(:url)",
+		)?;
+		let mut rows = stmt.query_named(&[(":url", &url.to_string())])?;
+		let first_row = rows.next()?.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+		Ok(first_row.get(0)?)
+	}
+
+	fn get_episode(&self, show_id: i64, guid: &String) -> rusqlite::Result<(bool, i64)> {
+		let conn: &Connection = self.db.borrow();
+		let mut stmt = conn.prepare(
+			"SELECT played IS NOT NULL, play_position FROM episodes WHERE show_id = :show AND guid = :guid",
+		)?;
+		let mut rows = stmt.query_named(&[(":show", &show_id), (":guid", guid)])?;
+		let first_row = rows.next()?;
+		first_row
+			.ok_or(rusqlite::Error::QueryReturnedNoRows)
+			.and_then(|row| Ok((row.get(0)?, row.get(1)?)))
+	}
+
+	fn update_episode(
+		&self,
+		show_id: i64,
+		guid: &String,
+		played: bool,
+		position: i32,
+		now: i64,
+	) -> rusqlite::Result<()> {
+		let conn: &Connection = self.db.borrow();
+		// Keep the original played time of episodes that were already played
+		conn.execute_named(
+			"UPDATE episodes SET played = CASE WHEN :played THEN COALESCE(played, :now) ELSE NULL END, play_position = :position WHERE show_id = :show AND guid = :guid",
+			&[
+				(":show", &show_id),
+				(":guid", guid),
+				(":played", &played),
+				(":now", &now),
+				(":position", &position),
+			],
+		)
+		.map(|_| ())
+	}
+}
+
+impl Player for GnomePodcasts {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		let id = self.get_show(&podcast.url)?;
+
+		populate_tracks(&mut podcast, |track| {
+			match self.get_episode(id, &track.guid) {
+				Ok((played, position)) => {
+					track.progress = std::cmp::max(position as i32, 0);
+
+					track.playing_status = if played {
+						PlayingStatus::Played
+					} else if track.progress > 0 {
+						PlayingStatus::Playing
+					} else {
+						PlayingStatus::Unplayed
+					};
+
+					Ok(())
+				}
+				Err(rusqlite::Error::QueryReturnedNoRows) => {
+					warn(
+						Warning::UnmatchedTrack,
+						format!("Track not found: {:?}", track),
+					);
+					Ok(())
+				}
+				Err(err) => Err(err),
+			}?;
+			Ok(())
+		});
+
+		Ok(podcast)
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		let conn: &Connection = self.db.borrow();
+		let mut stmt = conn.prepare(
+			"SELECT source.uri, shows.title FROM shows JOIN source ON source.id = shows.source_id",
+		)?;
+		let mut rows = stmt.query(rusqlite::NO_PARAMS)?;
+
+		let mut subs = Vec::new();
+		while let Some(row) = rows.next()? {
+			let url: String = row.get(0)?;
+			subs.push(Subscription {
+				url: Some(Url::parse(url.as_str())?),
+				title: row.get(1)?,
+			});
+		}
+		Ok(subs)
+	}
+
+	fn unsubscribe(&mut self, _sub: &Subscription) -> BoxResult<bool> {
+		// GNOME Podcasts has no unsubscribed flag, and its episodes can not be reached once the feed
+		// is deleted, so the history would be lost with it
+		Ok(false)
+	}
+
+	fn save(
+		self: Box<Self>,
+		podcasts: &mut dyn Iterator<Item = &'_ Podcast>,
+		w: &mut dyn IoWriteSeek,
+		options: &SaveOptions,
+	) -> BoxResult<WriteStats> {
+		let mut stats = WriteStats::default();
+		let now = options.clock.unix_secs();
+
+		for podcast in podcasts {
+			println!(
+				"Saving '{}' ({})",
+				podcast.title,
+				fetch::redact(&podcast.url)
+			);
+			let id = self.get_show(&podcast.url)?;
+
+			for track in podcast.tracks.iter() {
+				let played = PLAYED.encode(track.playing_status) != 0;
+				save_track(
+					track,
+					self.update_episode(id, &track.guid, played, track.progress, now),
+					&mut stats,
+				);
+			}
+		}
+
+		// Copy temp file to output
+		let mut temp_file = self.db.into_file()?;
+		std::io::copy(&mut temp_file, w)?;
+		Ok(stats)
+	}
+
+	fn episode_count(&self) -> BoxResult<Option<usize>> {
+		let conn: &Connection = self.db.borrow();
+		count_rows(conn, "episodes").map(Some)
+	}
+}
+
+impl NewPlayer for GnomePodcasts {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		Ok(Box::new(Self {
+			db: SQLLiteDatabase::open(path)?,
+		}))
+	}
+
+	fn name() -> &'static str {
+		"GNOME Podcasts"
+	}
+	fn cli_name() -> &'static str {
+		"gnomepodcasts"
+	}
+	fn file_names() -> &'static [&'static str] {
+		&["podcasts.db"]
+	}
+	fn status_encodings() -> &'static [StatusEncoding] {
+		&[PLAYED]
+	}
+}
@@ -0,0 +1,103 @@
+use crate::player::{NewPlayer, Player};
+use crate::podcast::{PlayingStatus, Podcast};
+use crate::BoxResult;
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Read;
+
+use serde::Deserialize;
+
+const ACTIVITY_FILE_SUFFIX: &str = "Podcasts/MyActivity.json";
+const LISTENED_PREFIX: &str = "Listened to ";
+
+#[derive(Deserialize)]
+struct Activity {
+	title: String,
+	#[serde(default)]
+	subtitles: Vec<ActivitySubtitle>,
+}
+
+#[derive(Deserialize)]
+struct ActivitySubtitle {
+	name: String,
+}
+
+pub struct GooglePodcasts {
+	// Lower cased (podcast title, episode title) pairs
+	listened: HashSet<(String, String)>,
+}
+
+impl GooglePodcasts {
+	fn read_activity(path: &str) -> BoxResult<String> {
+		let mut activity = String::new();
+
+		// Accept the activity file on its own, otherwise look for it in the Takeout zip
+		if path.ends_with(".json") {
+			File::open(path)?.read_to_string(&mut activity)?;
+			return Ok(activity);
+		}
+
+		let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+		let index = (0..archive.len())
+			.find(|&i| {
+				archive
+					.by_index(i)
+					.map(|f| f.name().ends_with(ACTIVITY_FILE_SUFFIX))
+					.unwrap_or(false)
+			})
+			.ok_or(zip::result::ZipError::FileNotFound)?;
+		archive.by_index(index)?.read_to_string(&mut activity)?;
+		Ok(activity)
+	}
+}
+
+impl Player for GooglePodcasts {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		let podcast_title = podcast.title.to_lowercase();
+
+		for track in podcast.tracks.iter_mut() {
+			let key = (podcast_title.clone(), track.title.to_lowercase());
+			if self.listened.contains(&key) {
+				track.playing_status = PlayingStatus::Played;
+			}
+		}
+
+		Ok(podcast)
+	}
+}
+
+impl NewPlayer for GooglePodcasts {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		// Only the JSON activity format is supported, Takeout must be requested with it
+		let activities: Vec<Activity> =
+			serde_json::from_str(GooglePodcasts::read_activity(path)?.as_str())?;
+
+		let listened = activities
+			.into_iter()
+			.filter_map(|activity| {
+				let episode = activity.title.trim_start_matches(LISTENED_PREFIX);
+				if episode.len() == activity.title.len() {
+					return None;
+				}
+				let podcast = activity.subtitles.first()?;
+				Some((podcast.name.to_lowercase(), episode.to_lowercase()))
+			})
+			.collect();
+
+		Ok(Box::new(Self { listened }))
+	}
+
+	fn name() -> &'static str {
+		"Google Podcasts"
+	}
+	fn cli_name() -> &'static str {
+		"googlepodcasts"
+	}
+	fn file_names() -> &'static [&'static str] {
+		&["takeout-*.zip", "MyActivity.json"]
+	}
+	fn read_only() -> bool {
+		true
+	}
+}
@@ -0,0 +1,406 @@
+use crate::clock::{parse_iso8601, Clock};
+use crate::fetch;
+use crate::player::server::Server;
+use crate::player::{IoWriteSeek, NewPlayer, Player, SaveOptions, Subscription, WriteStats};
+use crate::podcast::{PlayingStatus, Podcast, Track};
+use crate::url_key::url_key;
+use crate::warning::{warn, Warning};
+use crate::BoxResult;
+
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const DEVICE: &str = "podcast_history_converter";
+
+// Talks to the gpodder.net API (or a server implementing its episode actions,
+// like the Nextcloud GPodder Sync app) instead of reading a save file. Play
+// state is the latest episode action for each episode, positions are in
+// seconds. Saving uploads an action for every episode whose state differs from
+// the server and writes the uploaded actions to the output file as a record.
+
+/// Where a flavour of the API keeps each endpoint
+pub trait Api {
+	const NAME: &'static str;
+	const CLI_NAME: &'static str;
+	const USERNAME_VAR: &'static str;
+	const PASSWORD_VAR: &'static str;
+
+	fn episodes(username: &str) -> String;
+	fn upload_episodes(username: &str) -> String;
+	fn subscriptions(username: &str) -> String;
+	fn change_subscriptions(username: &str) -> String;
+	/// Feed URLs from the response of the subscriptions endpoint
+	fn parse_subscriptions(response: Value) -> BoxResult<Vec<String>>;
+}
+
+pub struct GpodderNet;
+
+impl Api for GpodderNet {
+	const NAME: &'static str = "gpodder.net";
+	const CLI_NAME: &'static str = "gpodder";
+	const USERNAME_VAR: &'static str = "GPODDER_USERNAME";
+	const PASSWORD_VAR: &'static str = "GPODDER_PASSWORD";
+
+	fn episodes(username: &str) -> String {
+		format!("api/2/episodes/{}.json", username)
+	}
+	fn upload_episodes(username: &str) -> String {
+		format!("api/2/episodes/{}.json", username)
+	}
+	fn subscriptions(username: &str) -> String {
+		format!("subscriptions/{}.json", username)
+	}
+	fn change_subscriptions(username: &str) -> String {
+		format!("api/2/subscriptions/{}/{}.json", username, DEVICE)
+	}
+	fn parse_subscriptions(response: Value) -> BoxResult<Vec<String>> {
+		Ok(serde_json::from_value(response)?)
+	}
+}
+
+/// The Nextcloud GPodder Sync app, authenticated with a Nextcloud app password
+pub struct Nextcloud;
+
+impl Api for Nextcloud {
+	const NAME: &'static str = "Nextcloud GPodder Sync";
+	const CLI_NAME: &'static str = "nextcloud";
+	const USERNAME_VAR: &'static str = "NEXTCLOUD_USERNAME";
+	const PASSWORD_VAR: &'static str = "NEXTCLOUD_PASSWORD";
+
+	fn episodes(_username: &str) -> String {
+		"index.php/apps/gpoddersync/episode_action".into()
+	}
+	fn upload_episodes(_username: &str) -> String {
+		"index.php/apps/gpoddersync/episode_action/create".into()
+	}
+	fn subscriptions(_username: &str) -> String {
+		"index.php/apps/gpoddersync/subscriptions".into()
+	}
+	fn change_subscriptions(_username: &str) -> String {
+		"index.php/apps/gpoddersync/subscription_change/create".into()
+	}
+	fn parse_subscriptions(response: Value) -> BoxResult<Vec<String>> {
+		#[derive(Deserialize)]
+		struct Changes {
+			add: Vec<String>,
+		}
+		let changes: Changes = serde_json::from_value(response)?;
+		Ok(changes.add)
+	}
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EpisodeAction {
+	podcast: String,
+	episode: String,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	guid: Option<String>,
+	action: String,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	timestamp: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	started: Option<i32>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	position: Option<i32>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	total: Option<i32>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	device: Option<String>,
+}
+
+impl EpisodeAction {
+	pub fn podcast(&self) -> &str {
+		self.podcast.as_str()
+	}
+
+	pub fn episode(&self) -> &str {
+		self.episode.as_str()
+	}
+
+	pub fn is_track(&self, track: &Track) -> bool {
+		track.has_url(self.episode.as_str()) || self.guid.as_ref() == Some(&track.guid)
+	}
+
+	pub fn state(&self) -> (PlayingStatus, i32) {
+		let position = std::cmp::max(self.position.unwrap_or(0), 0);
+		match (self.action.as_str(), self.total) {
+			("play", Some(total)) if total > 0 && position >= total => {
+				(PlayingStatus::Played, position)
+			}
+			("play", _) if position > 0 => (PlayingStatus::Playing, position),
+			_ => (PlayingStatus::Unplayed, 0),
+		}
+	}
+
+	/// Unix seconds the action was taken, for players that keep when an episode was last played
+	pub fn unix_secs(&self) -> Option<i64> {
+		self.timestamp.as_ref().and_then(|text| parse_iso8601(text))
+	}
+
+	/// The action to record for `track`, or None if `current` already gives its state. It is
+	/// dated when the source player last played the episode, which apps that apply it (AntennaPod
+	/// among them) keep as its last played time, unless that is older than the current action and
+	/// would not replace it
+	pub fn update(
+		current: &[EpisodeAction],
+		podcast: &Podcast,
+		track: &Track,
+		timestamp: &str,
+	) -> Option<Self> {
+		let current = current.iter().find(|action| action.is_track(track));
+		let state = current.map_or((PlayingStatus::Unplayed, 0), |action| action.state());
+		if state == (track.playing_status, track.progress) {
+			return None;
+		}
+
+		// Times before the epoch are not real play times
+		let last_played = track.last_played.filter(|secs| *secs >= 0);
+		let timestamp = match (track.playing_status, last_played) {
+			(PlayingStatus::Unplayed, _) | (_, None) => timestamp.into(),
+			(_, Some(secs)) => {
+				let newer = current
+					.and_then(|action| action.unix_secs())
+					.map_or(true, |current| secs > current);
+				if newer {
+					Clock::fixed(secs as u64).iso8601()
+				} else {
+					timestamp.into()
+				}
+			}
+		};
+
+		let (action, position, total) = match track.playing_status {
+			PlayingStatus::Unplayed => ("new", None, None),
+			PlayingStatus::Playing => ("play", Some(track.progress), track.duration),
+			PlayingStatus::Played => {
+				let total = track.duration.unwrap_or(track.progress);
+				("play", Some(total), Some(total))
+			}
+		};
+
+		Some(EpisodeAction {
+			podcast: podcast.url.to_string(),
+			episode: track.url.to_string(),
+			guid: Some(track.guid.clone()),
+			action: action.into(),
+			timestamp: Some(timestamp),
+			started: position.map(|_| 0),
+			position,
+			total,
+			device: Some(DEVICE.into()),
+		})
+	}
+}
+
+/// Latest play or new action for each episode of a feed
+pub fn latest_actions<I: IntoIterator<Item = EpisodeAction>>(
+	actions: I,
+	podcast_url: &Url,
+) -> Vec<EpisodeAction> {
+	let mut latest: HashMap<String, EpisodeAction> = HashMap::new();
+	for action in actions
+		.into_iter()
+		.filter(|action| url_key(action.podcast.as_str()) == url_key(podcast_url.as_str()))
+		.filter(|action| action.action == "play" || action.action == "new")
+	{
+		// ISO 8601 timestamps sort in time order
+		let key = url_key(action.episode.as_str());
+		let newer = latest
+			.get(&key)
+			.map_or(true, |current| action.timestamp >= current.timestamp);
+		if newer {
+			latest.insert(key, action);
+		}
+	}
+
+	latest.into_iter().map(|(_, action)| action).collect()
+}
+
+/// Set the state of every track in `podcast` from the latest `actions`
+pub fn populate_from(actions: &[EpisodeAction], podcast: &mut Podcast) {
+	for track in podcast.tracks.iter_mut() {
+		match actions.iter().find(|action| action.is_track(track)) {
+			Some(action) => {
+				let (status, progress) = action.state();
+				track.playing_status = status;
+				track.progress = progress;
+				if status != PlayingStatus::Unplayed {
+					track.last_played = action.unix_secs();
+				}
+			}
+			None => warn(
+				Warning::UnmatchedTrack,
+				format!("Track not found: {:?}", track),
+			),
+		}
+	}
+}
+
+#[derive(Deserialize)]
+struct EpisodeActions {
+	actions: Vec<EpisodeAction>,
+}
+
+pub struct GpodderSync<A: Api> {
+	client: Client,
+	server: Server,
+	api: PhantomData<A>,
+}
+
+pub type Gpodder = GpodderSync<GpodderNet>;
+pub type NextcloudGpodder = GpodderSync<Nextcloud>;
+
+impl<A: Api> GpodderSync<A> {
+	fn get_actions(&self, podcast_url: &Url) -> BoxResult<Vec<EpisodeAction>> {
+		let url = self.server.endpoint(&A::episodes(&self.server.username))?;
+		let response: EpisodeActions = self
+			.client
+			.get(url)
+			.basic_auth(&self.server.username, Some(&self.server.password))
+			.query(&[("podcast", podcast_url.as_str()), ("since", "0")])
+			.send()?
+			.error_for_status()?
+			.json()?;
+
+		// Not every server filters by podcast, so this filters again
+		Ok(latest_actions(response.actions, podcast_url))
+	}
+
+	fn update_subscriptions(&self, add: &[&Url], remove: &[&Url]) -> BoxResult<()> {
+		let url = self
+			.server
+			.endpoint(&A::change_subscriptions(&self.server.username))?;
+		let mut body = HashMap::new();
+		body.insert(
+			"add",
+			add.iter().map(|url| url.as_str()).collect::<Vec<_>>(),
+		);
+		body.insert(
+			"remove",
+			remove.iter().map(|url| url.as_str()).collect::<Vec<_>>(),
+		);
+
+		self.client
+			.post(url)
+			.basic_auth(&self.server.username, Some(&self.server.password))
+			.json(&body)
+			.send()?
+			.error_for_status()?;
+		Ok(())
+	}
+}
+
+impl<A: Api> Player for GpodderSync<A> {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		let actions = self.get_actions(&podcast.url)?;
+		populate_from(&actions, &mut podcast);
+		Ok(podcast)
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		let url = self
+			.server
+			.endpoint(&A::subscriptions(&self.server.username))?;
+		let urls = A::parse_subscriptions(
+			self.client
+				.get(url)
+				.basic_auth(&self.server.username, Some(&self.server.password))
+				.send()?
+				.error_for_status()?
+				.json()?,
+		)?;
+
+		// The API only lists feed URLs
+		Ok(urls
+			.into_iter()
+			.map(|url| Subscription {
+				title: url.clone(),
+				url: Url::parse(url.as_str()).ok(),
+			})
+			.collect())
+	}
+
+	fn subscribe(&mut self, sub: &Subscription) -> BoxResult<bool> {
+		match &sub.url {
+			Some(url) => {
+				self.update_subscriptions(&[url], &[])?;
+				Ok(true)
+			}
+			None => Ok(false),
+		}
+	}
+
+	fn unsubscribe(&mut self, sub: &Subscription) -> BoxResult<bool> {
+		let url = sub
+			.url
+			.as_ref()
+			.expect("gpodder subscription without a url");
+		self.update_subscriptions(&[], &[url])?;
+		Ok(true)
+	}
+
+	fn save(
+		self: Box<Self>,
+		podcasts: &mut dyn Iterator<Item = &'_ Podcast>,
+		w: &mut dyn IoWriteSeek,
+		options: &SaveOptions,
+	) -> BoxResult<WriteStats> {
+		let timestamp = options.clock.iso8601();
+		let mut upload = Vec::new();
+
+		for podcast in podcasts {
+			println!(
+				"Saving '{}' ({})",
+				podcast.title,
+				fetch::redact(&podcast.url)
+			);
+			let current = self.get_actions(&podcast.url)?;
+
+			upload.extend(
+				podcast.tracks.iter().filter_map(|track| {
+					EpisodeAction::update(&current, podcast, track, &timestamp)
+				}),
+			);
+		}
+
+		if !upload.is_empty() {
+			println!("Uploading {} episode actions", upload.len());
+			let url = self
+				.server
+				.endpoint(&A::upload_episodes(&self.server.username))?;
+			self.client
+				.post(url)
+				.basic_auth(&self.server.username, Some(&self.server.password))
+				.json(&upload)
+				.send()?
+				.error_for_status()?;
+		}
+
+		serde_json::to_writer_pretty(w, &upload)?;
+		Ok(WriteStats::default())
+	}
+}
+
+impl<A: Api + 'static> NewPlayer for GpodderSync<A> {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		Ok(Box::new(Self {
+			client: Client::new(),
+			server: Server::parse(path, A::USERNAME_VAR, A::PASSWORD_VAR, A::NAME)?,
+			api: PhantomData,
+		}))
+	}
+
+	fn name() -> &'static str {
+		A::NAME
+	}
+	fn cli_name() -> &'static str {
+		A::CLI_NAME
+	}
+	fn value_name() -> &'static str {
+		"URL"
+	}
+}
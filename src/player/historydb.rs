@@ -0,0 +1,450 @@
+use crate::fetch;
+use crate::player::{
+	count_rows, IoWriteSeek, NewPlayer, Player, SaveOptions, Subscription, WriteStats,
+};
+use crate::podcast::{PlayingStatus, Podcast, PodcastSettings, Track};
+use crate::warning::{warn, Warning};
+use crate::{BoxResult, Error, SQLLiteDatabase};
+
+use std::borrow::Borrow;
+use std::path::Path;
+
+use reqwest::Url;
+use rusqlite::{Connection, OptionalExtension, NO_PARAMS};
+
+// The tool's own SQLite database, holding everything the JSON file does. Each
+// track row records when its status or position last changed, so another
+// program reading the database can pick out only what changed since a given
+// conversion, and every conversion written to it is listed in `runs`. The
+// layout is versioned by the single row of `schema_version`; newer versions
+// only ever add to it, and an older database is brought up to date when opened.
+
+const SCHEMA_VERSION: i64 = 2;
+
+const SCHEMA: &str = "
+CREATE TABLE schema_version (version INTEGER NOT NULL);
+CREATE TABLE podcasts (
+	id INTEGER PRIMARY KEY,
+	url TEXT NOT NULL UNIQUE,
+	title TEXT NOT NULL,
+	auto_archive INTEGER,
+	notifications INTEGER,
+	update_interval INTEGER,
+	download_count INTEGER,
+	playlist TEXT
+);
+CREATE TABLE tracks (
+	id INTEGER PRIMARY KEY,
+	podcast_id INTEGER NOT NULL REFERENCES podcasts (id),
+	guid TEXT NOT NULL,
+	url TEXT NOT NULL,
+	title TEXT NOT NULL,
+	duration INTEGER,
+	chapters TEXT NOT NULL,
+	progress INTEGER NOT NULL,
+	status TEXT NOT NULL,
+	modified INTEGER NOT NULL,
+	last_played INTEGER,
+	UNIQUE (podcast_id, guid)
+);
+CREATE TABLE runs (
+	id INTEGER PRIMARY KEY,
+	timestamp INTEGER NOT NULL,
+	source_player TEXT NOT NULL,
+	tool_version TEXT NOT NULL,
+	podcasts INTEGER NOT NULL,
+	tracks INTEGER NOT NULL
+);
+";
+
+/// What each version adds to the one before it, starting with version 2
+const MIGRATIONS: [&str; 1] = ["ALTER TABLE tracks ADD COLUMN last_played INTEGER;"];
+
+fn status_name(status: PlayingStatus) -> &'static str {
+	match status {
+		PlayingStatus::Unplayed => "unplayed",
+		PlayingStatus::Playing => "playing",
+		PlayingStatus::Played => "played",
+	}
+}
+
+fn parse_status(name: &str) -> Result<PlayingStatus, Error> {
+	match name {
+		"unplayed" => Ok(PlayingStatus::Unplayed),
+		"playing" => Ok(PlayingStatus::Playing),
+		"played" => Ok(PlayingStatus::Played),
+		_ => Err(Error::InvalidPlayingStatus),
+	}
+}
+
+fn chapters_to_string(chapters: &[i32]) -> String {
+	chapters
+		.iter()
+		.map(|start| start.to_string())
+		.collect::<Vec<String>>()
+		.join(",")
+}
+
+fn chapters_from_string(chapters: &str) -> Vec<i32> {
+	chapters
+		.split(',')
+		.filter_map(|start| start.parse().ok())
+		.collect()
+}
+
+pub struct HistoryDb {
+	db: SQLLiteDatabase,
+}
+
+impl HistoryDb {
+	fn get_podcast(&self, url: &Url) -> rusqlite::Result<Option<i64>> {
+		let conn: &Connection = self.db.borrow();
+		conn.query_row_named(
+			"SELECT id FROM podcasts WHERE url_key(url) = url_key(:url)",
+			&[(":url", &url.to_string())],
+			|row| row.get(0),
+		)
+		.optional()
+	}
+
+	fn read_settings(&self, podcast_id: i64) -> rusqlite::Result<PodcastSettings> {
+		let conn: &Connection = self.db.borrow();
+		conn.query_row_named(
+			"SELECT auto_archive, notifications, update_interval, download_count, playlist FROM podcasts WHERE id = :id",
+			&[(":id", &podcast_id)],
+			|row| {
+				Ok(PodcastSettings {
+					auto_archive: row.get(0)?,
+					notifications: row.get(1)?,
+					update_interval: row.get(2)?,
+					download_count: row.get(3)?,
+					playlist: row.get(4)?,
+				})
+			},
+		)
+	}
+
+	fn read_tracks(&self, podcast_id: i64) -> BoxResult<Vec<Track>> {
+		let conn: &Connection = self.db.borrow();
+		let mut stmt = conn.prepare(
+			"SELECT guid, url, title, duration, chapters, progress, status, last_played FROM tracks WHERE podcast_id = :podcast_id ORDER BY id",
+		)?;
+		let mut rows = stmt.query_named(&[(":podcast_id", &podcast_id)])?;
+
+		let mut tracks = Vec::new();
+		while let Some(row) = rows.next()? {
+			let url: String = row.get(1)?;
+			let chapters: String = row.get(4)?;
+			let status: String = row.get(6)?;
+			tracks.push(Track {
+				guid: row.get(0)?,
+				url: Url::parse(&url)?,
+				title: row.get(2)?,
+				duration: row.get(3)?,
+				published: None,
+				chapters: chapters_from_string(&chapters),
+				season: None,
+				episode: None,
+				transcripts: Vec::new(),
+				alternate_urls: Vec::new(),
+				progress: row.get(5)?,
+				playing_status: parse_status(&status)?,
+				last_played: row.get(7)?,
+			});
+		}
+		Ok(tracks)
+	}
+
+	fn write_podcast(&self, podcast: &Podcast) -> BoxResult<i64> {
+		let conn: &Connection = self.db.borrow();
+		let settings = &podcast.settings;
+
+		if let Some(id) = self.get_podcast(&podcast.url)? {
+			// Settings the source player does not have are left as they were
+			conn.execute_named(
+				"UPDATE podcasts SET title = :title, auto_archive = COALESCE(:auto_archive, auto_archive), notifications = COALESCE(:notifications, notifications), update_interval = COALESCE(:update_interval, update_interval), download_count = COALESCE(:download_count, download_count), playlist = COALESCE(:playlist, playlist) WHERE id = :id",
+				&[
+					(":title", &podcast.title),
+					(":auto_archive", &settings.auto_archive),
+					(":notifications", &settings.notifications),
+					(":update_interval", &settings.update_interval),
+					(":download_count", &settings.download_count),
+					(":playlist", &settings.playlist),
+					(":id", &id),
+				],
+			)?;
+			return Ok(id);
+		}
+
+		conn.execute_named(
+			"INSERT INTO podcasts (url, title, auto_archive, notifications, update_interval, download_count, playlist) VALUES (:url, :title, :auto_archive, :notifications, :update_interval, :download_count, :playlist)",
+			&[
+				(":url", &podcast.url.to_string()),
+				(":title", &podcast.title),
+				(":auto_archive", &settings.auto_archive),
+				(":notifications", &settings.notifications),
+				(":update_interval", &settings.update_interval),
+				(":download_count", &settings.download_count),
+				(":playlist", &settings.playlist),
+			],
+		)?;
+		Ok(conn.last_insert_rowid())
+	}
+
+	/// Returns whether the status or position changed, which is when `modified` is moved on
+	fn write_track(&self, podcast_id: i64, track: &Track, now: i64) -> rusqlite::Result<bool> {
+		let conn: &Connection = self.db.borrow();
+		let status = status_name(track.playing_status);
+
+		let existing: Option<(i32, String)> = conn
+			.query_row_named(
+				"SELECT progress, status FROM tracks WHERE podcast_id = :podcast_id AND guid = :guid",
+				&[(":podcast_id", &podcast_id), (":guid", &track.guid)],
+				|row| Ok((row.get(0)?, row.get(1)?)),
+			)
+			.optional()?;
+		let changed = match &existing {
+			Some((progress, old_status)) => *progress != track.progress || old_status != status,
+			None => true,
+		};
+
+		if existing.is_none() {
+			conn.execute_named(
+				"INSERT INTO tracks (podcast_id, guid, url, title, duration, chapters, progress, status, modified, last_played) VALUES (:podcast_id, :guid, :url, :title, :duration, :chapters, :progress, :status, :modified, :last_played)",
+				&[
+					(":podcast_id", &podcast_id),
+					(":guid", &track.guid),
+					(":url", &track.url.to_string()),
+					(":title", &track.title),
+					(":duration", &track.duration),
+					(":chapters", &chapters_to_string(&track.chapters)),
+					(":progress", &track.progress),
+					(":status", &status),
+					(":modified", &now),
+					(":last_played", &track.last_played),
+				],
+			)?;
+			return Ok(true);
+		}
+
+		// An unchanged track keeps the time it last changed, and a source player that does not
+		// know when the track was last played keeps the time stored
+		conn.execute_named(
+			"UPDATE tracks SET url = :url, title = :title, duration = :duration, chapters = :chapters, progress = :progress, status = :status, modified = CASE WHEN :changed THEN :modified ELSE modified END, last_played = COALESCE(:last_played, last_played) WHERE podcast_id = :podcast_id AND guid = :guid",
+			&[
+				(":podcast_id", &podcast_id),
+				(":guid", &track.guid),
+				(":url", &track.url.to_string()),
+				(":title", &track.title),
+				(":duration", &track.duration),
+				(":chapters", &chapters_to_string(&track.chapters)),
+				(":progress", &track.progress),
+				(":status", &status),
+				(":modified", &now),
+				(":changed", &changed),
+				(":last_played", &track.last_played),
+			],
+		)?;
+		Ok(changed)
+	}
+
+	fn write_run(&self, options: &SaveOptions) -> rusqlite::Result<usize> {
+		let conn: &Connection = self.db.borrow();
+		let provenance = &options.provenance;
+		conn.execute_named(
+			"INSERT INTO runs (timestamp, source_player, tool_version, podcasts, tracks) VALUES (:timestamp, :source_player, :tool_version, :podcasts, :tracks)",
+			&[
+				(":timestamp", &provenance.timestamp),
+				(":source_player", &provenance.source_player),
+				(":tool_version", &provenance.version),
+				(":podcasts", &(provenance.podcasts as i64)),
+				(":tracks", &(provenance.tracks as i64)),
+			],
+		)
+	}
+}
+
+impl Player for HistoryDb {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		let id = match self.get_podcast(&podcast.url)? {
+			Some(id) => id,
+			None => {
+				warn(
+					Warning::UnmatchedTrack,
+					format!("Podcast not found: {}", fetch::redact(&podcast.url)),
+				);
+				return Ok(podcast);
+			}
+		};
+		let stored = self.read_tracks(id)?;
+
+		for track in podcast.tracks.iter_mut() {
+			match stored.iter().find(|stored| stored.guid == track.guid) {
+				Some(stored) => {
+					track.progress = stored.progress;
+					track.playing_status = stored.playing_status;
+					track.last_played = stored.last_played;
+				}
+				None => warn(
+					Warning::UnmatchedTrack,
+					format!("Track not found: {:?}", track),
+				),
+			}
+		}
+
+		Ok(podcast)
+	}
+
+	fn populate_settings(&self, podcast: &mut Podcast) -> BoxResult<()> {
+		if let Some(id) = self.get_podcast(&podcast.url)? {
+			podcast.settings = self.read_settings(id)?;
+		}
+		Ok(())
+	}
+
+	fn stored_podcasts(&self) -> BoxResult<Option<Vec<Podcast>>> {
+		let conn: &Connection = self.db.borrow();
+		let mut stmt = conn.prepare("SELECT id, url, title FROM podcasts ORDER BY id")?;
+		let mut rows = stmt.query(NO_PARAMS)?;
+
+		let mut podcasts = Vec::new();
+		while let Some(row) = rows.next()? {
+			let id: i64 = row.get(0)?;
+			let url: String = row.get(1)?;
+			podcasts.push(Podcast {
+				url: Url::parse(&url)?,
+				title: row.get(2)?,
+				tracks: self.read_tracks(id)?,
+				settings: self.read_settings(id)?,
+				previous_url: None,
+				guid: None,
+			});
+		}
+		Ok(Some(podcasts))
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		let conn: &Connection = self.db.borrow();
+		let mut stmt = conn.prepare("SELECT url, title FROM podcasts ORDER BY id")?;
+		let mut rows = stmt.query(NO_PARAMS)?;
+
+		let mut subs = Vec::new();
+		while let Some(row) = rows.next()? {
+			let url: String = row.get(0)?;
+			subs.push(Subscription {
+				url: Some(Url::parse(&url)?),
+				title: row.get(1)?,
+			});
+		}
+		Ok(subs)
+	}
+
+	fn schema_version(&self) -> Option<&'static str> {
+		Some("2")
+	}
+
+	fn episode_count(&self) -> BoxResult<Option<usize>> {
+		let conn: &Connection = self.db.borrow();
+		count_rows(conn, "tracks").map(Some)
+	}
+
+	fn stores_provenance(&self) -> bool {
+		true
+	}
+
+	fn save(
+		self: Box<Self>,
+		podcasts: &mut dyn Iterator<Item = &'_ Podcast>,
+		w: &mut dyn IoWriteSeek,
+		options: &SaveOptions,
+	) -> BoxResult<WriteStats> {
+		let now = options.clock.unix_secs();
+		let mut changed = 0;
+
+		for podcast in podcasts {
+			println!(
+				"Saving '{}' ({})",
+				podcast.title,
+				fetch::redact(&podcast.url)
+			);
+			let id = self.write_podcast(podcast)?;
+
+			for track in podcast.tracks.iter() {
+				if self.write_track(id, track, now)? {
+					changed += 1;
+				}
+			}
+		}
+
+		println!(
+			"{}: {} tracks changed since the last run",
+			Self::name(),
+			changed
+		);
+		self.write_run(options)?;
+
+		// Copy temp file to output
+		let mut temp_file = self.db.into_file()?;
+		std::io::copy(&mut temp_file, w)?;
+		Ok(WriteStats::default())
+	}
+}
+
+impl NewPlayer for HistoryDb {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		// A file that does not exist yet is an empty database to write to
+		let db = if Path::new(path).exists() {
+			SQLLiteDatabase::open(path)?
+		} else {
+			SQLLiteDatabase::open_from_reader(&mut std::io::empty())?
+		};
+
+		{
+			let conn: &Connection = db.borrow();
+			let has_schema: bool = conn.query_row(
+				"SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version'",
+				NO_PARAMS,
+				|row| row.get(0),
+			)?;
+
+			if has_schema {
+				let version: i64 =
+					conn.query_row("SELECT version FROM schema_version", NO_PARAMS, |row| {
+						row.get(0)
+					})?;
+				if version > SCHEMA_VERSION {
+					return Err(Error::UnknownSchema(
+						"History database",
+						String::from("version ") + &version.to_string(),
+					)
+					.into());
+				}
+				for migration in MIGRATIONS.iter().skip(version.max(1) as usize - 1) {
+					conn.execute_batch(migration)?;
+				}
+				conn.execute("UPDATE schema_version SET version = ?", &[SCHEMA_VERSION])?;
+			} else {
+				conn.execute_batch(SCHEMA)?;
+				conn.execute(
+					"INSERT INTO schema_version (version) VALUES (?)",
+					&[SCHEMA_VERSION],
+				)?;
+			}
+		}
+
+		Ok(Box::new(Self { db }))
+	}
+
+	fn name() -> &'static str {
+		"History database"
+	}
+	fn cli_name() -> &'static str {
+		"history-db"
+	}
+	fn writes_through() -> bool {
+		true
+	}
+	fn schema_versions() -> Vec<&'static str> {
+		vec!["1", "2"]
+	}
+}
@@ -0,0 +1,287 @@
+use crate::fetch;
+use crate::opml::escape;
+use crate::player::{IoWriteSeek, NewPlayer, Player, SaveOptions, WriteStats};
+use crate::podcast::{PlayingStatus, Podcast, Track};
+use crate::{BoxResult, Error};
+
+use std::io::Write;
+
+// A page to look over what a conversion would carry across before writing to a
+// real player: one table per podcast with each episode's state and a progress
+// bar, and totals at the top. The file given to --html is not read, it is only
+// there because every --out- argument needs one; give it the same path.
+
+const STYLE: &str = "body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; margin-bottom: 2em; width: 100%; }
+th, td { border-bottom: 1px solid #ddd; padding: 0.3em 0.6em; text-align: left; }
+td.time { font-variant-numeric: tabular-nums; white-space: nowrap; }
+.played { color: #2a7d2a; }
+.playing { color: #b06d00; }
+.unplayed { color: #888; }
+.bar { background: #eee; height: 0.6em; width: 10em; }
+.bar div { background: #4a90d9; height: 100%; }";
+
+fn status_name(status: PlayingStatus) -> &'static str {
+	match status {
+		PlayingStatus::Unplayed => "unplayed",
+		PlayingStatus::Playing => "playing",
+		PlayingStatus::Played => "played",
+	}
+}
+
+/// Seconds as h:mm:ss, or m:ss under an hour
+pub fn hms(secs: i32) -> String {
+	let secs = std::cmp::max(secs, 0);
+	if secs >= 3600 {
+		format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+	} else {
+		format!("{}:{:02}", secs / 60, secs % 60)
+	}
+}
+
+fn hours(secs: i64) -> String {
+	format!("{:.1}", secs as f64 / 3600.0)
+}
+
+/// Percentage of the episode listened to, played episodes are full
+fn percent(track: &Track) -> i32 {
+	match (track.playing_status, track.duration) {
+		(PlayingStatus::Played, _) => 100,
+		(PlayingStatus::Playing, Some(duration)) if duration > 0 => {
+			std::cmp::min(track.progress * 100 / duration, 100)
+		}
+		_ => 0,
+	}
+}
+
+#[derive(Default)]
+pub struct Totals {
+	pub episodes: usize,
+	pub played: usize,
+	pub playing: usize,
+	/// Seconds of the played episodes plus the positions of those in progress
+	pub listened: i64,
+	/// Seconds of the played episodes alone
+	pub listened_played: i64,
+	/// Sum of the percentage of each episode listened to
+	completion: i64,
+	/// Seconds left of the unplayed episodes and those in progress
+	pub backlog: i64,
+	/// Unplayed or in progress episodes the feed gives no duration for, missing from `backlog`
+	pub backlog_unknown: usize,
+}
+
+impl Totals {
+	pub fn of<'a, I: IntoIterator<Item = &'a Track>>(tracks: I) -> Self {
+		let mut totals = Self::default();
+		for track in tracks {
+			totals.add(track);
+		}
+		totals
+	}
+
+	pub fn unplayed(&self) -> usize {
+		self.episodes - self.played - self.playing
+	}
+
+	fn add(&mut self, track: &Track) {
+		self.episodes += 1;
+		self.completion += i64::from(percent(track));
+		match track.playing_status {
+			PlayingStatus::Played => {
+				self.played += 1;
+				let listened = i64::from(track.duration.unwrap_or(track.progress));
+				self.listened += listened;
+				self.listened_played += listened;
+				return;
+			}
+			PlayingStatus::Playing => {
+				self.playing += 1;
+				self.listened += i64::from(track.progress);
+			}
+			PlayingStatus::Unplayed => (),
+		}
+
+		match track.duration.filter(|&duration| duration > 0) {
+			Some(duration) => {
+				self.backlog += i64::from(std::cmp::max(duration - track.progress, 0))
+			}
+			None => self.backlog_unknown += 1,
+		}
+	}
+
+	/// Seconds listened to the episodes in progress
+	pub fn listened_playing(&self) -> i64 {
+		self.listened - self.listened_played
+	}
+
+	/// Average percentage of the episodes listened to, unplayed ones counting as none
+	pub fn completion(&self) -> i64 {
+		if self.episodes == 0 {
+			0
+		} else {
+			self.completion / self.episodes as i64
+		}
+	}
+
+	pub fn listened_hours(&self) -> String {
+		hours(self.listened)
+	}
+
+	pub fn backlog_hours(&self) -> String {
+		hours(self.backlog)
+	}
+}
+
+/// Note on the backlog of the episodes it leaves out for want of a duration
+pub fn unknown_lengths(totals: &Totals) -> String {
+	match totals.backlog_unknown {
+		0 => String::new(),
+		unknown => format!(", not counting {} of unknown length", unknown),
+	}
+}
+
+fn write_table<W: Write + ?Sized>(w: &mut W, podcast: &Podcast) -> BoxResult<()> {
+	let totals = Totals::of(podcast.tracks.iter());
+
+	writeln!(
+		w,
+		"<h2><a href=\"{}\">{}</a></h2>",
+		escape(&fetch::redact(&podcast.url)),
+		escape(&podcast.title)
+	)?;
+	writeln!(
+		w,
+		"<p>{} episodes, {} played, {} in progress, {} hours listened, {}% complete, {} hours left</p>",
+		totals.episodes,
+		totals.played,
+		totals.playing,
+		totals.listened_hours(),
+		totals.completion(),
+		totals.backlog_hours()
+	)?;
+	writeln!(w, "<table>")?;
+	writeln!(
+		w,
+		"<tr><th>Episode</th><th>Status</th><th>Progress</th><th>Position</th><th>Duration</th></tr>"
+	)?;
+	for track in podcast.tracks.iter() {
+		let title = if track.title.is_empty() {
+			track.url.as_str()
+		} else {
+			track.title.as_str()
+		};
+		let mut cell = escape(title);
+		if let Some(numbering) = track.numbering() {
+			cell = format!("{} {}", escape(&numbering), cell);
+		}
+		if let Some(transcript) = track.transcripts.first() {
+			cell += &format!(
+				" <a href=\"{}\">transcript</a>",
+				escape(transcript.as_str())
+			);
+		}
+		writeln!(
+			w,
+			"<tr><td>{}</td><td class=\"{status}\">{status}</td><td><div class=\"bar\"><div style=\"width: {}%\"></div></div></td><td class=\"time\">{}</td><td class=\"time\">{}</td></tr>",
+			cell,
+			percent(track),
+			hms(track.progress),
+			track.duration.map_or(String::new(), hms),
+			status = status_name(track.playing_status),
+		)?;
+	}
+	writeln!(w, "</table>")?;
+	Ok(())
+}
+
+pub struct HtmlReport;
+
+impl Player for HtmlReport {
+	fn populate(&mut self, _podcast: Podcast) -> BoxResult<Podcast> {
+		Err(Error::UnsupportedOperation("converting from an HTML report").into())
+	}
+
+	fn stores_provenance(&self) -> bool {
+		true
+	}
+
+	fn writes_whole_podcasts(&self) -> bool {
+		true
+	}
+
+	fn save(
+		self: Box<Self>,
+		podcasts: &mut dyn Iterator<Item = &'_ Podcast>,
+		w: &mut dyn IoWriteSeek,
+		options: &SaveOptions,
+	) -> BoxResult<WriteStats> {
+		let podcasts: Vec<&Podcast> = podcasts.collect();
+		let totals = Totals::of(podcasts.iter().flat_map(|podcast| podcast.tracks.iter()));
+
+		writeln!(w, "<!DOCTYPE html>")?;
+		writeln!(w, "<html>")?;
+		writeln!(w, "<head>")?;
+		writeln!(w, "<meta charset=\"utf-8\">")?;
+		writeln!(w, "<title>Podcast history</title>")?;
+		writeln!(w, "<style>\n{}\n</style>", STYLE)?;
+		writeln!(w, "</head>")?;
+		writeln!(w, "<body>")?;
+		writeln!(w, "<h1>Podcast history</h1>")?;
+		writeln!(
+			w,
+			"<p>Converted from {} by {} {} at {}</p>",
+			options.provenance.source_player,
+			options.provenance.tool,
+			options.provenance.version,
+			escape(&options.clock.iso8601())
+		)?;
+		writeln!(
+			w,
+			"<p>{} podcasts, {} episodes, {} played, {} in progress, {} hours listened</p>",
+			podcasts.len(),
+			totals.episodes,
+			totals.played,
+			totals.playing,
+			totals.listened_hours()
+		)?;
+		writeln!(
+			w,
+			"<p>{} to played episodes, {} to those in progress, {} hours left to listen to in {} episodes{}</p>",
+			hms(totals.listened_played as i32),
+			hms(totals.listened_playing() as i32),
+			totals.backlog_hours(),
+			totals.playing + totals.unplayed(),
+			unknown_lengths(&totals)
+		)?;
+
+		for podcast in podcasts.iter() {
+			println!(
+				"Saving '{}' ({})",
+				podcast.title,
+				fetch::redact(&podcast.url)
+			);
+			write_table(w, podcast)?;
+		}
+
+		writeln!(w, "</body>")?;
+		writeln!(w, "</html>")?;
+		Ok(WriteStats::default())
+	}
+}
+
+impl NewPlayer for HtmlReport {
+	fn new(_path: &str) -> BoxResult<Box<dyn Player>> {
+		Ok(Box::new(Self))
+	}
+
+	fn name() -> &'static str {
+		"HTML report"
+	}
+	fn cli_name() -> &'static str {
+		"html"
+	}
+	fn write_only() -> bool {
+		true
+	}
+}
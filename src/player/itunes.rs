@@ -0,0 +1,184 @@
+use crate::opml::find_child;
+use crate::player::{secs_from_millis, NewPlayer, Player, StatusEncoding, Subscription};
+use crate::podcast::{PlayingStatus, Podcast, Track};
+use crate::warning::{warn, Warning};
+use crate::{BoxResult, Error};
+
+use reqwest::Url;
+use roxmltree::Node;
+
+const PLAY_COUNT: StatusEncoding = StatusEncoding {
+	field: "Play Count",
+	values: &[
+		(PlayingStatus::Unplayed, 0),
+		(PlayingStatus::Playing, 0),
+		(PlayingStatus::Played, 1),
+	],
+};
+
+// iTunes and the Music app export their library as a property list in which
+// the "Tracks" dictionary holds one dictionary of key/value pairs per item.
+// Podcast episodes carry no feed or enclosure URL, so they are matched by the
+// album (the podcast title) and the episode name, or by the file name of their
+// downloaded Location. The resume position ("Bookmark") is in milliseconds.
+struct Episode {
+	album: String,
+	name: String,
+	file_name: Option<String>,
+	play_count: i64,
+	has_been_played: bool,
+	bookmark_ms: i64,
+}
+
+impl Episode {
+	/// Read the alternating key and value elements of a track's dictionary
+	fn from_dict(dict: Node) -> Option<Self> {
+		let mut episode = Self {
+			album: String::new(),
+			name: String::new(),
+			file_name: None,
+			play_count: 0,
+			has_been_played: false,
+			bookmark_ms: 0,
+		};
+		let mut podcast = false;
+
+		let mut elements = dict.children().filter(|n| n.is_element());
+		while let (Some(key), Some(value)) = (elements.next(), elements.next()) {
+			let text = value.text().unwrap_or("");
+			match key.text().unwrap_or("") {
+				"Podcast" => podcast = value.tag_name().name() == "true",
+				"Album" => episode.album = text.into(),
+				"Name" => episode.name = text.into(),
+				"Location" => {
+					episode.file_name = Url::parse(text)
+						.ok()
+						.and_then(|url| url.path_segments()?.last().map(String::from))
+				}
+				"Play Count" => episode.play_count = text.parse().unwrap_or(0),
+				"Has Been Played" => episode.has_been_played = value.tag_name().name() == "true",
+				"Bookmark" => episode.bookmark_ms = text.parse().unwrap_or(0),
+				_ => (),
+			}
+		}
+
+		if podcast {
+			Some(episode)
+		} else {
+			None
+		}
+	}
+
+	fn matches(&self, track: &Track) -> bool {
+		let same_name =
+			!self.name.is_empty() && self.name.to_lowercase() == track.title.to_lowercase();
+		let same_file = match &self.file_name {
+			Some(file_name) => track.urls().any(|url| {
+				url.path_segments().map_or(false, |segments| {
+					segments.last() == Some(file_name.as_str())
+				})
+			}),
+			None => false,
+		};
+		same_name || same_file
+	}
+}
+
+pub struct ITunes {
+	episodes: Vec<Episode>,
+}
+
+impl ITunes {
+	/// The value element following `<key>name</key>` in a dictionary
+	fn dict_value<'a>(dict: Node<'a, 'a>, name: &str) -> Option<Node<'a, 'a>> {
+		let mut elements = dict.children().filter(|n| n.is_element());
+		while let (Some(key), Some(value)) = (elements.next(), elements.next()) {
+			if key.text() == Some(name) {
+				return Some(value);
+			}
+		}
+		None
+	}
+}
+
+impl Player for ITunes {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		let album = podcast.title.to_lowercase();
+		let episodes: Vec<&Episode> = self
+			.episodes
+			.iter()
+			.filter(|episode| episode.album.to_lowercase() == album)
+			.collect();
+
+		for track in podcast.tracks.iter_mut() {
+			match episodes.iter().find(|episode| episode.matches(track)) {
+				Some(episode) => {
+					track.progress = secs_from_millis(episode.bookmark_ms);
+					track.playing_status = if episode.play_count > 0 || episode.has_been_played {
+						PlayingStatus::Played
+					} else if track.progress > 0 {
+						PlayingStatus::Playing
+					} else {
+						PlayingStatus::Unplayed
+					};
+				}
+				None => warn(
+					Warning::UnmatchedTrack,
+					format!("Track not found: {:?}", track),
+				),
+			}
+		}
+
+		Ok(podcast)
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		// The library only knows the podcast titles
+		let mut albums: Vec<&String> = self.episodes.iter().map(|episode| &episode.album).collect();
+		albums.sort();
+		albums.dedup();
+
+		Ok(albums
+			.into_iter()
+			.map(|album| Subscription {
+				url: None,
+				title: album.clone(),
+			})
+			.collect())
+	}
+}
+
+impl NewPlayer for ITunes {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		let text = std::fs::read_to_string(path)?;
+		let doc = roxmltree::Document::parse(text.as_str())?;
+
+		let library = find_child(doc.root_element(), "dict")?;
+		let tracks = Self::dict_value(library, "Tracks").ok_or(Error::MissingXMLNode("Tracks"))?;
+
+		// Track dictionaries alternate with their track id keys
+		let episodes = tracks
+			.children()
+			.filter(|n| n.is_element() && n.tag_name().name() == "dict")
+			.filter_map(Episode::from_dict)
+			.collect();
+
+		Ok(Box::new(Self { episodes }))
+	}
+
+	fn name() -> &'static str {
+		"iTunes"
+	}
+	fn cli_name() -> &'static str {
+		"itunes"
+	}
+	fn file_names() -> &'static [&'static str] {
+		&["*Library.xml"]
+	}
+	fn read_only() -> bool {
+		true
+	}
+	fn status_encodings() -> &'static [StatusEncoding] {
+		&[PLAY_COUNT]
+	}
+}
@@ -0,0 +1,256 @@
+use crate::fetch;
+use crate::player::server::Server;
+use crate::player::{
+	secs_from_f64, IoWriteSeek, NewPlayer, Player, SaveOptions, Subscription, WriteStats,
+};
+use crate::podcast::{PlayingStatus, Podcast, Track};
+use crate::warning::{warn, Warning};
+use crate::BoxResult;
+
+use std::path::Path;
+
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+// Jellyfin positions are in ticks of 100ns
+const TICKS_PER_SECOND: i64 = 10_000_000;
+
+// Jellyfin hosts podcast episodes as audio items in an album named after the
+// podcast, with no record of the feed. Podcasts are matched by album name and
+// episodes by title or by the file name of the enclosure. Saving posts the user
+// data of every episode whose state differs and writes it to the output file
+// as a record.
+
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+struct UserData {
+	#[serde(default)]
+	playback_position_ticks: i64,
+	#[serde(default)]
+	played: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Item {
+	id: String,
+	#[serde(default)]
+	name: String,
+	#[serde(default)]
+	album: Option<String>,
+	#[serde(default)]
+	path: Option<String>,
+	user_data: Option<UserData>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Items {
+	items: Vec<Item>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct User {
+	id: String,
+}
+
+#[derive(Serialize)]
+struct Update {
+	item: String,
+	#[serde(flatten)]
+	user_data: UserData,
+}
+
+pub struct Jellyfin {
+	client: Client,
+	server: Server,
+	user_id: String,
+	items: Vec<Item>,
+}
+
+impl Jellyfin {
+	fn get<T: DeserializeOwned>(&self, path: &str) -> BoxResult<T> {
+		Ok(self
+			.client
+			.get(self.server.endpoint(path)?)
+			.header("X-Emby-Token", self.server.password.as_str())
+			.send()?
+			.error_for_status()?
+			.json()?)
+	}
+
+	fn album_items(&self, title: &str) -> Vec<&Item> {
+		let title = title.to_lowercase();
+		self.items
+			.iter()
+			.filter(|item| {
+				item.album
+					.as_ref()
+					.map_or(false, |album| album.to_lowercase() == title)
+			})
+			.collect()
+	}
+
+	fn find_item<'a>(items: &[&'a Item], track: &Track) -> Option<&'a Item> {
+		let file_name = track
+			.url
+			.path_segments()
+			.and_then(|segments| segments.last())
+			.unwrap_or("");
+
+		items
+			.iter()
+			.find(|item| {
+				item.name == track.title
+					|| item.path.as_ref().map_or(false, |path| {
+						Path::new(path)
+							.file_name()
+							.map_or(false, |name| name == file_name)
+					})
+			})
+			.cloned()
+	}
+}
+
+impl Player for Jellyfin {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		let items = self.album_items(podcast.title.as_str());
+		if items.is_empty() {
+			warn(
+				Warning::UnmatchedTrack,
+				format!("Podcast not found: {}", podcast.title),
+			);
+			return Ok(podcast);
+		}
+
+		for track in podcast.tracks.iter_mut() {
+			match Self::find_item(&items, track) {
+				Some(item) => {
+					let user_data = item.user_data.clone().unwrap_or(UserData {
+						playback_position_ticks: 0,
+						played: false,
+					});
+					track.progress = secs_from_f64(
+						user_data.playback_position_ticks as f64 / TICKS_PER_SECOND as f64,
+					);
+					track.playing_status = if user_data.played {
+						PlayingStatus::Played
+					} else if track.progress > 0 {
+						PlayingStatus::Playing
+					} else {
+						PlayingStatus::Unplayed
+					};
+				}
+				None => warn(
+					Warning::UnmatchedTrack,
+					format!("Track not found: {:?}", track),
+				),
+			}
+		}
+
+		Ok(podcast)
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		// Jellyfin only knows the album names
+		let mut albums: Vec<&String> = self
+			.items
+			.iter()
+			.filter_map(|item| item.album.as_ref())
+			.collect();
+		albums.sort();
+		albums.dedup();
+
+		Ok(albums
+			.into_iter()
+			.map(|album| Subscription {
+				url: None,
+				title: album.clone(),
+			})
+			.collect())
+	}
+
+	fn save(
+		self: Box<Self>,
+		podcasts: &mut dyn Iterator<Item = &'_ Podcast>,
+		w: &mut dyn IoWriteSeek,
+		_options: &SaveOptions,
+	) -> BoxResult<WriteStats> {
+		let mut updates = Vec::new();
+
+		for podcast in podcasts {
+			println!(
+				"Saving '{}' ({})",
+				podcast.title,
+				fetch::redact(&podcast.url)
+			);
+			let items = self.album_items(podcast.title.as_str());
+
+			for track in podcast.tracks.iter() {
+				let item = match Self::find_item(&items, track) {
+					Some(item) => item,
+					None => continue,
+				};
+
+				let user_data = UserData {
+					playback_position_ticks: track.progress as i64 * TICKS_PER_SECOND,
+					played: track.playing_status == PlayingStatus::Played,
+				};
+				if item.user_data.as_ref() == Some(&user_data) {
+					continue;
+				}
+
+				self.client
+					.post(self.server.endpoint(&format!(
+						"Users/{}/Items/{}/UserData",
+						self.user_id, item.id
+					))?)
+					.header("X-Emby-Token", self.server.password.as_str())
+					.json(&user_data)
+					.send()?
+					.error_for_status()?;
+				updates.push(Update {
+					item: item.id.clone(),
+					user_data,
+				});
+			}
+		}
+
+		println!("Updated {} items", updates.len());
+		serde_json::to_writer_pretty(w, &updates)?;
+		Ok(WriteStats::default())
+	}
+}
+
+impl NewPlayer for Jellyfin {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		let mut player = Self {
+			client: Client::new(),
+			server: Server::parse_token(path, "JELLYFIN_TOKEN", Self::name())?,
+			user_id: String::new(),
+			items: Vec::new(),
+		};
+
+		let user: User = player.get("Users/Me")?;
+		let items: Items = player.get(&format!(
+			"Users/{}/Items?Recursive=true&IncludeItemTypes=Audio&Fields=Path&EnableUserData=true",
+			user.id
+		))?;
+		player.user_id = user.id;
+		player.items = items.items;
+
+		Ok(Box::new(player))
+	}
+
+	fn name() -> &'static str {
+		"Jellyfin"
+	}
+	fn cli_name() -> &'static str {
+		"jellyfin"
+	}
+	fn value_name() -> &'static str {
+		"URL"
+	}
+}
@@ -0,0 +1,376 @@
+use crate::fetch;
+use crate::player::{IoWriteSeek, NewPlayer, Player, SaveOptions, Subscription, WriteStats};
+use crate::podcast::{PlayingStatus, Podcast, PodcastSettings, Track};
+use crate::url_key::url_key;
+use crate::warning::{warn, Warning};
+use crate::{BoxResult, Error};
+
+use std::fs::File;
+use std::path::Path;
+
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+// The tool's own format: every podcast with all its tracks, as they were read
+// from the feeds and the source player. Converting from it does not need the
+// feeds again, so a history can be extracted once and imported later. Fields
+// are only ever added to the schema, a file names the version it was written
+// with and files from newer versions are refused.
+
+const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+	Unplayed,
+	Playing,
+	Played,
+}
+
+impl From<PlayingStatus> for Status {
+	fn from(status: PlayingStatus) -> Self {
+		match status {
+			PlayingStatus::Unplayed => Status::Unplayed,
+			PlayingStatus::Playing => Status::Playing,
+			PlayingStatus::Played => Status::Played,
+		}
+	}
+}
+
+impl From<Status> for PlayingStatus {
+	fn from(status: Status) -> Self {
+		match status {
+			Status::Unplayed => PlayingStatus::Unplayed,
+			Status::Playing => PlayingStatus::Playing,
+			Status::Played => PlayingStatus::Played,
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonTrack {
+	guid: String,
+	url: String,
+	#[serde(default)]
+	title: String,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	duration: Option<i32>,
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	chapters: Vec<i32>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	season: Option<i32>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	episode: Option<String>,
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	transcripts: Vec<String>,
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	alternate_urls: Vec<String>,
+	progress: i32,
+	status: Status,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	last_played: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct JsonSettings {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	auto_archive: Option<bool>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	notifications: Option<bool>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	update_interval: Option<i32>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	download_count: Option<i32>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	playlist: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct JsonPodcast {
+	url: String,
+	title: String,
+	#[serde(default)]
+	settings: JsonSettings,
+	#[serde(default)]
+	tracks: Vec<JsonTrack>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	previous_url: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	guid: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonArchive {
+	version: u32,
+	podcasts: Vec<JsonPodcast>,
+}
+
+impl From<&Podcast> for JsonPodcast {
+	fn from(podcast: &Podcast) -> Self {
+		Self {
+			url: podcast.url.to_string(),
+			title: podcast.title.clone(),
+			previous_url: podcast.previous_url.as_ref().map(Url::to_string),
+			guid: podcast.guid.clone(),
+			settings: JsonSettings {
+				auto_archive: podcast.settings.auto_archive,
+				notifications: podcast.settings.notifications,
+				update_interval: podcast.settings.update_interval,
+				download_count: podcast.settings.download_count,
+				playlist: podcast.settings.playlist.clone(),
+			},
+			tracks: podcast
+				.tracks
+				.iter()
+				.map(|track| JsonTrack {
+					guid: track.guid.clone(),
+					url: track.url.to_string(),
+					title: track.title.clone(),
+					duration: track.duration,
+					chapters: track.chapters.clone(),
+					season: track.season,
+					episode: track.episode.clone(),
+					transcripts: track.transcripts.iter().map(Url::to_string).collect(),
+					alternate_urls: track.alternate_urls.iter().map(Url::to_string).collect(),
+					progress: track.progress,
+					status: track.playing_status.into(),
+					last_played: track.last_played,
+				})
+				.collect(),
+		}
+	}
+}
+
+fn parse_urls(urls: &[String]) -> BoxResult<Vec<Url>> {
+	Ok(urls
+		.iter()
+		.map(|url| Url::parse(url))
+		.collect::<Result<_, _>>()?)
+}
+
+impl JsonPodcast {
+	pub fn to_podcast(&self) -> BoxResult<Podcast> {
+		let tracks = self
+			.tracks
+			.iter()
+			.map(|track| {
+				Ok(Track {
+					guid: track.guid.clone(),
+					url: Url::parse(&track.url)?,
+					title: track.title.clone(),
+					duration: track.duration,
+					published: None,
+					chapters: track.chapters.clone(),
+					season: track.season,
+					episode: track.episode.clone(),
+					transcripts: parse_urls(&track.transcripts)?,
+					alternate_urls: parse_urls(&track.alternate_urls)?,
+					progress: track.progress,
+					playing_status: track.status.into(),
+					last_played: track.last_played,
+				})
+			})
+			.collect::<BoxResult<Vec<Track>>>()?;
+
+		Ok(Podcast {
+			url: Url::parse(&self.url)?,
+			title: self.title.clone(),
+			tracks,
+			settings: PodcastSettings {
+				auto_archive: self.settings.auto_archive,
+				notifications: self.settings.notifications,
+				update_interval: self.settings.update_interval,
+				download_count: self.settings.download_count,
+				playlist: self.settings.playlist.clone(),
+			},
+			previous_url: match &self.previous_url {
+				Some(url) => Some(Url::parse(url)?),
+				None => None,
+			},
+			guid: self.guid.clone(),
+		})
+	}
+
+	/// Same feed GUID, or the same URL where either has none
+	fn is_podcast(&self, podcast: &Podcast) -> bool {
+		match (&self.guid, &podcast.guid) {
+			(Some(guid), Some(other)) => guid == other,
+			_ => url_key(&self.url) == url_key(podcast.url.as_str()),
+		}
+	}
+}
+
+pub struct JsonFile {
+	archive: JsonArchive,
+}
+
+impl JsonFile {
+	fn find_podcast(&self, podcast: &Podcast) -> Option<&JsonPodcast> {
+		self.archive
+			.podcasts
+			.iter()
+			.find(|json| json.is_podcast(podcast))
+	}
+}
+
+impl Player for JsonFile {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		let json = match self.find_podcast(&podcast) {
+			Some(json) => json,
+			None => {
+				warn(
+					Warning::UnmatchedTrack,
+					format!("Podcast not found: {}", fetch::redact(&podcast.url)),
+				);
+				return Ok(podcast);
+			}
+		};
+
+		for track in podcast.tracks.iter_mut() {
+			// Match on the GUID first, the enclosure URL may have changed since the file was written
+			if let Some(json_track) = json
+				.tracks
+				.iter()
+				.find(|json_track| json_track.guid == track.guid)
+				.or_else(|| {
+					json.tracks
+						.iter()
+						.find(|json_track| track.has_url(&json_track.url))
+				}) {
+				track.progress = json_track.progress;
+				track.playing_status = json_track.status.into();
+				track.last_played = json_track.last_played;
+			} else {
+				warn(
+					Warning::UnmatchedTrack,
+					format!("Track not found: {:?}", track),
+				);
+			}
+		}
+
+		Ok(podcast)
+	}
+
+	fn populate_settings(&self, podcast: &mut Podcast) -> BoxResult<()> {
+		if let Some(json) = self.find_podcast(podcast) {
+			podcast.settings = PodcastSettings {
+				auto_archive: json.settings.auto_archive,
+				notifications: json.settings.notifications,
+				update_interval: json.settings.update_interval,
+				download_count: json.settings.download_count,
+				playlist: json.settings.playlist.clone(),
+			};
+		}
+		Ok(())
+	}
+
+	fn stored_podcasts(&self) -> BoxResult<Option<Vec<Podcast>>> {
+		self.archive
+			.podcasts
+			.iter()
+			.map(JsonPodcast::to_podcast)
+			.collect::<BoxResult<Vec<Podcast>>>()
+			.map(Some)
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		self.archive
+			.podcasts
+			.iter()
+			.map(|json| {
+				Ok(Subscription {
+					url: Some(Url::parse(&json.url)?),
+					title: json.title.clone(),
+				})
+			})
+			.collect()
+	}
+
+	fn schema_version(&self) -> Option<&'static str> {
+		Some("1")
+	}
+
+	fn episode_count(&self) -> BoxResult<Option<usize>> {
+		Ok(Some(
+			self.archive
+				.podcasts
+				.iter()
+				.map(|json| json.tracks.len())
+				.sum(),
+		))
+	}
+
+	// Podcasts given replace those in the file
+	fn writes_whole_podcasts(&self) -> bool {
+		true
+	}
+
+	fn save(
+		mut self: Box<Self>,
+		podcasts: &mut dyn Iterator<Item = &'_ Podcast>,
+		w: &mut dyn IoWriteSeek,
+		_options: &SaveOptions,
+	) -> BoxResult<WriteStats> {
+		for podcast in podcasts {
+			println!(
+				"Saving '{}' ({})",
+				podcast.title,
+				fetch::redact(&podcast.url)
+			);
+
+			// Podcasts already in the file are replaced, the others are kept as they are
+			let json = JsonPodcast::from(podcast);
+			match self
+				.archive
+				.podcasts
+				.iter_mut()
+				.find(|existing| existing.is_podcast(podcast))
+			{
+				Some(existing) => *existing = json,
+				None => self.archive.podcasts.push(json),
+			}
+		}
+
+		self.archive.version = SCHEMA_VERSION;
+		serde_json::to_writer_pretty(w, &self.archive)?;
+		Ok(WriteStats::default())
+	}
+}
+
+impl NewPlayer for JsonFile {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		// A file that does not exist yet is an empty one to write to
+		if !Path::new(path).exists() {
+			return Ok(Box::new(Self {
+				archive: JsonArchive {
+					version: SCHEMA_VERSION,
+					podcasts: Vec::new(),
+				},
+			}));
+		}
+
+		let archive: JsonArchive = serde_json::from_reader(File::open(path)?)?;
+		if archive.version > SCHEMA_VERSION {
+			return Err(Error::UnknownSchema(
+				"JSON file",
+				String::from("version ") + &archive.version.to_string(),
+			)
+			.into());
+		}
+
+		Ok(Box::new(Self { archive }))
+	}
+
+	fn name() -> &'static str {
+		"JSON file"
+	}
+	fn cli_name() -> &'static str {
+		"json"
+	}
+	fn writes_through() -> bool {
+		true
+	}
+	fn schema_versions() -> Vec<&'static str> {
+		vec!["1"]
+	}
+}
@@ -0,0 +1,192 @@
+use crate::fetch;
+use crate::player::{
+	count_rows, populate_tracks, save_track, secs_from_millis, IoWriteSeek, NewPlayer, Player,
+	SaveOptions, StatusEncoding, Subscription, WriteStats,
+};
+use crate::podcast::{PlayingStatus, Podcast};
+use crate::warning::{warn, Warning};
+use crate::{BoxResult, SQLLiteDatabase};
+
+use std::borrow::Borrow;
+
+use reqwest::Url;
+use rusqlite::Connection;
+
+const READ: StatusEncoding = StatusEncoding {
+	field: "Entries.read",
+	values: &[
+		(PlayingStatus::Unplayed, 0),
+		(PlayingStatus::Playing, 0),
+		(PlayingStatus::Played, 1),
+	],
+};
+
+// Kasts keys entries by the feed URL and the item guid, with play positions
+// kept on the enclosure in milliseconds. The database normally lives at
+// ~/.local/share/KDE/kasts/database.db3
+pub struct Kasts {
+	db: SQLLiteDatabase,
+}
+
+impl Kasts {
+	fn has_feed(&self, url: &Url) -> BoxResult<()> {
+		let conn: &Connection = self.db.borrow();
+		let mut stmt = conn.prepare("SELECT url FROM Feeds WHERE url_key(url) = url_key(:url)")?;
+		let mut rows = stmt.query_named(&[(":url", &url.to_string())])?;
+		rows.next()?.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+		Ok(())
+	}
+
+	fn get_entry(&self, feed_url: &Url, guid: &String) -> rusqlite::Result<(bool, i64)> {
+		let conn: &Connection = self.db.borrow();
+		let mut stmt = conn
+			.prepare("SELECT Entries.read, Enclosures.playposition FROM Entries JOIN Enclosures ON Enclosures.feed = Entries.feed AND Enclosures.id = Entries.id WHERE url_key(Entries.feed) = url_key(:feed) AND Entries.id = :id")?;
+		let mut rows = stmt.query_named(&[(":feed", &feed_url.to_string()), (":id", guid)])?;
+		let first_row = rows.next()?;
+		first_row
+			.ok_or(rusqlite::Error::QueryReturnedNoRows)
+			.and_then(|row| Ok((row.get(0)?, row.get(1)?)))
+	}
+
+	fn update_entry(
+		&self,
+		feed_url: &Url,
+		guid: &String,
+		read: bool,
+		play_position_ms: i64,
+	) -> rusqlite::Result<()> {
+		let conn: &Connection = self.db.borrow();
+		conn.execute_named(
+			"UPDATE Entries SET read = :read WHERE url_key(feed) = url_key(:feed) AND id = :id",
+			&[
+				(":feed", &feed_url.to_string()),
+				(":id", guid),
+				(":read", &read),
+			],
+		)?;
+		conn.execute_named(
+			"UPDATE Enclosures SET playposition = :playposition WHERE url_key(feed) = url_key(:feed) AND id = :id",
+			&[
+				(":feed", &feed_url.to_string()),
+				(":id", guid),
+				(":playposition", &play_position_ms),
+			],
+		)
+		.map(|_| ())
+	}
+}
+
+impl Player for Kasts {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		self.has_feed(&podcast.url)?;
+
+		let url = podcast.url.clone();
+		populate_tracks(&mut podcast, |track| {
+			match self.get_entry(&url, &track.guid) {
+				Ok((read, play_position_ms)) => {
+					track.progress = secs_from_millis(play_position_ms);
+
+					track.playing_status = if read {
+						PlayingStatus::Played
+					} else if track.progress > 0 {
+						PlayingStatus::Playing
+					} else {
+						PlayingStatus::Unplayed
+					};
+
+					Ok(())
+				}
+				Err(rusqlite::Error::QueryReturnedNoRows) => {
+					warn(
+						Warning::UnmatchedTrack,
+						format!("Track not found: {:?}", track),
+					);
+					Ok(())
+				}
+				Err(err) => Err(err),
+			}?;
+			Ok(())
+		});
+
+		Ok(podcast)
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		let conn: &Connection = self.db.borrow();
+		let mut stmt = conn.prepare("SELECT url, name FROM Feeds")?;
+		let mut rows = stmt.query(rusqlite::NO_PARAMS)?;
+
+		let mut subs = Vec::new();
+		while let Some(row) = rows.next()? {
+			let url: String = row.get(0)?;
+			subs.push(Subscription {
+				url: Some(Url::parse(url.as_str())?),
+				title: row.get(1)?,
+			});
+		}
+		Ok(subs)
+	}
+
+	fn save(
+		self: Box<Self>,
+		podcasts: &mut dyn Iterator<Item = &'_ Podcast>,
+		w: &mut dyn IoWriteSeek,
+		_options: &SaveOptions,
+	) -> BoxResult<WriteStats> {
+		let mut stats = WriteStats::default();
+
+		for podcast in podcasts {
+			println!(
+				"Saving '{}' ({})",
+				podcast.title,
+				fetch::redact(&podcast.url)
+			);
+			self.has_feed(&podcast.url)?;
+
+			for track in podcast.tracks.iter() {
+				let read = READ.encode(track.playing_status) != 0;
+				save_track(
+					track,
+					self.update_entry(
+						&podcast.url,
+						&track.guid,
+						read,
+						track.progress as i64 * 1000,
+					),
+					&mut stats,
+				);
+			}
+		}
+
+		// Copy temp file to output
+		let mut temp_file = self.db.into_file()?;
+		std::io::copy(&mut temp_file, w)?;
+		Ok(stats)
+	}
+
+	fn episode_count(&self) -> BoxResult<Option<usize>> {
+		let conn: &Connection = self.db.borrow();
+		count_rows(conn, "Entries").map(Some)
+	}
+}
+
+impl NewPlayer for Kasts {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		Ok(Box::new(Self {
+			db: SQLLiteDatabase::open(path)?,
+		}))
+	}
+
+	fn name() -> &'static str {
+		"Kasts"
+	}
+	fn cli_name() -> &'static str {
+		"kasts"
+	}
+	fn file_names() -> &'static [&'static str] {
+		&["database.db3"]
+	}
+	fn status_encodings() -> &'static [StatusEncoding] {
+		&[READ]
+	}
+}
@@ -0,0 +1,336 @@
+use crate::fetch;
+use crate::player::{
+	count_rows, find_by_urls, populate_tracks, save_track, secs_from_f64, IoWriteSeek, NewPlayer,
+	Player, SaveOptions, StatusEncoding, WriteStats,
+};
+use crate::podcast::{PlayingStatus, Podcast, Track};
+use crate::warning::{warn, Warning};
+use crate::{BoxResult, Error, SQLLiteDatabase};
+
+use std::borrow::Borrow;
+
+use reqwest::Url;
+use rusqlite::{Connection, OptionalExtension};
+
+const PLAY_COUNT: StatusEncoding = StatusEncoding {
+	field: "files.playCount (MyVideos) or song.iTimesPlayed (MyMusic)",
+	values: &[
+		(PlayingStatus::Unplayed, 0),
+		(PlayingStatus::Playing, 0),
+		(PlayingStatus::Played, 1),
+	],
+};
+
+// Bookmarks of this type are resume points, the others are chapters and user bookmarks
+const RESUME_BOOKMARK: i64 = 1;
+
+/// Where one of Kodi's databases keeps the play count of a file
+struct Library {
+	name: &'static str,
+	items: &'static str,
+	item_id: &'static str,
+	file_name: &'static str,
+	play_count: &'static str,
+	last_played: &'static str,
+	/// Value of the play count column for a file that was never played
+	unplayed_count: Option<i64>,
+	/// Only the video database has a bookmark table for resume points
+	resume_points: bool,
+}
+
+const LIBRARIES: [Library; 2] = [
+	Library {
+		name: "MyVideos",
+		items: "files",
+		item_id: "idFile",
+		file_name: "strFilename",
+		play_count: "playCount",
+		last_played: "lastPlayed",
+		unplayed_count: None,
+		resume_points: true,
+	},
+	Library {
+		name: "MyMusic",
+		items: "song",
+		item_id: "idSong",
+		file_name: "strFileName",
+		play_count: "iTimesPlayed",
+		last_played: "lastplayed",
+		unplayed_count: Some(0),
+		resume_points: false,
+	},
+];
+
+impl Library {
+	fn detect(conn: &Connection) -> BoxResult<&'static Self> {
+		let mut stmt = conn.prepare("SELECT name FROM sqlite_master WHERE type = 'table'")?;
+		let mut rows = stmt.query(rusqlite::NO_PARAMS)?;
+		let mut tables = Vec::new();
+		while let Some(row) = rows.next()? {
+			let name: String = row.get(0)?;
+			tables.push(name);
+		}
+
+		let has = |table: &str| tables.iter().any(|name| name == table);
+		LIBRARIES
+			.iter()
+			.find(|library| {
+				has(library.items) && has("path") && (!library.resume_points || has("bookmark"))
+			})
+			.ok_or_else(|| {
+				Error::UnknownSchema("Kodi", String::from("tables ") + &tables.join(", ")).into()
+			})
+	}
+}
+
+// Kodi has no notion of feeds, it only knows the files it has played. Those
+// are split into a directory in the path table and a file name, which for
+// streamed episodes join back into the enclosure URL. Downloaded episodes are
+// matched by their file name when only one file has it. Play counts live in
+// MyVideosNN.db or MyMusicNN.db in ~/.kodi/userdata/Database, resume points in
+// seconds only in the former.
+pub struct Kodi {
+	db: SQLLiteDatabase,
+	library: &'static Library,
+}
+
+impl Kodi {
+	fn find_item(&self, url: &Url) -> rusqlite::Result<(i64, i64)> {
+		let lib = self.library;
+		let conn: &Connection = self.db.borrow();
+
+		let by_url = format!(
+			"SELECT {items}.{id}, {items}.{count} FROM {items} JOIN path ON path.idPath = {items}.idPath WHERE url_key(path.strPath || {items}.{file}) = url_key(:url) OR url_key({items}.{file}) = url_key(:url)",
+			items = lib.items,
+			id = lib.item_id,
+			count = lib.play_count,
+			file = lib.file_name,
+		);
+		let found = conn
+			.query_row_named(&by_url, &[(":url", &url.to_string())], |row| {
+				Ok((row.get(0)?, row.get::<_, Option<i64>>(1)?))
+			})
+			.optional()?;
+		if let Some((id, count)) = found {
+			return Ok((id, count.unwrap_or(0)));
+		}
+
+		let file_name = url
+			.path_segments()
+			.and_then(|segments| segments.last())
+			.unwrap_or("");
+		let by_name = format!(
+			"SELECT {id}, {count} FROM {items} WHERE {file} = :file LIMIT 2",
+			items = lib.items,
+			id = lib.item_id,
+			count = lib.play_count,
+			file = lib.file_name,
+		);
+		let mut stmt = conn.prepare(&by_name)?;
+		let mut rows = stmt.query_named(&[(":file", &file_name)])?;
+		let first = match rows.next()? {
+			Some(row) => (row.get(0)?, row.get::<_, Option<i64>>(1)?),
+			None => return Err(rusqlite::Error::QueryReturnedNoRows),
+		};
+		// The same file name in two places could be any episode
+		if rows.next()?.is_some() {
+			return Err(rusqlite::Error::QueryReturnedNoRows);
+		}
+		Ok((first.0, first.1.unwrap_or(0)))
+	}
+
+	fn get_resume_point(&self, id: i64) -> rusqlite::Result<f64> {
+		if !self.library.resume_points {
+			return Ok(0.0);
+		}
+		let conn: &Connection = self.db.borrow();
+		conn.query_row_named(
+			"SELECT timeInSeconds FROM bookmark WHERE idFile = :id AND type = :type",
+			&[(":id", &id), (":type", &RESUME_BOOKMARK)],
+			|row| row.get(0),
+		)
+		.optional()
+		.map(|time| time.unwrap_or(0.0))
+	}
+
+	fn update_item(
+		&self,
+		id: i64,
+		count: i64,
+		track: &Track,
+		options: &SaveOptions,
+	) -> rusqlite::Result<()> {
+		let lib = self.library;
+		let conn: &Connection = self.db.borrow();
+
+		let played = PLAY_COUNT.encode(track.playing_status) != 0;
+		if played && count == 0 {
+			// Kodi stores local time, UTC is the closest without a timezone database
+			conn.execute_named(
+				&format!(
+					"UPDATE {items} SET {count} = 1, {last} = :last WHERE {id} = :id",
+					items = lib.items,
+					count = lib.play_count,
+					last = lib.last_played,
+					id = lib.item_id,
+				),
+				&[
+					(":id", &id),
+					(":last", &options.clock.iso8601().replace('T', " ")),
+				],
+			)?;
+		} else if !played && count > 0 {
+			conn.execute_named(
+				&format!(
+					"UPDATE {items} SET {count} = :unplayed WHERE {id} = :id",
+					items = lib.items,
+					count = lib.play_count,
+					id = lib.item_id,
+				),
+				&[(":id", &id), (":unplayed", &lib.unplayed_count)],
+			)?;
+		}
+
+		if !lib.resume_points {
+			if track.playing_status == PlayingStatus::Playing && track.progress > 0 {
+				warn(
+					Warning::LossyField,
+					format!(
+						"Kodi's music database has no resume points, the position of {} is lost",
+						track.url
+					),
+				);
+			}
+			return Ok(());
+		}
+
+		conn.execute_named(
+			"DELETE FROM bookmark WHERE idFile = :id AND type = :type",
+			&[(":id", &id), (":type", &RESUME_BOOKMARK)],
+		)?;
+		if track.playing_status == PlayingStatus::Playing && track.progress > 0 {
+			conn.execute_named(
+				"INSERT INTO bookmark (idFile, timeInSeconds, totalTimeInSeconds, player, playerState, type) VALUES (:id, :time, :total, 'VideoPlayer', '', :type)",
+				&[
+					(":id", &id),
+					(":time", &(track.progress as f64)),
+					(":total", &(track.duration.unwrap_or(0) as f64)),
+					(":type", &RESUME_BOOKMARK),
+				],
+			)?;
+		}
+		Ok(())
+	}
+}
+
+impl Player for Kodi {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		populate_tracks(&mut podcast, |track| {
+			let (id, count) = match find_by_urls(track, |url| self.find_item(url)) {
+				Ok((_, item)) => item,
+				Err(rusqlite::Error::QueryReturnedNoRows) => {
+					warn(
+						Warning::UnmatchedTrack,
+						format!("Track not found: {:?}", track),
+					);
+					return Ok(());
+				}
+				Err(err) => return Err(err.into()),
+			};
+
+			track.progress = secs_from_f64(self.get_resume_point(id)?);
+			track.playing_status = if count > 0 {
+				PlayingStatus::Played
+			} else if track.progress > 0 {
+				PlayingStatus::Playing
+			} else {
+				PlayingStatus::Unplayed
+			};
+			Ok(())
+		});
+
+		Ok(podcast)
+	}
+
+	fn save(
+		self: Box<Self>,
+		podcasts: &mut dyn Iterator<Item = &'_ Podcast>,
+		w: &mut dyn IoWriteSeek,
+		options: &SaveOptions,
+	) -> BoxResult<WriteStats> {
+		let mut stats = WriteStats::default();
+
+		for podcast in podcasts {
+			println!(
+				"Saving '{}' ({})",
+				podcast.title,
+				fetch::redact(&podcast.url)
+			);
+
+			for track in podcast.tracks.iter() {
+				let (id, count) = match find_by_urls(track, |url| self.find_item(url)) {
+					Ok((_, item)) => item,
+					Err(rusqlite::Error::QueryReturnedNoRows) => {
+						stats.record(false);
+						continue;
+					}
+					Err(err) => {
+						save_track(track, Err::<(), _>(err), &mut stats);
+						continue;
+					}
+				};
+				if save_track(
+					track,
+					self.update_item(id, count, track, options),
+					&mut stats,
+				)
+				.is_some()
+				{
+					stats.record(true);
+				}
+			}
+		}
+
+		stats.report(Self::name());
+
+		// Copy temp file to output
+		let mut temp_file = self.db.into_file()?;
+		std::io::copy(&mut temp_file, w)?;
+		Ok(stats)
+	}
+
+	fn schema_version(&self) -> Option<&'static str> {
+		Some(self.library.name)
+	}
+
+	fn episode_count(&self) -> BoxResult<Option<usize>> {
+		let conn: &Connection = self.db.borrow();
+		count_rows(conn, self.library.items).map(Some)
+	}
+}
+
+impl NewPlayer for Kodi {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		let db = SQLLiteDatabase::open(path)?;
+		let library = Library::detect(db.borrow())?;
+		println!("Kodi {} database", library.name);
+
+		Ok(Box::new(Self { db, library }))
+	}
+
+	fn name() -> &'static str {
+		"Kodi"
+	}
+	fn cli_name() -> &'static str {
+		"kodi"
+	}
+	fn file_names() -> &'static [&'static str] {
+		&["MyVideos*.db", "MyMusic*.db"]
+	}
+	fn status_encodings() -> &'static [StatusEncoding] {
+		&[PLAY_COUNT]
+	}
+	fn schema_versions() -> Vec<&'static str> {
+		LIBRARIES.iter().map(|library| library.name).collect()
+	}
+}
@@ -0,0 +1,127 @@
+use crate::fetch;
+use crate::opml::find_child;
+use crate::player::{NewPlayer, Player};
+use crate::podcast::{PlayingStatus, Podcast, Track};
+use crate::warning::{warn, Warning};
+use crate::BoxResult;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use reqwest::Url;
+
+// Huffduffer, and listen later services like it, give each user a feed of the
+// episodes they bookmarked from any podcast. Its items carry the episode's
+// enclosure but not the feed it came from, so the podcasts still come from
+// --opml and the bookmarks are matched to their episodes by enclosure URL.
+// Titles are not enough, with no feed to go by a bookmark called "Trailer"
+// would match episodes of every podcast. The bookmarked episodes get the
+// --listen-later-status, the others are left as they are.
+
+pub const LISTEN_LATER_STATUS_NAMES: [&str; 2] = ["unplayed", "played"];
+
+/// Set by --listen-later-status, bookmarked episodes are unplayed unless this is set
+static BOOKMARKS_PLAYED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_listen_later_status(name: &str) {
+	BOOKMARKS_PLAYED.store(name == "played", Ordering::Relaxed);
+}
+
+struct Bookmark {
+	url: String,
+}
+
+impl Bookmark {
+	fn is_track(&self, track: &Track) -> bool {
+		track.has_url(&self.url)
+	}
+}
+
+pub struct ListenLater {
+	bookmarks: Vec<Bookmark>,
+}
+
+impl Player for ListenLater {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		let bookmarked: Vec<bool> = podcast
+			.tracks
+			.iter()
+			.map(|track| {
+				self.bookmarks
+					.iter()
+					.any(|bookmark| bookmark.is_track(track))
+			})
+			.collect();
+		if !bookmarked.contains(&true) {
+			warn(
+				Warning::UnmatchedTrack,
+				format!("Podcast not found: {}", fetch::redact(&podcast.url)),
+			);
+			return Ok(podcast);
+		}
+
+		let status = if BOOKMARKS_PLAYED.load(Ordering::Relaxed) {
+			PlayingStatus::Played
+		} else {
+			PlayingStatus::Unplayed
+		};
+		for (track, bookmarked) in podcast.tracks.iter_mut().zip(bookmarked) {
+			if bookmarked {
+				track.progress = 0;
+				track.playing_status = status;
+			}
+		}
+
+		Ok(podcast)
+	}
+
+	fn episode_count(&self) -> BoxResult<Option<usize>> {
+		Ok(Some(self.bookmarks.len()))
+	}
+}
+
+impl NewPlayer for ListenLater {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		// A bare name is a Huffduffer user, anything else the URL of a listen later feed
+		let url = if path.contains("://") {
+			Url::parse(path)?
+		} else if path.contains('.') || path.contains('/') {
+			Url::parse(&format!("https://{}", path))?
+		} else {
+			Url::parse(&format!("https://huffduffer.com/{}/rss", path))?
+		};
+		println!("Fetching bookmarks ({})", url);
+
+		let text = fetch::client()?
+			.get(url)
+			.send()?
+			.error_for_status()?
+			.text()?;
+		let doc = roxmltree::Document::parse(text.as_str())?;
+		let bookmarks = find_child(doc.root_element(), "channel")?
+			.children()
+			.filter(|item| item.is_element() && item.tag_name().name() == "item")
+			// Bookmarks without an enclosure can not be matched to an episode
+			.filter_map(|item| {
+				item.children()
+					.find(|n| n.is_element() && n.tag_name().name() == "enclosure")
+					.and_then(|n| n.attribute("url"))
+			})
+			.map(|url| Bookmark { url: url.into() })
+			.collect();
+
+		Ok(Box::new(Self { bookmarks }))
+	}
+
+	fn name() -> &'static str {
+		"Huffduffer / listen later feed"
+	}
+	fn cli_name() -> &'static str {
+		"listen-later"
+	}
+	fn value_name() -> &'static str {
+		"URL"
+	}
+	fn read_only() -> bool {
+		true
+	}
+}
@@ -0,0 +1,103 @@
+use crate::player::titles::normalise;
+use crate::player::Subscription;
+use crate::podcast::FailedFeeds;
+use crate::podcast::{Podcast, PodcastSettings, Track};
+use crate::url_key::url_key;
+use crate::warning::{warn, Warning};
+
+use reqwest::Url;
+
+// With --without-feeds nothing is fetched: the podcasts and their episodes are
+// read from the source player's own database, for shows whose feed is gone
+// but which both players still have. The destination players then find them
+// the way they always do, by feed URL or title and by each episode's URL or
+// GUID. A player that does not keep the feed URL has its podcasts given the
+// URL of the subscription with the same normalised title in another player of
+// the run.
+//
+// The same is done for each feed of a normal run that can not be fetched at
+// all, so the history of a show that has gone away is still converted from
+// what the source player has of it. Its podcast is found by feed URL, or by
+// normalised title where the player keeps none.
+
+/// A podcast as the source player keeps it, without its feed
+pub struct LocalPodcast {
+	pub title: String,
+	/// Where the player keeps the feed URL
+	pub url: Option<Url>,
+	pub tracks: Vec<Track>,
+}
+
+/// The podcasts with the feed URL of another player's subscription where the source player
+/// has none. Those left without one can not be found in any player and are left out
+pub fn podcasts_with_urls(local: Vec<LocalPodcast>, subs: &[Subscription]) -> Vec<Podcast> {
+	local
+		.into_iter()
+		.filter_map(|podcast| {
+			let title = normalise(&podcast.title);
+			let url = podcast.url.or_else(|| {
+				subs.iter()
+					.find(|sub| sub.url.is_some() && normalise(&sub.title) == title)
+					.and_then(|sub| sub.url.clone())
+			});
+			let url = match url {
+				Some(url) => url,
+				None => {
+					warn(
+						Warning::PodcastLeftOut,
+						format!(
+							"Leaving out '{}', no other player has a podcast of that title to take its feed URL from",
+							podcast.title
+						),
+					);
+					return None;
+				}
+			};
+
+			Some(Podcast {
+				url,
+				guid: None,
+				title: podcast.title,
+				tracks: podcast.tracks,
+				settings: PodcastSettings::default(),
+				previous_url: None,
+			})
+		})
+		.collect()
+}
+
+/// The podcasts of the feeds that could not be fetched, from the ones the source player has.
+/// Feeds it has no episodes of stay left out
+pub fn podcasts_for_failed(mut local: Vec<LocalPodcast>, failed: FailedFeeds) -> Vec<Podcast> {
+	failed
+		.into_iter()
+		.filter_map(|(url, title)| {
+			let (key, normalised) = (url_key(&url), normalise(&title));
+			let found = local.iter().position(|podcast| match podcast.url {
+				Some(ref local_url) => url_key(local_url.as_str()) == key,
+				None => normalise(&podcast.title) == normalised,
+			})?;
+			let podcast = local.swap_remove(found);
+			if podcast.tracks.is_empty() {
+				return None;
+			}
+
+			warn(
+				Warning::DeadFeed,
+				format!(
+					"Converting '{}' from the {} episodes the source player has of it, its feed could not be fetched",
+					title,
+					podcast.tracks.len()
+				),
+			);
+			Some(Podcast {
+				url: Url::parse(&url).ok()?,
+				guid: None,
+				title,
+				tracks: podcast.tracks,
+				settings: PodcastSettings::default(),
+				previous_url: None,
+			})
+		})
+		.collect()
+}
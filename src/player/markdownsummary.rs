@@ -0,0 +1,126 @@
+use crate::fetch;
+use crate::player::htmlreport::{hms, unknown_lengths, Totals};
+use crate::player::{IoWriteSeek, NewPlayer, Player, SaveOptions, WriteStats};
+use crate::podcast::Podcast;
+use crate::{BoxResult, Error};
+
+use std::io::Write;
+
+// A plain text snapshot of a history to keep next to the backups it came from:
+// how many episodes of each feed are played, in progress and unplayed, and how
+// long was listened to. Markdown so it reads as well in a text editor as
+// rendered. Like the HTML report, the --markdown file is not read.
+
+/// Pipes would end the table cell early
+fn cell(s: &str) -> String {
+	s.replace('|', "\\|")
+}
+
+pub struct MarkdownSummary;
+
+impl Player for MarkdownSummary {
+	fn populate(&mut self, _podcast: Podcast) -> BoxResult<Podcast> {
+		Err(Error::UnsupportedOperation("converting from a Markdown summary").into())
+	}
+
+	fn stores_provenance(&self) -> bool {
+		true
+	}
+
+	fn writes_whole_podcasts(&self) -> bool {
+		true
+	}
+
+	fn save(
+		self: Box<Self>,
+		podcasts: &mut dyn Iterator<Item = &'_ Podcast>,
+		w: &mut dyn IoWriteSeek,
+		options: &SaveOptions,
+	) -> BoxResult<WriteStats> {
+		let podcasts: Vec<&Podcast> = podcasts.collect();
+		let totals = Totals::of(podcasts.iter().flat_map(|podcast| podcast.tracks.iter()));
+
+		writeln!(w, "# Podcast history")?;
+		writeln!(w)?;
+		writeln!(
+			w,
+			"Converted from {} by {} {} at {}.",
+			options.provenance.source_player,
+			options.provenance.tool,
+			options.provenance.version,
+			options.clock.iso8601()
+		)?;
+		writeln!(w)?;
+		writeln!(w, "- Podcasts: {}", podcasts.len())?;
+		writeln!(w, "- Episodes: {}", totals.episodes)?;
+		writeln!(w, "- Played: {}", totals.played)?;
+		writeln!(w, "- In progress: {}", totals.playing)?;
+		writeln!(w, "- Unplayed: {}", totals.unplayed())?;
+		writeln!(
+			w,
+			"- Listening time: {} ({} hours)",
+			hms(totals.listened as i32),
+			totals.listened_hours()
+		)?;
+		writeln!(
+			w,
+			"  - Played episodes: {}",
+			hms(totals.listened_played as i32)
+		)?;
+		writeln!(
+			w,
+			"  - In progress: {}",
+			hms(totals.listened_playing() as i32)
+		)?;
+		writeln!(
+			w,
+			"- Backlog: {} hours{}",
+			totals.backlog_hours(),
+			unknown_lengths(&totals)
+		)?;
+		writeln!(w)?;
+		writeln!(
+			w,
+			"| Podcast | Played | In progress | Unplayed | Listening time | Complete | Backlog |"
+		)?;
+		writeln!(w, "| --- | ---: | ---: | ---: | ---: | ---: | ---: |")?;
+
+		for podcast in podcasts.iter() {
+			println!(
+				"Saving '{}' ({})",
+				podcast.title,
+				fetch::redact(&podcast.url)
+			);
+			let totals = Totals::of(podcast.tracks.iter());
+			writeln!(
+				w,
+				"| [{}]({}) | {} | {} | {} | {} | {}% | {} |",
+				cell(&podcast.title),
+				fetch::redact(&podcast.url),
+				totals.played,
+				totals.playing,
+				totals.unplayed(),
+				hms(totals.listened as i32),
+				totals.completion(),
+				hms(totals.backlog as i32)
+			)?;
+		}
+		Ok(WriteStats::default())
+	}
+}
+
+impl NewPlayer for MarkdownSummary {
+	fn new(_path: &str) -> BoxResult<Box<dyn Player>> {
+		Ok(Box::new(Self))
+	}
+
+	fn name() -> &'static str {
+		"Markdown summary"
+	}
+	fn cli_name() -> &'static str {
+		"markdown"
+	}
+	fn write_only() -> bool {
+		true
+	}
+}
@@ -0,0 +1,239 @@
+use crate::player::titles::normalise;
+use crate::podcast::Track;
+
+use std::sync::RwLock;
+
+use lazy_static::lazy_static;
+use reqwest::Url;
+
+// How an episode of the feed is found among those a player stored. Each
+// strategy in the --match-strategy chain is tried in turn until one finds it:
+// the guid and enclosure URL first, as before, then looser ones for feeds
+// that moved their files to another CDN or rewrote their guids. The loose
+// strategies only count a match when exactly one stored episode fits and it
+// fits no other episode of the feed, so two episodes called "Bonus", of the
+// same length or both served as media.mp3 are never mixed up.
+
+/// Published this close together, in seconds, is the same day in any time zone
+const DATE_TOLERANCE: i64 = 36 * 60 * 60;
+/// Durations this close, in seconds, are the same file re-encoded or re-hosted
+const DURATION_TOLERANCE: i32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+	/// The feed's guid, or the key the player made of it
+	Guid,
+	/// Any of the episode's URLs, by url_key
+	Url,
+	/// The file name at the end of any of the episode's URLs, whatever host or tracking
+	/// prefix it is served through
+	NormalisedUrl,
+	/// Normalised title and the day it was published
+	TitleDate,
+	/// Length in seconds
+	Duration,
+}
+
+const ALL: [Strategy; 5] = [
+	Strategy::Guid,
+	Strategy::Url,
+	Strategy::NormalisedUrl,
+	Strategy::TitleDate,
+	Strategy::Duration,
+];
+
+pub const MATCH_STRATEGY_NAMES: [&str; 5] =
+	["guid", "url", "normalized-url", "title-date", "duration"];
+
+/// Used without --match-strategy, duration alone is too weak to be tried unasked
+const DEFAULT: [&str; 4] = ["guid", "url", "normalized-url", "title-date"];
+
+lazy_static! {
+	static ref CHAIN: RwLock<Vec<Strategy>> = RwLock::new(
+		DEFAULT
+			.iter()
+			.filter_map(|name| Strategy::from_name(name))
+			.collect()
+	);
+}
+
+impl Strategy {
+	pub fn name(self) -> &'static str {
+		MATCH_STRATEGY_NAMES[self as usize]
+	}
+
+	fn from_name(name: &str) -> Option<Self> {
+		ALL.iter().cloned().find(|strategy| strategy.name() == name)
+	}
+
+	/// Exact strategies take the first stored episode that fits, as the players did before
+	fn is_exact(self) -> bool {
+		self == Strategy::Guid || self == Strategy::Url
+	}
+}
+
+/// Try these strategies, in this order, to find each episode
+pub fn set_match_strategies<'a, I: IntoIterator<Item = &'a str>>(names: I) {
+	*CHAIN.write().expect("match strategy lock poisoned") =
+		names.into_iter().filter_map(Strategy::from_name).collect();
+}
+
+/// An episode as a player stored it, with whatever of it the player keeps
+pub struct Candidate<T> {
+	pub guid: Option<String>,
+	pub url: Option<String>,
+	pub title: Option<String>,
+	/// Unix time in seconds
+	pub published: Option<i64>,
+	pub duration: Option<i32>,
+	/// What the player needs to find the episode again, such as its row id
+	pub value: T,
+}
+
+/// Last path segment of a media URL, None when it has no file name to go by
+fn file_name(url: &str) -> Option<String> {
+	let url = Url::parse(url).ok()?;
+	let name = url
+		.path_segments()?
+		.filter(|segment| !segment.is_empty())
+		.last()?;
+	if name.contains('.') {
+		Some(name.to_lowercase())
+	} else {
+		None
+	}
+}
+
+fn fits<T>(strategy: Strategy, track: &Track, guid_key: &str, candidate: &Candidate<T>) -> bool {
+	match strategy {
+		Strategy::Guid => candidate
+			.guid
+			.as_ref()
+			.map_or(false, |guid| guid == guid_key),
+		Strategy::Url => candidate
+			.url
+			.as_ref()
+			.map_or(false, |url| track.has_url(url)),
+		Strategy::NormalisedUrl => candidate
+			.url
+			.as_ref()
+			.and_then(|url| file_name(url))
+			.map_or(false, |name| {
+				track
+					.urls()
+					.any(|own| file_name(own.as_str()).as_ref() == Some(&name))
+			}),
+		Strategy::TitleDate => match (&candidate.title, candidate.published, track.published) {
+			(Some(title), Some(stored), Some(published)) => {
+				(stored - published).abs() <= DATE_TOLERANCE
+					&& !track.title.trim().is_empty()
+					&& normalise(title) == normalise(&track.title)
+			}
+			_ => false,
+		},
+		Strategy::Duration => match (candidate.duration, track.duration) {
+			(Some(stored), Some(duration)) => {
+				duration > 0 && (stored - duration).abs() <= DURATION_TOLERANCE
+			}
+			_ => false,
+		},
+	}
+}
+
+/// The stored episode `track` is, by the first strategy of the chain that finds it. A loose
+/// strategy only counts when the stored episode fits no other of the podcast's `tracks` by it
+/// either, so one stored episode with a generic file name is never given to several tracks
+fn find_among<'a, T>(
+	chain: &[Strategy],
+	track: &Track,
+	tracks: &[Track],
+	guid_key: &str,
+	candidates: &'a [Candidate<T>],
+) -> Option<&'a Candidate<T>> {
+	for &strategy in chain.iter() {
+		let mut fitting = candidates
+			.iter()
+			.filter(|candidate| fits(strategy, track, guid_key, candidate));
+		let found = match fitting.next() {
+			Some(found) => found,
+			None => continue,
+		};
+		if strategy.is_exact() {
+			return Some(found);
+		}
+		// Loose strategies do not use the guid key
+		let shared = fitting.next().is_some()
+			|| tracks
+				.iter()
+				.any(|other| !std::ptr::eq(other, track) && fits(strategy, other, "", found));
+		if !shared {
+			return Some(found);
+		}
+	}
+	None
+}
+
+/// The stored episode each of a podcast's `tracks` is, in the same order. Players that keep a
+/// key made from the guid rather than the guid give `guid_key` to make it
+pub fn find_all<'a, T, K: Fn(&str) -> String>(
+	tracks: &[Track],
+	guid_key: K,
+	candidates: &'a [Candidate<T>],
+) -> Vec<Option<&'a Candidate<T>>> {
+	let chain = CHAIN.read().expect("match strategy lock poisoned");
+	tracks
+		.iter()
+		.map(|track| find_among(&chain, track, tracks, &guid_key(&track.guid), candidates))
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn track(guid: &str, url: &str) -> Track {
+		Track::new(guid.into(), Url::parse(url).unwrap(), guid.into(), None)
+	}
+
+	fn candidate(guid: &str, url: &str, value: u32) -> Candidate<u32> {
+		Candidate {
+			guid: Some(guid.into()),
+			url: Some(url.into()),
+			title: None,
+			published: None,
+			duration: None,
+			value,
+		}
+	}
+
+	#[test]
+	fn generic_file_names_match_one_track_at_most() {
+		let tracks = [
+			track("a", "https://cdn.example.com/a/media.mp3"),
+			track("b", "https://cdn.example.com/b/media.mp3"),
+		];
+		let candidates = [candidate("old", "https://old.example.com/x/media.mp3", 1)];
+		let found: Vec<Option<u32>> = find_all(&tracks, |guid| guid.into(), &candidates)
+			.iter()
+			.map(|found| found.map(|candidate| candidate.value))
+			.collect();
+		assert_eq!(found, vec![None, None]);
+	}
+
+	#[test]
+	fn unique_file_names_still_match() {
+		let tracks = [
+			track("a", "https://cdn.example.com/a/episode-1.mp3"),
+			track("b", "https://cdn.example.com/b/episode-2.mp3"),
+		];
+		let candidates = [
+			candidate("old-2", "https://old.example.com/episode-2.mp3", 2),
+			candidate("a", "https://old.example.com/whatever.mp3", 1),
+		];
+		let found: Vec<Option<u32>> = find_all(&tracks, |guid| guid.into(), &candidates)
+			.iter()
+			.map(|found| found.map(|candidate| candidate.value))
+			.collect();
+		assert_eq!(found, vec![Some(1), Some(2)]);
+	}
+}
@@ -1,26 +1,451 @@
+mod android_prefs;
+mod annotatedfeed;
+mod antennapod;
+mod archive;
+mod audiobookshelf;
 mod beyondpod;
+mod castbox;
+mod castro;
+mod changeset;
+mod cpod;
+mod csvfile;
+mod doggcatcher;
+mod downcast;
+mod episodeactions;
+mod escapepod;
+mod gnomepodcasts;
+mod googlepodcasts;
+mod gpodder;
+mod historydb;
+mod htmlreport;
+mod itunes;
+mod jellyfin;
+mod jsonfile;
+mod kasts;
+mod kodi;
+mod listenlater;
+mod local;
+mod markdownsummary;
+mod matching;
+mod mpd;
+mod newsboat;
+mod playerfm;
+mod plex;
 mod pocketcasts;
+mod pocketcastscloud;
+mod podcastrepublic;
+mod podcini;
+mod podverse;
+mod server;
+mod spotify;
+mod stitcher;
+mod titles;
 
-use crate::podcast::Podcast;
-use crate::BoxResult;
+use crate::clock::Clock;
+use crate::podcast::{PlayingStatus, Podcast, Track};
+use crate::provenance::Provenance;
+use crate::warning::{self, warn, Warning};
+use crate::{BoxResult, Error};
 
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use reqwest::Url;
+use rusqlite::Connection;
+
+pub use annotatedfeed::AnnotatedFeed;
+pub use antennapod::AntennaPod;
+pub use audiobookshelf::Audiobookshelf;
 pub use beyondpod::BeyondPod;
+pub use castbox::Castbox;
+pub use castro::Castro;
+pub use changeset::ChangeSet;
+pub use cpod::CPod;
+pub use csvfile::CsvFile;
+pub use doggcatcher::DoggCatcher;
+pub use downcast::Downcast;
+pub use episodeactions::{AntennaPodActions, GpodderActions};
+pub use escapepod::Escapepod;
+pub use gnomepodcasts::GnomePodcasts;
+pub use googlepodcasts::GooglePodcasts;
+pub use gpodder::{Gpodder, NextcloudGpodder};
+pub use historydb::HistoryDb;
+pub use htmlreport::{HtmlReport, Totals};
+pub use itunes::ITunes;
+pub use jellyfin::Jellyfin;
+pub use jsonfile::{JsonFile, JsonPodcast};
+pub use kasts::Kasts;
+pub use kodi::Kodi;
+pub use listenlater::{set_listen_later_status, ListenLater, LISTEN_LATER_STATUS_NAMES};
+pub use local::{podcasts_for_failed, podcasts_with_urls, LocalPodcast};
+pub use markdownsummary::MarkdownSummary;
+pub use matching::find_all as find_episodes;
+pub use matching::{set_match_strategies, Candidate, MATCH_STRATEGY_NAMES};
+pub use mpd::MPD;
+pub use newsboat::Newsboat;
+pub use playerfm::PlayerFM;
+pub use plex::Plex;
 pub use pocketcasts::PocketCasts;
+pub use pocketcastscloud::PocketCastsCloud;
+pub use podcastrepublic::PodcastRepublic;
+pub use podcini::Podcini;
+pub use podverse::Podverse;
+pub use spotify::Spotify;
+pub use stitcher::Stitcher;
 
 pub trait IoWriteSeek: std::io::Write + std::io::Seek {}
 impl<T> IoWriteSeek for T where T: std::io::Write + std::io::Seek {}
 
+/// A reverse-engineered mapping between `PlayingStatus` and the value a player stores for it
+pub struct StatusEncoding {
+	pub field: &'static str,
+	pub values: &'static [(PlayingStatus, i64)],
+}
+
+impl StatusEncoding {
+	pub fn encode(&self, status: PlayingStatus) -> i64 {
+		self.values
+			.iter()
+			.find(|(s, _)| *s == status)
+			.map(|(_, v)| *v)
+			.expect("status missing from encoding")
+	}
+
+	/// The first status listed for a value wins when several share it
+	pub fn decode(&self, value: i64) -> Result<PlayingStatus, Error> {
+		self.values
+			.iter()
+			.find(|(_, v)| *v == value)
+			.map(|(s, _)| *s)
+			.ok_or(Error::InvalidPlayingStatus)
+	}
+}
+
 pub trait NewPlayer: Player {
 	fn new(path: &str) -> BoxResult<Box<dyn Player>>;
 	fn name() -> &'static str;
 	fn cli_name() -> &'static str;
+	/// What the player's argument refers to, network players take a server URL
+	fn value_name() -> &'static str {
+		"FILE"
+	}
+	/// Players that can only be converted from do not get an `--out-` argument
+	fn read_only() -> bool {
+		false
+	}
+	/// Reports whose player argument is never read, it is only given because every `--out-`
+	/// argument needs one
+	fn write_only() -> bool {
+		false
+	}
+	/// Files meant to be given to both the player argument and `--out-`, as writing one back keeps
+	/// what it held, so that is not refused as replacing an input
+	fn writes_through() -> bool {
+		false
+	}
+	fn status_encodings() -> &'static [StatusEncoding] {
+		&[]
+	}
+	/// Names the save file usually has, `*` matches anything, used to spot it in a directory
+	fn file_names() -> &'static [&'static str] {
+		&[]
+	}
+	/// Versions of the save file layout the player can tell apart
+	fn schema_versions() -> Vec<&'static str> {
+		Vec::new()
+	}
+}
+
+#[derive(Debug)]
+pub struct Subscription {
+	/// Not every player stores the feed URL, those are matched by title instead
+	pub url: Option<Url>,
+	pub title: String,
+}
+
+impl From<&Podcast> for Subscription {
+	fn from(podcast: &Podcast) -> Self {
+		Self {
+			url: Some(podcast.url.clone()),
+			title: podcast.title.clone(),
+		}
+	}
+}
+
+pub const ROUNDING_NAMES: [&str; 2] = ["nearest", "floor"];
+
+/// Set by --rounding, positions are rounded to the nearest second unless this is cleared
+static ROUND_NEAREST: AtomicBool = AtomicBool::new(true);
+
+pub fn set_rounding(name: &str) {
+	ROUND_NEAREST.store(name != "floor", Ordering::Relaxed);
+}
+
+/// Whole seconds of a position a player keeps as fractional seconds, never negative. Rounding
+/// to the nearest second means a position written out in milliseconds by another tool (or
+/// another run of this one) reads back as the second it was written from
+pub fn secs_from_f64(secs: f64) -> i32 {
+	round_secs(secs, ROUND_NEAREST.load(Ordering::Relaxed))
+}
+
+fn round_secs(secs: f64, nearest: bool) -> i32 {
+	let secs = if nearest { secs.round() } else { secs.floor() };
+	std::cmp::max(secs as i32, 0)
+}
+
+/// Whole seconds of a position a player keeps in milliseconds, never negative
+pub fn secs_from_millis(ms: i64) -> i32 {
+	secs_from_f64(ms as f64 / 1000.0)
+}
+
+/// Run `f` on every track of `podcast`. A track it fails on is reported and removed, so one
+/// bad row loses only that track and is never written out with a default status
+pub fn populate_tracks<F: FnMut(&mut Track) -> BoxResult<()>>(podcast: &mut Podcast, mut f: F) {
+	let mut tracks = Vec::with_capacity(podcast.tracks.len());
+	for mut track in podcast.tracks.drain(..) {
+		match f(&mut track) {
+			Ok(()) => tracks.push(track),
+			Err(err) => warn(
+				Warning::SkippedRow,
+				format!("Skipping track {}: {}", track.url, err),
+			),
+		}
+	}
+	podcast.tracks = tracks;
+}
+
+/// Look a track up with `find` by each of its URLs in turn, the enclosure's first, as players
+/// may have stored the URL of any alternate enclosure. Gives the URL it was found by
+pub fn find_by_urls<T, F>(track: &Track, mut find: F) -> rusqlite::Result<(Url, T)>
+where
+	F: FnMut(&Url) -> rusqlite::Result<T>,
+{
+	for url in track.urls() {
+		match find(url) {
+			Err(rusqlite::Error::QueryReturnedNoRows) => continue,
+			result => return result.map(|found| (url.clone(), found)),
+		}
+	}
+	Err(rusqlite::Error::QueryReturnedNoRows)
+}
+
+/// The URL a player stored the track by, the first `found` is true for. The enclosure's when
+/// it is none of them or the feed has no alternate enclosures, which are not looked up
+pub fn stored_url<F: FnMut(&Url) -> bool>(track: &Track, mut found: F) -> &Url {
+	if track.alternate_urls.is_empty() {
+		return &track.url;
+	}
+	track.urls().find(|url| found(*url)).unwrap_or(&track.url)
+}
+
+/// Report a failed write of `track` and count it in `stats` instead of aborting the save
+pub fn save_track<T, E: Into<Box<dyn std::error::Error>>>(
+	track: &Track,
+	result: Result<T, E>,
+	stats: &mut WriteStats,
+) -> Option<T> {
+	match result {
+		Ok(value) => Some(value),
+		Err(err) => {
+			println!("Skipping track {}: {}", track.url, err.into());
+			stats.skipped += 1;
+			None
+		}
+	}
+}
+
+/// `name` quoted as an SQL identifier, for table names read from a save file that may hold
+/// spaces, keywords or quotes
+pub fn quote_identifier(name: &str) -> String {
+	format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Number of rows in a table of a player's database
+pub fn count_rows(conn: &Connection, table: &str) -> BoxResult<usize> {
+	let count: i64 = conn.query_row(
+		&format!("SELECT COUNT(*) FROM {}", quote_identifier(table)),
+		rusqlite::NO_PARAMS,
+		|row| row.get(0),
+	)?;
+	Ok(count as usize)
+}
+
+/// How many track writes found a row to update, so saves that silently matched nothing show up,
+/// and how many failed and were left out of the output
+#[derive(Default)]
+pub struct WriteStats {
+	pub matched: usize,
+	pub unmatched: usize,
+	pub skipped: usize,
+}
+
+impl WriteStats {
+	pub fn record(&mut self, matched: bool) {
+		if matched {
+			self.matched += 1;
+		} else {
+			self.unmatched += 1;
+		}
+	}
+
+	pub fn report(&self, player: &str) {
+		println!(
+			"{}: {} tracks written, {} matched no row in the database",
+			player, self.matched, self.unmatched
+		);
+		if self.unmatched > 0 {
+			warn(
+				Warning::UnmatchedTrack,
+				format!("{} writes to {} matched no row", self.unmatched, player),
+			);
+		}
+	}
+}
+
+/// Settings that affect how every player writes its output
+pub struct SaveOptions {
+	pub clock: Clock,
+	/// Write everything in a canonical order so identical inputs give byte-identical outputs
+	pub deterministic: bool,
+	pub provenance: Provenance,
 }
 
 pub trait Player {
 	fn populate(&mut self, podcast: Podcast) -> BoxResult<Podcast>;
-	fn save(
-		self: Box<Self>,
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		Err(Error::UnsupportedOperation("listing subscriptions").into())
+	}
+	/// Read the podcast's preferences into `podcast.settings`, only done with --convert-settings
+	fn populate_settings(&self, _podcast: &mut Podcast) -> BoxResult<()> {
+		Ok(())
+	}
+	/// Layout of the save file that was recognised, for players that tell several apart
+	fn schema_version(&self) -> Option<&'static str> {
+		None
+	}
+	/// Every podcast with its tracks, for save files that keep the whole feed so it need not
+	/// be fetched again
+	fn stored_podcasts(&self) -> BoxResult<Option<Vec<Podcast>>> {
+		Ok(None)
+	}
+	/// Every podcast the save file has episodes of, with those episodes and their state, for
+	/// converting with --without-feeds
+	fn local_podcasts(&self) -> BoxResult<Vec<LocalPodcast>> {
+		Err(Error::UnsupportedOperation("converting without the feeds").into())
+	}
+	/// Number of episodes in the save file, where the player can tell
+	fn episode_count(&self) -> BoxResult<Option<usize>> {
+		Ok(None)
+	}
+	/// Returns false when the player can not create the subscription itself
+	fn subscribe(&mut self, _sub: &Subscription) -> BoxResult<bool> {
+		Ok(false)
+	}
+	/// Returns false when the player can not remove the subscription without losing the history
+	/// of its episodes, it is left for the user to remove in the app
+	fn unsubscribe(&mut self, _sub: &Subscription) -> BoxResult<bool> {
+		Err(Error::UnsupportedOperation("unsubscribing").into())
+	}
+	/// Whether `save` builds the output from only the podcasts and episodes it is given, rather
+	/// than updating them in the save file, so it has to be given every episode
+	fn writes_whole_podcasts(&self) -> bool {
+		false
+	}
+	/// Whether `save` stores the provenance record inside the output itself
+	fn stores_provenance(&self) -> bool {
+		false
+	}
+	/// First phase of a save, work out what `apply_changes` will write for `podcasts` without
+	/// writing anything, and which episodes that changes in the save file. The warnings of
+	/// reading the save file again were given when the podcasts were populated
+	fn compute_changes(
+		&mut self,
 		podcasts: &mut dyn Iterator<Item = &'_ Podcast>,
+	) -> BoxResult<ChangeSet> {
+		let podcasts: Vec<Podcast> = podcasts.cloned().collect();
+		let current = warning::quietly(|| {
+			podcasts
+				.iter()
+				.map(|podcast| self.populate(podcast.clone()).ok())
+				.collect()
+		});
+		Ok(ChangeSet::diff(podcasts, current))
+	}
+	/// Second phase of a save, write a change set out
+	fn apply_changes(
+		self: Box<Self>,
+		changes: &ChangeSet,
 		w: &mut dyn IoWriteSeek,
-	) -> BoxResult<()>;
+		options: &SaveOptions,
+	) -> BoxResult<WriteStats> {
+		self.save(&mut changes.apply_iter(), w, options)
+	}
+	/// Write `podcasts` out, what `apply_changes` hands the change set to. Gives what was
+	/// written, players that do not count their writes give the default
+	fn save(
+		self: Box<Self>,
+		_podcasts: &mut dyn Iterator<Item = &'_ Podcast>,
+		_w: &mut dyn IoWriteSeek,
+		_options: &SaveOptions,
+	) -> BoxResult<WriteStats> {
+		Err(Error::UnsupportedOperation("saving").into())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn rounds_to_the_nearest_second_by_default() {
+		assert_eq!(secs_from_f64(12.4), 12);
+		assert_eq!(secs_from_f64(12.6), 13);
+		assert_eq!(secs_from_millis(59_999), 60);
+		assert_eq!(secs_from_millis(1_499), 1);
+	}
+
+	#[test]
+	fn rounds_half_seconds_up() {
+		assert_eq!(round_secs(0.5, true), 1);
+		assert_eq!(round_secs(2.5, true), 3);
+		assert_eq!(secs_from_millis(2_500), 3);
+	}
+
+	#[test]
+	fn floor_rounds_down() {
+		assert_eq!(round_secs(12.999, false), 12);
+		assert_eq!(round_secs(2.5, false), 2);
+		assert_eq!(round_secs(7.0, false), 7);
+	}
+
+	#[test]
+	fn negative_positions_are_zero() {
+		assert_eq!(round_secs(-0.4, true), 0);
+		assert_eq!(round_secs(-3.0, true), 0);
+		assert_eq!(round_secs(-0.4, false), 0);
+		assert_eq!(secs_from_millis(-1_000), 0);
+	}
+
+	#[test]
+	fn quotes_identifiers() {
+		assert_eq!(quote_identifier("episodes"), "\"episodes\"");
+		assert_eq!(quote_identifier("my table"), "\"my table\"");
+		assert_eq!(quote_identifier("a\"b"), "\"a\"\"b\"");
+	}
+
+	#[test]
+	fn failed_writes_are_counted_per_save() {
+		let track = Track::new(
+			"ep-1".into(),
+			Url::parse("https://example.com/ep1.mp3").unwrap(),
+			"Episode 1".into(),
+			None,
+		);
+		let mut stats = WriteStats::default();
+		assert_eq!(save_track(&track, Ok::<_, Error>(3), &mut stats), Some(3));
+		assert_eq!(
+			save_track(&track, Err::<(), _>(Error::Skipped), &mut stats),
+			None
+		);
+		assert_eq!(stats.skipped, 1);
+	}
 }
@@ -0,0 +1,218 @@
+use crate::fetch;
+use crate::player::{
+	populate_tracks, save_track, secs_from_f64, IoWriteSeek, NewPlayer, Player, SaveOptions,
+	StatusEncoding, WriteStats,
+};
+use crate::podcast::{PlayingStatus, Podcast, Track};
+use crate::{BoxResult, SQLLiteDatabase};
+
+use std::borrow::Borrow;
+
+use reqwest::Url;
+use rusqlite::Connection;
+
+const PLAYCOUNT: StatusEncoding = StatusEncoding {
+	field: "sticker playcount",
+	values: &[
+		(PlayingStatus::Unplayed, 0),
+		(PlayingStatus::Playing, 0),
+		(PlayingStatus::Played, 1),
+	],
+};
+
+const ELAPSED: &str = "elapsed";
+const PLAY_COUNT: &str = "playcount";
+
+// MPD attaches free-form name/value stickers to song URIs in its sticker
+// database (sticker_file in mpd.conf, often ~/.local/share/mpd/sticker.sql).
+// Streamed episodes are keyed by their enclosure URL, files in the music
+// directory by their path in it, which is matched by file name when only one
+// URI ends with it. The position is the `elapsed` sticker in seconds and an
+// episode with a non-zero `playcount` sticker is played. Episodes MPD has no
+// stickers for are written under their enclosure URL.
+pub struct MPD {
+	db: SQLLiteDatabase,
+}
+
+impl MPD {
+	fn find_uri(&self, url: &Url) -> rusqlite::Result<Option<String>> {
+		let conn: &Connection = self.db.borrow();
+
+		let mut stmt = conn.prepare(
+			"SELECT DISTINCT uri FROM sticker WHERE type = 'song' AND url_key(uri) = url_key(:url)",
+		)?;
+		let mut rows = stmt.query_named(&[(":url", &url.to_string())])?;
+		if let Some(row) = rows.next()? {
+			return Ok(Some(row.get(0)?));
+		}
+
+		let file_name = match url.path_segments().and_then(|segments| segments.last()) {
+			Some(name) if !name.is_empty() => name,
+			_ => return Ok(None),
+		};
+		let mut stmt = conn.prepare(
+			"SELECT DISTINCT uri FROM sticker WHERE type = 'song' AND (uri = :file OR uri LIKE '%/' || :file) LIMIT 2",
+		)?;
+		let mut rows = stmt.query_named(&[(":file", &file_name)])?;
+		let first: String = match rows.next()? {
+			Some(row) => row.get(0)?,
+			None => return Ok(None),
+		};
+		// The same file name in two directories could be any episode
+		if rows.next()?.is_some() {
+			return Ok(None);
+		}
+		Ok(Some(first))
+	}
+
+	fn get_sticker(&self, uri: &str, name: &str) -> rusqlite::Result<Option<String>> {
+		let conn: &Connection = self.db.borrow();
+		let mut stmt = conn.prepare(
+			"SELECT value FROM sticker WHERE type = 'song' AND uri = :uri AND name = :name",
+		)?;
+		let mut rows = stmt.query_named(&[(":uri", &uri), (":name", &name)])?;
+		match rows.next()? {
+			Some(row) => Ok(Some(row.get(0)?)),
+			None => Ok(None),
+		}
+	}
+
+	fn set_sticker(&self, uri: &str, name: &str, value: Option<String>) -> rusqlite::Result<()> {
+		let conn: &Connection = self.db.borrow();
+		match value {
+			Some(value) => conn.execute_named(
+				"INSERT OR REPLACE INTO sticker (type, uri, name, value) VALUES ('song', :uri, :name, :value)",
+				&[(":uri", &uri), (":name", &name), (":value", &value)],
+			),
+			None => conn.execute_named(
+				"DELETE FROM sticker WHERE type = 'song' AND uri = :uri AND name = :name",
+				&[(":uri", &uri), (":name", &name)],
+			),
+		}
+		.map(|_| ())
+	}
+
+	/// The sticker URI of the track by any of its URLs
+	fn find_track_uri(&self, track: &Track) -> rusqlite::Result<Option<String>> {
+		for url in track.urls() {
+			if let Some(uri) = self.find_uri(url)? {
+				return Ok(Some(uri));
+			}
+		}
+		Ok(None)
+	}
+
+	fn update_track(&self, track: &Track) -> rusqlite::Result<()> {
+		let uri = self
+			.find_track_uri(track)?
+			.unwrap_or_else(|| track.url.to_string());
+
+		let played = PLAYCOUNT.encode(track.playing_status) != 0;
+		let play_count: i64 = self
+			.get_sticker(&uri, PLAY_COUNT)?
+			.and_then(|value| value.parse().ok())
+			.unwrap_or(0);
+		// Keep the count of episodes that were already played
+		if played && play_count == 0 {
+			self.set_sticker(&uri, PLAY_COUNT, Some(String::from("1")))?;
+		} else if !played {
+			self.set_sticker(&uri, PLAY_COUNT, None)?;
+		}
+
+		let elapsed = match track.playing_status {
+			PlayingStatus::Playing if track.progress > 0 => Some(track.progress.to_string()),
+			_ => None,
+		};
+		self.set_sticker(&uri, ELAPSED, elapsed)
+	}
+}
+
+impl Player for MPD {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		populate_tracks(&mut podcast, |track| {
+			// Episodes without stickers were never played in MPD
+			let uri = match self.find_track_uri(track)? {
+				Some(uri) => uri,
+				None => return Ok(()),
+			};
+
+			let elapsed: f64 = match self.get_sticker(&uri, ELAPSED)? {
+				Some(value) => value.parse()?,
+				None => 0.0,
+			};
+			let play_count: i64 = match self.get_sticker(&uri, PLAY_COUNT)? {
+				Some(value) => value.parse()?,
+				None => 0,
+			};
+
+			track.progress = secs_from_f64(elapsed);
+			track.playing_status = if play_count > 0 {
+				PlayingStatus::Played
+			} else if track.progress > 0 {
+				PlayingStatus::Playing
+			} else {
+				PlayingStatus::Unplayed
+			};
+			Ok(())
+		});
+
+		Ok(podcast)
+	}
+
+	fn save(
+		self: Box<Self>,
+		podcasts: &mut dyn Iterator<Item = &'_ Podcast>,
+		w: &mut dyn IoWriteSeek,
+		_options: &SaveOptions,
+	) -> BoxResult<WriteStats> {
+		let mut stats = WriteStats::default();
+
+		for podcast in podcasts {
+			println!(
+				"Saving '{}' ({})",
+				podcast.title,
+				fetch::redact(&podcast.url)
+			);
+
+			for track in podcast.tracks.iter() {
+				save_track(track, self.update_track(track), &mut stats);
+			}
+		}
+
+		// Copy temp file to output
+		let mut temp_file = self.db.into_file()?;
+		std::io::copy(&mut temp_file, w)?;
+		Ok(stats)
+	}
+
+	fn episode_count(&self) -> BoxResult<Option<usize>> {
+		let conn: &Connection = self.db.borrow();
+		let count: i64 = conn.query_row(
+			"SELECT COUNT(DISTINCT uri) FROM sticker WHERE type = 'song'",
+			rusqlite::NO_PARAMS,
+			|row| row.get(0),
+		)?;
+		Ok(Some(count as usize))
+	}
+}
+
+impl NewPlayer for MPD {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		Ok(Box::new(Self {
+			db: SQLLiteDatabase::open(path)?,
+		}))
+	}
+
+	fn name() -> &'static str {
+		"MPD"
+	}
+	fn cli_name() -> &'static str {
+		"mpd"
+	}
+	fn file_names() -> &'static [&'static str] {
+		&["sticker.sql"]
+	}
+	fn status_encodings() -> &'static [StatusEncoding] {
+		&[PLAYCOUNT]
+	}
+}
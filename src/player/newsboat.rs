@@ -0,0 +1,206 @@
+use crate::fetch;
+use crate::player::{
+	count_rows, populate_tracks, IoWriteSeek, NewPlayer, Player, SaveOptions, Subscription,
+	WriteStats,
+};
+use crate::podcast::{PlayingStatus, Podcast};
+use crate::url_key::url_key;
+use crate::warning::{warn, Warning};
+use crate::{BoxResult, SQLLiteDatabase};
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+use reqwest::Url;
+use rusqlite::Connection;
+
+const QUEUE_FILE: &str = "queue";
+
+// newsboat keeps feeds and items in cache.db, while podboat tracks downloads in
+// a `queue` file next to it. Each queue line is `URL "PATH"` optionally followed
+// by `downloaded`, `played` or `finished`. The player file is cache.db and the
+// output is a new queue file containing the episodes that are not played yet.
+pub struct Newsboat {
+	db: SQLLiteDatabase,
+	/// `url_key` of the enclosure url to (download path, status) from the existing queue file
+	queue: HashMap<String, (String, String)>,
+}
+
+fn parse_queue_line(line: &str) -> Option<(String, String, String)> {
+	let line = line.trim();
+	let (url, rest) = line.split_at(line.find(' ')?);
+	let rest = rest.trim_start().strip_prefix('"')?;
+	let (path, status) = rest.split_at(rest.find('"')?);
+	Some((url.into(), path.into(), status[1..].trim().into()))
+}
+
+impl Newsboat {
+	fn has_feed(&self, url: &Url) -> BoxResult<()> {
+		let conn: &Connection = self.db.borrow();
+		let mut stmt =
+			conn.prepare("SELECT rssurl FROM rss_feed WHERE url_key(rssurl) = url_key(:url)")?;
+		let mut rows = stmt.query_named(&[(":url", &url.to_string())])?;
+		rows.next()?.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+		Ok(())
+	}
+
+	fn get_enclosure(&self, feed_url: &Url, guid: &String) -> rusqlite::Result<String> {
+		let conn: &Connection = self.db.borrow();
+		let mut stmt = conn
+			.prepare("SELECT enclosure_url FROM rss_item WHERE url_key(feedurl) = url_key(:feed) AND guid = :guid")?;
+		let mut rows = stmt.query_named(&[(":feed", &feed_url.to_string()), (":guid", guid)])?;
+		let first_row = rows.next()?;
+		first_row
+			.ok_or(rusqlite::Error::QueryReturnedNoRows)
+			.and_then(|row| row.get(0))
+	}
+
+	/// Directory podboat downloads into, taken from the existing queue entries
+	fn download_dir(&self) -> String {
+		self.queue
+			.values()
+			.filter_map(|(path, _)| Path::new(path).parent())
+			.map(|dir| dir.to_string_lossy().into_owned())
+			.next()
+			.unwrap_or_else(|| "~".into())
+	}
+}
+
+impl Player for Newsboat {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		self.has_feed(&podcast.url)?;
+
+		let url = podcast.url.clone();
+		populate_tracks(&mut podcast, |track| {
+			match self.get_enclosure(&url, &track.guid) {
+				Ok(enclosure) => {
+					track.playing_status = match self.queue.get(&url_key(enclosure.as_str())) {
+						Some((_, status)) if status == "played" || status == "finished" => {
+							PlayingStatus::Played
+						}
+						_ => PlayingStatus::Unplayed,
+					};
+
+					Ok(())
+				}
+				Err(rusqlite::Error::QueryReturnedNoRows) => {
+					warn(
+						Warning::UnmatchedTrack,
+						format!("Track not found: {:?}", track),
+					);
+					Ok(())
+				}
+				Err(err) => Err(err),
+			}?;
+			Ok(())
+		});
+
+		Ok(podcast)
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		let conn: &Connection = self.db.borrow();
+		let mut stmt = conn.prepare("SELECT rssurl, title FROM rss_feed")?;
+		let mut rows = stmt.query(rusqlite::NO_PARAMS)?;
+
+		let mut subs = Vec::new();
+		while let Some(row) = rows.next()? {
+			let url: String = row.get(0)?;
+			subs.push(Subscription {
+				url: Url::parse(url.as_str()).ok(),
+				title: row.get(1)?,
+			});
+		}
+		Ok(subs)
+	}
+
+	// The queue is written anew from the episodes given
+	fn writes_whole_podcasts(&self) -> bool {
+		true
+	}
+
+	fn save(
+		self: Box<Self>,
+		podcasts: &mut dyn Iterator<Item = &'_ Podcast>,
+		w: &mut dyn IoWriteSeek,
+		_options: &SaveOptions,
+	) -> BoxResult<WriteStats> {
+		let download_dir = self.download_dir();
+
+		for podcast in podcasts {
+			println!(
+				"Saving '{}' ({})",
+				podcast.title,
+				fetch::redact(&podcast.url)
+			);
+
+			for track in podcast
+				.tracks
+				.iter()
+				.filter(|track| track.playing_status != PlayingStatus::Played)
+			{
+				let url = track.url.to_string();
+				if track.progress > 0 {
+					warn(
+						Warning::LossyField,
+						format!("The podboat queue can not store the position of {}", url),
+					);
+				}
+				match self.queue.get(&url_key(url.as_str())) {
+					// Keep the download state of episodes already in the queue
+					Some((path, status)) if status.is_empty() => {
+						writeln!(w, "{} \"{}\"", url, path)?
+					}
+					Some((path, status)) => writeln!(w, "{} \"{}\" {}", url, path, status)?,
+					None => {
+						let file_name = track
+							.url
+							.path_segments()
+							.and_then(|segments| segments.last())
+							.unwrap_or("episode");
+						writeln!(w, "{} \"{}/{}\"", url, download_dir, file_name)?
+					}
+				}
+			}
+		}
+
+		Ok(WriteStats::default())
+	}
+
+	fn episode_count(&self) -> BoxResult<Option<usize>> {
+		let conn: &Connection = self.db.borrow();
+		count_rows(conn, "rss_item").map(Some)
+	}
+}
+
+impl NewPlayer for Newsboat {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		let queue_path = Path::new(path).with_file_name(QUEUE_FILE);
+		let queue = if queue_path.is_file() {
+			std::fs::read_to_string(queue_path)?
+				.lines()
+				.filter_map(parse_queue_line)
+				.map(|(url, path, status)| (url_key(url.as_str()), (path, status)))
+				.collect()
+		} else {
+			HashMap::new()
+		};
+
+		Ok(Box::new(Self {
+			db: SQLLiteDatabase::open(path)?,
+			queue,
+		}))
+	}
+
+	fn name() -> &'static str {
+		"newsboat"
+	}
+	fn cli_name() -> &'static str {
+		"newsboat"
+	}
+	fn file_names() -> &'static [&'static str] {
+		&["cache.db"]
+	}
+}
@@ -0,0 +1,86 @@
+use crate::player::{NewPlayer, Player};
+use crate::podcast::{PlayingStatus, Podcast};
+use crate::{BoxResult, Error};
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct Play {
+	url: String,
+	#[serde(default)]
+	guid: Option<String>,
+	status: String,
+	#[serde(default)]
+	progress: i32,
+}
+
+#[derive(Deserialize)]
+struct Export {
+	plays: Vec<Play>,
+}
+
+pub struct PlayerFM {
+	// (status, progress) keyed by both episode url and guid
+	plays: HashMap<String, (PlayingStatus, i32)>,
+}
+
+impl PlayerFM {
+	fn status_from_str(status: &str) -> Result<PlayingStatus, Error> {
+		match status {
+			"played" => Ok(PlayingStatus::Played),
+			"in_progress" => Ok(PlayingStatus::Playing),
+			"unplayed" => Ok(PlayingStatus::Unplayed),
+			_ => Err(Error::InvalidPlayingStatus),
+		}
+	}
+}
+
+impl Player for PlayerFM {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		for track in podcast.tracks.iter_mut() {
+			let play = track
+				.urls()
+				.find_map(|url| self.plays.get(&url.to_string()))
+				.or_else(|| self.plays.get(&track.guid));
+
+			if let Some(&(status, progress)) = play {
+				track.playing_status = status;
+				track.progress = std::cmp::max(progress, 0);
+			}
+		}
+
+		Ok(podcast)
+	}
+}
+
+impl NewPlayer for PlayerFM {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		let export: Export = serde_json::from_reader(std::fs::File::open(path)?)?;
+
+		let mut plays = HashMap::new();
+		for play in export.plays.into_iter() {
+			let state = (
+				PlayerFM::status_from_str(play.status.as_str())?,
+				play.progress,
+			);
+			if let Some(guid) = play.guid {
+				plays.insert(guid, state);
+			}
+			plays.insert(play.url, state);
+		}
+
+		Ok(Box::new(Self { plays }))
+	}
+
+	fn name() -> &'static str {
+		"Player FM"
+	}
+	fn cli_name() -> &'static str {
+		"playerfm"
+	}
+	fn read_only() -> bool {
+		true
+	}
+}
@@ -0,0 +1,285 @@
+use crate::fetch;
+use crate::player::server::Server;
+use crate::player::{
+	secs_from_millis, IoWriteSeek, NewPlayer, Player, SaveOptions, Subscription, WriteStats,
+};
+use crate::podcast::{PlayingStatus, Podcast, Track};
+use crate::warning::{warn, Warning};
+use crate::BoxResult;
+
+use reqwest::{header, Client};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+const IDENTIFIER: &str = "com.plexapp.plugins.library";
+// Plex tracks have library type 10
+const TRACK_TYPE: &str = "10";
+// Episodes whose durations differ by more than this are not the same episode
+const DURATION_TOLERANCE_SECS: i32 = 5;
+
+// Plex keeps podcast episodes as tracks in music libraries and knows nothing
+// about feeds, so episodes are matched by title and duration within the album
+// or artist named after the podcast. Positions (view offsets) and durations
+// are in milliseconds, an episode is played once its view count is non-zero.
+// Saving scrobbles, unscrobbles or sets the progress of every episode whose
+// state differs and writes the changes to the output file as a record.
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Item {
+	rating_key: String,
+	title: String,
+	#[serde(default)]
+	parent_title: Option<String>,
+	#[serde(default)]
+	grandparent_title: Option<String>,
+	#[serde(default)]
+	duration: Option<i64>,
+	#[serde(default)]
+	view_offset: i64,
+	#[serde(default)]
+	view_count: i64,
+}
+
+#[derive(Deserialize)]
+struct Directory {
+	key: String,
+	#[serde(rename = "type")]
+	kind: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Container<T> {
+	#[serde(default = "Vec::new", alias = "Directory")]
+	metadata: Vec<T>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Response<T> {
+	media_container: Container<T>,
+}
+
+#[derive(Serialize)]
+struct Change {
+	rating_key: String,
+	title: String,
+	action: &'static str,
+	time_ms: Option<i64>,
+}
+
+pub struct Plex {
+	client: Client,
+	server: Server,
+	items: Vec<Item>,
+}
+
+impl Plex {
+	fn get<T: DeserializeOwned>(&self, path: &str, query: &[(&str, &str)]) -> BoxResult<Vec<T>> {
+		let response: Response<T> = self
+			.client
+			.get(self.server.endpoint(path)?)
+			.header("X-Plex-Token", self.server.password.as_str())
+			.header(header::ACCEPT, "application/json")
+			.query(query)
+			.send()?
+			.error_for_status()?
+			.json()?;
+		Ok(response.media_container.metadata)
+	}
+
+	fn podcast_items(&self, title: &str) -> Vec<&Item> {
+		let title = title.to_lowercase();
+		let matches = |name: &Option<String>| {
+			name.as_ref()
+				.map_or(false, |name| name.to_lowercase() == title)
+		};
+		self.items
+			.iter()
+			.filter(|item| matches(&item.parent_title) || matches(&item.grandparent_title))
+			.collect()
+	}
+
+	fn find_item<'a>(items: &[&'a Item], track: &Track) -> Option<&'a Item> {
+		items
+			.iter()
+			.find(|item| {
+				let same_length = match (item.duration, track.duration) {
+					(Some(item_ms), Some(track_secs)) => {
+						((item_ms / 1000) as i32 - track_secs).abs() <= DURATION_TOLERANCE_SECS
+					}
+					_ => true,
+				};
+				item.title.to_lowercase() == track.title.to_lowercase() && same_length
+			})
+			.cloned()
+	}
+
+	fn state(item: &Item) -> (PlayingStatus, i32) {
+		let position = secs_from_millis(item.view_offset);
+		if item.view_count > 0 {
+			(PlayingStatus::Played, position)
+		} else if position > 0 {
+			(PlayingStatus::Playing, position)
+		} else {
+			(PlayingStatus::Unplayed, 0)
+		}
+	}
+
+	fn call(&self, path: &str, query: &[(&str, &str)]) -> BoxResult<()> {
+		self.client
+			.get(self.server.endpoint(path)?)
+			.header("X-Plex-Token", self.server.password.as_str())
+			.query(query)
+			.send()?
+			.error_for_status()?;
+		Ok(())
+	}
+}
+
+impl Player for Plex {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		let items = self.podcast_items(podcast.title.as_str());
+		if items.is_empty() {
+			warn(
+				Warning::UnmatchedTrack,
+				format!("Podcast not found: {}", podcast.title),
+			);
+			return Ok(podcast);
+		}
+
+		for track in podcast.tracks.iter_mut() {
+			match Self::find_item(&items, track) {
+				Some(item) => {
+					let (status, progress) = Self::state(item);
+					track.playing_status = status;
+					track.progress = progress;
+				}
+				None => warn(
+					Warning::UnmatchedTrack,
+					format!("Track not found: {:?}", track),
+				),
+			}
+		}
+
+		Ok(podcast)
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		// Plex only knows the album names
+		let mut albums: Vec<&String> = self
+			.items
+			.iter()
+			.filter_map(|item| item.parent_title.as_ref())
+			.collect();
+		albums.sort();
+		albums.dedup();
+
+		Ok(albums
+			.into_iter()
+			.map(|album| Subscription {
+				url: None,
+				title: album.clone(),
+			})
+			.collect())
+	}
+
+	fn save(
+		self: Box<Self>,
+		podcasts: &mut dyn Iterator<Item = &'_ Podcast>,
+		w: &mut dyn IoWriteSeek,
+		_options: &SaveOptions,
+	) -> BoxResult<WriteStats> {
+		let mut changes = Vec::new();
+
+		for podcast in podcasts {
+			println!(
+				"Saving '{}' ({})",
+				podcast.title,
+				fetch::redact(&podcast.url)
+			);
+			let items = self.podcast_items(podcast.title.as_str());
+
+			for track in podcast.tracks.iter() {
+				let item = match Self::find_item(&items, track) {
+					Some(item) => item,
+					None => continue,
+				};
+				if Self::state(item) == (track.playing_status, track.progress) {
+					continue;
+				}
+
+				let key = item.rating_key.as_str();
+				let played = track.playing_status == PlayingStatus::Played;
+				if played != (item.view_count > 0) {
+					let action = if played { "scrobble" } else { "unscrobble" };
+					self.call(
+						&format!(":/{}", action),
+						&[("key", key), ("identifier", IDENTIFIER)],
+					)?;
+					changes.push(Change {
+						rating_key: key.into(),
+						title: item.title.clone(),
+						action,
+						time_ms: None,
+					});
+				}
+
+				let time_ms = track.progress as i64 * 1000;
+				if time_ms != item.view_offset {
+					self.call(
+						":/progress",
+						&[
+							("key", key),
+							("identifier", IDENTIFIER),
+							("time", time_ms.to_string().as_str()),
+							("state", "stopped"),
+						],
+					)?;
+					changes.push(Change {
+						rating_key: key.into(),
+						title: item.title.clone(),
+						action: "progress",
+						time_ms: Some(time_ms),
+					});
+				}
+			}
+		}
+
+		println!("Made {} changes", changes.len());
+		serde_json::to_writer_pretty(w, &changes)?;
+		Ok(WriteStats::default())
+	}
+}
+
+impl NewPlayer for Plex {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		let mut player = Self {
+			client: Client::new(),
+			server: Server::parse_token(path, "PLEX_TOKEN", Self::name())?,
+			items: Vec::new(),
+		};
+
+		let sections: Vec<Directory> = player.get("library/sections", &[])?;
+		for section in sections.iter().filter(|section| section.kind == "artist") {
+			let items: Vec<Item> = player.get(
+				&format!("library/sections/{}/all", section.key),
+				&[("type", TRACK_TYPE)],
+			)?;
+			player.items.extend(items);
+		}
+
+		Ok(Box::new(player))
+	}
+
+	fn name() -> &'static str {
+		"Plex"
+	}
+	fn cli_name() -> &'static str {
+		"plex"
+	}
+	fn value_name() -> &'static str {
+		"URL"
+	}
+}
@@ -1,15 +1,106 @@
-use crate::player::{IoWriteSeek, NewPlayer, Player};
-use crate::podcast::{PlayingStatus, Podcast};
+use crate::fetch;
+use crate::player::android_prefs::Preferences;
+use crate::player::archive::SQLiteArchive;
+use crate::player::matching::{self, Candidate};
+use crate::player::{
+	count_rows, populate_tracks, save_track, secs_from_f64, IoWriteSeek, LocalPodcast, NewPlayer,
+	Player, SaveOptions, StatusEncoding, Subscription, WriteStats,
+};
+use crate::podcast::{PlayingStatus, Podcast, Track};
+use crate::warning::{warn, Warning};
 use crate::{BoxResult, Error, SQLLiteDatabase, UUID};
 
 use std::borrow::Borrow;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read};
 
 use reqwest::Url;
 use rusqlite::Connection;
 
+const PLAYING_STATUS: StatusEncoding = StatusEncoding {
+	field: "episodes.playing_status",
+	values: &[
+		(PlayingStatus::Unplayed, 0),
+		(PlayingStatus::Playing, 1),
+		(PlayingStatus::Played, 2),
+	],
+};
+
+// Saving fails if more than this fraction of the tracks have no row in the output
+const MAX_MISSING_FRACTION: f64 = 0.5;
+
+/// What a track should read back as from the written database
+struct Expected {
+	podcast_id: UUID,
+	url: Url,
+	played_up_to: i32,
+	playing_status: i64,
+}
+
+// Names the database has inside the zip exports of newer app versions
+const EXPORT_DB_FILES: [&str; 3] = ["pocketcasts", "pocketcasts.db", "databases/pocketcasts"];
+
+// The app's shared preferences in the zip export, which keep the per-podcast
+// settings under keys ending in the podcast's UUID
+const PREFERENCES_FILE: &str = "au.com.shiftyjelly.pocketcasts_preferences.xml";
+// Newer app versions keep when each episode was last played, in milliseconds,
+// and list the episodes that have one on the History screen, latest first
+const LAST_PLAYED_COLUMN: &str = "last_playback_interaction_date";
+
+const AUTO_ARCHIVE_KEY: &str = "podcast_auto_archive_";
+const NOTIFICATIONS_KEY: &str = "podcast_notifications_";
+
+/// The bare database pulled from the phone, or an export zip with it and the preference files
+enum Storage {
+	Database(SQLLiteDatabase),
+	Export(SQLiteArchive),
+}
+
+impl Borrow<Connection> for Storage {
+	fn borrow(&self) -> &Connection {
+		match self {
+			Storage::Database(db) => db.borrow(),
+			Storage::Export(archive) => archive.borrow(),
+		}
+	}
+}
+
+impl Storage {
+	fn open(path: &str) -> BoxResult<Self> {
+		// Anything that is not a zip is taken to be the database itself
+		if zip::ZipArchive::new(File::open(path)?).is_err() {
+			return Ok(Storage::Database(SQLLiteDatabase::open(path)?));
+		}
+
+		for &db_file in EXPORT_DB_FILES.iter() {
+			if SQLiteArchive::contains(path, db_file)? {
+				return Ok(Storage::Export(SQLiteArchive::open(path, db_file)?));
+			}
+		}
+		Err(zip::result::ZipError::FileNotFound.into())
+	}
+
+	/// Close the database and open the written file again with a fresh connection
+	fn reopen(self) -> BoxResult<Self> {
+		match self {
+			Storage::Database(db) => {
+				let mut file = db.into_file()?;
+				Ok(Storage::Database(SQLLiteDatabase::open_from_reader(
+					&mut file,
+				)?))
+			}
+			Storage::Export(archive) => Ok(Storage::Export(archive.reopen()?)),
+		}
+	}
+}
+
 pub struct PocketCasts {
-	db: SQLLiteDatabase,
+	db: Storage,
+	/// Name of the preferences file in the export and its contents
+	preferences: Option<(String, Preferences)>,
+	/// Whether the episodes table has LAST_PLAYED_COLUMN
+	has_last_played: bool,
 }
 
 impl PocketCasts {
@@ -24,7 +115,7 @@ impl PocketCasts {
 	fn get_episode(&self, podcast_id: &UUID, episode_url: &Url) -> rusqlite::Result<(i64, f64)> {
 		let conn: &Connection = self.db.borrow();
 		let mut stmt = conn
-			.prepare("SELECT playing_status, played_up_to FROM episodes WHERE podcast_id = :podcast_id AND download_url = :download_url")?;
+			.prepare("SELECT playing_status, played_up_to FROM episodes WHERE podcast_id = :podcast_id AND url_key(download_url) = url_key(:download_url)")?;
 		let mut rows = stmt.query_named(&[
 			(":podcast_id", &podcast_id.to_string()),
 			(":download_url", &episode_url.to_string()),
@@ -35,6 +126,72 @@ impl PocketCasts {
 			.and_then(|row| Ok((row.get(0)?, row.get(1)?)))
 	}
 
+	/// Every episode of the podcast, for the match strategies to find the feed's among. Pocket
+	/// Casts gives episodes its own UUIDs, so they have no guid to match
+	fn get_candidates(&self, podcast_id: &UUID) -> rusqlite::Result<Vec<Candidate<Url>>> {
+		let conn: &Connection = self.db.borrow();
+		let mut stmt = conn.prepare("SELECT download_url, title, published_date, duration FROM episodes WHERE podcast_id = :podcast_id")?;
+		let mut rows = stmt.query_named(&[(":podcast_id", &podcast_id.to_string())])?;
+
+		let mut candidates = Vec::new();
+		while let Some(row) = rows.next()? {
+			let url: Option<String> = row.get(0)?;
+			let value = match url.as_ref().and_then(|url| Url::parse(url).ok()) {
+				Some(value) => value,
+				None => continue,
+			};
+			candidates.push(Candidate {
+				guid: None,
+				url,
+				title: row.get(1)?,
+				// Milliseconds since the epoch
+				published: row.get::<_, Option<i64>>(2)?.map(|millis| millis / 1000),
+				duration: row.get::<_, Option<f64>>(3)?.map(secs_from_f64),
+				value,
+			});
+		}
+		Ok(candidates)
+	}
+
+	/// Milliseconds since the epoch the episode was last played, None if it never was
+	fn get_last_played(
+		&self,
+		podcast_id: &UUID,
+		episode_url: &Url,
+	) -> rusqlite::Result<Option<i64>> {
+		let conn: &Connection = self.db.borrow();
+		let last_played: Option<i64> = conn.query_row_named(
+			&format!("SELECT {} FROM episodes WHERE podcast_id = :podcast_id AND url_key(download_url) = url_key(:download_url)", LAST_PLAYED_COLUMN),
+			&[
+				(":podcast_id", &podcast_id.to_string()),
+				(":download_url", &episode_url.to_string()),
+			],
+			|row| row.get(0),
+		)?;
+		Ok(last_played.filter(|&millis| millis > 0))
+	}
+
+	/// Put a played or started episode on the History screen. A time from the source player
+	/// replaces the one in the database, otherwise `now` is only filled in where there is none
+	fn update_last_played(
+		&self,
+		podcast_id: &UUID,
+		episode_url: &Url,
+		last_played: Option<i64>,
+		now: i64,
+	) -> rusqlite::Result<usize> {
+		let conn: &Connection = self.db.borrow();
+		conn.execute_named(
+			&format!("UPDATE episodes SET {column} = :time WHERE podcast_id = :podcast_id AND url_key(download_url) = url_key(:download_url) AND (:replace AND IFNULL({column}, 0) <> :time OR IFNULL({column}, 0) = 0)", column = LAST_PLAYED_COLUMN),
+			&[
+				(":podcast_id", &podcast_id.to_string()),
+				(":download_url", &episode_url.to_string()),
+				(":time", &last_played.map_or(now, |secs| secs * 1000)),
+				(":replace", &last_played.is_some()),
+			],
+		)
+	}
+
 	fn update_episode_part(
 		&self,
 		podcast_id: &UUID,
@@ -42,17 +199,88 @@ impl PocketCasts {
 		field: &'static str,
 		value: i32,
 		time: i64,
-	) -> rusqlite::Result<()> {
+	) -> rusqlite::Result<usize> {
 		let conn: &Connection = self.db.borrow();
 		conn.execute_named(
-			("UPDATE episodes SET ".to_string() + field + " = :value, " + field + "_modified = :time WHERE podcast_id = :podcast_id AND download_url = :download_url AND " + field + " <> :value").as_str(),
+			("UPDATE episodes SET ".to_string() + field + " = :value, " + field + "_modified = :time WHERE podcast_id = :podcast_id AND url_key(download_url) = url_key(:download_url) AND " + field + " <> :value").as_str(),
 			&[
 				(":podcast_id", &podcast_id.to_string()),
 				(":download_url", &episode_url.to_string()),
 				(":value", &value),
 				(":time", &time),
 			],
-		).map(|_| ())
+		)
+	}
+
+	fn status_counts(&self) -> BoxResult<BTreeMap<i64, i64>> {
+		let conn: &Connection = self.db.borrow();
+		let mut stmt =
+			conn.prepare("SELECT playing_status, COUNT(*) FROM episodes GROUP BY playing_status")?;
+		let mut rows = stmt.query(rusqlite::NO_PARAMS)?;
+
+		let mut counts = BTreeMap::new();
+		while let Some(row) = rows.next()? {
+			counts.insert(row.get(0)?, row.get(1)?);
+		}
+		Ok(counts)
+	}
+
+	/// Read every saved track back the way the app would and compare it with what was written
+	fn verify(&self, before: &BTreeMap<i64, i64>, expected: &[Expected]) -> BoxResult<()> {
+		let mut missing = 0;
+		let mut mismatched = 0;
+
+		for e in expected.iter() {
+			match self.get_episode(&e.podcast_id, &e.url) {
+				Ok((playing_status, played_up_to)) => {
+					if playing_status != e.playing_status || played_up_to as i32 != e.played_up_to {
+						println!(
+							"Verify: {} reads back as status {} at {}s, expected status {} at {}s",
+							e.url, playing_status, played_up_to, e.playing_status, e.played_up_to
+						);
+						mismatched += 1;
+					}
+				}
+				Err(rusqlite::Error::QueryReturnedNoRows) => missing += 1,
+				Err(err) => return Err(err.into()),
+			}
+		}
+
+		let after = self.status_counts()?;
+		for &(status, value) in PLAYING_STATUS.values.iter() {
+			println!(
+				"Verify: {:?} episodes {} -> {}",
+				status,
+				before.get(&value).unwrap_or(&0),
+				after.get(&value).unwrap_or(&0)
+			);
+		}
+		println!(
+			"Verify: {} tracks saved, {} without a matching episode, {} read back differently",
+			expected.len(),
+			missing,
+			mismatched
+		);
+
+		if mismatched > 0 {
+			return Err(Error::VerificationFailed(
+				"Pocket Casts",
+				format!("{} tracks read back differently", mismatched),
+			)
+			.into());
+		}
+		if !expected.is_empty() && missing as f64 / expected.len() as f64 > MAX_MISSING_FRACTION {
+			return Err(Error::VerificationFailed(
+				"Pocket Casts",
+				format!(
+					"{} of {} tracks have no matching episode",
+					missing,
+					expected.len()
+				),
+			)
+			.into());
+		}
+		Ok(())
 	}
 
 	fn update_episode(
@@ -62,14 +290,16 @@ impl PocketCasts {
 		played_up_to: i32,
 		playing_status: i32,
 		time: i64,
-	) -> rusqlite::Result<()> {
-		self.update_episode_part(podcast_id, episode_url, "played_up_to", played_up_to, time)?;
-		self.update_episode_part(
-			podcast_id,
-			episode_url,
-			"playing_status",
-			playing_status,
-			time,
+	) -> rusqlite::Result<usize> {
+		Ok(
+			self.update_episode_part(podcast_id, episode_url, "played_up_to", played_up_to, time)?
+				+ self.update_episode_part(
+					podcast_id,
+					episode_url,
+					"playing_status",
+					playing_status,
+					time,
+				)?,
 		)
 	}
 }
@@ -77,66 +307,308 @@ impl PocketCasts {
 impl Player for PocketCasts {
 	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
 		let id = self.get_podcast(&podcast.title)?;
+		let candidates = self.get_candidates(&id)?;
+		// populate_tracks runs on every track in order, so the matches are taken in turn
+		let mut matches =
+			matching::find_all(&podcast.tracks, |guid: &str| guid.to_string(), &candidates)
+				.into_iter();
 
-		for track in podcast.tracks.iter_mut() {
-			match self.get_episode(&id, &track.url) {
-				Ok((playing_status_i, played_up_to_f)) => {
-					let played_up_to = played_up_to_f as i32;
-					track.progress = std::cmp::max(played_up_to, 0);
+		populate_tracks(&mut podcast, |track| {
+			let found = match matches.next().and_then(|found| found) {
+				Some(candidate) => self
+					.get_episode(&id, &candidate.value)
+					.map(|found| (candidate.value.clone(), found)),
+				None => Err(rusqlite::Error::QueryReturnedNoRows),
+			};
+			match found {
+				Ok((url, (playing_status_i, played_up_to_f))) => {
+					track.progress = secs_from_f64(played_up_to_f);
 
-					track.playing_status = match playing_status_i {
-						0 => Ok(PlayingStatus::Unplayed),
-						1 => Ok(PlayingStatus::Playing),
-						2 => Ok(PlayingStatus::Played),
-						_ => Err(Error::InvalidPlayingStatus),
-					}?;
+					track.playing_status = PLAYING_STATUS.decode(playing_status_i)?;
+
+					if self.has_last_played {
+						track.last_played =
+							self.get_last_played(&id, &url)?.map(|millis| millis / 1000);
+					}
 
 					Ok(())
 				}
 				Err(rusqlite::Error::QueryReturnedNoRows) => {
-					println!("Track not found: {:?}", track);
+					warn(
+						Warning::UnmatchedTrack,
+						format!("Track not found: {:?}", track),
+					);
 					Ok(())
 				}
 				Err(err) => Err(err),
 			}?;
-		}
+			Ok(())
+		});
 
 		Ok(podcast)
 	}
 
+	fn populate_settings(&self, podcast: &mut Podcast) -> BoxResult<()> {
+		// The bare database has no preference files
+		let prefs = match &self.preferences {
+			Some((_, prefs)) => prefs,
+			None => return Ok(()),
+		};
+		let id = self.get_podcast(&podcast.title)?;
+		podcast.settings.auto_archive = prefs.get_bool(&format!("{}{}", AUTO_ARCHIVE_KEY, id));
+		podcast.settings.notifications = prefs.get_bool(&format!("{}{}", NOTIFICATIONS_KEY, id));
+		Ok(())
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		// Pocket Casts does not keep the feed URL, only the title
+		let conn: &Connection = self.db.borrow();
+		let mut stmt = conn.prepare("SELECT title FROM podcasts WHERE subscribed = 1")?;
+		let mut rows = stmt.query(rusqlite::NO_PARAMS)?;
+
+		let mut subs = Vec::new();
+		while let Some(row) = rows.next()? {
+			subs.push(Subscription {
+				url: None,
+				title: row.get(0)?,
+			});
+		}
+		Ok(subs)
+	}
+
+	fn local_podcasts(&self) -> BoxResult<Vec<LocalPodcast>> {
+		// The episode UUIDs are Pocket Casts' own, so other players find these by their URL
+		let conn: &Connection = self.db.borrow();
+		let mut stmt = conn.prepare(
+			"SELECT podcasts.title, episodes.uuid, episodes.download_url, episodes.title, episodes.duration, episodes.playing_status, episodes.played_up_to, podcasts.uuid FROM episodes JOIN podcasts ON podcasts.uuid = episodes.podcast_id ORDER BY podcasts.title, podcasts.uuid, episodes.published_date",
+		)?;
+		let mut rows = stmt.query(rusqlite::NO_PARAMS)?;
+
+		let mut podcasts: Vec<LocalPodcast> = Vec::new();
+		// Podcasts of the same title are told apart by their UUID
+		let mut last_uuid: Option<String> = None;
+		while let Some(row) = rows.next()? {
+			let url = match row
+				.get::<_, Option<String>>(2)?
+				.and_then(|url| Url::parse(&url).ok())
+			{
+				Some(url) => url,
+				None => continue,
+			};
+			let mut track = Track::new(
+				row.get(1)?,
+				url,
+				row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+				row.get::<_, Option<f64>>(4)?
+					.map(secs_from_f64)
+					.filter(|&duration| duration > 0),
+			);
+			let playing_status: i64 = row.get(5)?;
+			track.playing_status = match PLAYING_STATUS.decode(playing_status) {
+				Ok(status) => status,
+				Err(err) => {
+					warn(
+						Warning::SkippedRow,
+						format!(
+							"Skipping episode {} with playing status {}: {}",
+							track.url, playing_status, err
+						),
+					);
+					continue;
+				}
+			};
+			track.progress = secs_from_f64(row.get(6)?);
+
+			let uuid: String = row.get(7)?;
+			match podcasts.last_mut() {
+				Some(podcast) if last_uuid.as_ref() == Some(&uuid) => podcast.tracks.push(track),
+				_ => {
+					podcasts.push(LocalPodcast {
+						title: row.get(0)?,
+						url: None,
+						tracks: vec![track],
+					});
+					last_uuid = Some(uuid);
+				}
+			}
+		}
+		Ok(podcasts)
+	}
+
+	fn subscribe(&mut self, sub: &Subscription) -> BoxResult<bool> {
+		// Podcasts can only be resubscribed to as their UUIDs come from the Pocket Casts servers
+		let conn: &Connection = self.db.borrow();
+		let rows = conn.execute_named(
+			"UPDATE podcasts SET subscribed = 1 WHERE title = :title",
+			&[(":title", &sub.title)],
+		)?;
+		Ok(rows > 0)
+	}
+
+	fn unsubscribe(&mut self, sub: &Subscription) -> BoxResult<bool> {
+		let conn: &Connection = self.db.borrow();
+		conn.execute_named(
+			"UPDATE podcasts SET subscribed = 0 WHERE title = :title",
+			&[(":title", &sub.title)],
+		)?;
+		Ok(true)
+	}
+
 	fn save(
-		self: Box<Self>,
+		mut self: Box<Self>,
 		podcasts: &mut dyn Iterator<Item = &'_ Podcast>,
 		w: &mut dyn IoWriteSeek,
-	) -> BoxResult<()> {
-		let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+		options: &SaveOptions,
+	) -> BoxResult<WriteStats> {
+		// Pocket Casts keeps modification times in milliseconds
+		let now = options.clock.unix_millis();
+		let before = self.status_counts()?;
+		let mut expected = Vec::new();
+		let mut stats = WriteStats::default();
+		let mut settings_changed = false;
 
 		for podcast in podcasts {
-			println!("Saving '{}' ({})", podcast.title, podcast.url);
+			println!(
+				"Saving '{}' ({})",
+				podcast.title,
+				fetch::redact(&podcast.url)
+			);
 			let id = self.get_podcast(&podcast.title)?;
 
-			for track in podcast.tracks.iter() {
-				let playing_status: i32 = match track.playing_status {
-					PlayingStatus::Unplayed => 0,
-					PlayingStatus::Playing => 1,
-					PlayingStatus::Played => 2,
+			if podcast.settings.auto_archive.is_some() || podcast.settings.notifications.is_some() {
+				match &mut self.preferences {
+					Some((_, prefs)) => {
+						if let Some(auto_archive) = podcast.settings.auto_archive {
+							prefs.set_bool(&format!("{}{}", AUTO_ARCHIVE_KEY, id), auto_archive);
+						}
+						if let Some(notifications) = podcast.settings.notifications {
+							prefs.set_bool(&format!("{}{}", NOTIFICATIONS_KEY, id), notifications);
+						}
+						settings_changed = true;
+					}
+					None => warn(
+						Warning::LossyField,
+						format!(
+							"Only the Pocket Casts zip export has preference files, the settings of '{}' are not written",
+							podcast.title
+						),
+					),
+				}
+			}
+
+			let candidates = self.get_candidates(&id)?;
+			let matches =
+				matching::find_all(&podcast.tracks, |guid: &str| guid.to_string(), &candidates);
+			for (track, found) in podcast.tracks.iter().zip(matches) {
+				let playing_status = PLAYING_STATUS.encode(track.playing_status) as i32;
+				let url = found.map_or(&track.url, |candidate| &candidate.value);
+
+				// Unchanged fields are not rewritten, so zero rows only means no match if the
+				// episode is missing as well
+				let rows = match save_track(
+					track,
+					self.update_episode(&id, url, track.progress, playing_status, now),
+					&mut stats,
+				) {
+					Some(rows) => rows,
+					None => continue,
 				};
+				stats.record(rows > 0 || self.get_episode(&id, url).is_ok());
 
-				self.update_episode(&id, &track.url, track.progress, playing_status, now)?;
+				if self.has_last_played && track.playing_status != PlayingStatus::Unplayed {
+					save_track(
+						track,
+						self.update_last_played(&id, url, track.last_played, now),
+						&mut stats,
+					);
+				}
+
+				expected.push(Expected {
+					podcast_id: id.clone(),
+					url: url.clone(),
+					played_up_to: track.progress,
+					playing_status: playing_status as i64,
+				});
 			}
 		}
 
-		// Copy temp file to output
-		let mut temp_file = self.db.into_file()?;
-		std::io::copy(&mut temp_file, w)?;
-		Ok(())
+		stats.report(Self::name());
+
+		// Check the written file with a fresh connection before handing it over
+		let written = Self {
+			db: self.db.reopen()?,
+			preferences: self.preferences,
+			has_last_played: self.has_last_played,
+		};
+		written.verify(&before, &expected)?;
+
+		match (written.db, written.preferences) {
+			(Storage::Database(db), _) => {
+				// Copy temp file to output
+				let mut temp_file = db.into_file()?;
+				std::io::copy(&mut temp_file, w)?;
+			}
+			(Storage::Export(archive), Some((name, prefs))) if settings_changed => {
+				let mut prefs_file = Vec::new();
+				prefs.write(&mut prefs_file)?;
+				archive.save(
+					w,
+					&mut [(name.as_str(), &mut io::Cursor::new(prefs_file))],
+					options,
+				)?;
+			}
+			// The preference files and everything else in the export are kept as they are
+			(Storage::Export(archive), _) => archive.save(w, &mut [], options)?,
+		}
+		Ok(stats)
+	}
+
+	fn stores_provenance(&self) -> bool {
+		match self.db {
+			Storage::Export(_) => true,
+			Storage::Database(_) => false,
+		}
+	}
+
+	fn episode_count(&self) -> BoxResult<Option<usize>> {
+		let conn: &Connection = self.db.borrow();
+		count_rows(conn, "episodes").map(Some)
 	}
 }
 
 impl NewPlayer for PocketCasts {
 	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		let mut db = Storage::open(path)?;
+
+		let mut preferences = None;
+		if let Storage::Export(archive) = &mut db {
+			let name = archive
+				.file_names()?
+				.into_iter()
+				.find(|name| name.ends_with(PREFERENCES_FILE));
+			if let Some(name) = name {
+				let mut text = String::new();
+				archive.by_name(&name)?.read_to_string(&mut text)?;
+				preferences = Some((name, Preferences::parse(&text)?));
+			}
+		}
+
+		let has_last_played = {
+			let conn: &Connection = db.borrow();
+			let mut stmt = conn.prepare("PRAGMA table_info(\"episodes\")")?;
+			let mut rows = stmt.query(rusqlite::NO_PARAMS)?;
+			let mut found = false;
+			while let Some(row) = rows.next()? {
+				let name: String = row.get(1)?;
+				found |= name == LAST_PLAYED_COLUMN;
+			}
+			found
+		};
+
 		Ok(Box::new(Self {
-			db: SQLLiteDatabase::open(path)?,
+			db,
+			preferences,
+			has_last_played,
 		}))
 	}
 
@@ -146,4 +618,10 @@ impl NewPlayer for PocketCasts {
 	fn cli_name() -> &'static str {
 		"pocketcasts"
 	}
+	fn file_names() -> &'static [&'static str] {
+		&["pocketcasts", "pocketcasts*.zip"]
+	}
+	fn status_encodings() -> &'static [StatusEncoding] {
+		&[PLAYING_STATUS]
+	}
 }
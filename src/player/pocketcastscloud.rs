@@ -0,0 +1,252 @@
+use crate::fetch;
+use crate::player::server::Server;
+use crate::player::{
+	populate_tracks, secs_from_f64, IoWriteSeek, NewPlayer, Player, SaveOptions, StatusEncoding,
+	Subscription, WriteStats,
+};
+use crate::podcast::{PlayingStatus, Podcast};
+use crate::url_key::url_key;
+use crate::warning::{warn, Warning};
+use crate::BoxResult;
+
+use std::collections::HashMap;
+
+use reqwest::{header, Client};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+const PLAYING_STATUS: StatusEncoding = StatusEncoding {
+	field: "episode playingStatus",
+	values: &[
+		(PlayingStatus::Unplayed, 1),
+		(PlayingStatus::Playing, 2),
+		(PlayingStatus::Played, 3),
+	],
+};
+
+// Uses the sync API behind the Pocket Casts web player, so no rooted phone is
+// needed. Like the local database podcasts are matched by title and episodes by
+// their download URL. Saving sends an update for every episode whose state
+// differs from the server and writes the updates to the output file as a record.
+
+#[derive(Deserialize)]
+struct CloudPodcast {
+	uuid: String,
+	title: String,
+}
+
+#[derive(Deserialize)]
+struct PodcastList {
+	podcasts: Vec<CloudPodcast>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CloudEpisode {
+	uuid: String,
+	url: String,
+	playing_status: i64,
+	#[serde(default)]
+	played_up_to: f64,
+}
+
+#[derive(Deserialize)]
+struct EpisodeList {
+	episodes: Vec<CloudEpisode>,
+}
+
+#[derive(Serialize)]
+struct EpisodeUpdate {
+	uuid: String,
+	podcast: String,
+	status: i64,
+	position: i32,
+}
+
+#[derive(Deserialize)]
+struct Login {
+	token: String,
+}
+
+pub struct PocketCastsCloud {
+	client: Client,
+	server: Server,
+	token: String,
+	podcasts: Vec<CloudPodcast>,
+}
+
+impl PocketCastsCloud {
+	fn post<T: Serialize, R: serde::de::DeserializeOwned>(
+		&self,
+		path: &str,
+		body: &T,
+	) -> BoxResult<R> {
+		Ok(self
+			.client
+			.post(self.server.endpoint(path)?)
+			.header(header::AUTHORIZATION, format!("Bearer {}", self.token))
+			.json(body)
+			.send()?
+			.error_for_status()?
+			.json()?)
+	}
+
+	fn get_podcast(&self, title: &String) -> Option<&CloudPodcast> {
+		self.podcasts.iter().find(|podcast| &podcast.title == title)
+	}
+
+	fn get_episodes(&self, podcast_uuid: &str) -> BoxResult<HashMap<String, CloudEpisode>> {
+		let list: EpisodeList =
+			self.post("user/podcast/episodes", &json!({ "uuid": podcast_uuid }))?;
+		Ok(list
+			.episodes
+			.into_iter()
+			.map(|episode| (url_key(episode.url.as_str()), episode))
+			.collect())
+	}
+}
+
+impl Player for PocketCastsCloud {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		let uuid = match self.get_podcast(&podcast.title) {
+			Some(cloud_podcast) => cloud_podcast.uuid.clone(),
+			None => {
+				warn(
+					Warning::UnmatchedTrack,
+					format!("Podcast not found: {}", podcast.title),
+				);
+				return Ok(podcast);
+			}
+		};
+		let episodes = self.get_episodes(uuid.as_str())?;
+
+		populate_tracks(&mut podcast, |track| {
+			match episodes.get(&url_key(track.url.as_str())) {
+				Some(episode) => {
+					track.progress = secs_from_f64(episode.played_up_to);
+					track.playing_status = PLAYING_STATUS.decode(episode.playing_status)?;
+				}
+				None => warn(
+					Warning::UnmatchedTrack,
+					format!("Track not found: {:?}", track),
+				),
+			}
+			Ok(())
+		});
+
+		Ok(podcast)
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		// The API does not return feed URLs either
+		Ok(self
+			.podcasts
+			.iter()
+			.map(|podcast| Subscription {
+				url: None,
+				title: podcast.title.clone(),
+			})
+			.collect())
+	}
+
+	fn save(
+		self: Box<Self>,
+		podcasts: &mut dyn Iterator<Item = &'_ Podcast>,
+		w: &mut dyn IoWriteSeek,
+		_options: &SaveOptions,
+	) -> BoxResult<WriteStats> {
+		let mut updates = Vec::new();
+
+		for podcast in podcasts {
+			println!(
+				"Saving '{}' ({})",
+				podcast.title,
+				fetch::redact(&podcast.url)
+			);
+			let uuid = match self.get_podcast(&podcast.title) {
+				Some(cloud_podcast) => cloud_podcast.uuid.clone(),
+				None => {
+					warn(
+						Warning::UnmatchedTrack,
+						format!("Podcast not found: {}", podcast.title),
+					);
+					continue;
+				}
+			};
+			let episodes = self.get_episodes(uuid.as_str())?;
+
+			for track in podcast.tracks.iter() {
+				let episode = match episodes.get(&url_key(track.url.as_str())) {
+					Some(episode) => episode,
+					None => continue,
+				};
+
+				let status = PLAYING_STATUS.encode(track.playing_status);
+				if episode.playing_status == status && episode.played_up_to as i32 == track.progress
+				{
+					continue;
+				}
+
+				let update = EpisodeUpdate {
+					uuid: episode.uuid.clone(),
+					podcast: uuid.clone(),
+					status,
+					position: track.progress,
+				};
+				let _: serde_json::Value = self.post("sync/update_episode", &update)?;
+				updates.push(update);
+			}
+		}
+
+		println!("Updated {} episodes", updates.len());
+		serde_json::to_writer_pretty(w, &updates)?;
+		Ok(WriteStats::default())
+	}
+}
+
+impl NewPlayer for PocketCastsCloud {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		let server = Server::parse(
+			path,
+			"POCKETCASTS_EMAIL",
+			"POCKETCASTS_PASSWORD",
+			Self::name(),
+		)?;
+		let client = Client::new();
+
+		let login: Login = client
+			.post(server.endpoint("user/login")?)
+			.json(&json!({
+				"email": server.username,
+				"password": server.password,
+				"scope": "webplayer",
+			}))
+			.send()?
+			.error_for_status()?
+			.json()?;
+
+		let mut player = Self {
+			client,
+			server,
+			token: login.token,
+			podcasts: Vec::new(),
+		};
+		let list: PodcastList = player.post("user/podcast/list", &json!({ "v": 1 }))?;
+		player.podcasts = list.podcasts;
+
+		Ok(Box::new(player))
+	}
+
+	fn name() -> &'static str {
+		"Pocket Casts cloud"
+	}
+	fn cli_name() -> &'static str {
+		"pocketcastscloud"
+	}
+	fn value_name() -> &'static str {
+		"URL"
+	}
+	fn status_encodings() -> &'static [StatusEncoding] {
+		&[PLAYING_STATUS]
+	}
+}
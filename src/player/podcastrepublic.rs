@@ -0,0 +1,185 @@
+use crate::fetch;
+use crate::player::archive::SQLiteArchive;
+use crate::player::{
+	count_rows, find_by_urls, populate_tracks, save_track, secs_from_millis, stored_url,
+	IoWriteSeek, NewPlayer, Player, SaveOptions, StatusEncoding, Subscription, WriteStats,
+};
+use crate::podcast::{PlayingStatus, Podcast};
+use crate::warning::{warn, Warning};
+use crate::BoxResult;
+
+use std::borrow::Borrow;
+
+use reqwest::Url;
+use rusqlite::Connection;
+
+const DB_FILE: &str = "podcastrepublic.db";
+
+const PLAYED: StatusEncoding = StatusEncoding {
+	field: "episodes.played",
+	values: &[
+		(PlayingStatus::Unplayed, 0),
+		(PlayingStatus::Playing, 0),
+		(PlayingStatus::Played, 1),
+	],
+};
+
+pub struct PodcastRepublic {
+	archive: SQLiteArchive,
+}
+
+impl PodcastRepublic {
+	fn get_podcast(&self, url: &Url) -> BoxResult<i64> {
+		let conn: &Connection = self.archive.borrow();
+		let mut stmt =
+			conn.prepare("SELECT _id FROM podcasts WHERE url_key(feed_url) = url_key(:url)")?;
+		let mut rows = stmt.query_named(&[(":url", &url.to_string())])?;
+		let first_row = rows.next()?.ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+		Ok(first_row.get(0)?)
+	}
+
+	fn get_episode(&self, podcast_id: i64, episode_url: &Url) -> rusqlite::Result<(i64, i64)> {
+		let conn: &Connection = self.archive.borrow();
+		let mut stmt = conn
+			.prepare("SELECT played, position_ms FROM episodes WHERE podcast_id = :podcast_id AND url_key(media_url) = url_key(:media_url)")?;
+		let mut rows = stmt.query_named(&[
+			(":podcast_id", &podcast_id),
+			(":media_url", &episode_url.to_string()),
+		])?;
+		let first_row = rows.next()?;
+		first_row
+			.ok_or(rusqlite::Error::QueryReturnedNoRows)
+			.and_then(|row| Ok((row.get(0)?, row.get(1)?)))
+	}
+
+	fn update_episode(
+		&self,
+		podcast_id: i64,
+		episode_url: &Url,
+		played: i64,
+		position_ms: i64,
+	) -> rusqlite::Result<()> {
+		let conn: &Connection = self.archive.borrow();
+		conn.execute_named(
+			"UPDATE episodes SET played = :played, position_ms = :position_ms WHERE podcast_id = :podcast_id AND url_key(media_url) = url_key(:media_url)",
+			&[
+				(":podcast_id", &podcast_id),
+				(":media_url", &episode_url.to_string()),
+				(":played", &played),
+				(":position_ms", &position_ms),
+			],
+		).map(|_| ())
+	}
+}
+
+impl Player for PodcastRepublic {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		let id = self.get_podcast(&podcast.url)?;
+
+		populate_tracks(&mut podcast, |track| {
+			match find_by_urls(track, |url| self.get_episode(id, url)).map(|(_, found)| found) {
+				Ok((played, position_ms)) => {
+					track.progress = secs_from_millis(position_ms);
+
+					track.playing_status = if PLAYED.decode(played)? == PlayingStatus::Played {
+						PlayingStatus::Played
+					} else if track.progress > 0 {
+						PlayingStatus::Playing
+					} else {
+						PlayingStatus::Unplayed
+					};
+
+					Ok(())
+				}
+				Err(rusqlite::Error::QueryReturnedNoRows) => {
+					warn(
+						Warning::UnmatchedTrack,
+						format!("Track not found: {:?}", track),
+					);
+					Ok(())
+				}
+				Err(err) => Err(err),
+			}?;
+			Ok(())
+		});
+
+		Ok(podcast)
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		let conn: &Connection = self.archive.borrow();
+		let mut stmt = conn.prepare("SELECT feed_url, title FROM podcasts")?;
+		let mut rows = stmt.query(rusqlite::NO_PARAMS)?;
+
+		let mut subs = Vec::new();
+		while let Some(row) = rows.next()? {
+			let url: String = row.get(0)?;
+			subs.push(Subscription {
+				url: Some(Url::parse(url.as_str())?),
+				title: row.get(1)?,
+			});
+		}
+		Ok(subs)
+	}
+
+	fn stores_provenance(&self) -> bool {
+		true
+	}
+
+	fn save(
+		self: Box<Self>,
+		podcasts: &mut dyn Iterator<Item = &'_ Podcast>,
+		w: &mut dyn IoWriteSeek,
+		options: &SaveOptions,
+	) -> BoxResult<WriteStats> {
+		let mut stats = WriteStats::default();
+
+		for podcast in podcasts {
+			println!(
+				"Saving '{}' ({})",
+				podcast.title,
+				fetch::redact(&podcast.url)
+			);
+			let id = self.get_podcast(&podcast.url)?;
+
+			for track in podcast.tracks.iter() {
+				save_track(
+					track,
+					self.update_episode(
+						id,
+						stored_url(track, |url| self.get_episode(id, url).is_ok()),
+						PLAYED.encode(track.playing_status),
+						track.progress as i64 * 1000,
+					),
+					&mut stats,
+				);
+			}
+		}
+
+		self.archive.save(w, &mut [], options)?;
+		Ok(stats)
+	}
+
+	fn episode_count(&self) -> BoxResult<Option<usize>> {
+		let conn: &Connection = self.archive.borrow();
+		count_rows(conn, "episodes").map(Some)
+	}
+}
+
+impl NewPlayer for PodcastRepublic {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		Ok(Box::new(Self {
+			archive: SQLiteArchive::open(path, DB_FILE)?,
+		}))
+	}
+
+	fn name() -> &'static str {
+		"Podcast Republic"
+	}
+	fn cli_name() -> &'static str {
+		"podcastrepublic"
+	}
+	fn status_encodings() -> &'static [StatusEncoding] {
+		&[PLAYED]
+	}
+}
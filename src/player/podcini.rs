@@ -0,0 +1,107 @@
+use crate::player::{secs_from_millis, NewPlayer, Player, StatusEncoding};
+use crate::podcast::{PlayingStatus, Podcast};
+use crate::warning::{warn, Warning};
+use crate::BoxResult;
+
+use std::collections::HashMap;
+use std::fs::File;
+
+use serde::Deserialize;
+
+// Podcini's play states extend AntennaPod's NEW (-1), UNPLAYED (0) and PLAYED (1)
+const PLAY_STATE: StatusEncoding = StatusEncoding {
+	field: "playState",
+	values: &[
+		(PlayingStatus::Unplayed, 0),
+		(PlayingStatus::Playing, 5),
+		(PlayingStatus::Played, 1),
+		(PlayingStatus::Unplayed, -1),
+	],
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EpisodeState {
+	#[serde(default)]
+	identifier: Option<String>,
+	download_url: String,
+	play_state: i64,
+	#[serde(default)]
+	position: i64,
+}
+
+pub struct Podcini {
+	// (status, progress) keyed by both episode url and guid
+	episodes: HashMap<String, (PlayingStatus, i32)>,
+}
+
+impl Player for Podcini {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		for track in podcast.tracks.iter_mut() {
+			let state = self.episodes.get(&track.guid).or_else(|| {
+				track
+					.urls()
+					.find_map(|url| self.episodes.get(&url.to_string()))
+			});
+
+			match state {
+				Some(&(status, progress)) => {
+					track.playing_status = status;
+					track.progress = progress;
+				}
+				None => warn(
+					Warning::UnmatchedTrack,
+					format!("Track not found: {:?}", track),
+				),
+			}
+		}
+
+		Ok(podcast)
+	}
+}
+
+impl NewPlayer for Podcini {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		let states: Vec<EpisodeState> = serde_json::from_reader(File::open(path)?)?;
+
+		let mut episodes = HashMap::new();
+		for episode in states.into_iter() {
+			// Positions are stored in milliseconds
+			let progress = secs_from_millis(episode.position);
+			let status = match PLAY_STATE.decode(episode.play_state) {
+				Ok(status) => status,
+				Err(err) => {
+					warn(
+						Warning::SkippedRow,
+						format!(
+							"Skipping episode {} with play state {}: {}",
+							episode.download_url, episode.play_state, err
+						),
+					);
+					continue;
+				}
+			};
+			let state = (status, progress);
+
+			if let Some(guid) = episode.identifier {
+				episodes.insert(guid, state);
+			}
+			episodes.insert(episode.download_url, state);
+		}
+
+		Ok(Box::new(Self { episodes }))
+	}
+
+	fn name() -> &'static str {
+		"Podcini"
+	}
+	fn cli_name() -> &'static str {
+		"podcini"
+	}
+	fn read_only() -> bool {
+		true
+	}
+	fn status_encodings() -> &'static [StatusEncoding] {
+		&[PLAY_STATE]
+	}
+}
@@ -0,0 +1,182 @@
+use crate::fetch;
+use crate::player::{
+	secs_from_f64, IoWriteSeek, NewPlayer, Player, SaveOptions, Subscription, WriteStats,
+};
+use crate::podcast::{PlayingStatus, Podcast, Track};
+use crate::BoxResult;
+
+use std::fs::File;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+// Podverse exports the user's data as one JSON document. The history holds an
+// item per episode that was started, keyed by its media URL, with the position
+// in seconds and whether it was completed. The queue is kept as it is, except
+// that completed episodes are taken off it like the app does. Episodes that
+// were started elsewhere get a new history item. Fields this tool does not know
+// about are kept in `other` so they survive the round trip untouched.
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HistoryItem {
+	episode_media_url: String,
+	#[serde(default)]
+	episode_title: String,
+	#[serde(default)]
+	podcast_title: String,
+	#[serde(default)]
+	user_playback_position: f64,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	media_file_duration: Option<f64>,
+	#[serde(default)]
+	completed: bool,
+	#[serde(flatten)]
+	other: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct QueueItem {
+	episode_media_url: String,
+	#[serde(flatten)]
+	other: Map<String, Value>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UserData {
+	#[serde(default)]
+	history_items: Vec<HistoryItem>,
+	#[serde(default)]
+	queue_items: Vec<QueueItem>,
+	#[serde(flatten)]
+	other: Map<String, Value>,
+}
+
+pub struct Podverse {
+	data: UserData,
+}
+
+impl Podverse {
+	fn is_episode(url: &str, track: &Track) -> bool {
+		track.has_url(url)
+	}
+}
+
+impl Player for Podverse {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		for track in podcast.tracks.iter_mut() {
+			// Episodes without a history item were never started
+			if let Some(item) = self
+				.data
+				.history_items
+				.iter()
+				.find(|item| Self::is_episode(&item.episode_media_url, track))
+			{
+				track.progress = secs_from_f64(item.user_playback_position);
+				track.playing_status = if item.completed {
+					PlayingStatus::Played
+				} else if track.progress > 0 {
+					PlayingStatus::Playing
+				} else {
+					PlayingStatus::Unplayed
+				};
+			}
+		}
+
+		Ok(podcast)
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		// The export names the podcasts of history items but not their feeds
+		let mut titles: Vec<&String> = self
+			.data
+			.history_items
+			.iter()
+			.map(|item| &item.podcast_title)
+			.filter(|title| !title.is_empty())
+			.collect();
+		titles.sort();
+		titles.dedup();
+
+		Ok(titles
+			.into_iter()
+			.map(|title| Subscription {
+				url: None,
+				title: title.clone(),
+			})
+			.collect())
+	}
+
+	fn save(
+		mut self: Box<Self>,
+		podcasts: &mut dyn Iterator<Item = &'_ Podcast>,
+		w: &mut dyn IoWriteSeek,
+		_options: &SaveOptions,
+	) -> BoxResult<WriteStats> {
+		for podcast in podcasts {
+			println!(
+				"Saving '{}' ({})",
+				podcast.title,
+				fetch::redact(&podcast.url)
+			);
+
+			for track in podcast.tracks.iter() {
+				let completed = track.playing_status == PlayingStatus::Played;
+				let position = match track.playing_status {
+					PlayingStatus::Playing => track.progress as f64,
+					_ => 0.0,
+				};
+
+				match self
+					.data
+					.history_items
+					.iter_mut()
+					.find(|item| Self::is_episode(&item.episode_media_url, track))
+				{
+					Some(item) => {
+						item.user_playback_position = position;
+						item.completed = completed;
+					}
+					None if track.playing_status != PlayingStatus::Unplayed => {
+						self.data.history_items.push(HistoryItem {
+							episode_media_url: track.url.to_string(),
+							episode_title: track.title.clone(),
+							podcast_title: podcast.title.clone(),
+							user_playback_position: position,
+							media_file_duration: track.duration.map(|duration| duration as f64),
+							completed,
+							other: Map::new(),
+						})
+					}
+					None => (),
+				}
+
+				if completed {
+					self.data
+						.queue_items
+						.retain(|item| !Self::is_episode(&item.episode_media_url, track));
+				}
+			}
+		}
+
+		serde_json::to_writer_pretty(w, &self.data)?;
+		Ok(WriteStats::default())
+	}
+}
+
+impl NewPlayer for Podverse {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		Ok(Box::new(Self {
+			data: serde_json::from_reader(File::open(path)?)?,
+		}))
+	}
+
+	fn name() -> &'static str {
+		"Podverse"
+	}
+	fn cli_name() -> &'static str {
+		"podverse"
+	}
+}
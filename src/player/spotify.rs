@@ -0,0 +1,164 @@
+use crate::player::titles::{best_match, normalise};
+use crate::player::{secs_from_millis, NewPlayer, Player, Subscription};
+use crate::podcast::{PlayingStatus, Podcast, Track};
+use crate::BoxResult;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Deserialize;
+
+const FILE_NAMES: [&str; 2] = ["StreamingHistory", "Streaming_History"];
+
+// Share of an episode that has to have been streamed for it to count as played
+const PLAYED_FRACTION: f64 = 0.9;
+
+// Spotify's data export lists every stream with the show and episode names and
+// how long it played, but no feed or enclosure URLs. Shows are matched to the
+// OPML feeds and episodes to the feed items by their normalised titles, and the
+// milliseconds of every stream of an episode are added up. An episode streamed
+// for most of its duration is played, otherwise that much of it is taken as the
+// position. The account data export has `StreamingHistory*.json` (music and
+// podcasts mixed, or `StreamingHistory_podcast_*.json`), the extended one has
+// `Streaming_History_Audio_*.json`.
+
+#[derive(Deserialize)]
+struct Stream {
+	#[serde(
+		alias = "artistName",
+		alias = "podcastName",
+		alias = "episode_show_name"
+	)]
+	show: Option<String>,
+	#[serde(alias = "trackName", alias = "episodeName", alias = "episode_name")]
+	episode: Option<String>,
+	#[serde(alias = "msPlayed")]
+	ms_played: i64,
+}
+
+pub struct Spotify {
+	/// Milliseconds streamed keyed by normalised show name, then by normalised episode name
+	shows: HashMap<String, HashMap<String, i64>>,
+}
+
+impl Spotify {
+	fn read_file(&mut self, path: &Path) -> BoxResult<()> {
+		let streams: Vec<Stream> = serde_json::from_reader(std::fs::File::open(path)?)?;
+		for stream in streams.into_iter() {
+			// The account export lists music with the artist and track in the same fields as
+			// the show and episode, so it is kept and matches no feed. Music in the extended
+			// history has neither
+			if let (Some(show), Some(episode)) = (stream.show, stream.episode) {
+				*self
+					.shows
+					.entry(normalise(&show))
+					.or_insert_with(HashMap::new)
+					.entry(normalise(&episode))
+					.or_insert(0) += stream.ms_played;
+			}
+		}
+		Ok(())
+	}
+
+	fn find_episode(episodes: &HashMap<String, i64>, track: &Track) -> Option<i64> {
+		best_match(
+			&normalise(&track.title),
+			episodes.iter().map(|(episode, &ms)| (episode.as_str(), ms)),
+		)
+	}
+}
+
+impl Player for Spotify {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		let title = normalise(&podcast.title);
+		let shows = self
+			.shows
+			.iter()
+			.map(|(show, episodes)| (show.as_str(), episodes));
+		let episodes = match best_match(&title, shows) {
+			Some(episodes) => episodes,
+			// Shows that were never streamed have nothing to convert
+			None => return Ok(podcast),
+		};
+
+		for track in podcast.tracks.iter_mut() {
+			let ms_played = match Self::find_episode(episodes, track) {
+				Some(ms) => ms,
+				None => continue,
+			};
+
+			let secs_played = secs_from_millis(ms_played);
+			let played = track.duration.map_or(false, |duration| {
+				duration > 0 && secs_played as f64 >= duration as f64 * PLAYED_FRACTION
+			});
+
+			if played {
+				track.playing_status = PlayingStatus::Played;
+				track.progress = 0;
+			} else if secs_played > 0 {
+				track.playing_status = PlayingStatus::Playing;
+				track.progress = secs_played;
+			}
+		}
+
+		Ok(podcast)
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		// Only the normalised names are kept, which is enough to match by title
+		let mut shows: Vec<&String> = self.shows.keys().collect();
+		shows.sort();
+
+		Ok(shows
+			.into_iter()
+			.map(|show| Subscription {
+				url: None,
+				title: show.clone(),
+			})
+			.collect())
+	}
+}
+
+impl NewPlayer for Spotify {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		let mut player = Self {
+			shows: HashMap::new(),
+		};
+
+		// Accept a single history file, otherwise read every one in the export directory
+		let path = Path::new(path);
+		if path.is_dir() {
+			let mut files = Vec::new();
+			for entry in std::fs::read_dir(path)? {
+				let file = entry?.path();
+				let name = file.file_name().and_then(|n| n.to_str()).unwrap_or("");
+				if name.ends_with(".json")
+					&& FILE_NAMES.iter().any(|prefix| name.starts_with(prefix))
+				{
+					files.push(file);
+				}
+			}
+			files.sort();
+			for file in files.iter() {
+				player.read_file(file)?;
+			}
+		} else {
+			player.read_file(path)?;
+		}
+
+		Ok(Box::new(player))
+	}
+
+	fn name() -> &'static str {
+		"Spotify"
+	}
+	fn cli_name() -> &'static str {
+		"spotify"
+	}
+	fn file_names() -> &'static [&'static str] {
+		&["StreamingHistory*.json", "Streaming_History*.json"]
+	}
+	fn read_only() -> bool {
+		true
+	}
+}
@@ -0,0 +1,210 @@
+use crate::csv;
+use crate::player::titles::{best_match, normalise, similar};
+use crate::player::{secs_from_f64, NewPlayer, Player, Subscription};
+use crate::podcast::{PlayingStatus, Podcast, Track};
+use crate::url_key::url_key;
+use crate::BoxResult;
+
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+// Stitcher shut down in 2023 and its data exports came in a few shapes over the
+// years, as CSV or as a JSON list of objects. Rather than one fixed layout the
+// columns are found by name, ignoring case and punctuation. Every row is an
+// episode that was listened to, unless a listened/completed column says it was
+// not, in which case its position (if any) makes it in progress. Episodes are
+// matched by their URL when the export has one, otherwise by show and episode
+// title.
+
+const SHOW_COLUMNS: [&str; 6] = [
+	"show",
+	"showname",
+	"feed",
+	"feedname",
+	"podcast",
+	"podcastname",
+];
+const EPISODE_COLUMNS: [&str; 4] = ["episode", "episodetitle", "episodename", "title"];
+const URL_COLUMNS: [&str; 4] = ["url", "episodeurl", "enclosureurl", "audiourl"];
+const LISTENED_COLUMNS: [&str; 4] = ["listened", "completed", "played", "finished"];
+const POSITION_COLUMNS: [&str; 3] = ["position", "offset", "secondslistened"];
+
+struct Listen {
+	show: String,
+	episode: String,
+	url: Option<String>,
+	played: bool,
+	position: i32,
+}
+
+impl Listen {
+	/// Build from a row keyed by lowercase alphanumeric column names
+	fn from_row(row: &HashMap<String, String>) -> Option<Self> {
+		let get = |columns: &[&str]| {
+			columns
+				.iter()
+				.filter_map(|column| row.get(*column))
+				.find(|value| !value.is_empty())
+		};
+
+		let played = get(&LISTENED_COLUMNS).map_or(true, |value| {
+			["true", "yes", "y", "1"].contains(&value.to_lowercase().as_str())
+		});
+		let position = get(&POSITION_COLUMNS)
+			.and_then(|value| value.parse::<f64>().ok())
+			.map_or(0, secs_from_f64);
+
+		let listen = Self {
+			show: normalise(get(&SHOW_COLUMNS).map_or("", String::as_str)),
+			episode: normalise(get(&EPISODE_COLUMNS).map_or("", String::as_str)),
+			url: get(&URL_COLUMNS).map(|url| url_key(url)),
+			played,
+			position,
+		};
+
+		if listen.url.is_none() && listen.episode.is_empty() {
+			None
+		} else {
+			Some(listen)
+		}
+	}
+
+	fn has_url(&self, track: &Track) -> bool {
+		self.url.as_ref().map_or(false, |url| {
+			track.urls().any(|own| *url == url_key(own.as_str()))
+		})
+	}
+}
+
+fn column_key(name: &str) -> String {
+	name.to_lowercase()
+		.chars()
+		.filter(|c| c.is_alphanumeric())
+		.collect()
+}
+
+fn rows_from_csv(text: &str) -> Vec<HashMap<String, String>> {
+	let mut rows = csv::read(text).into_iter();
+	let header: Vec<String> = match rows.next() {
+		Some(header) => header.iter().map(|name| column_key(name)).collect(),
+		None => return Vec::new(),
+	};
+	rows.map(|row| header.iter().cloned().zip(row.into_iter()).collect())
+		.collect()
+}
+
+fn rows_from_json(json: Value) -> Vec<HashMap<String, String>> {
+	let items = match json {
+		Value::Array(items) => items,
+		_ => return Vec::new(),
+	};
+	items
+		.into_iter()
+		.filter_map(|item| match item {
+			Value::Object(fields) => Some(
+				fields
+					.into_iter()
+					.map(|(name, value)| {
+						let value = match value {
+							Value::String(s) => s,
+							Value::Null => String::new(),
+							other => other.to_string(),
+						};
+						(column_key(&name), value)
+					})
+					.collect(),
+			),
+			_ => None,
+		})
+		.collect()
+}
+
+pub struct Stitcher {
+	listens: Vec<Listen>,
+}
+
+impl Stitcher {
+	/// The listen with the track's URL, otherwise the one of the show whose episode title is
+	/// closest to the track's
+	fn find_listen(&self, show: &str, track: &Track) -> Option<&Listen> {
+		self.listens
+			.iter()
+			.find(|listen| listen.has_url(track))
+			.or_else(|| {
+				best_match(
+					&normalise(&track.title),
+					self.listens
+						.iter()
+						.filter(|listen| similar(&listen.show, show))
+						.map(|listen| (listen.episode.as_str(), listen)),
+				)
+			})
+	}
+}
+
+impl Player for Stitcher {
+	fn populate(&mut self, mut podcast: Podcast) -> BoxResult<Podcast> {
+		let show = normalise(&podcast.title);
+
+		for track in podcast.tracks.iter_mut() {
+			if let Some(listen) = self.find_listen(&show, track) {
+				if listen.played {
+					track.playing_status = PlayingStatus::Played;
+				} else if listen.position > 0 {
+					track.playing_status = PlayingStatus::Playing;
+					track.progress = listen.position;
+				}
+			}
+		}
+
+		Ok(podcast)
+	}
+
+	fn subscriptions(&self) -> BoxResult<Vec<Subscription>> {
+		// Only the normalised show names are kept, which is enough to match by title
+		let mut shows: Vec<&String> = self
+			.listens
+			.iter()
+			.map(|listen| &listen.show)
+			.filter(|show| !show.is_empty())
+			.collect();
+		shows.sort();
+		shows.dedup();
+
+		Ok(shows
+			.into_iter()
+			.map(|show| Subscription {
+				url: None,
+				title: show.clone(),
+			})
+			.collect())
+	}
+}
+
+impl NewPlayer for Stitcher {
+	fn new(path: &str) -> BoxResult<Box<dyn Player>> {
+		let text = std::fs::read_to_string(path)?;
+		let rows = match serde_json::from_str(&text) {
+			Ok(json) => rows_from_json(json),
+			Err(_) => rows_from_csv(&text),
+		};
+
+		Ok(Box::new(Self {
+			listens: rows.iter().filter_map(Listen::from_row).collect(),
+		}))
+	}
+
+	fn name() -> &'static str {
+		"Stitcher"
+	}
+	fn cli_name() -> &'static str {
+		"stitcher"
+	}
+	fn file_names() -> &'static [&'static str] {
+		&["*stitcher*.csv", "*stitcher*.json"]
+	}
+	fn read_only() -> bool {
+		true
+	}
+}
@@ -0,0 +1,105 @@
+// Share of their words two titles need in common to be the same episode
+const MIN_WORD_OVERLAP: f64 = 0.8;
+
+/// Lowercase words without punctuation, so "Episode 12: Title!" and "episode 12 - title" agree
+pub fn normalise(title: &str) -> String {
+	title
+		.to_lowercase()
+		.chars()
+		.map(|c| if c.is_alphanumeric() { c } else { ' ' })
+		.collect::<String>()
+		.split_whitespace()
+		.collect::<Vec<&str>>()
+		.join(" ")
+}
+
+/// Share of the words of the longer of two normalised titles that the other has too. A title
+/// inside a much longer one, such as "news" in "morning news roundup", shares few of them
+fn word_overlap(a: &str, b: &str) -> f64 {
+	if a.is_empty() || b.is_empty() {
+		return 0.0;
+	}
+
+	let a_words: Vec<&str> = a.split(' ').collect();
+	let b_words: Vec<&str> = b.split(' ').collect();
+	let common = a_words.iter().filter(|word| b_words.contains(word)).count();
+	common as f64 / std::cmp::max(a_words.len(), b_words.len()) as f64
+}
+
+/// Whether two normalised titles are close enough to name the same show or episode
+pub fn similar(a: &str, b: &str) -> bool {
+	!a.is_empty() && (a == b || word_overlap(a, b) >= MIN_WORD_OVERLAP)
+}
+
+/// The value of the candidate whose normalised title is `title`, otherwise of the one most
+/// similar to it. Equally similar candidates go to the title that sorts first, so the same
+/// history always matches the same way whatever order the candidates come in
+pub fn best_match<'a, T, I: IntoIterator<Item = (&'a str, T)>>(
+	title: &str,
+	candidates: I,
+) -> Option<T> {
+	let mut best: Option<(f64, &str, T)> = None;
+	for (candidate, value) in candidates {
+		if candidate == title && !title.is_empty() {
+			return Some(value);
+		}
+
+		let overlap = word_overlap(candidate, title);
+		if overlap < MIN_WORD_OVERLAP {
+			continue;
+		}
+		let better = best.as_ref().map_or(true, |(best_overlap, best_title, _)| {
+			overlap > *best_overlap || (overlap == *best_overlap && candidate < *best_title)
+		});
+		if better {
+			best = Some((overlap, candidate, value));
+		}
+	}
+	best.map(|(_, _, value)| value)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn normalises_case_and_punctuation() {
+		assert_eq!(normalise("Episode 12: The Title!"), "episode 12 the title");
+		assert_eq!(
+			normalise("  episode 12 - the title "),
+			"episode 12 the title"
+		);
+	}
+
+	#[test]
+	fn short_titles_are_not_found_in_long_ones() {
+		assert!(!similar("news", "morning news roundup"));
+		assert!(!similar("episode 1", "episode 12 the title"));
+		assert!(similar(
+			"the daily show with guest",
+			"the daily show with guests"
+		));
+	}
+
+	#[test]
+	fn exact_title_wins() {
+		let candidates = vec![("the title part one", 1), ("the title part two", 2)];
+		assert_eq!(best_match("the title part two", candidates), Some(2));
+	}
+
+	#[test]
+	fn best_match_is_deterministic() {
+		let title = "a b c d e f g h i j";
+		let forward = vec![("a b c d e f g h i x", 1), ("a b c d e f g h i y", 2)];
+		let backward = vec![("a b c d e f g h i y", 2), ("a b c d e f g h i x", 1)];
+		assert_eq!(best_match(title, forward), Some(1));
+		assert_eq!(best_match(title, backward), Some(1));
+	}
+
+	#[test]
+	fn closest_title_wins() {
+		let title = "a b c d e f g h i j";
+		let candidates = vec![("a b c d e f g h x y", 1), ("a b c d e f g h i x", 2)];
+		assert_eq!(best_match(title, candidates), Some(2));
+	}
+}
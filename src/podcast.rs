@@ -1,68 +1,419 @@
+use crate::apple_lookup;
+use crate::clock;
+use crate::fetch;
+use crate::heartbeat::Heartbeat;
+use crate::i18n::tr;
+use crate::json_feed;
+use crate::opml::{self, find_child};
+use crate::player::Subscription;
+use crate::podcast_index;
+use crate::skip;
+use crate::url_key::url_key;
+use crate::warning::{warn, Warning};
 use crate::{BoxResult, Error};
 
-use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
 
 use reqwest::Url;
 use roxmltree::Node;
 
-fn find_child<'a>(node: Node<'a, 'a>, child: &'static str) -> Result<Node<'a, 'a>, Error> {
-	node.children()
-		.find(|n| n.is_element() && n.tag_name().name() == child)
-		.ok_or(Error::MissingXMLNode(child))
+const PSC_NAMESPACE: &str = "http://podlove.org/simple-chapters";
+const ATOM_NAMESPACE: &str = "http://www.w3.org/2005/Atom";
+const PODCAST_NAMESPACE: &str = "https://podcastindex.org/namespace/1.0";
+
+/// Set by --jobs, how many feeds are fetched at once
+static JOBS: AtomicUsize = AtomicUsize::new(4);
+
+/// Set by --feed-pages, how many pages of a paginated feed are read at most
+static MAX_PAGES: AtomicUsize = AtomicUsize::new(50);
+
+pub fn set_jobs(jobs: usize) {
+	JOBS.store(std::cmp::max(jobs, 1), Ordering::Relaxed);
 }
 
-pub fn from_opml<P: AsRef<Path>>(path: P) -> BoxResult<Vec<Podcast>> {
-	let opml_str = std::fs::read_to_string(path)?;
-	let doc = roxmltree::Document::parse(opml_str.as_str())?;
+pub fn jobs() -> usize {
+	JOBS.load(Ordering::Relaxed)
+}
 
-	find_child(doc.root_element(), "body")? // body node
-		.children()
-		.filter(|n| n.is_element() && n.tag_name().name() == "outline") // all category nodes
-		.map(|category| {
-			category
-				.children()
-				.filter(|n| n.is_element() && n.tag_name().name() == "outline") // all feed nodes in this category
-				.filter_map(|feed| {
-					Some(Podcast::new(
-						feed.attribute("xmlUrl")?,
-						feed.attribute("text")?,
-					))
-				})
+pub fn set_max_pages(pages: usize) {
+	MAX_PAGES.store(std::cmp::max(pages, 1), Ordering::Relaxed);
+}
+
+type TrackSubnodes<'a> = (
+	Node<'a, 'a>,
+	Node<'a, 'a>,
+	Option<Node<'a, 'a>>,
+	Option<Node<'a, 'a>>,
+	Option<Node<'a, 'a>>,
+);
+
+/// (url, title) of each feed that could not be fetched
+pub type FailedFeeds = Vec<(String, String)>;
+
+/// Fetch every feed in the OPML files, or only those in the given categories, along with the
+/// feeds that failed
+pub fn from_opml<'a, I: IntoIterator<Item = &'a str>>(
+	paths: I,
+	categories: Option<Vec<&str>>,
+) -> BoxResult<(Vec<Podcast>, FailedFeeds)> {
+	let mut all = opml::read_all(paths)?;
+
+	if let Some(names) = categories {
+		if let Some(missing) = names
+			.iter()
+			.find(|name| !all.iter().any(|category| category.title == **name))
+		{
+			return Err(Error::UnknownCategory(missing.to_string()).into());
+		}
+		all.retain(|category| names.contains(&category.title.as_str()));
+	}
+
+	fetch_all(
+		all.into_iter()
+			.map(|category| category.feeds.into_iter())
+			.flatten()
+			.map(|feed| (feed.url, feed.title)),
+	)
+}
+
+/// Fetch the feed of every subscription, leaving out those the player keeps without a URL,
+/// along with the feeds that failed
+pub fn from_subscriptions(subs: Vec<Subscription>) -> BoxResult<(Vec<Podcast>, FailedFeeds)> {
+	fetch_all(subs.into_iter().filter_map(|sub| match sub.url {
+		Some(url) => Some((url.to_string(), sub.title)),
+		None => {
+			println!("{}", tr("skipping-no-url", &[("title", &sub.title)]));
+			None
+		}
+	}))
+}
+
+/// A feed URL to print, without its credentials
+fn shown(url: &str) -> String {
+	Url::parse(url).map_or_else(|_| url.to_string(), |url| fetch::redact(&url))
+}
+
+enum Fetched {
+	Podcast(Podcast),
+	Skipped,
+	Failed,
+}
+
+/// Fetch one feed
+fn fetch_one(url: &str, title: &str) -> Fetched {
+	match Podcast::new(url, title) {
+		Ok(podcast) => Fetched::Podcast(podcast),
+		Err(ref err) if skip::is_skipped(err.as_ref()) => {
+			println!(
+				"{}",
+				tr("skipping-feed", &[("title", &title), ("url", &shown(url))])
+			);
+			Fetched::Skipped
+		}
+		Err(err) => {
+			if let Some(podcast) = repair(url, title, fetch::is_gone(err.as_ref())) {
+				return Fetched::Podcast(podcast);
+			}
+			let err = match Url::parse(url) {
+				Ok(url) => fetch::redact_in(&err.to_string(), &url),
+				Err(_) => err.to_string(),
+			};
+			warn(
+				Warning::FeedFailed,
+				format!("Could not fetch '{}' ({}): {}", title, shown(url), err),
+			);
+			Fetched::Failed
+		}
+	}
+}
+
+/// Fetch a feed that failed again from where the Podcast Index has it now, None if it has
+/// nowhere else for it or that fails too
+fn repair(url: &str, title: &str, gone: bool) -> Option<Podcast> {
+	let old_url = Url::parse(url).ok()?;
+	let new_url = podcast_index::current_url(&old_url, title, gone)?;
+	println!(
+		"The Podcast Index has '{}' at {}, fetching it from there",
+		title,
+		fetch::redact(&new_url)
+	);
+
+	let mut podcast = Podcast::new(new_url.as_str(), title).ok()?;
+	podcast.previous_url = Some(old_url);
+	Some(podcast)
+}
+
+/// Fetch each (url, title) feed, up to --jobs at once. Podcasts keep the order of `feeds`,
+/// feeds that were skipped are left out and those that failed are given separately
+fn fetch_all<I: Iterator<Item = (String, String)>>(
+	feeds: I,
+) -> BoxResult<(Vec<Podcast>, FailedFeeds)> {
+	let queue = Arc::new(Mutex::new(
+		feeds.collect::<Vec<_>>().into_iter().enumerate(),
+	));
+	let (tx, rx) = mpsc::channel();
+
+	let workers: Vec<_> = (0..jobs())
+		.map(|_| {
+			let (queue, tx) = (queue.clone(), tx.clone());
+			thread::spawn(move || loop {
+				let next = queue.lock().expect("feed queue lock poisoned").next();
+				let (i, (url, title)) = match next {
+					Some(next) => next,
+					None => break,
+				};
+				let fetched = fetch_one(&url, &title);
+				if tx.send((i, url, title, fetched)).is_err() {
+					break;
+				}
+			})
 		})
-		.flatten()
-		.collect()
+		.collect();
+	drop(tx);
+
+	let mut results: Vec<_> = rx.iter().collect();
+	// A worker that panicked took the feed it was fetching with it, so the podcasts are
+	// incomplete and converting them would write out a history missing that feed
+	let mut panicked = None;
+	for worker in workers {
+		if let Err(payload) = worker.join() {
+			let reason = payload
+				.downcast_ref::<&str>()
+				.map(|reason| reason.to_string())
+				.or_else(|| payload.downcast_ref::<String>().cloned())
+				.unwrap_or_default();
+			panicked.get_or_insert(reason);
+		}
+	}
+	if let Some(reason) = panicked {
+		return Err(Error::FetchPanicked(reason).into());
+	}
+	results.sort_by_key(|(i, _, _, _)| *i);
+
+	let (mut podcasts, mut failed) = (Vec::new(), Vec::new());
+	for (_, url, title, fetched) in results {
+		match fetched {
+			Fetched::Podcast(podcast) => podcasts.push(podcast),
+			Fetched::Skipped => {}
+			Fetched::Failed => failed.push((url, title)),
+		}
+	}
+	Ok((podcasts, failed))
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Podcast {
 	pub url: Url,
+	/// `podcast:guid` of the feed, which stays the same when the feed moves host
+	pub guid: Option<String>,
 	pub title: String,
 	pub tracks: Vec<Track>,
+	pub settings: PodcastSettings,
+	/// Where the feed was before it permanently moved to `url`, players that still know it by
+	/// this URL are given it instead
+	pub previous_url: Option<Url>,
+}
+
+/// The episodes and links on one page of a feed
+pub struct Page {
+	pub tracks: Vec<Track>,
+	/// URL of the next page of a paginated feed, maybe relative
+	pub next: Option<String>,
+	/// `podcast:guid` of the feed
+	pub guid: Option<String>,
+}
+
+/// Per-podcast preferences carried over with --convert-settings, `None` where the source
+/// player has no equivalent so the destination keeps its own value
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PodcastSettings {
+	pub auto_archive: Option<bool>,
+	pub notifications: Option<bool>,
+	/// Minutes between checks of the feed for new episodes
+	pub update_interval: Option<i32>,
+	/// Number of episodes to keep downloaded
+	pub download_count: Option<i32>,
+	/// Name of the playlist or category new episodes go to
+	pub playlist: Option<String>,
+}
+
+impl PodcastSettings {
+	pub fn is_empty(&self) -> bool {
+		*self == Self::default()
+	}
 }
 
 impl Podcast {
 	pub fn new(url: &str, title: &str) -> BoxResult<Self> {
-		let url = Url::parse(url)?;
-		println!("Fetching '{}' ({})", title, url);
+		let mut url = Url::parse(url)?;
+		println!("Fetching '{}' ({})", title, fetch::redact(&url));
 
-		let feed_body = reqwest::get(url.clone())?.text()?;
-		let doc = roxmltree::Document::parse(feed_body.as_str())?;
+		let mut previous_url = None;
+		let mut local = false;
+		let feed_body = match fetch::local_feed(&url, title)? {
+			Some(body) => {
+				local = true;
+				body
+			}
+			None => {
+				if let Some(feed_url) = apple_lookup::resolve(&url)? {
+					println!(
+						"'{}' is an Apple Podcasts page, its feed is {}",
+						title,
+						fetch::redact(&feed_url)
+					);
+					previous_url = Some(std::mem::replace(&mut url, feed_url));
+				}
 
-		let tracks = find_child(doc.root_element(), "channel")? // channel node
-			.children()
-			.filter(|item| item.is_element() && item.tag_name().name() == "item") // all item nodes
-			.filter_map(Podcast::track_subnodes_from_item)
-			.filter_map(Track::from_subnodes)
-			.collect();
+				let _heartbeat = Heartbeat::start(tr(
+					"operation-fetch",
+					&[("title", &title), ("url", &fetch::redact(&url))],
+				));
+				let fetch_url = url.clone();
+				let (body, moved_to) = skip::skippable(move || fetch::feed(fetch_url))?;
+				if let Some(mut moved_to) = moved_to {
+					println!("'{}' has moved to {}", title, fetch::redact(&moved_to));
+					// The credentials were sent as basic auth, the new URL still needs them
+					moved_to.set_username(url.username()).ok();
+					moved_to.set_password(url.password()).ok();
+					// The page URL of an Apple Podcasts link is the one the OPML file has
+					let moved_from = std::mem::replace(&mut url, moved_to);
+					previous_url.get_or_insert(moved_from);
+				}
+				body
+			}
+		};
+		let Page {
+			mut tracks,
+			mut next,
+			guid,
+		} = Podcast::read_page(&feed_body)?;
+
+		// Paginated feeds (RFC 5005) only list their latest episodes on the first page. Pages
+		// are only followed online, a feed read from --feeds-dir is taken as complete
+		let mut page_url = url.clone();
+		let mut seen = vec![page_url.clone()];
+		while let Some(href) = next.take().filter(|_| !local) {
+			let mut next_url = match page_url.join(&href) {
+				Ok(next_url) => next_url,
+				Err(_) => break,
+			};
+			if seen.contains(&next_url) {
+				break;
+			}
+			if seen.len() >= MAX_PAGES.load(Ordering::Relaxed) {
+				warn(
+					Warning::FeedFailed,
+					format!(
+						"Only read the first {} pages of '{}', older episodes are left out, raise --feed-pages to read more",
+						seen.len(),
+						title
+					),
+				);
+				break;
+			}
+			// Later pages on the same host are as private as the first
+			if next_url.host_str() == url.host_str() && next_url.username().is_empty() {
+				next_url.set_username(url.username()).ok();
+				next_url.set_password(url.password()).ok();
+			}
+
+			println!("Fetching page {} of '{}'", seen.len() + 1, title);
+			let fetch_url = next_url.clone();
+			let page = match skip::skippable(move || fetch::feed(fetch_url)) {
+				Ok((body, _)) => Podcast::read_page(&body),
+				Err(err) => Err(err),
+			};
+			match page {
+				Ok(page) => {
+					// Pages can overlap when episodes were published while paging
+					for track in page.tracks.into_iter() {
+						if !tracks.iter().any(|known| known.guid == track.guid) {
+							tracks.push(track);
+						}
+					}
+					next = page.next;
+				}
+				Err(err) if skip::is_skipped(err.as_ref()) => return Err(err),
+				Err(err) => {
+					warn(
+						Warning::FeedFailed,
+						format!(
+							"Only read the first {} pages of '{}', older episodes are left out: {}",
+							seen.len(),
+							title,
+							fetch::redact_in(&err.to_string(), &next_url)
+						),
+					);
+					break;
+				}
+			}
+			seen.push(next_url.clone());
+			page_url = next_url;
+		}
 
 		Ok(Self {
-			url: url,
+			url,
 			title: title.into(),
-			tracks: tracks,
+			tracks,
+			settings: PodcastSettings::default(),
+			previous_url,
+			guid,
 		})
 	}
 
-	fn track_subnodes_from_item<'a>(item: Node<'a, 'a>) -> Option<(Node, Node, Option<Node>)> {
+	/// One page of a feed, RSS or JSON Feed
+	fn read_page(body: &str) -> BoxResult<Page> {
+		if json_feed::is_json_feed(body) {
+			return json_feed::read_page(body);
+		}
+
+		let doc = roxmltree::Document::parse(body)?;
+		let channel = find_child(doc.root_element(), "channel")?;
+
+		let tracks = channel
+			.children()
+			.filter(|item| item.is_element() && item.tag_name().name() == "item") // all item nodes
+			.filter_map(|item| {
+				let mut track = Track::from_subnodes(Podcast::track_subnodes_from_item(item)?)?;
+				track.published = item
+					.children()
+					.find(|n| n.is_element() && n.tag_name().name() == "pubDate")
+					.and_then(|n| n.text())
+					.and_then(clock::parse_rfc2822);
+				track.read_podcast_namespace(item);
+				Some(track)
+			})
+			.collect();
+
+		let next = channel
+			.children()
+			.find(|n| {
+				n.is_element()
+					&& n.tag_name().name() == "link"
+					&& n.tag_name().namespace() == Some(ATOM_NAMESPACE)
+					&& n.attribute("rel") == Some("next")
+			})
+			.and_then(|link| link.attribute("href"))
+			.map(String::from);
+
+		let guid = channel
+			.children()
+			.find(|n| {
+				n.is_element()
+					&& n.tag_name().name() == "guid"
+					&& n.tag_name().namespace() == Some(PODCAST_NAMESPACE)
+			})
+			.and_then(|n| n.text())
+			.map(|text| text.trim().to_lowercase())
+			.filter(|text| !text.is_empty());
+
+		Ok(Page { tracks, next, guid })
+	}
+
+	fn track_subnodes_from_item<'a>(item: Node<'a, 'a>) -> Option<TrackSubnodes<'a>> {
 		let guid = item
 			.children()
 			.find(|n| n.is_element() && n.tag_name().name() == "guid")?;
@@ -79,46 +430,175 @@ impl Podcast {
 					.map_or_else(|| false, |prefix| prefix == "itunes")
 		});
 
-		Some((guid, enclosure, duration))
+		let title = item
+			.children()
+			.find(|n| n.is_element() && n.tag_name().name() == "title");
+
+		// Podlove Simple Chapters, the only chapters a feed can have inline
+		let chapters = item.children().find(|n| {
+			n.is_element()
+				&& n.tag_name().name() == "chapters"
+				&& n.tag_name().namespace() == Some(PSC_NAMESPACE)
+		});
+
+		Some((guid, enclosure, duration, title, chapters))
 	}
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum PlayingStatus {
 	Unplayed,
 	Playing,
 	Played,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Track {
 	pub guid: String,
 	pub url: Url,
+	pub title: String,
 	pub duration: Option<i32>,
 
+	/// Unix time in seconds of the episode's `pubDate`
+	pub published: Option<i64>,
+	/// Start of each chapter in seconds, empty when the feed has none
+	pub chapters: Vec<i32>,
+
+	/// `podcast:season` and `podcast:episode` numbers, the episode's may have a fraction
+	pub season: Option<i32>,
+	pub episode: Option<String>,
+	/// URLs of the episode's `podcast:transcript`s
+	pub transcripts: Vec<Url>,
+	/// Other URLs of the episode's media from `podcast:alternateEnclosure`, such as other
+	/// bitrates or formats, or the same file on another CDN
+	pub alternate_urls: Vec<Url>,
+
 	pub progress: i32,
 	pub playing_status: PlayingStatus,
+	/// Unix time in seconds the episode was last listened to, where the source player keeps it
+	pub last_played: Option<i64>,
 }
 
 impl Track {
-	fn new(guid: String, url: Url, duration: Option<i32>) -> Self {
+	pub fn new(guid: String, url: Url, title: String, duration: Option<i32>) -> Self {
 		Self {
-			guid: guid,
-			url: url,
-			duration: duration,
+			guid,
+			url,
+			title,
+			duration,
+			published: None,
+			chapters: Vec::new(),
+			season: None,
+			episode: None,
+			transcripts: Vec::new(),
+			alternate_urls: Vec::new(),
 			progress: 0,
 			playing_status: PlayingStatus::Unplayed,
+			last_played: None,
 		}
 	}
 
-	fn from_subnodes((guid, url, duration): (Node, Node, Option<Node>)) -> Option<Self> {
-		Some(Self::new(
+	fn from_subnodes((guid, url, duration, title, chapters): TrackSubnodes) -> Option<Self> {
+		let mut track = Self::new(
 			guid.text()?.into(),
 			Url::parse(url.attribute("url")?).ok()?,
+			title.and_then(|n| n.text()).unwrap_or("").into(),
 			duration
 				.and_then(|n| n.text())
 				.and_then(Track::duration_from_str),
-		))
+		);
+
+		if let Some(chapters) = chapters {
+			track.chapters = chapters
+				.children()
+				.filter(|n| n.is_element() && n.tag_name().name() == "chapter")
+				.filter_map(|n| n.attribute("start"))
+				.filter_map(|start| {
+					// Normal play time, the milliseconds after the dot are dropped
+					Track::duration_from_str(start.split('.').next().unwrap_or(start))
+				})
+				.collect();
+		}
+
+		Some(track)
+	}
+
+	/// Read the Podcasting 2.0 tags of the item the track is from
+	fn read_podcast_namespace(&mut self, item: Node) {
+		let tags = item
+			.children()
+			.filter(|n| n.is_element() && n.tag_name().namespace() == Some(PODCAST_NAMESPACE));
+
+		for tag in tags {
+			match tag.tag_name().name() {
+				"season" => self.season = tag.text().and_then(|text| text.trim().parse().ok()),
+				"episode" => {
+					self.episode = tag
+						.text()
+						.map(str::trim)
+						.filter(|text| text.parse::<f64>().is_ok())
+						.map(String::from)
+				}
+				"transcript" => self
+					.transcripts
+					.extend(tag.attribute("url").and_then(|url| Url::parse(url).ok())),
+				"alternateEnclosure" => self.alternate_urls.extend(
+					tag.children()
+						.filter(|n| n.is_element() && n.tag_name().name() == "source")
+						.filter_map(|source| source.attribute("uri"))
+						.filter_map(|uri| Url::parse(uri).ok())
+						.filter(|url| {
+							// Only media that can be downloaded, not torrents or IPFS
+							(url.scheme() == "http" || url.scheme() == "https") && *url != self.url
+						}),
+				),
+				_ => (),
+			}
+		}
+	}
+
+	/// Whether the episode was played or started, so there is something to convert
+	pub fn has_history(&self) -> bool {
+		self.playing_status != PlayingStatus::Unplayed || self.progress > 0
+	}
+
+	/// The enclosure's URL, then those of the alternate enclosures
+	pub fn urls(&self) -> impl Iterator<Item = &Url> {
+		std::iter::once(&self.url).chain(self.alternate_urls.iter())
+	}
+
+	/// Whether `url` is the URL of the enclosure or of an alternate enclosure, by url_key
+	pub fn has_url(&self, url: &str) -> bool {
+		let key = url_key(url);
+		self.urls().any(|own| url_key(own.as_str()) == key)
+	}
+
+	/// Season and episode number as "S2 E5", None when the feed does not number the episode
+	pub fn numbering(&self) -> Option<String> {
+		match (self.season, &self.episode) {
+			(Some(season), Some(episode)) => Some(format!("S{} E{}", season, episode)),
+			(None, Some(episode)) => Some(format!("E{}", episode)),
+			(Some(season), None) => Some(format!("S{}", season)),
+			(None, None) => None,
+		}
+	}
+
+	/// Move an in progress position to the start of the nearest chapter within `tolerance`
+	/// seconds, so positions that drift by a second or two each conversion settle on a boundary
+	pub fn snap_to_chapter(&mut self, tolerance: i32) {
+		if self.playing_status != PlayingStatus::Playing {
+			return;
+		}
+
+		let progress = self.progress;
+		if let Some(start) = self
+			.chapters
+			.iter()
+			.filter(|&&start| start > 0 && (start - progress).abs() <= tolerance)
+			.min_by_key(|&&start| (start - progress).abs())
+		{
+			self.progress = *start;
+		}
 	}
 
 	pub fn duration_from_str(dur_text: &str) -> Option<i32> {
@@ -157,3 +637,53 @@ impl Track {
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn track_at(progress: i32, status: PlayingStatus) -> Track {
+		let mut track = Track::new(
+			String::from("guid"),
+			Url::parse("https://example.com/episode.mp3").unwrap(),
+			String::from("Episode"),
+			Some(3600),
+		);
+		track.chapters = vec![0, 300, 600, 605];
+		track.progress = progress;
+		track.playing_status = status;
+		track
+	}
+
+	#[test]
+	fn snaps_to_the_nearest_chapter_within_tolerance() {
+		let mut track = track_at(298, PlayingStatus::Playing);
+		track.snap_to_chapter(2);
+		assert_eq!(track.progress, 300);
+
+		let mut track = track_at(603, PlayingStatus::Playing);
+		track.snap_to_chapter(5);
+		assert_eq!(track.progress, 605);
+	}
+
+	#[test]
+	fn keeps_positions_away_from_chapters() {
+		let mut track = track_at(310, PlayingStatus::Playing);
+		track.snap_to_chapter(2);
+		assert_eq!(track.progress, 310);
+	}
+
+	#[test]
+	fn never_snaps_to_the_start() {
+		let mut track = track_at(1, PlayingStatus::Playing);
+		track.snap_to_chapter(2);
+		assert_eq!(track.progress, 1);
+	}
+
+	#[test]
+	fn only_snaps_episodes_in_progress() {
+		let mut track = track_at(599, PlayingStatus::Played);
+		track.snap_to_chapter(2);
+		assert_eq!(track.progress, 599);
+	}
+}
@@ -0,0 +1,136 @@
+use crate::fetch;
+use crate::{BoxResult, Error};
+
+use std::sync::RwLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use lazy_static::lazy_static;
+use reqwest::Url;
+use serde::Deserialize;
+
+// With a Podcast Index (https://podcastindex.org) API key, a feed that can not
+// be fetched is looked up there before it is given up on: first by its URL, as
+// the index follows feeds that moved, then, only when the server said the feed
+// is gone (404 or 410), by its title if exactly one live show has it. Another
+// show of the same name is too easily taken for it after a passing failure. A
+// show the index lists at another URL, and does not mark as dead, is fetched
+// from there instead, keeping the old URL as its previous one for the players that still
+// know it by that. Only the feed URL without its credentials and the title are
+// sent to the index.
+
+const API: &str = "https://api.podcastindex.org/api/1.0/";
+
+lazy_static! {
+	/// Set by --podcast-index-key and --podcast-index-secret
+	static ref CREDENTIALS: RwLock<Option<(String, String)>> = RwLock::new(None);
+}
+
+/// Look failed feeds up with this API key, the secret defaults to the PODCAST_INDEX_SECRET
+/// environment variable
+pub fn set_credentials(key: &str, secret: Option<&str>) -> BoxResult<()> {
+	let secret = match secret {
+		Some(secret) => secret.into(),
+		None => std::env::var("PODCAST_INDEX_SECRET")
+			.map_err(|_| Error::MissingCredentials("the Podcast Index"))?,
+	};
+	*CREDENTIALS
+		.write()
+		.expect("podcast index credentials lock poisoned") = Some((key.into(), secret));
+	Ok(())
+}
+
+#[derive(Deserialize)]
+struct IndexFeed {
+	url: String,
+	#[serde(default)]
+	title: String,
+	/// 1 once the index has given up on the feed
+	#[serde(default)]
+	dead: i32,
+}
+
+#[derive(Deserialize)]
+struct ByUrl {
+	/// An empty array rather than null when the feed is not known
+	feed: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct Search {
+	#[serde(default)]
+	feeds: Vec<IndexFeed>,
+}
+
+fn get<T: serde::de::DeserializeOwned>(
+	credentials: &(String, String),
+	endpoint: &str,
+	query: &[(&str, &str)],
+) -> BoxResult<T> {
+	let (key, secret) = credentials;
+	let date = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |since| since.as_secs())
+		.to_string();
+	let authorization = sha1::Sha1::from(format!("{}{}{}", key, secret, date)).digest();
+
+	let url = Url::parse_with_params(&(String::from(API) + endpoint), query)?;
+	Ok(fetch::client()?
+		.get(url)
+		.header("X-Auth-Key", key.as_str())
+		.header("X-Auth-Date", date.as_str())
+		.header("Authorization", authorization.to_string())
+		.send()?
+		.error_for_status()?
+		.json()?)
+}
+
+fn lookup(
+	credentials: &(String, String),
+	url: &Url,
+	title: &str,
+	gone: bool,
+) -> BoxResult<Option<Url>> {
+	let (url, _) = fetch::split_credentials(url);
+	let live =
+		|feed: &IndexFeed| feed.dead == 0 && Url::parse(&feed.url).map_or(false, |new| new != url);
+
+	let by_url: ByUrl = get(credentials, "podcasts/byfeedurl", &[("url", url.as_str())])?;
+	if let Ok(feed) = serde_json::from_value::<IndexFeed>(by_url.feed) {
+		if live(&feed) {
+			return Ok(Some(Url::parse(&feed.url)?));
+		}
+	}
+
+	if !gone {
+		return Ok(None);
+	}
+
+	let search: Search = get(credentials, "search/bytitle", &[("q", title)])?;
+	let mut found = search.feeds.into_iter().filter(|feed| {
+		feed.title.trim().to_lowercase() == title.trim().to_lowercase() && live(feed)
+	});
+	match (found.next(), found.next()) {
+		(Some(feed), None) => Ok(Some(Url::parse(&feed.url)?)),
+		_ => Ok(None),
+	}
+}
+
+/// Where the Podcast Index has the feed now, None if it has no other URL for it or no API key
+/// was given. Its title is only searched for when the feed is `gone`
+pub fn current_url(url: &Url, title: &str, gone: bool) -> Option<Url> {
+	let credentials = CREDENTIALS
+		.read()
+		.expect("podcast index credentials lock poisoned")
+		.clone()?;
+
+	match lookup(&credentials, url, title, gone) {
+		Ok(found) => found,
+		Err(err) => {
+			println!(
+				"Could not look '{}' up in the Podcast Index: {}",
+				title, err
+			);
+			None
+		}
+	}
+}
@@ -0,0 +1,42 @@
+use crate::BoxResult;
+
+use serde::Serialize;
+
+/// Name of the provenance entry added to archive based backups
+pub const ARCHIVE_ENTRY: &str = "podcast_history_converter.json";
+
+/// Record of the conversion that produced an output file
+#[derive(Debug, Serialize)]
+pub struct Provenance {
+	pub tool: &'static str,
+	pub version: &'static str,
+	pub source_player: &'static str,
+	pub timestamp: i64,
+	pub podcasts: usize,
+	pub tracks: usize,
+}
+
+impl Provenance {
+	pub fn new(
+		source_player: &'static str,
+		timestamp: i64,
+		podcasts: usize,
+		tracks: usize,
+	) -> Self {
+		Self {
+			tool: env!("CARGO_PKG_NAME"),
+			version: env!("CARGO_PKG_VERSION"),
+			source_player,
+			timestamp,
+			podcasts,
+			tracks,
+		}
+	}
+
+	/// Write the record next to an output that has no room for it
+	pub fn write_sidecar(&self, output_path: &str) -> BoxResult<()> {
+		let file = std::fs::File::create(String::from(output_path) + ".provenance.json")?;
+		serde_json::to_writer_pretty(file, self)?;
+		Ok(())
+	}
+}
@@ -0,0 +1,90 @@
+use crate::i18n::tr;
+use crate::player::SaveOptions;
+use crate::podcast::Podcast;
+use crate::url_key::url_key;
+use crate::{temp_file_beside, BoxResult, PlayerArgs};
+
+use std::fs::File;
+
+use serde::{Deserialize, Serialize};
+
+// When saving to a player fails part way, the podcasts it got through are
+// saved again on their own, from the same save file, to OUTPUT.partial. A
+// resume token next to it records which podcasts those were, and running the
+// same command again with --resume TOKEN saves only the others, on top of the
+// partial output, to OUTPUT. Players that take in every podcast before writing
+// any may have failed on one counted as done, saving the partial output then
+// fails too and no token is written.
+
+#[derive(Serialize, Deserialize)]
+pub struct ResumeToken {
+	/// Command line name of the player being saved to
+	pub player: String,
+	pub output: String,
+	pub partial: String,
+	/// url_key of every podcast already in the partial output
+	completed: Vec<String>,
+}
+
+impl ResumeToken {
+	pub fn load(path: &str) -> BoxResult<Self> {
+		Ok(serde_json::from_reader(File::open(path)?)?)
+	}
+
+	/// By either URL of a moved feed, it is saved under the one the player knows
+	pub fn is_completed(&self, podcast: &Podcast) -> bool {
+		podcast
+			.previous_url
+			.iter()
+			.chain(Some(&podcast.url))
+			.map(|url| url_key(url.as_str()))
+			.any(|key| self.completed.contains(&key))
+	}
+}
+
+/// Save the podcasts the failed save got through to OUTPUT.partial and write a token to resume
+/// from, returning the token's path. `source` is the save file the failed save started from
+pub fn save_partial(
+	player_args: &PlayerArgs,
+	source: &str,
+	output: &str,
+	completed: &[Podcast],
+	previous: Option<&ResumeToken>,
+	options: &SaveOptions,
+) -> BoxResult<String> {
+	let partial = format!("{}.partial", output);
+
+	// A resumed save starts from the partial output, which is only replaced once written
+	let mut partial_file = temp_file_beside(output)?;
+	let player = (player_args.factory)(source)?;
+	player.save(&mut completed.iter(), partial_file.as_file_mut(), options)?;
+	partial_file.as_file().sync_all()?;
+	partial_file.persist(&partial)?;
+
+	let mut token = ResumeToken {
+		player: player_args.cli_name.into(),
+		output: output.into(),
+		partial,
+		completed: previous.map_or(Vec::new(), |previous| previous.completed.clone()),
+	};
+	token.completed.extend(
+		completed
+			.iter()
+			.map(|podcast| url_key(podcast.url.as_str())),
+	);
+
+	let token_path = format!("{}.resume.json", token.partial);
+	serde_json::to_writer_pretty(File::create(&token_path)?, &token)?;
+	println!(
+		"{}",
+		tr(
+			"partial-saved",
+			&[
+				("count", &token.completed.len()),
+				("path", &token.partial),
+				("token", &token_path)
+			]
+		)
+	);
+	Ok(token_path)
+}
@@ -0,0 +1,225 @@
+use crate::clock::Clock;
+use crate::i18n::tr;
+use crate::player::{Player, SaveOptions};
+use crate::podcast::{self, PlayingStatus, Podcast};
+use crate::provenance::Provenance;
+use crate::{output, populate, BoxResult, Error, PlayerArgs};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use tempfile::NamedTempFile;
+
+// Converting A to B and back to A should leave A's history as it was. Each
+// step writes to a temporary file and reads it back, so the files given are
+// never changed. A track that comes back with another status, or with a
+// position further off than the tolerance, is reported, and any such track
+// fails the check. tests/round_trip.rs runs it over every pair of players
+// that can be written to a file.
+
+pub fn subcommand<'a>(players_args: &'a [PlayerArgs]) -> App<'a, 'a> {
+	// Server players would be changed by the check, so only files take part
+	let names: Vec<&str> = players_args
+		.iter()
+		.filter(|player_args| !player_args.read_only && player_args.value_name == "FILE")
+		.map(|player_args| player_args.cli_name)
+		.collect();
+
+	SubCommand::with_name("check-round-trip")
+		.about(
+			"Convert from a player to others and back, and check its history comes back unchanged",
+		)
+		.arg(
+			Arg::with_name("opml")
+				.long("opml")
+				.takes_value(true)
+				.value_name("FILE")
+				.multiple(true)
+				.number_of_values(1)
+				.help("OPML file (or directory of OPML files) containing the feeds to convert")
+				.required(true),
+		)
+		.arg(
+			Arg::with_name("from")
+				.long("from")
+				.takes_value(true)
+				.value_name("PLAYER")
+				.possible_values(&names)
+				.help("Player whose history is converted and read back")
+				.required(true),
+		)
+		.arg(
+			Arg::with_name("via")
+				.long("via")
+				.takes_value(true)
+				.value_name("PLAYER")
+				.multiple(true)
+				.number_of_values(1)
+				.possible_values(&names)
+				.help("Player to convert through, can be given more than once")
+				.required(true),
+		)
+		.arg(
+			Arg::with_name("tolerance")
+				.long("tolerance")
+				.takes_value(true)
+				.value_name("SECS")
+				.default_value("2")
+				.help("How far a position may move and still count as unchanged"),
+		)
+		.args(
+			&players_args
+				.iter()
+				.map(|player_args| player_args.get_player())
+				.collect::<Vec<Arg>>(),
+		)
+}
+
+/// Podcasts as they come from the feeds, before any player filled them in
+fn unpopulated(podcasts: &[Podcast]) -> Vec<Podcast> {
+	podcasts
+		.iter()
+		.map(|podcast| {
+			let mut podcast = podcast.clone();
+			for track in podcast.tracks.iter_mut() {
+				track.progress = 0;
+				track.playing_status = PlayingStatus::Unplayed;
+				track.last_played = None;
+			}
+			podcast
+		})
+		.collect()
+}
+
+/// Write `podcasts` to a copy of `player`'s save file and read them back from it
+fn through(
+	player_args: &PlayerArgs,
+	path: &str,
+	podcasts: &[Podcast],
+	options: &SaveOptions,
+) -> BoxResult<(NamedTempFile, Vec<Podcast>)> {
+	let mut out_file = NamedTempFile::new()?;
+	let player: Box<dyn Player> = (player_args.factory)(path)?;
+	player.save(&mut podcasts.iter(), out_file.as_file_mut(), options)?;
+
+	let out_path = out_file.path().to_string_lossy().into_owned();
+	let mut player = (player_args.factory)(&out_path)?;
+	let (read_back, _skipped) = populate(&mut player, unpopulated(podcasts), false);
+	Ok((out_file, read_back))
+}
+
+/// Number of tracks of `before` that came back different in `after`
+fn compare(before: &[Podcast], after: &[Podcast], tolerance: i32) -> usize {
+	let mut changed = 0;
+
+	for podcast in before.iter() {
+		let after_podcast = after.iter().find(|after| after.url == podcast.url);
+
+		for track in podcast.tracks.iter() {
+			let after_track = after_podcast
+				.and_then(|after| after.tracks.iter().find(|after| after.guid == track.guid));
+
+			let problem = match after_track {
+				None => Some(String::from("missing")),
+				Some(after) if after.playing_status != track.playing_status => Some(format!(
+					"{:?} became {:?}",
+					track.playing_status, after.playing_status
+				)),
+				Some(after)
+					if track.playing_status == PlayingStatus::Playing
+						&& (after.progress - track.progress).abs() > tolerance =>
+				{
+					Some(format!(
+						"position {}s became {}s",
+						track.progress, after.progress
+					))
+				}
+				Some(_) => None,
+			};
+
+			if let Some(problem) = problem {
+				changed += 1;
+				if output::plain() {
+					println!("Changed {}: {}", track.url, problem);
+				} else {
+					println!("\t{}: {}", track.url, problem);
+				}
+			}
+		}
+	}
+
+	changed
+}
+
+pub fn run(matches: &ArgMatches, players_args: &[PlayerArgs]) -> BoxResult<()> {
+	let find = |cli_name: &str| {
+		players_args
+			.iter()
+			.find(|player_args| player_args.cli_name == cli_name)
+			.expect("player not found in args list")
+	};
+	let path_of = |player_args: &PlayerArgs| -> String {
+		match matches.value_of(player_args.cli_name) {
+			Some(path) => String::from(path),
+			None => clap::Error::with_description(
+				tr(
+					"error-round-trip-file",
+					&[("player", &player_args.cli_name)],
+				)
+				.as_str(),
+				clap::ErrorKind::MissingRequiredArgument,
+			)
+			.exit(),
+		}
+	};
+
+	let from = find(matches.value_of("from").expect("no from player"));
+	let from_path = path_of(from);
+	let tolerance: i32 = matches
+		.value_of("tolerance")
+		.expect("tolerance has a default")
+		.parse()?;
+
+	let (feeds, _) = podcast::from_opml(matches.values_of("opml").expect("no opml file"), None)?;
+	let (original, _skipped) = populate(&mut (from.factory)(&from_path)?, feeds, false);
+
+	let clock = Clock::system()?;
+	let options = SaveOptions {
+		clock,
+		deterministic: false,
+		provenance: Provenance::new(
+			from.cli_name,
+			clock.unix_secs(),
+			original.len(),
+			original.iter().map(|pod| pod.tracks.len()).sum(),
+		),
+	};
+
+	let mut failed = Vec::new();
+	for via in matches.values_of("via").expect("no via player").map(find) {
+		println!(
+			"Round trip {} -> {} -> {}",
+			from.cli_name, via.cli_name, from.cli_name
+		);
+
+		let via_path = path_of(via);
+		let (_via_file, there) = through(via, &via_path, &original, &options)?;
+		let (_from_file, back) = through(from, &from_path, &there, &options)?;
+
+		let changed = compare(&original, &back, tolerance);
+		if changed > 0 {
+			println!("{} tracks changed going through {}", changed, via.name);
+			failed.push(via.cli_name);
+		} else {
+			println!("Unchanged going through {}", via.name);
+		}
+	}
+
+	if failed.is_empty() {
+		Ok(())
+	} else {
+		Err(Error::VerificationFailed(
+			from.name,
+			String::from("round trip through ") + &failed.join(", "),
+		)
+		.into())
+	}
+}
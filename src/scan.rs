@@ -0,0 +1,133 @@
+use crate::detect;
+use crate::wizard::{find_files, shell_quote, OPML_FILE_NAMES};
+use crate::{BoxResult, PlayerArgs};
+
+use std::path::{Path, PathBuf};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+	SubCommand::with_name("scan")
+		.about("Find every player backup in a directory tree and suggest conversion commands")
+		.arg(
+			Arg::with_name("dir")
+				.takes_value(true)
+				.value_name("DIR")
+				.required(true)
+				.help("Directory to search, e.g. a copy of a phone's storage"),
+		)
+}
+
+/// Every file below `dir`, without following symlinks
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> BoxResult<()> {
+	for entry in std::fs::read_dir(dir)? {
+		let entry = entry?;
+		let file_type = entry.file_type()?;
+		if file_type.is_dir() {
+			// An unreadable subdirectory should not stop the rest of the scan
+			if let Err(err) = walk(&entry.path(), files) {
+				println!("Skipping '{}': {}", entry.path().display(), err);
+			}
+		} else if file_type.is_file() {
+			files.push(entry.path());
+		}
+	}
+	Ok(())
+}
+
+struct Backup<'a> {
+	path: String,
+	player_args: &'a PlayerArgs,
+}
+
+fn count(count: Option<usize>) -> String {
+	count.map_or(String::from("unknown"), |count| count.to_string())
+}
+
+pub fn run(matches: &ArgMatches, players_args: &[PlayerArgs]) -> BoxResult<()> {
+	let dir = Path::new(matches.value_of("dir").expect("no directory"));
+	let mut files = Vec::new();
+	walk(dir, &mut files)?;
+	files.sort();
+
+	let mut backups = Vec::new();
+	for file in files.iter() {
+		let path = file.to_string_lossy().into_owned();
+		// Files that can not be read or parsed are just not backups
+		let cli_name = match detect::sniff(&path) {
+			Ok(Some(cli_name)) => cli_name,
+			_ => continue,
+		};
+		let player_args = match players_args.iter().find(|p| p.cli_name == cli_name) {
+			Some(player_args) => player_args,
+			None => continue,
+		};
+
+		let player = match (player_args.factory)(&path) {
+			Ok(player) => player,
+			Err(err) => {
+				println!(
+					"{}: looks like {} but could not be read: {}",
+					path, player_args.name, err
+				);
+				continue;
+			}
+		};
+
+		let version = player
+			.schema_version()
+			.map_or(String::new(), |version| format!(" ({})", version));
+		let subscriptions = player.subscriptions().ok().map(|subs| subs.len());
+		let episodes = player.episode_count().unwrap_or(None);
+		println!(
+			"{}: {}{}, {} subscriptions, {} episodes",
+			path,
+			player_args.name,
+			version,
+			count(subscriptions),
+			count(episodes)
+		);
+
+		backups.push(Backup { path, player_args });
+	}
+
+	if backups.is_empty() {
+		println!("No player backups found in '{}'", dir.display());
+		return Ok(());
+	}
+
+	let opml = find_files(&files, &OPML_FILE_NAMES)
+		.into_iter()
+		.next()
+		.unwrap_or_else(|| String::from("podcasts_opml.xml"));
+
+	println!("Possible conversions:");
+	for from in backups.iter() {
+		for to in backups.iter() {
+			if to.player_args.read_only || to.player_args.cli_name == from.player_args.cli_name {
+				continue;
+			}
+			let args = [
+				String::from("--opml"),
+				opml.clone(),
+				format!("--{}", from.player_args.cli_name),
+				from.path.clone(),
+				String::from("--") + from.player_args.in_name.as_str(),
+				format!("--{}", to.player_args.cli_name),
+				to.path.clone(),
+				String::from("--") + to.player_args.out_name.as_str(),
+				to.path.clone() + "_new",
+			];
+			println!(
+				"{} {}",
+				env!("CARGO_PKG_NAME"),
+				args.iter()
+					.map(|arg| shell_quote(arg))
+					.collect::<Vec<String>>()
+					.join(" ")
+			);
+		}
+	}
+
+	Ok(())
+}
@@ -0,0 +1,88 @@
+use crate::i18n::tr;
+use crate::podcast;
+use crate::{BoxResult, Error};
+
+use std::error;
+use std::io::{self, BufRead};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Once};
+use std::thread;
+use std::time::Duration;
+
+// On a terminal, typing `s` and Enter gives up on the feeds being fetched, so
+// one very slow host does not hold up the whole conversion. Each fetch goes on
+// in a thread of its own whose result is thrown away, and the feed is left out
+// like one that failed to download. With --jobs above 1 every feed being
+// fetched at that moment is skipped. Without a terminal nothing reads stdin
+// and fetches run as before.
+
+static LISTEN: Once = Once::new();
+static LISTENING: AtomicBool = AtomicBool::new(false);
+/// Number of skip requests typed so far
+static SKIPS: AtomicUsize = AtomicUsize::new(0);
+
+/// Start reading skip requests from stdin when it is a terminal
+pub fn listen() {
+	if !atty::is(atty::Stream::Stdin) {
+		return;
+	}
+
+	LISTEN.call_once(|| {
+		LISTENING.store(true, Ordering::Relaxed);
+		// Every fetch in progress takes the same request, say so when that is more than one
+		let jobs = podcast::jobs();
+		if jobs > 1 {
+			println!("{}", tr("skip-hint-jobs", &[("jobs", &jobs)]));
+		} else {
+			println!("{}", tr("skip-hint", &[]));
+		}
+
+		thread::spawn(|| {
+			for line in io::stdin().lock().lines() {
+				match line {
+					Ok(ref line) if line.trim().eq_ignore_ascii_case("s") => {
+						SKIPS.fetch_add(1, Ordering::Relaxed);
+					}
+					Ok(_) => (),
+					Err(_) => break,
+				}
+			}
+		});
+	});
+}
+
+/// Whether `err` is from a fetch the user skipped
+pub fn is_skipped(err: &(dyn error::Error + 'static)) -> bool {
+	matches!(err.downcast_ref::<Error>(), Some(Error::Skipped))
+}
+
+/// Run `f`, giving up with `Error::Skipped` if the user asks to skip while it runs
+pub fn skippable<T, E, F>(f: F) -> BoxResult<T>
+where
+	T: Send + 'static,
+	E: Into<Box<dyn error::Error>> + Send + 'static,
+	F: FnOnce() -> Result<T, E> + Send + 'static,
+{
+	if !LISTENING.load(Ordering::Relaxed) {
+		return f().map_err(|err| err.into());
+	}
+
+	// Only a request typed after this started is for it
+	let skips = SKIPS.load(Ordering::Relaxed);
+
+	let (tx, rx) = mpsc::channel();
+	thread::spawn(move || {
+		let _ = tx.send(f());
+	});
+
+	loop {
+		match rx.recv_timeout(Duration::from_millis(100)) {
+			Ok(result) => return result.map_err(|err| err.into()),
+			Err(mpsc::RecvTimeoutError::Timeout) if SKIPS.load(Ordering::Relaxed) != skips => {
+				return Err(Error::Skipped.into())
+			}
+			Err(mpsc::RecvTimeoutError::Timeout) => (),
+			Err(mpsc::RecvTimeoutError::Disconnected) => return Err("fetch thread panicked".into()),
+		}
+	}
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+// The feed cache goes through a `Storage` of named values rather than straight
+// to files. A location given on the command line opens a directory, with a
+// file for each value, or with `:memory:` a store that lasts only for the run,
+// for read-only machines and scheduled syncs that should leave nothing behind.
+// Another backend, such as a single database file, only has to implement the
+// trait and be picked out in `open`. Everything else the tool writes, such as
+// resume tokens, sidecar files and exports, still goes straight to files.
+
+/// Location that keeps values in memory for the run instead of on disk
+pub const MEMORY: &str = ":memory:";
+
+/// Values stored by key. Keys are plain file names: letters, digits, dots, dashes and underscores
+pub trait Storage: Send + Sync {
+	/// The value stored under `key`, None if there is none
+	fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>>;
+
+	/// Store `value` under `key`, replacing what was there
+	fn put(&self, key: &str, value: &[u8]) -> io::Result<()>;
+}
+
+/// A file for each key in a directory
+pub struct FileStorage {
+	dir: PathBuf,
+}
+
+impl FileStorage {
+	/// Store in `dir`, creating it if needed
+	pub fn new(dir: &str) -> io::Result<Self> {
+		fs::create_dir_all(dir)?;
+		Ok(Self {
+			dir: PathBuf::from(dir),
+		})
+	}
+}
+
+impl Storage for FileStorage {
+	fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+		match fs::read(self.dir.join(key)) {
+			Ok(value) => Ok(Some(value)),
+			Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+			Err(err) => Err(err),
+		}
+	}
+
+	fn put(&self, key: &str, value: &[u8]) -> io::Result<()> {
+		fs::write(self.dir.join(key), value)
+	}
+}
+
+/// Values kept in memory until the run ends
+#[derive(Default)]
+pub struct MemoryStorage {
+	values: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl Storage for MemoryStorage {
+	fn get(&self, key: &str) -> io::Result<Option<Vec<u8>>> {
+		Ok(self
+			.values
+			.lock()
+			.expect("memory storage lock poisoned")
+			.get(key)
+			.cloned())
+	}
+
+	fn put(&self, key: &str, value: &[u8]) -> io::Result<()> {
+		self.values
+			.lock()
+			.expect("memory storage lock poisoned")
+			.insert(key.into(), value.into());
+		Ok(())
+	}
+}
+
+/// The storage a location given on the command line names
+pub fn open(location: &str) -> io::Result<Arc<dyn Storage>> {
+	if location == MEMORY {
+		Ok(Arc::new(MemoryStorage::default()))
+	} else {
+		Ok(Arc::new(FileStorage::new(location)?))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn stores_values(storage: &dyn Storage) {
+		assert_eq!(storage.get("feed.xml").unwrap(), None);
+		storage.put("feed.xml", b"first").unwrap();
+		assert_eq!(storage.get("feed.xml").unwrap(), Some(b"first".to_vec()));
+		storage.put("feed.xml", b"second").unwrap();
+		storage.put("other.json", b"other").unwrap();
+		assert_eq!(storage.get("feed.xml").unwrap(), Some(b"second".to_vec()));
+		assert_eq!(storage.get("other.json").unwrap(), Some(b"other".to_vec()));
+	}
+
+	#[test]
+	fn memory_storage_keeps_the_latest_value() {
+		stores_values(open(MEMORY).unwrap().as_ref());
+	}
+
+	#[test]
+	fn file_storage_keeps_the_latest_value() {
+		let dir = tempfile::tempdir().unwrap();
+		let location = dir.path().join("cache");
+		stores_values(open(location.to_str().unwrap()).unwrap().as_ref());
+
+		// A later run opening the directory again finds the values
+		let reopened = open(location.to_str().unwrap()).unwrap();
+		assert_eq!(reopened.get("other.json").unwrap(), Some(b"other".to_vec()));
+		assert!(location.join("feed.xml").is_file());
+	}
+}
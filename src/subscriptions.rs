@@ -0,0 +1,133 @@
+use crate::player::{Player, Subscription};
+use crate::url_key::url_key;
+use crate::{get_players, opml, output, BoxResult, PlayerArgs};
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use reqwest::Url;
+
+pub fn subcommand<'a>(players_args: &'a [PlayerArgs]) -> App<'a, 'a> {
+	SubCommand::with_name("compare-subscriptions")
+		.about("Compare the feeds in an OPML file with the subscriptions of each given player")
+		.arg(
+			Arg::with_name("opml")
+				.long("opml")
+				.takes_value(true)
+				.value_name("FILE")
+				.multiple(true)
+				.number_of_values(1)
+				.help("OPML file (or directory of OPML files) containing the feeds to compare")
+				.required(true),
+		)
+		.args(
+			&players_args
+				.iter()
+				.map(|player_args| player_args.get_player())
+				.collect::<Vec<Arg>>(),
+		)
+}
+
+fn same_feed(a: &Subscription, b: &Subscription) -> bool {
+	match (&a.url, &b.url) {
+		(Some(a_url), Some(b_url)) => url_key(a_url.as_str()) == url_key(b_url.as_str()),
+		_ => a.title.to_lowercase() == b.title.to_lowercase(),
+	}
+}
+
+fn describe(sub: &Subscription) -> String {
+	match &sub.url {
+		Some(url) => format!("'{}' ({})", sub.title, url),
+		None => format!("'{}'", sub.title),
+	}
+}
+
+pub fn contains(subs: &[Subscription], sub: &Subscription) -> bool {
+	subs.iter().any(|s| same_feed(s, sub))
+}
+
+/// Subscribe and unsubscribe `player` so its subscriptions match `wanted`
+pub fn apply_diff(player: &mut dyn Player, wanted: &[Subscription]) -> BoxResult<()> {
+	let current = player.subscriptions()?;
+
+	for sub in wanted.iter().filter(|sub| !contains(&current, sub)) {
+		if player.subscribe(sub)? {
+			println!("Subscribed to '{}'", sub.title);
+		} else {
+			println!(
+				"Unable to subscribe to '{}', subscribe to it in the app first",
+				sub.title
+			);
+		}
+	}
+
+	for sub in current.iter().filter(|sub| !contains(wanted, sub)) {
+		if player.unsubscribe(sub)? {
+			println!("Unsubscribed from '{}'", sub.title);
+		} else {
+			println!(
+				"Unable to unsubscribe from '{}' without losing its history, unsubscribe from it in the app",
+				sub.title
+			);
+		}
+	}
+
+	Ok(())
+}
+
+pub fn run(matches: &ArgMatches, players_args: &[PlayerArgs]) -> BoxResult<()> {
+	let feeds: Vec<Subscription> =
+		opml::read_all(matches.values_of("opml").expect("no opml file"))?
+			.into_iter()
+			.map(|category| category.feeds.into_iter())
+			.flatten()
+			.map(|feed| Subscription {
+				url: Url::parse(feed.url.as_str()).ok(),
+				title: feed.title,
+			})
+			.collect();
+
+	let players = get_players(matches, players_args)?;
+
+	for player_args in players_args.iter() {
+		let player = match players.get(player_args.cli_name) {
+			Some(player) => player,
+			None => continue,
+		};
+
+		let subs = player.subscriptions()?;
+
+		let missing = feeds.iter().filter(|feed| !contains(&subs, feed));
+		let extra = subs.iter().filter(|sub| !contains(&feeds, sub));
+
+		if output::plain() {
+			for feed in missing {
+				println!(
+					"Not subscribed in '{}': {}",
+					player_args.cli_name,
+					describe(feed)
+				);
+			}
+			for sub in extra {
+				println!("Not in the OPML: {}", describe(sub));
+			}
+			continue;
+		}
+
+		println!(
+			"In the OPML but not subscribed in '{}':",
+			player_args.cli_name
+		);
+		for feed in missing {
+			println!("\t+ {}", describe(feed));
+		}
+
+		println!(
+			"Subscribed in '{}' but not in the OPML:",
+			player_args.cli_name
+		);
+		for sub in extra {
+			println!("\t- {}", describe(sub));
+		}
+	}
+
+	Ok(())
+}
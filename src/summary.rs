@@ -0,0 +1,188 @@
+use crate::clock::Clock;
+use crate::i18n::tr;
+use crate::warning;
+use crate::BoxResult;
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, RwLock};
+
+use lazy_static::lazy_static;
+
+// With --email-summary the outcome of the run is mailed once it ends, whether
+// it succeeded or not, for scheduled syncs on headless machines where nobody
+// reads the output. The mail goes through the SMTP relay given with
+// --smtp-relay, which has to take mail without a login or TLS (such as a NAS's
+// own relay or a local mail server), and otherwise through `sendmail`. A mail
+// that can not be sent is reported and does not change the outcome of the run.
+
+const DEFAULT_FROM: &str = "podcast_history_converter@localhost";
+const SMTP_PORT: u16 = 25;
+
+struct Config {
+	to: String,
+	from: String,
+	relay: Option<String>,
+}
+
+lazy_static! {
+	/// Set by --email-summary, --email-from and --smtp-relay
+	static ref CONFIG: RwLock<Option<Config>> = RwLock::new(None);
+	/// What the run did, in order
+	static ref LINES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+}
+
+pub fn configure(to: &str, from: Option<&str>, relay: Option<&str>) {
+	*CONFIG.write().expect("summary config lock poisoned") = Some(Config {
+		to: to.into(),
+		from: from.unwrap_or(DEFAULT_FROM).into(),
+		relay: relay.map(String::from),
+	});
+}
+
+/// Add a line to the summary
+pub fn note(line: String) {
+	LINES.lock().expect("summary lock poisoned").push(line);
+}
+
+fn message(config: &Config, result: &BoxResult<()>) -> String {
+	let mut body: Vec<String> = LINES.lock().expect("summary lock poisoned").clone();
+	for (name, count) in warning::counts() {
+		if count > 0 {
+			body.push(tr(
+				"summary-warnings",
+				&[("count", &count), ("name", &name)],
+			));
+		}
+	}
+	let subject = match result {
+		Ok(()) => tr("summary-subject-succeeded", &[]),
+		Err(err) => {
+			body.push(tr("summary-error", &[("error", err)]));
+			tr("summary-subject-failed", &[])
+		}
+	};
+
+	// The mail is sent when the run ends, whatever clock the outputs were written with
+	let now = Clock::system().unwrap_or_else(|_| Clock::fixed(0));
+	let domain = match config.from.rfind('@') {
+		Some(at) => config.from[at + 1..].trim_end_matches('>'),
+		None => "localhost",
+	};
+	format!(
+		"From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMessage-ID: <{}.{}@{}>\r\nContent-Type: text/plain; charset=utf-8\r\n\r\n{}\r\n",
+		config.from,
+		config.to,
+		subject,
+		now.rfc2822(),
+		now.unix_millis(),
+		std::process::id(),
+		domain,
+		body.join("\r\n")
+	)
+}
+
+/// Read one SMTP reply, which may span several lines, and check its code
+fn expect_reply<R: BufRead>(reader: &mut R, code: &str) -> io::Result<()> {
+	loop {
+		let mut line = String::new();
+		if reader.read_line(&mut line)? == 0 {
+			return Err(io::Error::new(
+				io::ErrorKind::UnexpectedEof,
+				"the relay closed the connection",
+			));
+		}
+		if !line.starts_with(code) {
+			return Err(io::Error::new(
+				io::ErrorKind::Other,
+				format!("the relay answered {}", line.trim_end()),
+			));
+		}
+		// The last line of a reply has a space after the code, the others a dash
+		if line.as_bytes().get(3) != Some(&b'-') {
+			return Ok(());
+		}
+	}
+}
+
+fn send_smtp(relay: &str, config: &Config, message: &str) -> io::Result<()> {
+	let stream = if relay.contains(':') {
+		TcpStream::connect(relay)?
+	} else {
+		TcpStream::connect((relay, SMTP_PORT))?
+	};
+	let mut reader = BufReader::new(stream.try_clone()?);
+	let mut writer = stream;
+
+	expect_reply(&mut reader, "220")?;
+	for (command, code) in [
+		(String::from("EHLO localhost"), "250"),
+		(format!("MAIL FROM:<{}>", config.from), "250"),
+		(format!("RCPT TO:<{}>", config.to), "250"),
+		(String::from("DATA"), "354"),
+	]
+	.iter()
+	{
+		write!(writer, "{}\r\n", command)?;
+		expect_reply(&mut reader, code)?;
+	}
+
+	// Lines starting with a dot get another so none ends the message early
+	for line in message.split("\r\n") {
+		if line.starts_with('.') {
+			write!(writer, ".")?;
+		}
+		write!(writer, "{}\r\n", line)?;
+	}
+	write!(writer, ".\r\n")?;
+	expect_reply(&mut reader, "250")?;
+	write!(writer, "QUIT\r\n")?;
+	Ok(())
+}
+
+fn send_sendmail(config: &Config, message: &str) -> io::Result<()> {
+	let mut child = Command::new("sendmail")
+		.arg("-i")
+		.arg(&config.to)
+		.stdin(Stdio::piped())
+		.spawn()?;
+	child
+		.stdin
+		.as_mut()
+		.expect("sendmail stdin not piped")
+		.write_all(message.replace("\r\n", "\n").as_bytes())?;
+	let status = child.wait()?;
+	if !status.success() {
+		return Err(io::Error::new(
+			io::ErrorKind::Other,
+			format!("sendmail exited with {}", status),
+		));
+	}
+	Ok(())
+}
+
+/// Mail the summary with the run's outcome, if --email-summary was given
+pub fn send(result: &BoxResult<()>) {
+	let config = CONFIG.read().expect("summary config lock poisoned");
+	let config = match &*config {
+		Some(config) => config,
+		None => return,
+	};
+
+	let message = message(config, result);
+	let sent = match &config.relay {
+		Some(relay) => send_smtp(relay, config, &message),
+		None => send_sendmail(config, &message),
+	};
+	match sent {
+		Ok(()) => println!("{}", tr("summary-mailed", &[("to", &config.to)])),
+		Err(err) => println!(
+			"{}",
+			tr(
+				"summary-mail-failed",
+				&[("to", &config.to), ("error", &err)]
+			)
+		),
+	}
+}
@@ -0,0 +1,28 @@
+use reqwest::Url;
+use rusqlite::functions::Context;
+use rusqlite::Connection;
+
+/// Name of the SQL function registered on every player database
+const SQL_FUNCTION: &str = "url_key";
+
+/// Comparison key for URLs that only differ in trivial ways, such as the case of the
+/// host or a trailing slash. Parsing also converts internationalised host names to
+/// punycode, so `bücher.example` and `xn--bcher-kva.example` give the same key.
+/// Every URL comparison, in SQL or not, should go through this.
+pub fn url_key(url: &str) -> String {
+	let url = url.trim();
+	let normalized = match Url::parse(url) {
+		Ok(parsed) => parsed.to_string(),
+		Err(_) => url.to_string(),
+	};
+	normalized.trim_end_matches('/').to_string()
+}
+
+/// Make `url_key(x)` available to the queries run against `conn`
+pub fn register(conn: &Connection) -> rusqlite::Result<()> {
+	conn.create_scalar_function(SQL_FUNCTION, 1, true, |ctx: &Context| {
+		Ok(ctx
+			.get::<Option<String>>(0)?
+			.map(|url| url_key(url.as_str())))
+	})
+}
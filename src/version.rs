@@ -0,0 +1,83 @@
+use crate::i18n;
+use crate::warning;
+use crate::{BoxResult, PlayerArgs};
+
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct EncodingInfo {
+	field: &'static str,
+	/// Value stored for each playing status, keyed by the status name
+	values: Vec<(String, i64)>,
+}
+
+#[derive(Serialize)]
+struct PlayerInfo {
+	name: &'static str,
+	cli_name: &'static str,
+	value_name: &'static str,
+	input: bool,
+	output: bool,
+	status_encodings: Vec<EncodingInfo>,
+	/// Database layouts the player recognises, empty when it only knows one
+	schema_versions: Vec<&'static str>,
+}
+
+/// What this build supports, for packagers and wrapper GUIs
+#[derive(Serialize)]
+struct VersionInfo<'a> {
+	name: &'static str,
+	version: &'static str,
+	/// Cargo features compiled in, the crate has no optional ones yet
+	features: Vec<&'static str>,
+	/// In the order they are added to the cli
+	subcommands: &'a [String],
+	languages: &'static [&'static str],
+	warnings: &'static [&'static str],
+	players: Vec<PlayerInfo>,
+}
+
+pub fn print(players_args: &[PlayerArgs], subcommands: &[String], json: bool) -> BoxResult<()> {
+	if !json {
+		println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+		return Ok(());
+	}
+
+	let players = players_args
+		.iter()
+		.map(|player_args| PlayerInfo {
+			name: player_args.name,
+			cli_name: player_args.cli_name,
+			value_name: player_args.value_name,
+			input: true,
+			output: !player_args.read_only,
+			status_encodings: player_args
+				.status_encodings
+				.iter()
+				.map(|encoding| EncodingInfo {
+					field: encoding.field,
+					values: encoding
+						.values
+						.iter()
+						.map(|(status, value)| (format!("{:?}", status), *value))
+						.collect(),
+				})
+				.collect(),
+			schema_versions: player_args.schema_versions.clone(),
+		})
+		.collect();
+
+	let info = VersionInfo {
+		name: env!("CARGO_PKG_NAME"),
+		version: env!("CARGO_PKG_VERSION"),
+		features: Vec::new(),
+		subcommands,
+		languages: &i18n::LANGUAGES,
+		warnings: &warning::NAMES,
+		players,
+	};
+
+	serde_json::to_writer_pretty(std::io::stdout(), &info)?;
+	println!();
+	Ok(())
+}
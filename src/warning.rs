@@ -0,0 +1,140 @@
+use crate::i18n::tr;
+use crate::Error;
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Kinds of warnings that can be silenced with --suppress or made fatal with --deny
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Warning {
+	/// A podcast or track from the OPML has no match in the player
+	UnmatchedTrack,
+	/// A value looks like it was stored in a different unit than expected
+	UnitMismatch,
+	/// The destination can not store everything that was read
+	LossyField,
+	/// A feed no longer exists
+	DeadFeed,
+	/// A feed, or some pages of it, could not be fetched or read and is left out of the conversion,
+	/// unless the source player has the episodes of it
+	FeedFailed,
+	/// A row or episode of a save file could not be read and is left out of the conversion
+	SkippedRow,
+	/// A podcast the source player has can not be found in any player and is left out
+	PodcastLeftOut,
+}
+
+const ALL: [Warning; 7] = [
+	Warning::UnmatchedTrack,
+	Warning::UnitMismatch,
+	Warning::LossyField,
+	Warning::DeadFeed,
+	Warning::FeedFailed,
+	Warning::SkippedRow,
+	Warning::PodcastLeftOut,
+];
+
+pub const NAMES: [&str; 7] = [
+	"unmatched-track",
+	"unit-mismatch",
+	"lossy-field",
+	"dead-feed",
+	"feed-failed",
+	"skipped-row",
+	"podcast-left-out",
+];
+
+static SUPPRESSED: AtomicUsize = AtomicUsize::new(0);
+static DENIED: AtomicUsize = AtomicUsize::new(0);
+static DENIED_COUNT: AtomicUsize = AtomicUsize::new(0);
+static QUIET: AtomicBool = AtomicBool::new(false);
+/// How often each kind of warning was raised, suppressed ones included
+static RAISED: [AtomicUsize; 7] = [
+	AtomicUsize::new(0),
+	AtomicUsize::new(0),
+	AtomicUsize::new(0),
+	AtomicUsize::new(0),
+	AtomicUsize::new(0),
+	AtomicUsize::new(0),
+	AtomicUsize::new(0),
+];
+
+impl Warning {
+	pub fn name(self) -> &'static str {
+		NAMES[self as usize]
+	}
+
+	fn from_name(name: &str) -> Option<Self> {
+		ALL.iter().cloned().find(|warning| warning.name() == name)
+	}
+
+	fn bit(self) -> usize {
+		1 << self as usize
+	}
+}
+
+fn mask<'a, I: IntoIterator<Item = &'a str>>(names: I) -> usize {
+	names
+		.into_iter()
+		.filter_map(Warning::from_name)
+		.fold(0, |mask, warning| mask | warning.bit())
+}
+
+/// Set which warnings are suppressed and which are denied, deny wins if both are given
+pub fn configure<'a, S, D>(suppress: S, deny: D)
+where
+	S: IntoIterator<Item = &'a str>,
+	D: IntoIterator<Item = &'a str>,
+{
+	SUPPRESSED.store(mask(suppress), Ordering::Relaxed);
+	DENIED.store(mask(deny), Ordering::Relaxed);
+}
+
+pub fn warn(warning: Warning, message: String) {
+	if QUIET.load(Ordering::Relaxed) {
+		return;
+	}
+	RAISED[warning as usize].fetch_add(1, Ordering::Relaxed);
+	if DENIED.load(Ordering::Relaxed) & warning.bit() != 0 {
+		println!(
+			"{}[{}]: {}",
+			tr("warning-denied-prefix", &[]),
+			warning.name(),
+			message
+		);
+		DENIED_COUNT.fetch_add(1, Ordering::Relaxed);
+	} else if SUPPRESSED.load(Ordering::Relaxed) & warning.bit() == 0 {
+		println!(
+			"{}[{}]: {}",
+			tr("warning-prefix", &[]),
+			warning.name(),
+			message
+		);
+	}
+}
+
+/// Run `f` without printing or counting the warnings it raises, for reading again what was
+/// already warned about
+pub fn quietly<T, F: FnOnce() -> T>(f: F) -> T {
+	QUIET.store(true, Ordering::Relaxed);
+	let result = f();
+	QUIET.store(false, Ordering::Relaxed);
+	result
+}
+
+/// Fails once any denied warning has been raised
+pub fn check_denied() -> Result<(), Error> {
+	match DENIED_COUNT.load(Ordering::Relaxed) {
+		0 => Ok(()),
+		count => Err(Error::DeniedWarnings(count)),
+	}
+}
+
+/// Name of each kind of warning with how often it was raised
+pub fn counts() -> impl Iterator<Item = (&'static str, usize)> {
+	ALL.iter().map(|warning| {
+		(
+			warning.name(),
+			RAISED[*warning as usize].load(Ordering::Relaxed),
+		)
+	})
+}
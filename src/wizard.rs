@@ -0,0 +1,244 @@
+use crate::{BoxResult, PlayerArgs};
+
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+
+pub const OPML_FILE_NAMES: [&str; 3] = ["*.opml", "*opml*.xml", "*subscriptions*.xml"];
+
+pub fn subcommand<'a, 'b>() -> App<'a, 'b> {
+	SubCommand::with_name("wizard")
+		.about("Ask step by step what to convert, then print and run the matching command")
+		.arg(
+			Arg::with_name("dir")
+				.takes_value(true)
+				.value_name("DIR")
+				.default_value(".")
+				.help("Directory with the OPML file and the players' save files"),
+		)
+}
+
+/// Case-insensitive match of a file name against a pattern where `*` matches anything
+fn glob_match(pattern: &str, name: &str) -> bool {
+	let (pattern, name) = (pattern.to_lowercase(), name.to_lowercase());
+	let parts: Vec<&str> = pattern.split('*').collect();
+	let (first, last) = (parts[0], parts[parts.len() - 1]);
+	if parts.len() == 1 {
+		return name == first;
+	}
+	if !name.starts_with(first) || !name[first.len()..].ends_with(last) {
+		return false;
+	}
+
+	let mut rest = &name[first.len()..name.len() - last.len()];
+	for part in parts[1..parts.len() - 1].iter() {
+		match rest.find(part) {
+			Some(i) => rest = &rest[i + part.len()..],
+			None => return false,
+		}
+	}
+	true
+}
+
+pub fn find_files(files: &[PathBuf], patterns: &[&str]) -> Vec<String> {
+	files
+		.iter()
+		.filter(|path| {
+			let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+			patterns.iter().any(|pattern| glob_match(pattern, name))
+		})
+		.map(|path| path.to_string_lossy().into_owned())
+		.collect()
+}
+
+fn ask(question: &str, default: Option<&str>) -> BoxResult<String> {
+	loop {
+		match default {
+			Some(default) => print!("{} [{}]: ", question, default),
+			None => print!("{}: ", question),
+		}
+		io::stdout().flush()?;
+
+		let mut answer = String::new();
+		if io::stdin().lock().read_line(&mut answer)? == 0 {
+			return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "no answer given").into());
+		}
+		match (answer.trim(), default) {
+			("", Some(default)) => return Ok(default.into()),
+			("", None) => continue,
+			(answer, _) => return Ok(answer.into()),
+		}
+	}
+}
+
+/// Index of the chosen option, the options are numbered from 1
+fn choose(question: &str, options: &[String]) -> BoxResult<usize> {
+	println!("{}", question);
+	for (i, option) in options.iter().enumerate() {
+		println!("  {}) {}", i + 1, option);
+	}
+	loop {
+		let answer = ask("Number", None)?;
+		match answer.parse::<usize>() {
+			Ok(n) if n >= 1 && n <= options.len() => return Ok(n - 1),
+			_ => println!("Enter a number from 1 to {}", options.len()),
+		}
+	}
+}
+
+fn confirm(question: &str) -> BoxResult<bool> {
+	let answer = ask(&format!("{} (y/n)", question), Some("y"))?;
+	Ok(answer.to_lowercase().starts_with('y'))
+}
+
+/// Pick one of the detected files, or ask for another
+fn choose_file(question: &str, found: &[String], what: &str) -> BoxResult<String> {
+	if found.is_empty() {
+		return ask(question, None);
+	}
+	let mut options = found.to_vec();
+	options.push(format!("Another {}", what));
+	match choose(question, &options)? {
+		i if i < found.len() => Ok(found[i].clone()),
+		_ => ask(&format!("Path of the {}", what), None),
+	}
+}
+
+fn player_location(player_args: &PlayerArgs, files: &[PathBuf]) -> BoxResult<String> {
+	if player_args.value_name == "URL" {
+		return ask(
+			&format!(
+				"URL of the {} server, credentials can be given in the URL",
+				player_args.name
+			),
+			None,
+		);
+	}
+	choose_file(
+		&format!("Which {} save file?", player_args.name),
+		&find_files(files, player_args.file_names),
+		"file",
+	)
+}
+
+fn player_names(list: &[&PlayerArgs]) -> Vec<String> {
+	list.iter()
+		.map(|player_args| String::from(player_args.name))
+		.collect()
+}
+
+/// Quote an argument for a POSIX shell when it needs it
+pub fn shell_quote(arg: &str) -> String {
+	let plain = arg
+		.chars()
+		.all(|c| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c));
+	if plain && !arg.is_empty() {
+		arg.into()
+	} else {
+		format!("'{}'", arg.replace('\'', "'\\''"))
+	}
+}
+
+pub fn run(matches: &ArgMatches, players_args: &[PlayerArgs]) -> BoxResult<()> {
+	let dir = Path::new(matches.value_of("dir").expect("no directory"));
+	let mut files: Vec<PathBuf> = std::fs::read_dir(dir)?
+		.map(|entry| entry.map(|entry| entry.path()))
+		.collect::<io::Result<_>>()?;
+	files.retain(|path| path.is_file());
+	files.sort();
+
+	// What was found, so the questions below make sense
+	let detected: Vec<(&PlayerArgs, Vec<String>)> = players_args
+		.iter()
+		.map(|player_args| (player_args, find_files(&files, player_args.file_names)))
+		.filter(|(_, found)| !found.is_empty())
+		.collect();
+	println!("Looking in '{}'", dir.display());
+	for (player_args, found) in detected.iter() {
+		for path in found.iter() {
+			println!("Found a {} save file: {}", player_args.name, path);
+		}
+	}
+
+	let opml = choose_file(
+		"Which OPML file lists the podcasts to convert?",
+		&find_files(&files, &OPML_FILE_NAMES),
+		"OPML file",
+	)?;
+
+	// Players with a detected file are offered first
+	let mut order: Vec<&PlayerArgs> = detected
+		.iter()
+		.map(|(player_args, _)| *player_args)
+		.collect();
+	order.extend(players_args.iter().filter(|player_args| {
+		!detected
+			.iter()
+			.any(|(found, _)| found.cli_name == player_args.cli_name)
+	}));
+
+	let source = order[choose(
+		"Which player do you want to convert from?",
+		&player_names(&order),
+	)?];
+	let source_location = player_location(source, &files)?;
+
+	let writable: Vec<&PlayerArgs> = order
+		.iter()
+		.cloned()
+		.filter(|player_args| !player_args.read_only && player_args.cli_name != source.cli_name)
+		.collect();
+	let destination = writable[choose(
+		"Which player do you want to convert to?",
+		&player_names(&writable),
+	)?];
+	let destination_location = player_location(destination, &files)?;
+
+	let default_output = match destination.value_name {
+		"URL" => dir
+			.join(String::from(destination.cli_name) + "_changes.json")
+			.to_string_lossy()
+			.into_owned(),
+		_ => destination_location.clone() + "_new",
+	};
+	let output = ask(
+		"Where should the converted file be written?",
+		Some(default_output.as_str()),
+	)?;
+
+	let args: Vec<String> = vec![
+		String::from("--opml"),
+		opml,
+		format!("--{}", source.cli_name),
+		source_location,
+		String::from("--") + source.in_name.as_str(),
+		format!("--{}", destination.cli_name),
+		destination_location,
+		String::from("--") + destination.out_name.as_str(),
+		output,
+	];
+
+	println!("The same conversion can be run later with:");
+	println!(
+		"\t{} {}",
+		env!("CARGO_PKG_NAME"),
+		args.iter()
+			.map(|arg| shell_quote(arg))
+			.collect::<Vec<String>>()
+			.join(" ")
+	);
+
+	if !confirm("Run it now?")? {
+		return Ok(());
+	}
+
+	let status = Command::new(std::env::current_exe()?)
+		.args(&args)
+		.status()?;
+	if !status.success() {
+		std::process::exit(status.code().unwrap_or(1));
+	}
+	Ok(())
+}
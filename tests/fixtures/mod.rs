@@ -0,0 +1,583 @@
+// Small save files for every player the converter can write to a file, each
+// holding the episodes of one local feed in given states. They are built the
+// way the players lay them out, with only the tables and fields the converter
+// reads or writes.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use byteorder::{BigEndian, WriteBytesExt};
+use rusqlite::Connection;
+use serde_json::{json, Value};
+use zip::write::{FileOptions, ZipWriter};
+
+pub const TITLE: &str = "Round Trip Show";
+/// Length of every episode in seconds
+pub const DURATION: i32 = 600;
+
+pub struct Episode {
+	pub guid: &'static str,
+	pub url: &'static str,
+	pub title: &'static str,
+}
+
+pub const EPISODES: [Episode; 3] = [
+	Episode {
+		guid: "ep-1",
+		url: "https://example.com/ep1.mp3",
+		title: "Episode One",
+	},
+	Episode {
+		guid: "ep-2",
+		url: "https://example.com/ep2.mp3",
+		title: "Episode Two",
+	},
+	Episode {
+		guid: "ep-3",
+		url: "https://example.com/ep3.mp3",
+		title: "Episode Three",
+	},
+];
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum State {
+	Unplayed,
+	/// Position in seconds
+	Playing(i32),
+	Played,
+}
+
+impl State {
+	fn played(self) -> bool {
+		self == State::Played
+	}
+
+	fn started(self) -> bool {
+		self != State::Unplayed
+	}
+
+	/// Position in seconds, a played episode is at its end
+	fn position(self) -> i32 {
+		match self {
+			State::Unplayed => 0,
+			State::Playing(position) => position,
+			State::Played => DURATION,
+		}
+	}
+
+	fn name(self) -> &'static str {
+		match self {
+			State::Unplayed => "unplayed",
+			State::Playing(_) => "playing",
+			State::Played => "played",
+		}
+	}
+}
+
+/// A history with an episode in each state, for the player converted from
+pub const HISTORY: [State; 3] = [State::Played, State::Playing(120), State::Unplayed];
+/// Nothing played yet, for the players converted through
+pub const BLANK: [State; 3] = [State::Unplayed; 3];
+
+/// Writes the save file of a player at the path, for the feed at the URL
+pub type Writer = fn(&Path, &str, &[State]);
+
+/// Every player that can be written to a file and read back, by its command line name
+pub const PLAYERS: [(&str, Writer); 17] = [
+	("antennapod", antennapod),
+	("antennapod-actions", episode_actions),
+	("beyondpod", beyondpod),
+	("cpod", cpod),
+	("csv", csv),
+	("downcast", downcast),
+	("escapepod", escapepod),
+	("gnomepodcasts", gnomepodcasts),
+	("gpodder-actions", episode_actions),
+	("history-db", history_db),
+	("json", json_file),
+	("kasts", kasts),
+	("kodi", kodi),
+	("mpd", mpd),
+	("pocketcasts", pocketcasts),
+	("podcastrepublic", podcastrepublic),
+	("podverse", podverse),
+];
+
+pub struct Feed {
+	pub url: String,
+	pub opml: PathBuf,
+}
+
+/// Write the feed and an OPML file subscribing to it into the directory
+pub fn feed(dir: &Path) -> Feed {
+	let feed_path = dir.join("feed.xml");
+	let items: String = EPISODES
+		.iter()
+		.map(|episode| {
+			format!(
+				"<item><title>{}</title><guid>{}</guid><enclosure url=\"{}\" type=\"audio/mpeg\"/><itunes:duration>{}</itunes:duration></item>",
+				episode.title, episode.guid, episode.url, DURATION
+			)
+		})
+		.collect();
+	fs::write(
+		&feed_path,
+		format!(
+			"<?xml version=\"1.0\"?><rss version=\"2.0\" xmlns:itunes=\"http://www.itunes.com/dtds/podcast-1.0.dtd\"><channel><title>{}</title>{}</channel></rss>",
+			TITLE, items
+		),
+	)
+	.expect("write feed");
+
+	let url = format!("file://{}", feed_path.display());
+	let opml = dir.join("feeds.opml");
+	fs::write(
+		&opml,
+		format!(
+			"<?xml version=\"1.0\"?><opml version=\"1.0\"><body><outline text=\"Tests\"><outline text=\"{}\" type=\"rss\" xmlUrl=\"{}\"/></outline></body></opml>",
+			TITLE, url
+		),
+	)
+	.expect("write opml");
+
+	Feed { url, opml }
+}
+
+/// SQL string literal
+fn text(value: &str) -> String {
+	format!("'{}'", value.replace('\'', "''"))
+}
+
+fn episodes<'a>(states: &'a [State]) -> impl Iterator<Item = (&'static Episode, State)> + 'a {
+	EPISODES.iter().zip(states.iter().cloned())
+}
+
+fn sqlite(path: &Path, sql: &str) {
+	let conn = Connection::open(path).expect("create fixture database");
+	conn.execute_batch(sql).expect("fill fixture database");
+}
+
+/// A zip backup holding the database as `db_file`, along with any other files
+fn zipped_sqlite(path: &Path, db_file: &str, sql: &str, others: &[(&str, Vec<u8>)]) {
+	let db_path = path.with_extension("sqlite");
+	sqlite(&db_path, sql);
+
+	let mut zip = ZipWriter::new(File::create(path).expect("create fixture archive"));
+	let db = fs::read(&db_path).expect("read fixture database");
+	for (name, bytes) in [(db_file, db)].iter().chain(others.iter()) {
+		zip.start_file(*name, FileOptions::default())
+			.expect("add to fixture archive");
+		zip.write_all(bytes).expect("write to fixture archive");
+	}
+	zip.finish().expect("finish fixture archive");
+}
+
+fn write_json(path: &Path, value: &Value) {
+	fs::write(
+		path,
+		serde_json::to_vec_pretty(value).expect("serialise fixture"),
+	)
+	.expect("write fixture");
+}
+
+fn csv(path: &Path, feed_url: &str, states: &[State]) {
+	let mut rows = String::from("podcast,feed_url,episode,guid,url,status,progress,duration\n");
+	for (episode, state) in episodes(states) {
+		rows += &format!(
+			"{},{},{},{},{},{},{},{}\n",
+			TITLE,
+			feed_url,
+			episode.title,
+			episode.guid,
+			episode.url,
+			state.name(),
+			state.position(),
+			DURATION
+		);
+	}
+	fs::write(path, rows).expect("write fixture");
+}
+
+fn json_file(path: &Path, feed_url: &str, states: &[State]) {
+	let tracks: Vec<Value> = episodes(states)
+		.map(|(episode, state)| {
+			json!({
+				"guid": episode.guid,
+				"url": episode.url,
+				"title": episode.title,
+				"duration": DURATION,
+				"progress": state.position(),
+				"status": state.name(),
+			})
+		})
+		.collect();
+	write_json(
+		path,
+		&json!({
+			"version": 1,
+			"podcasts": [{ "url": feed_url, "title": TITLE, "tracks": tracks }],
+		}),
+	);
+}
+
+fn history_db(path: &Path, feed_url: &str, states: &[State]) {
+	// The schema as the first release of the history database wrote it
+	let mut sql = String::from(
+		"CREATE TABLE schema_version (version INTEGER NOT NULL);
+		INSERT INTO schema_version (version) VALUES (1);
+		CREATE TABLE podcasts (id INTEGER PRIMARY KEY, url TEXT NOT NULL UNIQUE, title TEXT NOT NULL, auto_archive INTEGER, notifications INTEGER, update_interval INTEGER, download_count INTEGER, playlist TEXT);
+		CREATE TABLE tracks (id INTEGER PRIMARY KEY, podcast_id INTEGER NOT NULL REFERENCES podcasts (id), guid TEXT NOT NULL, url TEXT NOT NULL, title TEXT NOT NULL, duration INTEGER, chapters TEXT NOT NULL, progress INTEGER NOT NULL, status TEXT NOT NULL, modified INTEGER NOT NULL, UNIQUE (podcast_id, guid));
+		CREATE TABLE runs (id INTEGER PRIMARY KEY, timestamp INTEGER NOT NULL, source_player TEXT NOT NULL, tool_version TEXT NOT NULL, podcasts INTEGER NOT NULL, tracks INTEGER NOT NULL);",
+	);
+	sql += &format!(
+		"INSERT INTO podcasts (id, url, title) VALUES (1, {}, {});",
+		text(feed_url),
+		text(TITLE)
+	);
+	for (episode, state) in episodes(states) {
+		sql += &format!(
+			"INSERT INTO tracks (podcast_id, guid, url, title, duration, chapters, progress, status, modified) VALUES (1, {}, {}, {}, {}, '', {}, {}, 0);",
+			text(episode.guid),
+			text(episode.url),
+			text(episode.title),
+			DURATION,
+			state.position(),
+			text(state.name())
+		);
+	}
+	sqlite(path, &sql);
+}
+
+fn cpod(path: &Path, feed_url: &str, states: &[State]) {
+	let queue: Vec<Value> = episodes(states)
+		.filter(|(_, state)| state.started() && !state.played())
+		.map(|(episode, state)| {
+			json!({
+				"feedUrl": feed_url,
+				"guid": episode.guid,
+				"url": episode.url,
+				"title": episode.title,
+				"progress": state.position(),
+			})
+		})
+		.collect();
+	let completed: Vec<Value> = episodes(states)
+		.filter(|(_, state)| state.played())
+		.map(|(episode, _)| json!({ "feedUrl": feed_url, "guid": episode.guid }))
+		.collect();
+	write_json(
+		path,
+		&json!({
+			"subscriptions": [{ "title": TITLE, "feedUrl": feed_url }],
+			"queue": queue,
+			"completed": completed,
+		}),
+	);
+}
+
+fn escapepod(path: &Path, feed_url: &str, states: &[State]) {
+	let stored: Vec<Value> = episodes(states)
+		.map(|(episode, state)| {
+			json!({
+				"guid": episode.guid,
+				"url": episode.url,
+				"positionMs": state.position() as i64 * 1000,
+				"played": state.played(),
+			})
+		})
+		.collect();
+	write_json(
+		path,
+		&json!({
+			"podcasts": [{ "title": TITLE, "feedUrl": feed_url, "episodes": stored }],
+		}),
+	);
+}
+
+fn podverse(path: &Path, _feed_url: &str, states: &[State]) {
+	let history: Vec<Value> = episodes(states)
+		.filter(|(_, state)| state.started())
+		.map(|(episode, state)| {
+			json!({
+				"episodeMediaUrl": episode.url,
+				"episodeTitle": episode.title,
+				"podcastTitle": TITLE,
+				"userPlaybackPosition": state.position(),
+				"mediaFileDuration": DURATION,
+				"completed": state.played(),
+			})
+		})
+		.collect();
+	write_json(path, &json!({ "historyItems": history, "queueItems": [] }));
+}
+
+fn episode_actions(path: &Path, feed_url: &str, states: &[State]) {
+	let actions: Vec<Value> = episodes(states)
+		.filter(|(_, state)| state.started())
+		.map(|(episode, state)| {
+			json!({
+				"podcast": feed_url,
+				"episode": episode.url,
+				"guid": episode.guid,
+				"action": "play",
+				"timestamp": "2020-01-01T00:00:00",
+				"started": 0,
+				"position": state.position(),
+				"total": DURATION,
+			})
+		})
+		.collect();
+	write_json(path, &Value::Array(actions));
+}
+
+fn antennapod(path: &Path, feed_url: &str, states: &[State]) {
+	let mut sql = format!(
+		"CREATE TABLE Feeds (id INTEGER PRIMARY KEY, title TEXT, download_url TEXT);
+		CREATE TABLE FeedItems (id INTEGER PRIMARY KEY, title TEXT, read INTEGER, feed INTEGER, item_identifier TEXT);
+		CREATE TABLE FeedMedia (id INTEGER PRIMARY KEY, duration INTEGER, download_url TEXT, position INTEGER, playback_completion_date INTEGER, feeditem INTEGER, played_duration INTEGER, last_played_time INTEGER);
+		INSERT INTO Feeds (id, title, download_url) VALUES (1, {}, {});",
+		text(TITLE),
+		text(feed_url)
+	);
+	for (i, (episode, state)) in episodes(states).enumerate() {
+		sql += &format!(
+			"INSERT INTO FeedItems (id, title, read, feed, item_identifier) VALUES ({id}, {}, {}, 1, {});
+			INSERT INTO FeedMedia (duration, download_url, position, playback_completion_date, feeditem, played_duration, last_played_time) VALUES ({}, {}, {}, {}, {id}, 0, {});",
+			text(episode.title),
+			if state.played() { 1 } else { -1 },
+			text(episode.guid),
+			DURATION as i64 * 1000,
+			text(episode.url),
+			state.position() as i64 * 1000,
+			if state.played() { 1_577_836_800_000i64 } else { 0 },
+			if state.started() { 1_577_836_800_000i64 } else { 0 },
+			id = i + 1
+		);
+	}
+	sqlite(path, &sql);
+}
+
+fn downcast(path: &Path, feed_url: &str, states: &[State]) {
+	let mut sql = format!(
+		"CREATE TABLE ZPODCAST (Z_PK INTEGER PRIMARY KEY, ZFEEDURL TEXT, ZTITLE TEXT);
+		CREATE TABLE ZEPISODE (Z_PK INTEGER PRIMARY KEY, ZPODCAST INTEGER, ZGUID TEXT, ZPLAYED INTEGER, ZPLAYBACKPOSITION REAL);
+		INSERT INTO ZPODCAST (Z_PK, ZFEEDURL, ZTITLE) VALUES (1, {}, {});",
+		text(feed_url),
+		text(TITLE)
+	);
+	for (episode, state) in episodes(states) {
+		sql += &format!(
+			"INSERT INTO ZEPISODE (ZPODCAST, ZGUID, ZPLAYED, ZPLAYBACKPOSITION) VALUES (1, {}, {}, {}.0);",
+			text(episode.guid),
+			state.played() as i32,
+			state.position()
+		);
+	}
+	sqlite(path, &sql);
+}
+
+fn gnomepodcasts(path: &Path, feed_url: &str, states: &[State]) {
+	let mut sql = format!(
+		"CREATE TABLE source (id INTEGER PRIMARY KEY, uri TEXT NOT NULL);
+		CREATE TABLE shows (id INTEGER PRIMARY KEY, source_id INTEGER NOT NULL, title TEXT NOT NULL);
+		CREATE TABLE episodes (id INTEGER PRIMARY KEY, show_id INTEGER NOT NULL, guid TEXT, played INTEGER, play_position INTEGER NOT NULL);
+		INSERT INTO source (id, uri) VALUES (1, {});
+		INSERT INTO shows (id, source_id, title) VALUES (1, 1, {});",
+		text(feed_url),
+		text(TITLE)
+	);
+	for (episode, state) in episodes(states) {
+		sql += &format!(
+			"INSERT INTO episodes (show_id, guid, played, play_position) VALUES (1, {}, {}, {});",
+			text(episode.guid),
+			if state.played() { "1577836800" } else { "NULL" },
+			state.position()
+		);
+	}
+	sqlite(path, &sql);
+}
+
+fn kasts(path: &Path, feed_url: &str, states: &[State]) {
+	let mut sql = format!(
+		"CREATE TABLE Feeds (url TEXT PRIMARY KEY, name TEXT);
+		CREATE TABLE Entries (feed TEXT, id TEXT, title TEXT, read INTEGER);
+		CREATE TABLE Enclosures (feed TEXT, id TEXT, url TEXT, playposition INTEGER);
+		INSERT INTO Feeds (url, name) VALUES ({}, {});",
+		text(feed_url),
+		text(TITLE)
+	);
+	for (episode, state) in episodes(states) {
+		sql += &format!(
+			"INSERT INTO Entries (feed, id, title, read) VALUES ({feed}, {id}, {}, {});
+			INSERT INTO Enclosures (feed, id, url, playposition) VALUES ({feed}, {id}, {}, {});",
+			text(episode.title),
+			state.played() as i32,
+			text(episode.url),
+			state.position() as i64 * 1000,
+			feed = text(feed_url),
+			id = text(episode.guid)
+		);
+	}
+	sqlite(path, &sql);
+}
+
+fn kodi(path: &Path, _feed_url: &str, states: &[State]) {
+	// Kodi only knows the files it played, streamed ones under their URL split in two
+	let mut sql = String::from(
+		"CREATE TABLE path (idPath INTEGER PRIMARY KEY, strPath TEXT);
+		CREATE TABLE files (idFile INTEGER PRIMARY KEY, idPath INTEGER, strFilename TEXT, playCount INTEGER, lastPlayed TEXT);
+		CREATE TABLE bookmark (idBookmark INTEGER PRIMARY KEY, idFile INTEGER, timeInSeconds REAL, totalTimeInSeconds REAL, thumbNailImage TEXT, player TEXT, playerState TEXT, type INTEGER);
+		INSERT INTO path (idPath, strPath) VALUES (1, 'https://example.com/');",
+	);
+	for (id, (episode, state)) in episodes(states).enumerate() {
+		let file_name = episode
+			.url
+			.rsplit('/')
+			.next()
+			.expect("episode URL has a file name");
+		sql += &format!(
+			"INSERT INTO files (idFile, idPath, strFilename, playCount, lastPlayed) VALUES ({}, 1, {}, {}, {});",
+			id + 1,
+			text(file_name),
+			if state.played() { "1" } else { "NULL" },
+			if state.played() { "'2020-01-01 00:00:00'" } else { "NULL" }
+		);
+		if let State::Playing(position) = state {
+			sql += &format!(
+				"INSERT INTO bookmark (idFile, timeInSeconds, totalTimeInSeconds, player, playerState, type) VALUES ({}, {}.0, {}.0, 'VideoPlayer', '', 1);",
+				id + 1,
+				position,
+				DURATION
+			);
+		}
+	}
+	sqlite(path, &sql);
+}
+
+fn mpd(path: &Path, _feed_url: &str, states: &[State]) {
+	let mut sql = String::from(
+		"CREATE TABLE sticker (type VARCHAR NOT NULL, uri VARCHAR NOT NULL, name VARCHAR NOT NULL, value VARCHAR NOT NULL);
+		CREATE UNIQUE INDEX sticker_value ON sticker (type, uri, name);",
+	);
+	for (episode, state) in episodes(states) {
+		if let State::Playing(position) = state {
+			sql += &format!(
+				"INSERT INTO sticker VALUES ('song', {}, 'elapsed', '{}');",
+				text(episode.url),
+				position
+			);
+		}
+		if state.played() {
+			sql += &format!(
+				"INSERT INTO sticker VALUES ('song', {}, 'playcount', '1');",
+				text(episode.url)
+			);
+		}
+	}
+	sqlite(path, &sql);
+}
+
+fn pocketcasts(path: &Path, _feed_url: &str, states: &[State]) {
+	// Podcasts are found by their title, episodes by their enclosure URL
+	let podcast_id = "00000000-0000-0000-0000-000000000001";
+	let mut sql = format!(
+		"CREATE TABLE podcasts (uuid TEXT PRIMARY KEY, title TEXT, subscribed INTEGER);
+		CREATE TABLE episodes (uuid TEXT PRIMARY KEY, podcast_id TEXT, download_url TEXT, title TEXT, duration REAL, published_date INTEGER, playing_status INTEGER, playing_status_modified INTEGER, played_up_to REAL, played_up_to_modified INTEGER);
+		INSERT INTO podcasts (uuid, title, subscribed) VALUES ({}, {}, 1);",
+		text(podcast_id),
+		text(TITLE)
+	);
+	for (n, (episode, state)) in episodes(states).enumerate() {
+		let playing_status = match state {
+			State::Unplayed => 0,
+			State::Playing(_) => 1,
+			State::Played => 2,
+		};
+		sql += &format!(
+			"INSERT INTO episodes VALUES ('00000000-0000-0000-0001-{:012}', {}, {}, {}, {}.0, NULL, {}, 0, {}.0, 0);",
+			n + 1,
+			text(podcast_id),
+			text(episode.url),
+			text(episode.title),
+			DURATION,
+			playing_status,
+			state.position()
+		);
+	}
+	sqlite(path, &sql);
+}
+
+fn podcastrepublic(path: &Path, feed_url: &str, states: &[State]) {
+	let mut sql = format!(
+		"CREATE TABLE podcasts (_id INTEGER PRIMARY KEY, feed_url TEXT, title TEXT);
+		CREATE TABLE episodes (_id INTEGER PRIMARY KEY, podcast_id INTEGER, media_url TEXT, played INTEGER, position_ms INTEGER);
+		INSERT INTO podcasts (_id, feed_url, title) VALUES (1, {}, {});",
+		text(feed_url),
+		text(TITLE)
+	);
+	for (episode, state) in episodes(states) {
+		sql += &format!(
+			"INSERT INTO episodes (podcast_id, media_url, played, position_ms) VALUES (1, {}, {}, {});",
+			text(episode.url),
+			state.played() as i32,
+			state.position() as i64 * 1000
+		);
+	}
+	zipped_sqlite(path, "podcastrepublic.db", &sql, &[]);
+}
+
+/// BeyondPod keeps tracks by a hash of their guid
+fn beyondpod_track_id(guid: &str) -> String {
+	let hash = guid
+		.bytes()
+		.fold(0u32, |acc, b| acc.wrapping_mul(31).wrapping_add(b as u32));
+	(hash as i32).to_string()
+}
+
+fn beyondpod(path: &Path, feed_url: &str, states: &[State]) {
+	let feed_id = "00000000-0000-0000-0000-000000000001";
+	let mut sql = format!(
+		"CREATE TABLE feeds (feedid TEXT PRIMARY KEY, url TEXT, name TEXT, hasunread INTEGER, lastupdate INTEGER);
+		CREATE TABLE tracks (orgrssitemid TEXT, parentfeedid TEXT, played INTEGER, playedtime INTEGER, url TEXT, name TEXT, totaltime INTEGER);
+		INSERT INTO feeds (feedid, url, name, hasunread, lastupdate) VALUES ({}, {}, {}, 0, 0);",
+		text(feed_id),
+		text(feed_url),
+		text(TITLE)
+	);
+
+	// The item history lists the feed, then a played flag for each of its tracks
+	let mut history = Vec::new();
+	let history_token = |w: &mut Vec<u8>, string: &str, data: u32| {
+		w.write_u16::<BigEndian>(string.len() as u16).unwrap();
+		w.write_all(string.as_bytes()).unwrap();
+		w.write_u32::<BigEndian>(data).unwrap();
+	};
+	history_token(&mut history, feed_id, states.len() as u32);
+
+	for (episode, state) in episodes(states) {
+		let track_id = beyondpod_track_id(episode.guid);
+		sql += &format!(
+			"INSERT INTO tracks VALUES ({}, {}, {}, {}, {}, {}, {});",
+			text(&track_id),
+			text(feed_id),
+			state.played() as i32,
+			state.position(),
+			text(episode.url),
+			text(episode.title),
+			DURATION
+		);
+		history_token(
+			&mut history,
+			&track_id,
+			if state.played() { 65 } else { 64 },
+		);
+	}
+
+	zipped_sqlite(
+		path,
+		"beyondpod.db.autobak",
+		&sql,
+		&[("BeyondPodItemHistory.bin.autobak", history)],
+	);
+}
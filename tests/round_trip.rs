@@ -0,0 +1,72 @@
+// Converts a small history from each player that can be written to a file
+// through every other one and back with check-round-trip, which fails when a
+// status or position does not come back as it was.
+
+mod fixtures;
+
+use fixtures::{BLANK, HISTORY, PLAYERS};
+
+use std::process::Command;
+
+fn round_trip(from: &str) {
+	let dir = tempfile::tempdir().expect("create temporary directory");
+	let feed = fixtures::feed(dir.path());
+
+	let mut command = Command::new(env!("CARGO_BIN_EXE_podcast_history_converter"));
+	command
+		.arg("check-round-trip")
+		.arg("--opml")
+		.arg(&feed.opml)
+		.args(&["--from", from]);
+
+	for (cli_name, write) in PLAYERS.iter() {
+		let path = dir.path().join(cli_name);
+		if *cli_name == from {
+			write(&path, &feed.url, &HISTORY);
+		} else {
+			write(&path, &feed.url, &BLANK);
+			command.args(&["--via", cli_name]);
+		}
+		command.arg(format!("--{}", cli_name)).arg(&path);
+	}
+
+	let output = command.output().expect("run podcast_history_converter");
+	assert!(
+		output.status.success(),
+		"round trip from {} failed\n{}\n{}",
+		from,
+		String::from_utf8_lossy(&output.stdout),
+		String::from_utf8_lossy(&output.stderr)
+	);
+}
+
+macro_rules! round_trip_tests {
+	($($name:ident: $player:expr,)*) => {
+		$(
+			#[test]
+			fn $name() {
+				round_trip($player);
+			}
+		)*
+	};
+}
+
+round_trip_tests! {
+	from_antennapod: "antennapod",
+	from_antennapod_actions: "antennapod-actions",
+	from_beyondpod: "beyondpod",
+	from_cpod: "cpod",
+	from_csv: "csv",
+	from_downcast: "downcast",
+	from_escapepod: "escapepod",
+	from_gnomepodcasts: "gnomepodcasts",
+	from_gpodder_actions: "gpodder-actions",
+	from_history_db: "history-db",
+	from_json: "json",
+	from_kasts: "kasts",
+	from_kodi: "kodi",
+	from_mpd: "mpd",
+	from_pocketcasts: "pocketcasts",
+	from_podcastrepublic: "podcastrepublic",
+	from_podverse: "podverse",
+}